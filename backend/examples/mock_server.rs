@@ -10,7 +10,6 @@ use repoyear_backend::api::{
     mock::{MockApiImpl, MockAppState},
     repo_year_api_mod,
 };
-use slog::Drain;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -32,10 +31,10 @@ async fn main() -> anyhow::Result<()> {
     };
 
     // Create a logger
-    let decorator = slog_term::TermDecorator::new().build();
-    let drain = slog_term::FullFormat::new(decorator).build().fuse();
-    let drain = slog_async::Async::new(drain).build().fuse();
-    let log = slog::Logger::root(drain, slog::o!());
+    let log = dropshot::ConfigLogging::StderrTerminal {
+        level: dropshot::ConfigLoggingLevel::Info,
+    }
+    .to_logger("mock_server")?;
 
     // Start the server with the mock implementation
     let server = dropshot::HttpServerStarter::new(
@@ -0,0 +1,293 @@
+//! Write an HTTP access log, one line per request.
+//!
+//! Dropshot logs a `request completed` message for every request. This picks
+//! those out of the slog stream and writes them in a format that standard log
+//! analyzers understand, separately from the application logs.
+
+use crate::params::AccessLogFormat;
+use slog::Drain;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+/// Message Dropshot logs when it finishes a request.
+const REQUEST_COMPLETED: &str = "request completed";
+
+/// Where and how to write the access log.
+pub struct AccessLog {
+    /// Line format.
+    format: AccessLogFormat,
+
+    /// Where to write lines.
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// Open an access log at `path`, or standard output if `path` is `-`.
+    ///
+    /// Files are appended to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can’t be opened.
+    pub fn open(path: &Path, format: AccessLogFormat) -> io::Result<Self> {
+        let output: Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            Box::new(fs::File::options().create(true).append(true).open(path)?)
+        };
+        Ok(Self { format, output: Mutex::new(output) })
+    }
+
+    /// Request headers that Dropshot needs to include in its logs.
+    ///
+    /// These go in `ConfigDropshot::log_headers`.
+    #[must_use]
+    pub fn headers(&self) -> Vec<String> {
+        match self.format {
+            AccessLogFormat::Common => Vec::new(),
+            AccessLogFormat::Combined | AccessLogFormat::Json => {
+                vec!["referer".to_owned(), "user-agent".to_owned()]
+            }
+        }
+    }
+
+    /// Make a logger that sends everything to `log`, and also writes
+    /// completed requests to this access log.
+    pub fn wrap(self, log: &slog::Logger) -> slog::Logger {
+        slog::Logger::root(
+            slog::Duplicate::new(log.clone(), self).ignore_res(),
+            slog::o!(),
+        )
+    }
+}
+
+impl slog::Drain for AccessLog {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &slog::Record<'_>,
+        values: &slog::OwnedKVList,
+    ) -> Result<(), slog::Never> {
+        if record.msg().to_string() != REQUEST_COMPLETED {
+            return Ok(());
+        }
+        let mut fields = Fields::default();
+        let _ = slog::KV::serialize(&record.kv(), record, &mut fields);
+        let _ = slog::KV::serialize(values, record, &mut fields);
+        let line = Request::from_fields(&fields.0).format(self.format);
+
+        // There’s nowhere to report a failure to write the access log.
+        let _ = write_line(
+            &mut *self.output.lock().unwrap_or_else(PoisonError::into_inner),
+            &line,
+        );
+        Ok(())
+    }
+}
+
+/// Write a line and flush it so that it shows up immediately.
+fn write_line(output: &mut dyn Write, line: &str) -> io::Result<()> {
+    writeln!(output, "{line}")?;
+    output.flush()
+}
+
+/// A completed request.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Request {
+    /// Client IP address.
+    remote_ip: Option<String>,
+
+    /// Request method.
+    method: String,
+
+    /// Path and query.
+    uri: String,
+
+    /// Response status code.
+    status: String,
+
+    /// How long the request took in microseconds.
+    latency_us: Option<u64>,
+
+    /// Dropshot’s request ID.
+    id: Option<String>,
+
+    /// `Referer` header.
+    referer: Option<String>,
+
+    /// `User-Agent` header.
+    user_agent: Option<String>,
+
+    /// When the request was received.
+    received: chrono::DateTime<chrono::Local>,
+}
+
+impl Request {
+    /// Get a request from the fields Dropshot logs.
+    fn from_fields(fields: &HashMap<String, String>) -> Self {
+        let get = |key: &str| fields.get(key).cloned();
+        let latency_us = fields
+            .get("latency_us")
+            .and_then(|latency| latency.parse().ok());
+        let now = chrono::Local::now();
+        let received = latency_us
+            .and_then(|latency: u64| {
+                now.checked_sub_signed(chrono::Duration::microseconds(
+                    latency.try_into().ok()?,
+                ))
+            })
+            .unwrap_or(now);
+        Self {
+            remote_ip: fields.get("remote_addr").map(|address| {
+                address.parse::<SocketAddr>().map_or_else(
+                    |_| address.clone(),
+                    |address| address.ip().to_string(),
+                )
+            }),
+            method: get("method").unwrap_or_default(),
+            uri: get("uri").unwrap_or_default(),
+            status: get("response_code").unwrap_or_default(),
+            latency_us,
+            id: get("req_id"),
+            referer: get("hdr_referer"),
+            user_agent: get("hdr_user_agent"),
+            received,
+        }
+    }
+
+    /// Format as a line of the access log.
+    fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Common => self.common(),
+            AccessLogFormat::Combined => format!(
+                "{} {} {}",
+                self.common(),
+                Quoted(self.referer.as_deref()),
+                Quoted(self.user_agent.as_deref()),
+            ),
+            AccessLogFormat::Json => serde_json::json!({
+                "time": self.received.to_rfc3339(),
+                "remote_ip": self.remote_ip,
+                "method": self.method,
+                "uri": self.uri,
+                "status": self.status.parse::<u16>().ok(),
+                // Dropshot doesn’t report the size of the response.
+                "bytes": null,
+                "latency_us": self.latency_us,
+                "request_id": self.id,
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+            })
+            .to_string(),
+        }
+    }
+
+    /// Format in Common Log Format.
+    ///
+    /// The response size is always `-`, since Dropshot doesn’t report it.
+    fn common(&self) -> String {
+        format!(
+            "{} - - [{}] {} {} -",
+            self.remote_ip.as_deref().unwrap_or("-"),
+            self.received.format("%d/%b/%Y:%H:%M:%S %z"),
+            Quoted(Some(&format!("{} {} HTTP/1.1", self.method, self.uri))),
+            self.status,
+        )
+    }
+}
+
+/// Format a value in double quotes, or `"-"` if it’s missing.
+struct Quoted<'a>(Option<&'a str>);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.unwrap_or("-").chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if c.is_control() => write!(f, "\\x{:02x}", u32::from(c))?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Collects slog key-value pairs.
+#[derive(Default)]
+struct Fields(HashMap<String, String>);
+
+impl slog::Serializer for Fields {
+    fn emit_arguments(
+        &mut self,
+        key: slog::Key,
+        value: &fmt::Arguments<'_>,
+    ) -> slog::Result {
+        self.0.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use chrono::TimeZone;
+
+    fn request() -> Request {
+        let fields = [
+            ("remote_addr", "192.0.2.1:50000"),
+            ("method", "GET"),
+            ("uri", "/api/contributions?repo=a"),
+            ("response_code", "200"),
+            ("latency_us", "1500"),
+            ("req_id", "abc"),
+            ("hdr_user_agent", "curl/8.0 \"test\""),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect();
+        Request {
+            received: chrono::Local
+                .with_ymd_and_hms(2025, 1, 2, 3, 4, 5)
+                .unwrap(),
+            ..Request::from_fields(&fields)
+        }
+    }
+
+    #[test]
+    fn common() {
+        let line = request().format(AccessLogFormat::Common);
+        assert!(line.starts_with("192.0.2.1 - - [02/Jan/2025:03:04:05 "));
+        assert!(
+            line.ends_with(
+                r#"] "GET /api/contributions?repo=a HTTP/1.1" 200 -"#
+            )
+        );
+    }
+
+    #[test]
+    fn combined() {
+        let line = request().format(AccessLogFormat::Combined);
+        assert!(line.ends_with(r#" 200 - "-" "curl/8.0 \"test\"""#));
+    }
+
+    #[test]
+    fn json() {
+        let line = request().format(AccessLogFormat::Json);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert!(json["status"] == 200);
+        assert!(json["latency_us"] == 1500);
+        assert!(json["request_id"] == "abc");
+        assert!(json["remote_ip"] == "192.0.2.1");
+        assert!(json["bytes"].is_null());
+    }
+}
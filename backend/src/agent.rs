@@ -0,0 +1,652 @@
+//! Scan on one machine and report to a central server.
+//!
+//! An agent scans local repositories and pushes a [`Report`] to the central
+//! server’s `/api/ingest` endpoint. Reports are written to a [`Queue`] on disk
+//! first, so nothing is lost if the server can’t be reached; they are sent in
+//! order the next time it can be.
+
+use crate::repos::{self, Commit};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Delay before the first retry.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Maximum delay between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Results of scanning the repositories on one machine.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct Report {
+    /// Name of the agent that produced the report.
+    pub agent: String,
+
+    /// When the scan finished (seconds since epoch).
+    pub scanned_at: i64,
+
+    /// Repositories by name.
+    pub repos: BTreeMap<String, RepoReport>,
+}
+
+/// Results of scanning one repository.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct RepoReport {
+    /// URL of the repository’s main remote, if it has one.
+    ///
+    /// This is used to recognize the same repository on different machines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+
+    /// Commits on the default branch.
+    pub commits: Vec<Commit>,
+}
+
+impl Report {
+    /// Scan all repositories in `config`.
+    ///
+    /// Repositories in trees that set [`fetch`](repos::TreeConfig::fetch) are
+    /// fetched first.
+    ///
+    /// Returns the report along with any errors encountered. Repos that could
+    /// not be scanned are left out of the report.
+    #[must_use]
+    pub fn scan(
+        agent: &str,
+        config: &repos::Config,
+    ) -> (Self, Vec<anyhow::Error>) {
+        let mut errors = Vec::new();
+        let repos = config
+            .repo_iter()
+            .filter_map(|result| {
+                result
+                    .map_err(anyhow::Error::from)
+                    .and_then(|(name, repo)| {
+                        config.fetch_if_configured(&repo);
+                        let commits = repos::scan_repo_commits(
+                            &repo,
+                            &config.scan_options(&repo)?,
+                        )?;
+                        let remote = repos::remote_url(&repo)?;
+                        Ok((name, RepoReport { remote, commits }))
+                    })
+                    .map_err(|error| errors.push(error))
+                    .ok()
+            })
+            .collect();
+
+        let report = Self {
+            agent: agent.to_owned(),
+            scanned_at: chrono::Utc::now().timestamp(),
+            repos,
+        };
+        (report, errors)
+    }
+
+    /// Check that the report is well formed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message describing the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.agent.is_empty() {
+            return Err("Agent name is empty".to_owned());
+        }
+        for (repo, report) in &self.repos {
+            if repo.is_empty() {
+                return Err("Repository name is empty".to_owned());
+            }
+            if let Some(commit) = report.commits.iter().find(|commit| {
+                !matches!(commit.oid.len(), 40 | 64)
+                    || !commit.oid.bytes().all(|c| c.is_ascii_hexdigit())
+            }) {
+                return Err(format!(
+                    "Invalid commit ID in {repo:?}: {:?}",
+                    commit.oid
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Total number of commits in the report.
+    #[must_use]
+    pub fn commit_count(&self) -> usize {
+        self.repos.values().map(|repo| repo.commits.len()).sum()
+    }
+}
+
+/// An API key that an agent uses to authenticate to the server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AgentKey {
+    /// Name of the agent. Reports must use this name.
+    pub agent: String,
+
+    /// Secret key.
+    pub key: String,
+}
+
+impl FromStr for AgentKey {
+    type Err = ParseAgentKeyError;
+
+    /// Parse `NAME=KEY`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.split_once('=') {
+            Some((agent, key)) if !agent.is_empty() && !key.is_empty() => {
+                Ok(Self { agent: agent.to_owned(), key: key.to_owned() })
+            }
+            _ => Err(ParseAgentKeyError),
+        }
+    }
+}
+
+/// Error returned when an [`AgentKey`] can’t be parsed.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid agent key (expected NAME=KEY)")]
+pub struct ParseAgentKeyError;
+
+/// Reports waiting to be sent, stored as files in a directory.
+#[derive(Clone, Debug)]
+pub struct Queue {
+    /// Directory containing queued reports.
+    pub directory: PathBuf,
+}
+
+impl Queue {
+    /// Add a report to the end of the queue.
+    ///
+    /// The report is written to a temporary file first, so a half-written
+    /// report is never queued.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report could not be written.
+    pub fn push(&self, report: &Report) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.directory)?;
+        let contents = serde_json::to_vec(report)?;
+        let mut n = 0_u32;
+        let path = loop {
+            let path = self
+                .directory
+                .join(format!("{:020}-{n:04}.json", report.scanned_at));
+            if !path.try_exists()? {
+                break path;
+            }
+            n = n.checked_add(1).ok_or_else(|| {
+                io::Error::other("too many reports with the same time")
+            })?;
+        };
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(temporary, &path)?;
+        Ok(path)
+    }
+
+    /// Move a report the server will never accept out of the queue, into the
+    /// `rejected` subdirectory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report could not be moved.
+    pub fn reject(&self, path: &Path) -> io::Result<PathBuf> {
+        let rejected = self.directory.join("rejected");
+        fs::create_dir_all(&rejected)?;
+        let destination = rejected.join(path.file_name().unwrap_or_default());
+        fs::rename(path, &destination)?;
+        Ok(destination)
+    }
+
+    /// Get the paths of queued reports, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory could not be read.
+    pub fn pending(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .filter(|path| {
+                    path.as_ref().map_or(true, |path| {
+                        path.extension().is_some_and(|ext| ext == "json")
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+/// Reports received from agents, saved so they survive a server restart.
+///
+/// The latest report from each agent is stored as a JSON file named after the
+/// agent.
+#[derive(Clone, Debug)]
+pub struct ReportStore {
+    /// Directory containing the reports.
+    pub directory: PathBuf,
+}
+
+impl ReportStore {
+    /// Get the default directory, `$XDG_DATA_HOME/repoyear/reports` or
+    /// `~/.local/share/repoyear/reports`.
+    ///
+    /// Returns `None` if neither `$XDG_DATA_HOME` nor the home directory are
+    /// set.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| {
+                std::env::home_dir().map(|home| home.join(".local/share"))
+            })
+            .map(|data| data.join("repoyear/reports"))
+    }
+
+    /// Save a report, replacing the previous one from the same agent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report could not be written.
+    pub fn save(&self, report: &Report) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        // Agent names can contain anything, so hex encode them.
+        let name =
+            report.agent.bytes().fold(String::new(), |mut name, byte| {
+                let _ = write!(name, "{byte:02x}");
+                name
+            });
+        let path = self.directory.join(format!("{name}.json"));
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_vec(report)?)?;
+        fs::rename(temporary, path)
+    }
+
+    /// Load the saved reports by agent name.
+    ///
+    /// Files that can’t be parsed are skipped with a warning.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory could not be read.
+    pub fn load(&self) -> io::Result<BTreeMap<String, Report>> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(BTreeMap::new());
+            }
+            Err(error) => return Err(error),
+        };
+        let mut reports = BTreeMap::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let report = fs::read(&path).map_err(anyhow::Error::from).and_then(
+                |contents| Ok(serde_json::from_slice::<Report>(&contents)?),
+            );
+            match report {
+                Ok(report) => {
+                    reports.insert(report.agent.clone(), report);
+                }
+                Err(error) => {
+                    tracing::warn!("Skipping saved report {path:?}: {error}");
+                }
+            }
+        }
+        Ok(reports)
+    }
+}
+
+/// Client that sends reports to a central server.
+#[derive(Clone, Debug)]
+pub struct Agent {
+    /// Base URL of the central server, e.g. `https://repoyear.example.com`.
+    pub server_url: String,
+
+    /// API key identifying this agent to the server.
+    pub api_key: String,
+
+    /// Reports waiting to be sent.
+    pub queue: Queue,
+
+    /// Number of times to retry sending a report before giving up until the
+    /// next flush.
+    pub max_retries: u32,
+
+    /// HTTP client for making requests.
+    pub http_client: reqwest::Client,
+}
+
+impl Agent {
+    /// Scan every `interval` and send the results, forever.
+    pub async fn run(
+        &self,
+        name: &str,
+        config: &repos::Config,
+        interval: Duration,
+    ) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+
+            let (name, config) = (name.to_owned(), config.clone());
+            let (report, errors) =
+                match tokio::task::spawn_blocking(move || {
+                    Report::scan(&name, &config)
+                })
+                .await
+                {
+                    Ok(result) => result,
+                    Err(error) => {
+                        tracing::error!("Scan failed: {error}");
+                        continue;
+                    }
+                };
+            for error in errors {
+                tracing::warn!("{error}");
+            }
+
+            if let Err(error) = self.submit(&report).await {
+                tracing::error!("Could not queue report: {error}");
+            }
+        }
+    }
+
+    /// Queue a report and try to send everything in the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report could not be queued. Failures to send
+    /// are logged; the reports stay queued for next time.
+    pub async fn submit(&self, report: &Report) -> io::Result<()> {
+        self.queue.push(report)?;
+        self.flush().await
+    }
+
+    /// Send queued reports in order, stopping at the first failure.
+    ///
+    /// Reports the server rejects, or that can’t be parsed, will never be
+    /// accepted, so they are moved aside with [`Queue::reject()`] instead of
+    /// blocking the rest of the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the queue could not be read or updated. Failures to
+    /// send are logged; the reports stay queued for next time.
+    pub async fn flush(&self) -> io::Result<()> {
+        for path in self.queue.pending()? {
+            let body = fs::read(&path)?;
+            let result = match serde_json::from_slice::<Report>(&body) {
+                Ok(_) => self.send_with_retries(&body).await,
+                Err(error) => Err(SendError::Invalid(error)),
+            };
+            match result {
+                Ok(()) => {
+                    tracing::info!("Sent report {path:?}");
+                    fs::remove_file(&path)?;
+                }
+                Err(error) if error.is_permanent() => {
+                    let destination = self.queue.reject(&path)?;
+                    tracing::error!(
+                        "Report will never be accepted; moved to \
+                        {destination:?}: {error}"
+                    );
+                }
+                Err(error) => {
+                    let count = self.queue.pending()?.len();
+                    tracing::warn!(
+                        "Could not send report; {count} report(s) queued: \
+                        {error}"
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a serialized report, retrying with exponential backoff.
+    ///
+    /// Permanent failures aren’t retried.
+    async fn send_with_retries(&self, body: &[u8]) -> Result<(), SendError> {
+        let mut attempt = 0;
+        loop {
+            match self.send(body).await {
+                Ok(()) => return Ok(()),
+                Err(error)
+                    if attempt < self.max_retries && !error.is_permanent() =>
+                {
+                    let delay = backoff(attempt);
+                    tracing::debug!(
+                        "Failed to send report (retrying in {delay:?}): {error}"
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt = attempt.saturating_add(1);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Send a serialized report once.
+    async fn send(&self, body: &[u8]) -> Result<(), SendError> {
+        self.http_client
+            .post(format!(
+                "{}/api/ingest",
+                self.server_url.trim_end_matches('/')
+            ))
+            .bearer_auth(&self.api_key)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_vec())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Why a report couldn’t be sent.
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    /// The queued report couldn’t be parsed, e.g. because it was only
+    /// partly written.
+    #[error("Invalid report: {0}")]
+    Invalid(#[from] serde_json::Error),
+
+    /// The request failed or the server returned an error.
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+impl SendError {
+    /// Check if sending the report again can’t succeed.
+    ///
+    /// Client errors (4xx) mean the server won’t accept the report, except
+    /// for 408 Request Timeout and 429 Too Many Requests.
+    #[must_use]
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Invalid(_) => true,
+            Self::Http(error) => error.status().is_some_and(|status| {
+                status.is_client_error()
+                    && status != reqwest::StatusCode::REQUEST_TIMEOUT
+                    && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+            }),
+        }
+    }
+}
+
+/// How long to wait before retry number `attempt` (starting from 0).
+#[must_use]
+pub fn backoff(attempt: u32) -> Duration {
+    2_u32
+        .checked_pow(attempt)
+        .and_then(|factor| INITIAL_BACKOFF.checked_mul(factor))
+        .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use testdir::testdir;
+
+    fn report(scanned_at: i64) -> Report {
+        Report {
+            agent: "laptop".to_owned(),
+            scanned_at,
+            repos: BTreeMap::from([(
+                "repo".to_owned(),
+                RepoReport {
+                    remote: None,
+                    commits: vec![Commit { oid: "a".repeat(40), time: 10 }],
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn report_store() {
+        let store = ReportStore { directory: testdir!().join("reports") };
+        assert!(store.load().unwrap().is_empty());
+
+        store.save(&report(100)).unwrap();
+        store.save(&report(200)).unwrap();
+        let other = Report { agent: "a/b".to_owned(), ..report(50) };
+        store.save(&other).unwrap();
+        fs::write(store.directory.join("broken.json"), "{").unwrap();
+
+        let reports = store.load().unwrap();
+        assert!(reports.len() == 2);
+        assert!(reports["laptop"] == report(200));
+        assert!(reports["a/b"] == other);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert!(backoff(0) == Duration::from_secs(1));
+        assert!(backoff(3) == Duration::from_secs(8));
+        assert!(backoff(9) == MAX_BACKOFF);
+        assert!(backoff(100) == MAX_BACKOFF);
+    }
+
+    #[test]
+    fn validate_report() {
+        assert!(let Ok(()) = report(0).validate());
+
+        let mut report = report(0);
+        report.repos.insert(
+            "other".to_owned(),
+            RepoReport {
+                remote: None,
+                commits: vec![Commit { oid: "f".repeat(40), time: 10 }],
+            },
+        );
+        assert!(let Ok(()) = report.validate());
+
+        report.repos.insert(
+            "bad".to_owned(),
+            RepoReport {
+                remote: None,
+                commits: vec![Commit { oid: "xyz".to_owned(), time: 10 }],
+            },
+        );
+        assert!(let Err(_) = report.validate());
+    }
+
+    #[test]
+    fn parse_agent_key() {
+        assert!(
+            "laptop=s3cr=t".parse::<AgentKey>().unwrap()
+                == AgentKey {
+                    agent: "laptop".to_owned(),
+                    key: "s3cr=t".to_owned()
+                }
+        );
+        assert!(let Err(_) = "laptop".parse::<AgentKey>());
+        assert!(let Err(_) = "=key".parse::<AgentKey>());
+    }
+
+    #[test]
+    fn queue_order() {
+        let queue = Queue { directory: testdir!().join("queue") };
+        assert!(queue.pending().unwrap().is_empty());
+
+        let second = queue.push(&report(200)).unwrap();
+        let first = queue.push(&report(100)).unwrap();
+        let mut same_time: Vec<_> =
+            (0..11).map(|_| queue.push(&report(200)).unwrap()).collect();
+        same_time.insert(0, second);
+        same_time.insert(0, first);
+        assert!(queue.pending().unwrap() == same_time);
+
+        let queued: Report =
+            serde_json::from_slice(&fs::read(&same_time[12]).unwrap()).unwrap();
+        assert!(queued == report(200));
+
+        let rejected = queue.reject(&same_time[0]).unwrap();
+        assert!(rejected.is_file());
+        assert!(queue.pending().unwrap() == same_time[1..]);
+    }
+
+    #[tokio::test]
+    async fn flush_moves_rejected_reports_aside() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A server that rejects every report.
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                while !request.ends_with(b"}") {
+                    let n = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                }
+                stream
+                    .write_all(
+                        b"HTTP/1.1 400 Bad Request\r\n\
+                        content-length: 0\r\nconnection: close\r\n\r\n",
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let queue = Queue { directory: testdir!().join("queue") };
+        queue.push(&report(200)).unwrap();
+        fs::write(queue.directory.join("00000000000000000100-0000.json"), "{")
+            .unwrap();
+        let agent = Agent {
+            server_url,
+            api_key: "key".to_owned(),
+            queue: queue.clone(),
+            max_retries: 3,
+            http_client: reqwest::Client::new(),
+        };
+        agent.flush().await.unwrap();
+
+        assert!(queue.pending().unwrap().is_empty());
+        assert!(
+            fs::read_dir(queue.directory.join("rejected"))
+                .unwrap()
+                .count()
+                == 2
+        );
+    }
+}
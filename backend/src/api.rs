@@ -1,13 +1,25 @@
 //! Backend server to help with GitHub OAuth.
 //!
 //! This module is organized into:
+//! - [`backoff`] - Waiting before retrying GitHub after it fails
+//! - [`coalesce`] - Sharing scans between concurrent requests
 //! - [`definition`] - API contract (traits and types)
+//! - [`etag`] - Conditional responses with `ETag` and `If-None-Match`
 //! - [`implementation`] - Production implementation
 //! - [`mock`] - Mock implementation for testing
+//! - [`readiness`] - Waiting for the first scan after startup
+//! - [`reload`] - Reloading configuration while running
+//! - [`sse`] - Streaming scan progress as server-sent events
 
+pub mod backoff;
+pub mod coalesce;
 pub mod definition;
+pub mod etag;
 pub mod implementation;
 pub mod mock;
+pub mod readiness;
+pub mod reload;
+pub mod sse;
 
 // Re-export commonly used items from definition
 pub use definition::{
@@ -0,0 +1,77 @@
+//! Back off from a service that isn’t responding.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Tracks consecutive failures to reach a service, and how long to wait
+/// before trying it again.
+///
+/// Each consecutive failure doubles the wait, starting at [`Backoff::MIN`] and
+/// going up to [`Backoff::MAX`]. A success resets it.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    /// Number of consecutive failures, and when it’s OK to try again.
+    state: Mutex<(u32, Option<Instant>)>,
+}
+
+impl Backoff {
+    /// Wait after the first failure.
+    pub const MIN: Duration = Duration::from_secs(1);
+
+    /// Longest wait after any number of failures.
+    pub const MAX: Duration = Duration::from_secs(300);
+
+    /// Get how much longer to wait before trying again, or `None` if it’s OK
+    /// to try now.
+    pub fn wait(&self) -> Option<Duration> {
+        let (_, retry_at) =
+            *self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        retry_at
+            .map(|retry_at| retry_at.saturating_duration_since(Instant::now()))
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// Record a failure. Returns how long to wait before trying again.
+    pub fn failure(&self) -> Duration {
+        let mut state =
+            self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let failures = state.0;
+        state.0 = failures.saturating_add(1);
+        let wait = Self::MIN
+            .saturating_mul(2_u32.saturating_pow(failures))
+            .min(Self::MAX);
+        state.1 = Instant::now().checked_add(wait);
+        drop(state);
+        wait
+    }
+
+    /// Record a success.
+    pub fn success(&self) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = (0, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn doubles_until_success() {
+        let backoff = Backoff::default();
+        assert!(backoff.wait().is_none());
+
+        assert!(backoff.failure() == Duration::from_secs(1));
+        assert!(backoff.failure() == Duration::from_secs(2));
+        assert!(backoff.failure() == Duration::from_secs(4));
+        assert!(let Some(_) = backoff.wait());
+        for _ in 0..20 {
+            backoff.failure();
+        }
+        assert!(backoff.failure() == Backoff::MAX);
+
+        backoff.success();
+        assert!(backoff.wait().is_none());
+        assert!(backoff.failure() == Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,104 @@
+//! Share the result of expensive work between concurrent requests.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Mutex;
+
+/// Runs one job at a time, and shares its result with everyone who was
+/// waiting while it ran.
+///
+/// If ten requests call [`Coalescer::run()`] at once, the first runs the job
+/// and the other nine wait for it and get a copy of its result instead of
+/// running the job again. A call that arrives after a job finished always
+/// starts a new job, so results are never older than the call.
+#[derive(Debug)]
+pub struct Coalescer<T> {
+    /// Number of jobs that have finished.
+    finished: AtomicU64,
+
+    /// Result of the most recent job, along with its job number. Holding the
+    /// lock means running a job.
+    latest: Mutex<(u64, Option<T>)>,
+}
+
+impl<T> Coalescer<T> {
+    /// Create a coalescer that hasn’t run any jobs yet.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            finished: AtomicU64::new(0),
+            latest: Mutex::const_new((0, None)),
+        }
+    }
+}
+
+impl<T> Default for Coalescer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Coalescer<T> {
+    /// Run `job`, or wait for a job that is already running and use its
+    /// result.
+    pub async fn run<F, Fut>(&self, job: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let ticket = self.finished.load(Ordering::Acquire);
+        let mut latest = self.latest.lock().await;
+        if let (number, Some(result)) = &*latest
+            && *number > ticket
+        {
+            // A job finished while we were waiting.
+            return result.clone();
+        }
+
+        let result = job().await;
+        let number = latest.0.saturating_add(1);
+        *latest = (number, Some(result.clone()));
+        self.finished.store(number, Ordering::Release);
+        drop(latest);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn concurrent_calls_share_one_job() {
+        let coalescer = Arc::new(Coalescer::new());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let coalescer = coalescer.clone();
+                let runs = runs.clone();
+                tokio::spawn(async move {
+                    coalescer
+                        .run(|| async {
+                            tokio::time::sleep(Duration::from_millis(100))
+                                .await;
+                            runs.fetch_add(1, Ordering::SeqCst)
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Calls that arrived while the first job was running shared it.
+        assert!(runs.load(Ordering::SeqCst) == 1);
+
+        // Later calls start a new job.
+        assert!(coalescer.run(|| async { 100 }).await == 100);
+    }
+}
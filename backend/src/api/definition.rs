@@ -3,11 +3,35 @@
 //! This module contains the trait definitions and type signatures that define
 //! the API surface. These are independent of any particular implementation.
 
-use dropshot::{HttpError, HttpResponseOk, Query, RequestContext};
+use crate::agent::Report;
+use crate::api::etag::ETagged;
+use crate::api::sse::{EventStream, ScanEvent};
+use crate::feed::{self, Period};
+use crate::render::{self, Heatmap, Theme};
+use crate::repos::{
+    CommitStats, FileTypeStats, RepoOutcome, ScanStatus, glob_match,
+    write_metric,
+};
+use crate::stats::{self, Statistics, Velocity};
+use crate::users::UserError;
+use chrono::Datelike;
+use dropshot::{
+    Body, ClientErrorStatusCode, ErrorStatusCode, HttpError, HttpResponseOk,
+    Path, Query, RequestContext, TypedBody, WebsocketChannelResult,
+    WebsocketConnection,
+};
+use futures_util::{Sink, SinkExt, StreamExt};
+use http::{Response, header};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::future::Future;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::{self, Message, protocol::Role};
+use tracing::Instrument;
 
 /// Response from `/api/health`.
 #[derive(Debug, Serialize, JsonSchema)]
@@ -18,6 +42,16 @@ pub struct HealthResponse {
     pub status: String,
 }
 
+/// Response from `/api/ready`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReadyResponse {
+    /// Readiness status (always `"ready"`).
+    ///
+    /// If the server is still warming up, `/api/ready` returns a 503 error
+    /// instead.
+    pub status: String,
+}
+
 /// Response from `/api/version`.
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct VersionResponse {
@@ -25,15 +59,672 @@ pub struct VersionResponse {
     pub version: String,
 }
 
+/// Commit times by repository name, sorted so that the same contributions
+/// always serialize the same way. See [`ETagged`].
+pub type SortedContributions = BTreeMap<String, Vec<i64>>;
+
 /// Response from `/api/contributions`.
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct ContributionsResponse {
     /// Repository commit times (seconds since epoch) by repository name.
-    pub repos: LocalContributions,
+    pub repos: SortedContributions,
+
+    /// Times of commits on branches that haven’t been merged into the default
+    /// branch, by repository name.
+    ///
+    /// These aren’t included in `repos`. This is empty unless `unmerged` is
+    /// enabled in the server’s scan configuration.
+    pub unmerged: SortedContributions,
+
+    /// Times of `HEAD` reflog entries by repository name, e.g. for commits,
+    /// amends, and rebases.
+    ///
+    /// This catches activity whose commits were later rewritten or deleted,
+    /// but also includes activity that produced commits in `repos` or
+    /// `unmerged`. This is empty unless `reflog` is enabled in the server’s
+    /// scan configuration.
+    pub reflog: SortedContributions,
+
+    /// Names of repositories in `repos` that reached the server’s
+    /// `commit_cap`, sorted. Older commits in them may have been left out.
+    pub capped: Vec<String>,
+
+    /// Names of repositories in `repos` that are shallow clones, sorted.
+    /// Commits older than the history they have aren’t counted.
+    pub shallow: Vec<String>,
+
+    /// What happened to each repository in the most recent scan, by name.
+    ///
+    /// Repositories that couldn’t be scanned are left out of `repos`, but
+    /// they’re listed here with the error.
+    pub outcomes: BTreeMap<String, RepoOutcome>,
+
+    /// Groups of repositories in `repos` by name, for repositories in trees
+    /// with a `group` in the server’s scan configuration.
+    pub groups: BTreeMap<String, String>,
+
+    /// Cursor for the next page of repositories, if `limit` was passed and
+    /// there are more. Pass it as `cursor` to get the next page.
+    pub next: Option<String>,
+}
+
+/// Parameters for `/api/contributions`.
+#[derive(Debug, Default, JsonSchema)]
+pub struct ContributionsParams {
+    /// Only include repositories with names matching these patterns.
+    ///
+    /// May be repeated. `*` matches any number of characters and `?` matches
+    /// exactly one. Includes all repositories if not specified.
+    // Dropshot only allows scalar query parameters in the schema.
+    #[schemars(with = "Option<String>")]
+    pub repo: Vec<String>,
+
+    /// Only include repositories after this cursor, which should be `next`
+    /// from the previous page.
+    ///
+    /// Only `/api/contributions` is paginated; other endpoints ignore this.
+    pub cursor: Option<String>,
+
+    /// Maximum number of repositories to include, sorted by name. Includes all
+    /// repositories if not specified.
+    ///
+    /// Only `/api/contributions` is paginated; other endpoints ignore this.
+    pub limit: Option<NonZeroUsize>,
+}
+
+impl ContributionsParams {
+    /// Check if a repository should be included.
+    #[must_use]
+    pub fn matches(&self, name: &str) -> bool {
+        self.repo.is_empty()
+            || self.repo.iter().any(|pattern| glob_match(pattern, name))
+    }
+
+    /// Remove repositories that don’t match.
+    #[must_use]
+    pub fn filter<T>(
+        &self,
+        contributions: HashMap<String, T>,
+    ) -> HashMap<String, T> {
+        contributions
+            .into_iter()
+            .filter(|(name, _)| self.matches(name))
+            .collect()
+    }
+
+    /// Choose the page of matching repositories to include from `names`,
+    /// which may contain duplicates.
+    #[must_use]
+    pub fn page<'a, I: IntoIterator<Item = &'a String>>(
+        &self,
+        names: I,
+    ) -> Page {
+        let mut names: BTreeSet<String> = names
+            .into_iter()
+            .filter(|name| {
+                self.matches(name)
+                    && self.cursor.as_ref().is_none_or(|cursor| *name > cursor)
+            })
+            .cloned()
+            .collect();
+        let next = if let Some(limit) = self.limit
+            && let Some(rest) = names.iter().nth(limit.get()).cloned()
+        {
+            names.split_off(&rest);
+            names.last().cloned()
+        } else {
+            None
+        };
+        Page { names, next }
+    }
+}
+
+/// One page of repositories from [`ContributionsParams::page()`].
+#[derive(Debug, Default)]
+pub struct Page {
+    /// Names of repositories on the page.
+    pub names: BTreeSet<String>,
+
+    /// Cursor for the next page, if there is one.
+    pub next: Option<String>,
+}
+
+impl Page {
+    /// Remove repositories that aren’t on the page.
+    #[must_use]
+    pub fn filter<V, I, C>(&self, contributions: I) -> C
+    where
+        I: IntoIterator<Item = (String, V)>,
+        C: FromIterator<(String, V)>,
+    {
+        contributions
+            .into_iter()
+            .filter(|(name, _)| self.names.contains(name))
+            .collect()
+    }
+}
+
+// The query string is parsed with `serde_urlencoded`, which can’t deserialize
+// repeated parameters into a `Vec`.
+impl<'de> Deserialize<'de> for ContributionsParams {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        /// Collects every `repo` parameter.
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = ContributionsParams;
+
+            fn expecting(
+                &self,
+                f: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                f.write_str("query parameters")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut params = ContributionsParams::default();
+                while let Some((key, value)) =
+                    map.next_entry::<String, String>()?
+                {
+                    match key.as_str() {
+                        "repo" => params.repo.push(value),
+                        "cursor" => params.cursor = Some(value),
+                        "limit" => {
+                            params.limit = Some(
+                                value
+                                    .parse()
+                                    .map_err(serde::de::Error::custom)?,
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(params)
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+/// Message from the `/api/contributions/live` WebSocket.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct ContributionsUpdate {
+    /// Commit times (seconds since epoch) for repositories that changed, by
+    /// name, or `null` for repositories that no longer have any, e.g. because
+    /// they couldn’t be scanned.
+    ///
+    /// The first message includes every repository.
+    pub repos: BTreeMap<String, Option<Vec<i64>>>,
+}
+
+impl ContributionsUpdate {
+    /// Get an update with every repository in `contributions`.
+    #[must_use]
+    pub fn all(contributions: LocalContributions) -> Self {
+        Self {
+            repos: contributions
+                .into_iter()
+                .map(|(name, times)| (name, Some(times)))
+                .collect(),
+        }
+    }
+
+    /// Get an update with the repositories `names` from `contributions`.
+    #[must_use]
+    pub fn changed(
+        names: Vec<String>,
+        contributions: &LocalContributions,
+    ) -> Self {
+        Self {
+            repos: names
+                .into_iter()
+                .map(|name| {
+                    let times = contributions.get(&name).cloned();
+                    (name, times)
+                })
+                .collect(),
+        }
+    }
+
+    /// Send the update as a JSON text message.
+    async fn send<S>(&self, socket: &mut S) -> WebsocketChannelResult
+    where
+        S: Sink<Message, Error = tungstenite::Error> + Unpin,
+    {
+        socket
+            .send(Message::text(serde_json::to_string(self)?))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Response from `/api/repos`.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct ReposResponse {
+    /// Repositories found by the server’s scan configuration, sorted by name.
+    pub repos: Vec<RepoInfo>,
+
+    /// Errors finding repositories.
+    pub errors: Vec<String>,
+}
+
+/// A repository found by the server’s scan configuration.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct RepoInfo {
+    /// Name of the repository.
+    pub name: String,
+
+    /// Path to the working directory, or to the repository if it’s bare.
+    pub path: String,
+
+    /// Name of the default branch, or `HEAD` if there isn’t one.
+    pub default_branch: Option<String>,
+
+    /// Time of the commit at the tip of the default branch (seconds since
+    /// epoch).
+    pub last_commit: Option<i64>,
+
+    /// What happened to the repository in the most recent scan, if it was
+    /// scanned.
+    pub outcome: Option<RepoOutcome>,
+
+    /// Problem reading the default branch, if any.
+    pub error: Option<String>,
+}
+
+/// Response from `/api/identities`.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct IdentitiesResponse {
+    /// Author email addresses counted as the user.
+    pub emails: Vec<String>,
+
+    /// Author names counted as the user.
+    pub names: Vec<String>,
+
+    /// GitHub login, if known.
+    pub github: Option<String>,
+}
+
+/// Response from `/api/contributions/stats`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DiffStatsResponse {
+    /// Commits with the number of lines they changed, by repository name.
+    ///
+    /// This is empty unless `diff_stats` is enabled in the server’s scan
+    /// configuration.
+    pub repos: DiffStatsContributions,
+
+    /// Lines changed in each type of file, e.g. `rs` or `toml`, totaled for
+    /// each repository.
+    pub file_types: HashMap<String, FileTypeStats>,
+}
+
+/// Response from `/api/contributions/identities`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct IdentityContributionsResponse {
+    /// Repository commit times (seconds since epoch) by repository name, for
+    /// each label in `identities` in the server’s scan configuration.
+    ///
+    /// A commit may be counted for more than one identity. Identities
+    /// without any commits are left out.
+    pub identities: HashMap<String, LocalContributions>,
+}
+
+/// Commits with diff stats by repository name.
+pub type DiffStatsContributions = HashMap<String, Vec<CommitStats>>;
+
+pub use crate::repos::LocalContributions;
+
+/// Parameters for `/api/heatmap.png` and `/embed/heatmap`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HeatmapParams {
+    /// Year to display (defaults to the current year).
+    pub year: Option<i32>,
+
+    /// Width and height of each day in pixels.
+    pub size: Option<u32>,
+
+    /// Color scheme.
+    #[serde(default)]
+    pub theme: Theme,
+}
+
+/// Parameters for `/api/summary`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SummaryParams {
+    /// Maximum number of repositories in `top_repos` (default 5).
+    pub top: Option<usize>,
+}
+
+/// Parameters for `/api/velocity`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct VelocityParams {
+    /// Length of the rolling window, e.g. `28d` or `4w` (default `28d`).
+    pub window: Option<String>,
+}
+
+/// Parameters for `/api/feed.atom`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FeedParams {
+    /// How much time each entry covers.
+    #[serde(default)]
+    pub period: Period,
+
+    /// Maximum number of entries.
+    pub limit: Option<usize>,
+}
+
+/// Response from `/api/ingest`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct IngestResponse {
+    /// Number of repositories received.
+    pub repos: usize,
+
+    /// Number of commits received.
+    pub commits: usize,
+
+    /// Disagreements between this report and reports from other agents.
+    pub conflicts: Vec<String>,
+}
+
+/// Error from ingesting a report.
+#[derive(Debug, thiserror::Error)]
+pub enum IngestError {
+    /// The API key was missing or not recognized, or doesn’t belong to the
+    /// agent named in the report.
+    #[error("Invalid API key")]
+    Unauthorized,
+
+    /// The report was malformed.
+    #[error("Invalid report: {0}")]
+    Invalid(String),
+}
+
+impl From<IngestError> for HttpError {
+    fn from(error: IngestError) -> Self {
+        let status = match error {
+            IngestError::Unauthorized => ClientErrorStatusCode::UNAUTHORIZED,
+            IngestError::Invalid(_) => ClientErrorStatusCode::BAD_REQUEST,
+        };
+        Self::for_client_error(None, status, error.to_string())
+    }
+}
+
+/// Maximum number of paths in one request to `POST /api/scan`.
+pub const MAX_SCAN_PATHS: usize = 100;
+
+/// Request body for `POST /api/scan`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ScanRequest {
+    /// Absolute paths to repositories to scan.
+    ///
+    /// Each must be under a configured tree root, or be a configured
+    /// repository.
+    pub paths: Vec<String>,
+}
+
+/// Response from `POST /api/scan`.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct ScanResponse {
+    /// Results in the same order as the requested paths.
+    pub results: Vec<PathScanResult>,
+}
+
+/// Result of scanning one path for `POST /api/scan`.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct PathScanResult {
+    /// The path as requested.
+    pub path: String,
+
+    /// Name of the repository, if the path is allowed.
+    pub name: Option<String>,
+
+    /// Commit times (seconds since epoch), if the scan succeeded.
+    pub commits: Option<Vec<i64>>,
+
+    /// Why the path couldn’t be scanned, if it couldn’t.
+    pub error: Option<String>,
+}
+
+/// Error from `POST /api/scan`.
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ScanError {
+    /// The server has no repositories configured.
+    #[error("No repositories are configured")]
+    NotConfigured,
+
+    /// Too many paths were requested.
+    #[error("Too many paths (maximum {MAX_SCAN_PATHS})")]
+    TooManyPaths,
+
+    /// The scan couldn’t be run.
+    #[error("Scan failed: {0}")]
+    Failed(String),
+}
+
+impl From<ScanError> for HttpError {
+    fn from(error: ScanError) -> Self {
+        match error {
+            ScanError::NotConfigured => Self::for_client_error(
+                None,
+                ClientErrorStatusCode::NOT_FOUND,
+                error.to_string(),
+            ),
+            ScanError::TooManyPaths => Self::for_client_error(
+                None,
+                ClientErrorStatusCode::BAD_REQUEST,
+                error.to_string(),
+            ),
+            ScanError::Failed(_) => Self::for_internal_error(error.to_string()),
+        }
+    }
+}
+
+/// Response from `POST /api/admin/reload`.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct ReloadResponse {
+    /// Settings from the configuration files that changed and are now in
+    /// effect.
+    ///
+    /// Everything in the configuration files can be reloaded. Settings from
+    /// the command line or environment only change when the server restarts.
+    pub changed: Vec<String>,
+}
+
+/// Error from reloading the configuration.
+#[derive(Debug, thiserror::Error)]
+pub enum ReloadError {
+    /// There is no admin token, so reloading over HTTP is disabled.
+    #[error("Reloading is not enabled")]
+    Disabled,
+
+    /// The admin token was missing or wrong.
+    #[error("Invalid admin token")]
+    Unauthorized,
+
+    /// The configuration couldn’t be loaded. Nothing was changed.
+    #[error("Could not load configuration: {0}")]
+    Failed(String),
+}
+
+impl From<ReloadError> for HttpError {
+    fn from(error: ReloadError) -> Self {
+        match error {
+            ReloadError::Disabled => Self::for_client_error(
+                None,
+                ClientErrorStatusCode::NOT_FOUND,
+                error.to_string(),
+            ),
+            ReloadError::Unauthorized => Self::for_client_error(
+                None,
+                ClientErrorStatusCode::UNAUTHORIZED,
+                error.to_string(),
+            ),
+            ReloadError::Failed(_) => {
+                Self::for_internal_error(error.to_string())
+            }
+        }
+    }
+}
+
+/// Path parameters for `/api/u/{user}/...`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UserPath {
+    /// Name of the user.
+    pub user: String,
+}
+
+impl From<UserError> for HttpError {
+    fn from(error: UserError) -> Self {
+        let status = match error {
+            UserError::NotFound => ClientErrorStatusCode::NOT_FOUND,
+            UserError::Unauthorized => ClientErrorStatusCode::UNAUTHORIZED,
+        };
+        Self::for_client_error(None, status, error.to_string())
+    }
+}
+
+/// Error from an OAuth token request to GitHub.
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum OAuthError {
+    /// GitHub rejected the code or refresh token, e.g. because it expired.
+    #[error("{description}")]
+    Rejected {
+        /// Error code from GitHub, e.g. `bad_verification_code`.
+        code: String,
+
+        /// Description of the error from GitHub.
+        description: String,
+    },
+
+    /// GitHub rejected the server’s OAuth configuration, e.g. because the
+    /// client secret is wrong. Contains the error code from GitHub.
+    #[error("OAuth is not configured correctly on the server")]
+    Misconfigured(String),
+
+    /// GitHub could not be reached.
+    #[error("GitHub is unavailable")]
+    Unavailable {
+        /// How long the client should wait before trying again.
+        retry_after: Duration,
+    },
+
+    /// GitHub is rate limiting requests from the server.
+    #[error("GitHub rate limit exceeded")]
+    RateLimited {
+        /// How long the client should wait before trying again.
+        retry_after: Duration,
+    },
+
+    /// GitHub’s response could not be understood.
+    #[error("Invalid response from GitHub")]
+    BadResponse,
 }
 
-/// Local contributions (map repo name to commit times as seconds since epoch).
-pub type LocalContributions = HashMap<String, Vec<i64>>;
+impl OAuthError {
+    /// Classify an error code returned by GitHub.
+    ///
+    /// See [GitHub’s documentation][docs] for the possible codes.
+    ///
+    /// [docs]: https://docs.github.com/en/apps/oauth-apps/maintaining-oauth-apps/troubleshooting-oauth-app-access-token-request-errors
+    #[must_use]
+    pub fn from_github(code: String, description: Option<String>) -> Self {
+        match code.as_str() {
+            "incorrect_client_credentials"
+            | "redirect_uri_mismatch"
+            | "device_flow_disabled" => Self::Misconfigured(code),
+            _ => Self::Rejected {
+                description: description
+                    .unwrap_or_else(|| format!("GitHub returned {code}")),
+                code,
+            },
+        }
+    }
+}
+
+impl From<OAuthError> for HttpError {
+    fn from(error: OAuthError) -> Self {
+        let (status_code, error_code) = match &error {
+            OAuthError::Rejected { code, .. } => {
+                return Self::for_bad_request(
+                    Some(code.clone()),
+                    error.to_string(),
+                );
+            }
+            OAuthError::Misconfigured(code) => {
+                (ErrorStatusCode::INTERNAL_SERVER_ERROR, code.as_str())
+            }
+            OAuthError::Unavailable { .. } => {
+                (ErrorStatusCode::SERVICE_UNAVAILABLE, "github_unavailable")
+            }
+            OAuthError::RateLimited { .. } => {
+                (ErrorStatusCode::SERVICE_UNAVAILABLE, "github_rate_limited")
+            }
+            OAuthError::BadResponse => {
+                (ErrorStatusCode::BAD_GATEWAY, "github_bad_response")
+            }
+        };
+        let headers = if let OAuthError::Unavailable { retry_after }
+        | OAuthError::RateLimited { retry_after } = error
+        {
+            // Round up to whole seconds so clients never retry too early.
+            let seconds = retry_after
+                .as_secs()
+                .saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+            let mut headers = http::HeaderMap::new();
+            headers.insert(header::RETRY_AFTER, seconds.into());
+            Some(Box::new(headers))
+        } else {
+            None
+        };
+        Self {
+            status_code,
+            error_code: Some(error_code.to_owned()),
+            external_message: error.to_string(),
+            internal_message: format!("{error} ({error_code})"),
+            headers,
+        }
+    }
+}
+
+/// Problems reaching GitHub since the server started.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GitHubStatus {
+    /// Number of requests that could not reach GitHub.
+    pub unavailable: u64,
+
+    /// Number of responses saying that GitHub was rate limiting the server.
+    pub rate_limited: u64,
+}
+
+impl GitHubStatus {
+    /// Format as Prometheus metrics.
+    #[must_use]
+    pub fn prometheus(&self) -> String {
+        let mut output = String::new();
+        write_metric(
+            &mut output,
+            "github_unavailable_total",
+            "counter",
+            "Requests that could not reach GitHub.",
+            &[("", self.unavailable.to_string())],
+        );
+        write_metric(
+            &mut output,
+            "github_rate_limited_total",
+            "counter",
+            "Responses from GitHub saying it was rate limiting requests.",
+            &[("", self.rate_limited.to_string())],
+        );
+        output
+    }
+}
 
 /// Parameters for `/api/oauth/callback`.
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -53,6 +744,7 @@ pub struct RefreshParams {
 ///
 ///   * `/api/oauth/callback`
 ///   * `/api/oauth/refresh`
+///   * `/api/oauth/device/poll`
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct OAuthTokenResponse {
     /// The access token from GitHub.
@@ -71,6 +763,59 @@ pub struct OAuthTokenResponse {
     pub refresh_token_expires_in: Option<u64>,
 }
 
+/// Parameters for `/api/oauth/device/poll`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DevicePollParams {
+    /// The device code from `/api/oauth/device`.
+    pub device_code: String,
+}
+
+/// Response from `/api/oauth/device`.
+///
+/// Show the user `user_code` and ask them to enter it at `verification_uri`,
+/// then poll `/api/oauth/device/poll` until it returns a token.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DeviceCodeResponse {
+    /// Code identifying this login to pass to `/api/oauth/device/poll`.
+    pub device_code: String,
+
+    /// Code for the user to enter on GitHub.
+    pub user_code: String,
+
+    /// Where the user should enter `user_code`.
+    pub verification_uri: String,
+
+    /// Number of seconds until the codes expire.
+    pub expires_in: u64,
+
+    /// Minimum number of seconds between polls.
+    pub interval: u64,
+}
+
+/// Response from `/api/oauth/device/poll`.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DevicePollResponse {
+    /// The user hasn’t entered the code yet.
+    Pending {
+        /// Minimum number of seconds between polls. GitHub may increase this
+        /// if polls come too quickly.
+        interval: u64,
+
+        /// Number of seconds to wait before polling again.
+        retry_after: u64,
+
+        /// Number of seconds until the codes expire.
+        expires_in: u64,
+    },
+
+    /// The user entered the code, and GitHub issued a token.
+    Complete {
+        /// The token.
+        token: OAuthTokenResponse,
+    },
+}
+
 /// Base trait defining the business logic for the API.
 ///
 /// This trait contains the actual implementation methods that handle
@@ -83,36 +828,168 @@ pub trait ApiBase: Send + Sync {
     /// intentionally does not check anything else.
     fn check_health(&self) -> impl Future<Output = String> + Send;
 
+    /// Check if the server has finished warming up.
+    ///
+    /// Until it has, requests for contributions wait for the initial scan.
+    fn is_ready(&self) -> impl Future<Output = bool> + Send;
+
     /// Get the application version.
     fn get_version(&self) -> impl Future<Output = String> + Send;
 
     /// Get contributions for local repositories.
     fn get_contributions(
         &self,
-        log: &slog::Logger,
     ) -> impl Future<Output = LocalContributions> + Send;
 
+    /// Get contributions on unmerged branches in local repositories.
+    ///
+    /// These are not included in
+    /// [`get_contributions()`](Self::get_contributions).
+    fn get_unmerged_contributions(
+        &self,
+    ) -> impl Future<Output = LocalContributions> + Send;
+
+    /// Get activity from the `HEAD` reflogs of local repositories.
+    fn get_reflog_contributions(
+        &self,
+    ) -> impl Future<Output = LocalContributions> + Send;
+
+    /// Get the maximum number of commits counted in each local repository,
+    /// if there is one.
+    fn get_commit_cap(&self) -> impl Future<Output = Option<usize>> + Send;
+
+    /// Get commits in local repositories with the number of lines they
+    /// changed.
+    ///
+    /// This is empty unless diff stats are enabled.
+    fn get_diff_stats(
+        &self,
+    ) -> impl Future<Output = DiffStatsContributions> + Send;
+
+    /// Get contributions for local repositories for each identity in the
+    /// scan configuration’s `identities`, by label.
+    fn get_identity_contributions(
+        &self,
+    ) -> impl Future<Output = HashMap<String, LocalContributions>> + Send;
+
+    /// Scan specific repositories by path.
+    ///
+    /// Paths outside of the configured repositories get an error in their
+    /// result rather than being scanned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no repositories are configured, if there are too
+    /// many paths, or if the scan couldn’t be run at all.
+    fn scan_paths(
+        &self,
+        paths: Vec<String>,
+    ) -> impl Future<Output = Result<ScanResponse, ScanError>> + Send;
+
+    /// Scan local repositories, sending an event as each one finishes and
+    /// another when the scan is done.
+    ///
+    /// The scan keeps going if the receiver is dropped.
+    fn stream_contributions(
+        &self,
+    ) -> impl Future<Output = mpsc::UnboundedReceiver<ScanEvent>> + Send;
+
+    /// Subscribe to the names of local repositories whose contributions
+    /// changed.
+    ///
+    /// Nothing is sent unless the repositories are being watched.
+    fn subscribe_contributions(
+        &self,
+    ) -> impl Future<Output = broadcast::Receiver<Vec<String>>> + Send;
+
+    /// List the local repositories in the scan configuration without
+    /// scanning their history.
+    fn list_repos(&self) -> impl Future<Output = ReposResponse> + Send;
+
+    /// Get counts and timing from recent scans.
+    fn get_scan_status(&self) -> impl Future<Output = ScanStatus> + Send;
+
+    /// Get counts of problems reaching GitHub.
+    fn get_github_status(&self) -> impl Future<Output = GitHubStatus> + Send;
+
+    /// Get the identity commits are counted for.
+    fn get_identities(&self)
+    -> impl Future<Output = IdentitiesResponse> + Send;
+
+    /// Get contributions for one user’s repositories.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the user doesn’t exist or `token` is wrong.
+    fn get_user_contributions(
+        &self,
+        user: &str,
+        token: Option<&str>,
+    ) -> impl Future<Output = Result<LocalContributions, UserError>> + Send;
+
+    /// Accept a scan report from a remote agent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `api_key` doesn’t belong to the agent that sent the
+    /// report, or if the report is malformed.
+    fn ingest(
+        &self,
+        api_key: &str,
+        report: Report,
+    ) -> impl Future<Output = Result<IngestResponse, IngestError>> + Send;
+
+    /// Read the configuration files again and apply the changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reloading isn’t enabled, if `token` isn’t the
+    /// admin token, or if the configuration couldn’t be loaded. If loading
+    /// fails, the old configuration stays in effect.
+    fn reload_config(
+        &self,
+        token: Option<&str>,
+    ) -> impl Future<Output = Result<ReloadResponse, ReloadError>> + Send;
+
     /// Exchange a GitHub OAuth code for an access token.
     ///
     /// # Errors
     ///
-    /// Returns an error message if the OAuth exchange fails.
+    /// Returns an error if the OAuth exchange fails.
     fn exchange_oauth_token(
         &self,
         code: &str,
-        log: &slog::Logger,
-    ) -> impl Future<Output = Result<OAuthTokenResponse, String>> + Send;
+    ) -> impl Future<Output = Result<OAuthTokenResponse, OAuthError>> + Send;
+
+    /// Start logging in with GitHub’s device flow.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if GitHub doesn’t issue a device code.
+    fn start_device_flow(
+        &self,
+    ) -> impl Future<Output = Result<DeviceCodeResponse, OAuthError>> + Send;
+
+    /// Check whether the user has finished logging in with the device flow.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the login failed, e.g. because the code expired or
+    /// the user denied access.
+    fn poll_device_flow(
+        &self,
+        device_code: &str,
+    ) -> impl Future<Output = Result<DevicePollResponse, OAuthError>> + Send;
 
     /// Refresh a GitHub OAuth access token using a refresh token.
     ///
     /// # Errors
     ///
-    /// Returns an error message if the token refresh fails.
+    /// Returns an error if the token refresh fails.
     fn refresh_oauth_token(
         &self,
         refresh_token: &str,
-        log: &slog::Logger,
-    ) -> impl Future<Output = Result<OAuthTokenResponse, String>> + Send;
+    ) -> impl Future<Output = Result<OAuthTokenResponse, OAuthError>> + Send;
 }
 
 /// API trait with endpoint definitions.
@@ -137,6 +1014,26 @@ pub trait RepoYearApi {
         Ok(HttpResponseOk(HealthResponse { status }))
     }
 
+    /// Handle `/api/ready`
+    ///
+    /// Returns a 503 error until the server has finished its initial scan.
+    #[endpoint {
+        method = GET,
+        path = "/api/ready",
+    }]
+    async fn ready(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<HttpResponseOk<ReadyResponse>, HttpError> {
+        if rqctx.context().is_ready().await {
+            Ok(HttpResponseOk(ReadyResponse { status: "ready".to_owned() }))
+        } else {
+            Err(HttpError::for_unavail(
+                Some("warming_up".to_owned()),
+                "Server is still warming up".to_owned(),
+            ))
+        }
+    }
+
     /// Handle `/api/version`
     #[endpoint {
         method = GET,
@@ -150,15 +1047,555 @@ pub trait RepoYearApi {
     }
 
     /// Handle `/api/contributions`
+    ///
+    /// The response has an `ETag` header. Send it back in `If-None-Match` to
+    /// get an empty 304 response if nothing has changed.
     #[endpoint {
         method = GET,
         path = "/api/contributions",
     }]
     async fn contributions(
         rqctx: RequestContext<Self::Context>,
+        query: Query<ContributionsParams>,
+    ) -> Result<ETagged<ContributionsResponse>, HttpError> {
+        let params = query.into_inner();
+        let context = rqctx.context();
+        let repos = context
+            .get_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        let unmerged = context
+            .get_unmerged_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        let reflog = context
+            .get_reflog_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        let last = context.get_scan_status().await.last.unwrap_or_default();
+        let page = params.page(
+            repos
+                .keys()
+                .chain(unmerged.keys())
+                .chain(reflog.keys())
+                .chain(last.outcomes.keys()),
+        );
+        let repos: SortedContributions = page.filter(repos);
+        let mut capped: Vec<String> = match context.get_commit_cap().await {
+            Some(cap) => repos
+                .iter()
+                .filter(|(_, times)| times.len() >= cap)
+                .map(|(name, _)| name.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        capped.sort();
+        let shallow = last
+            .shallow_repos
+            .into_iter()
+            .filter(|name| repos.contains_key(name))
+            .collect();
+        let outcomes = page.filter(last.outcomes);
+        let groups = last
+            .groups
+            .into_iter()
+            .filter(|(name, _)| repos.contains_key(name))
+            .collect();
+        let response = ContributionsResponse {
+            repos,
+            unmerged: page.filter(unmerged),
+            reflog: page.filter(reflog),
+            capped,
+            shallow,
+            outcomes,
+            groups,
+            next: page.next,
+        };
+        ETagged::new(&rqctx, &response)
+    }
+
+    /// Handle `/api/contributions/stream`
+    ///
+    /// Scans local repositories and sends progress as server-sent events, so
+    /// that the calendar can be filled in as repositories finish. A `repo`
+    /// event is sent for each repository, and a `done` event at the end.
+    ///
+    /// Contributions from agent reports are not included.
+    #[endpoint {
+        method = GET,
+        path = "/api/contributions/stream",
+    }]
+    async fn contributions_stream(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<Response<Body>, HttpError> {
+        let events = rqctx
+            .context()
+            .stream_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::wrap(EventStream(events)))
+            .map_err(|error| HttpError::for_internal_error(error.to_string()))
+    }
+
+    /// Handle `/api/contributions/live`
+    ///
+    /// Sends contributions for every local repository as a JSON message when
+    /// the WebSocket connects, then sends the repositories that changed each
+    /// time the server notices new commits. Each message is a
+    /// `ContributionsUpdate`.
+    ///
+    /// Changes are only noticed if the server is watching the repositories.
+    #[channel {
+        protocol = WEBSOCKETS,
+        path = "/api/contributions/live",
+    }]
+    async fn contributions_live(
+        rqctx: RequestContext<Self::Context>,
+        upgraded: WebsocketConnection,
+    ) -> WebsocketChannelResult {
+        let context = rqctx.context();
+        let mut updates = context.subscribe_contributions().await;
+        let mut socket = WebSocketStream::from_raw_socket(
+            upgraded.into_inner(),
+            Role::Server,
+            None,
+        )
+        .await;
+        ContributionsUpdate::all(context.get_contributions().await)
+            .send(&mut socket)
+            .await?;
+        loop {
+            let update = tokio::select! {
+                update = updates.recv() => update,
+                message = socket.next() => match message {
+                    // Pings are answered automatically.
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(error)) => return Err(error.into()),
+                },
+            };
+            let contributions = context.get_contributions().await;
+            match update {
+                Ok(names) => {
+                    ContributionsUpdate::changed(names, &contributions)
+                        .send(&mut socket)
+                        .await?;
+                }
+                // Too many changes arrived at once to keep track of.
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    ContributionsUpdate::all(contributions)
+                        .send(&mut socket)
+                        .await?;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+
+    /// Handle `/api/contributions/stats`
+    ///
+    /// Returns lines inserted and deleted by each local commit, so that
+    /// activity can be weighted by size rather than only counted.
+    #[endpoint {
+        method = GET,
+        path = "/api/contributions/stats",
+    }]
+    async fn contribution_stats(
+        rqctx: RequestContext<Self::Context>,
+        query: Query<ContributionsParams>,
+    ) -> Result<HttpResponseOk<DiffStatsResponse>, HttpError> {
+        let params = query.into_inner();
+        let repos = rqctx
+            .context()
+            .get_diff_stats()
+            .instrument(request_span(&rqctx))
+            .await;
+        let repos = params.filter(repos);
+        let file_types = repos
+            .iter()
+            .map(|(name, commits)| {
+                let mut totals = FileTypeStats::new();
+                for commit in commits {
+                    for (file_type, stats) in &commit.file_types {
+                        totals
+                            .entry(file_type.clone())
+                            .or_default()
+                            .add(*stats);
+                    }
+                }
+                (name.clone(), totals)
+            })
+            .collect();
+        Ok(HttpResponseOk(DiffStatsResponse { repos, file_types }))
+    }
+
+    /// Handle `/api/contributions/identities`
+    ///
+    /// Returns local contributions grouped by identity, e.g. to show work and
+    /// personal contributions in separate calendars.
+    #[endpoint {
+        method = GET,
+        path = "/api/contributions/identities",
+    }]
+    async fn identity_contributions(
+        rqctx: RequestContext<Self::Context>,
+        query: Query<ContributionsParams>,
+    ) -> Result<HttpResponseOk<IdentityContributionsResponse>, HttpError> {
+        let params = query.into_inner();
+        let identities = rqctx
+            .context()
+            .get_identity_contributions()
+            .instrument(request_span(&rqctx))
+            .await
+            .into_iter()
+            .map(|(label, repos)| (label, params.filter(repos)))
+            .filter(|(_, repos)| !repos.is_empty())
+            .collect();
+        Ok(HttpResponseOk(IdentityContributionsResponse { identities }))
+    }
+
+    /// Handle `/api/summary`
+    ///
+    /// Returns totals, streaks, and distributions of local contributions, so
+    /// that widgets don’t need every commit time.
+    #[endpoint {
+        method = GET,
+        path = "/api/summary",
+    }]
+    async fn summary(
+        rqctx: RequestContext<Self::Context>,
+        query: Query<SummaryParams>,
+    ) -> Result<HttpResponseOk<Statistics>, HttpError> {
+        let params = query.into_inner();
+        let contributions = rqctx
+            .context()
+            .get_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        Ok(HttpResponseOk(Statistics::new(
+            &contributions,
+            &chrono::Local::now(),
+            params.top.unwrap_or(stats::DEFAULT_TOP_REPOS),
+        )))
+    }
+
+    /// Handle `/api/velocity`
+    ///
+    /// Returns rolling averages of commits per day for the past year, and
+    /// whether the latest window is up or down from the one before it.
+    #[endpoint {
+        method = GET,
+        path = "/api/velocity",
+    }]
+    async fn velocity(
+        rqctx: RequestContext<Self::Context>,
+        query: Query<VelocityParams>,
+    ) -> Result<HttpResponseOk<Velocity>, HttpError> {
+        let window = match query.into_inner().window {
+            Some(window) => stats::parse_window(&window).ok_or_else(|| {
+                HttpError::for_bad_request(
+                    None,
+                    format!(
+                        "Invalid window {window:?} (expected e.g. 28d or 4w, \
+                        up to {} days)",
+                        stats::MAX_VELOCITY_WINDOW,
+                    ),
+                )
+            })?,
+            None => stats::DEFAULT_VELOCITY_WINDOW,
+        };
+        let contributions = rqctx
+            .context()
+            .get_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        Ok(HttpResponseOk(Velocity::new(
+            &contributions,
+            &chrono::Local::now(),
+            window,
+        )))
+    }
+
+    /// Handle `POST /api/scan`
+    ///
+    /// Scans the repositories at the paths in the request, which must be
+    /// covered by the scan configuration.
+    #[endpoint {
+        method = POST,
+        path = "/api/scan",
+    }]
+    async fn scan(
+        rqctx: RequestContext<Self::Context>,
+        body: TypedBody<ScanRequest>,
+    ) -> Result<HttpResponseOk<ScanResponse>, HttpError> {
+        Ok(HttpResponseOk(
+            rqctx
+                .context()
+                .scan_paths(body.into_inner().paths)
+                .instrument(request_span(&rqctx))
+                .await?,
+        ))
+    }
+
+    /// Handle `/api/repos`
+    ///
+    /// Lists the repositories found by the scan configuration along with
+    /// their default branches and latest commits. This doesn’t scan their
+    /// history.
+    #[endpoint {
+        method = GET,
+        path = "/api/repos",
+    }]
+    async fn repos(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<HttpResponseOk<ReposResponse>, HttpError> {
+        Ok(HttpResponseOk(
+            rqctx
+                .context()
+                .list_repos()
+                .instrument(request_span(&rqctx))
+                .await,
+        ))
+    }
+
+    /// Handle `/api/scan/status`
+    #[endpoint {
+        method = GET,
+        path = "/api/scan/status",
+    }]
+    async fn scan_status(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<HttpResponseOk<ScanStatus>, HttpError> {
+        Ok(HttpResponseOk(rqctx.context().get_scan_status().await))
+    }
+
+    /// Handle `/metrics`
+    ///
+    /// Returns scan and GitHub metrics in the Prometheus text format.
+    #[endpoint {
+        method = GET,
+        path = "/metrics",
+    }]
+    async fn metrics(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<Response<Body>, HttpError> {
+        let context = rqctx.context();
+        let mut output = context.get_scan_status().await.prometheus();
+        output.push_str(&context.get_github_status().await.prometheus());
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(output.into())
+            .map_err(|error| HttpError::for_internal_error(error.to_string()))
+    }
+
+    /// Handle `/api/identities`
+    ///
+    /// If all emails and names are empty, every commit is counted.
+    #[endpoint {
+        method = GET,
+        path = "/api/identities",
+    }]
+    async fn identities(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<HttpResponseOk<IdentitiesResponse>, HttpError> {
+        Ok(HttpResponseOk(rqctx.context().get_identities().await))
+    }
+
+    /// Handle `/api/u/{user}/contributions`
+    ///
+    /// Requires `Authorization: Bearer <user token>`.
+    #[endpoint {
+        method = GET,
+        path = "/api/u/{user}/contributions",
+    }]
+    async fn user_contributions(
+        rqctx: RequestContext<Self::Context>,
+        path: Path<UserPath>,
     ) -> Result<HttpResponseOk<ContributionsResponse>, HttpError> {
-        let repos = rqctx.context().get_contributions(&rqctx.log).await;
-        Ok(HttpResponseOk(ContributionsResponse { repos }))
+        let repos = rqctx
+            .context()
+            .get_user_contributions(
+                &path.into_inner().user,
+                bearer_token(&rqctx),
+            )
+            .instrument(request_span(&rqctx))
+            .await?;
+        Ok(HttpResponseOk(ContributionsResponse {
+            repos: repos.into_iter().collect(),
+            unmerged: SortedContributions::new(),
+            reflog: SortedContributions::new(),
+            capped: Vec::new(),
+            shallow: Vec::new(),
+            outcomes: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            next: None,
+        }))
+    }
+
+    /// Handle `/api/heatmap.png`
+    #[endpoint {
+        method = GET,
+        path = "/api/heatmap.png",
+    }]
+    async fn heatmap_png(
+        rqctx: RequestContext<Self::Context>,
+        query: Query<HeatmapParams>,
+    ) -> Result<Response<Body>, HttpError> {
+        let params = query.into_inner();
+        let contributions = rqctx
+            .context()
+            .get_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        let heatmap = local_heatmap(&contributions, params.year)?;
+        let png = render::png::render(
+            &heatmap,
+            params.size.unwrap_or(render::DEFAULT_CELL_SIZE),
+            params.theme,
+        )
+        .map_err(|error| HttpError::for_internal_error(error.to_string()))?;
+
+        Response::builder()
+            .header(header::CONTENT_TYPE, "image/png")
+            .body(png.into())
+            .map_err(|error| HttpError::for_internal_error(error.to_string()))
+    }
+
+    /// Handle `/embed/heatmap`
+    ///
+    /// Returns a self-contained HTML page suitable for an `<iframe>` on any
+    /// site.
+    #[endpoint {
+        method = GET,
+        path = "/embed/heatmap",
+    }]
+    async fn embed_heatmap(
+        rqctx: RequestContext<Self::Context>,
+        query: Query<HeatmapParams>,
+    ) -> Result<Response<Body>, HttpError> {
+        let params = query.into_inner();
+        let contributions = rqctx
+            .context()
+            .get_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        let heatmap = local_heatmap(&contributions, params.year)?;
+        let html = render::html::render(
+            &heatmap,
+            params.size.unwrap_or(render::DEFAULT_CELL_SIZE),
+            params.theme,
+        );
+
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(header::CONTENT_SECURITY_POLICY, "frame-ancestors *")
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .body(html.into())
+            .map_err(|error| HttpError::for_internal_error(error.to_string()))
+    }
+
+    /// Handle `/api/feed.atom`
+    #[endpoint {
+        method = GET,
+        path = "/api/feed.atom",
+    }]
+    async fn feed_atom(
+        rqctx: RequestContext<Self::Context>,
+        query: Query<FeedParams>,
+    ) -> Result<Response<Body>, HttpError> {
+        let params = query.into_inner();
+        let contributions = rqctx
+            .context()
+            .get_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        let entries = feed::entries(
+            &contributions,
+            &chrono::Local,
+            params.period,
+            params.limit.unwrap_or(feed::DEFAULT_LIMIT),
+        );
+
+        Response::builder()
+            .header(header::CONTENT_TYPE, "application/atom+xml")
+            .body(feed::atom::render(&entries, params.period).into())
+            .map_err(|error| HttpError::for_internal_error(error.to_string()))
+    }
+
+    /// Handle `/api/calendar.ics`
+    ///
+    /// Returns a calendar with an all-day event for every day with
+    /// contributions.
+    #[endpoint {
+        method = GET,
+        path = "/api/calendar.ics",
+    }]
+    async fn calendar_ics(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<Response<Body>, HttpError> {
+        let contributions = rqctx
+            .context()
+            .get_contributions()
+            .instrument(request_span(&rqctx))
+            .await;
+        let entries = feed::entries(
+            &contributions,
+            &chrono::Local,
+            Period::Day,
+            usize::MAX,
+        );
+
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/calendar; charset=utf-8")
+            .body(feed::ics::render(&entries).into())
+            .map_err(|error| HttpError::for_internal_error(error.to_string()))
+    }
+
+    /// Handle `/api/ingest`
+    ///
+    /// Accepts scan results from an agent, authenticated with
+    /// `Authorization: Bearer <API key>`.
+    #[endpoint {
+        method = POST,
+        path = "/api/ingest",
+        request_body_max_bytes = 64 * 1024 * 1024,
+    }]
+    async fn ingest(
+        rqctx: RequestContext<Self::Context>,
+        body: TypedBody<Report>,
+    ) -> Result<HttpResponseOk<IngestResponse>, HttpError> {
+        let api_key = bearer_token(&rqctx).ok_or(IngestError::Unauthorized)?;
+        Ok(HttpResponseOk(
+            rqctx
+                .context()
+                .ingest(api_key, body.into_inner())
+                .instrument(request_span(&rqctx))
+                .await?,
+        ))
+    }
+
+    /// Handle `/api/admin/reload`
+    ///
+    /// Reads the configuration files again, authenticated with
+    /// `Authorization: Bearer <admin token>`. Sending the server `SIGHUP` does
+    /// the same thing.
+    #[endpoint {
+        method = POST,
+        path = "/api/admin/reload",
+    }]
+    async fn admin_reload(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<HttpResponseOk<ReloadResponse>, HttpError> {
+        Ok(HttpResponseOk(
+            rqctx
+                .context()
+                .reload_config(bearer_token(&rqctx))
+                .instrument(request_span(&rqctx))
+                .await?,
+        ))
     }
 
     /// Handle `/api/oauth/callback`
@@ -173,9 +1610,9 @@ pub trait RepoYearApi {
         Ok(HttpResponseOk(
             rqctx
                 .context()
-                .exchange_oauth_token(&query.into_inner().code, &rqctx.log)
-                .await
-                .map_err(|error| HttpError::for_bad_request(None, error))?,
+                .exchange_oauth_token(&query.into_inner().code)
+                .instrument(request_span(&rqctx))
+                .await?,
         ))
     }
 
@@ -191,12 +1628,171 @@ pub trait RepoYearApi {
         Ok(HttpResponseOk(
             rqctx
                 .context()
-                .refresh_oauth_token(
-                    &query.into_inner().refresh_token,
-                    &rqctx.log,
-                )
-                .await
-                .map_err(|error| HttpError::for_bad_request(None, error))?,
+                .refresh_oauth_token(&query.into_inner().refresh_token)
+                .instrument(request_span(&rqctx))
+                .await?,
         ))
     }
+
+    /// Handle `/api/oauth/device`
+    ///
+    /// Starts logging in with GitHub’s device flow.
+    #[endpoint {
+        method = POST,
+        path = "/api/oauth/device",
+    }]
+    async fn oauth_device(
+        rqctx: RequestContext<Self::Context>,
+    ) -> Result<HttpResponseOk<DeviceCodeResponse>, HttpError> {
+        Ok(HttpResponseOk(
+            rqctx
+                .context()
+                .start_device_flow()
+                .instrument(request_span(&rqctx))
+                .await?,
+        ))
+    }
+
+    /// Handle `/api/oauth/device/poll`
+    ///
+    /// Polls faster than the interval are answered without contacting GitHub.
+    #[endpoint {
+        method = GET,
+        path = "/api/oauth/device/poll",
+    }]
+    async fn oauth_device_poll(
+        rqctx: RequestContext<Self::Context>,
+        query: Query<DevicePollParams>,
+    ) -> Result<HttpResponseOk<DevicePollResponse>, HttpError> {
+        Ok(HttpResponseOk(
+            rqctx
+                .context()
+                .poll_device_flow(&query.into_inner().device_code)
+                .instrument(request_span(&rqctx))
+                .await?,
+        ))
+    }
+}
+
+/// Get the token from an `Authorization: Bearer <token>` header.
+fn bearer_token<C: dropshot::ServerContext>(
+    rqctx: &RequestContext<C>,
+) -> Option<&str> {
+    rqctx
+        .request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Create a span for logs from handling a request.
+///
+/// Dropshot logs the request ID with each request, so this makes it possible
+/// to match up other logs with the request that caused them.
+fn request_span<C: dropshot::ServerContext>(
+    rqctx: &RequestContext<C>,
+) -> tracing::Span {
+    tracing::info_span!("request", id = %rqctx.request_id)
+}
+
+/// Build a [`Heatmap`] in the server’s local time zone.
+///
+/// `year` defaults to the current year.
+fn local_heatmap(
+    contributions: &LocalContributions,
+    year: Option<i32>,
+) -> Result<Heatmap, HttpError> {
+    let year = year.unwrap_or_else(|| chrono::Local::now().year());
+    Heatmap::new(contributions, year, &chrono::Local).ok_or_else(|| {
+        HttpError::for_bad_request(None, format!("Invalid year: {year}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn contributions_params_repeated() {
+        let params: ContributionsParams =
+            serde_urlencoded::from_str("repo=a&other=x&repo=b*").unwrap();
+        assert!(params.repo == ["a", "b*"]);
+        assert!(params.matches("a"));
+        assert!(params.matches("bc"));
+        assert!(!params.matches("c"));
+
+        let params: ContributionsParams =
+            serde_urlencoded::from_str("").unwrap();
+        assert!(params.matches("c"));
+    }
+
+    #[test]
+    fn contributions_response_stable() {
+        let contributions: LocalContributions =
+            (0..100).map(|n| (format!("repo{n}"), vec![n])).collect();
+        let params = ContributionsParams::default();
+        // Each `HashMap` has its own random iteration order.
+        let serialize = || {
+            let page = params.page(contributions.keys());
+            let repos: HashMap<_, _> =
+                contributions.clone().into_iter().collect();
+            serde_json::to_vec(&ContributionsResponse {
+                repos: page.filter(repos.clone()),
+                unmerged: page.filter(repos.clone()),
+                reflog: page.filter(repos),
+                capped: Vec::new(),
+                shallow: Vec::new(),
+                outcomes: BTreeMap::new(),
+                groups: BTreeMap::new(),
+                next: page.next,
+            })
+            .unwrap()
+        };
+        assert!(serialize() == serialize());
+    }
+
+    #[test]
+    fn contributions_update() {
+        let contributions =
+            LocalContributions::from([("a".to_owned(), vec![1, 2])]);
+        let update = ContributionsUpdate::changed(
+            vec!["a".to_owned(), "gone".to_owned()],
+            &contributions,
+        );
+        assert!(
+            serde_json::to_string(&update).unwrap()
+                == r#"{"repos":{"a":[1,2],"gone":null}}"#
+        );
+        assert!(
+            ContributionsUpdate::all(contributions).repos
+                == BTreeMap::from([("a".to_owned(), Some(vec![1, 2]))])
+        );
+    }
+
+    #[test]
+    fn contributions_params_page() {
+        let names = ["c", "a", "d", "b", "a", "other"].map(String::from);
+        let params: ContributionsParams =
+            serde_urlencoded::from_str("repo=?&limit=2").unwrap();
+        let page = params.page(&names);
+        assert!(page.names == BTreeSet::from(["a", "b"].map(String::from)));
+        assert!(page.next.as_deref() == Some("b"));
+
+        let params: ContributionsParams =
+            serde_urlencoded::from_str("repo=?&limit=2&cursor=b").unwrap();
+        let page = params.page(&names);
+        assert!(page.names == BTreeSet::from(["c", "d"].map(String::from)));
+        assert!(page.next == None);
+
+        let filtered: BTreeMap<String, i64> =
+            page.filter([("a".to_owned(), 1), ("c".to_owned(), 2)]);
+        assert!(filtered == BTreeMap::from([("c".to_owned(), 2)]));
+
+        assert!(
+            serde_urlencoded::from_str::<ContributionsParams>("limit=0")
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,145 @@
+//! Conditional responses with `ETag` and `If-None-Match`.
+
+use dropshot::{
+    ApiEndpointResponse, Body, HttpError, HttpResponse, HttpResponseHeaders,
+    HttpResponseOk, RequestContext, ServerContext,
+};
+use http::{Response, StatusCode, header};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::marker::PhantomData;
+
+/// A JSON response with an `ETag` header, or an empty 304 Not Modified
+/// response if the client already has the same content.
+///
+/// The entity tag is a hash of the serialized body, so it changes exactly when
+/// the content changes. This is documented in the OpenAPI description just
+/// like [`HttpResponseOk<T>`].
+#[derive(Debug)]
+pub struct ETagged<T> {
+    /// The serialized body, or `None` if the client’s copy is current.
+    body: Option<Vec<u8>>,
+
+    /// The quoted entity tag.
+    etag: String,
+
+    /// The type of the body, for the OpenAPI description.
+    kind: PhantomData<T>,
+}
+
+/// How [`ETagged`] responses appear in the OpenAPI description.
+type Documented<T> = HttpResponseHeaders<HttpResponseOk<T>, ETagHeaders>;
+
+/// Headers documented for [`ETagged`] responses.
+#[derive(JsonSchema, Serialize)]
+struct ETagHeaders {
+    /// Hash of the response body. Pass it in `If-None-Match` to get a 304
+    /// response if the content hasn’t changed.
+    #[serde(rename = "ETag")]
+    etag: String,
+}
+
+impl<T: JsonSchema + Serialize + Send + Sync + 'static> ETagged<T> {
+    /// Serialize `body` and compare its entity tag to the `If-None-Match`
+    /// header in the request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an internal error if `body` can’t be serialized.
+    pub fn new<C: ServerContext>(
+        rqctx: &RequestContext<C>,
+        body: &T,
+    ) -> Result<Self, HttpError> {
+        let body = serde_json::to_vec(body).map_err(|error| {
+            HttpError::for_internal_error(error.to_string())
+        })?;
+        let etag = etag(&body);
+        let if_none_match = rqctx
+            .request
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok());
+        let body = if if_none_match.is_some_and(|tags| matches(tags, &etag)) {
+            None
+        } else {
+            Some(body)
+        };
+        Ok(Self { body, etag, kind: PhantomData })
+    }
+}
+
+impl<T: JsonSchema + Serialize + Send + Sync + 'static> HttpResponse
+    for ETagged<T>
+{
+    fn to_result(self) -> Result<Response<Body>, HttpError> {
+        let builder = Response::builder()
+            .status(self.status_code())
+            .header(header::ETAG, self.etag);
+        match self.body {
+            Some(body) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.into()),
+            None => builder.body(Body::empty()),
+        }
+        .map_err(|error| HttpError::for_internal_error(error.to_string()))
+    }
+
+    fn response_metadata() -> ApiEndpointResponse {
+        Documented::<T>::response_metadata()
+    }
+
+    fn status_code(&self) -> StatusCode {
+        if self.body.is_some() {
+            StatusCode::OK
+        } else {
+            StatusCode::NOT_MODIFIED
+        }
+    }
+}
+
+/// Get the quoted entity tag for `body`.
+///
+/// This is the first 16 hex digits of the SHA-256 hash.
+fn etag(body: &[u8]) -> String {
+    let hex = Sha256::digest(body).iter().take(8).fold(
+        String::new(),
+        |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        },
+    );
+    format!("\"{hex}\"")
+}
+
+/// Check if an `If-None-Match` header value matches `etag`.
+///
+/// The header is either `*` or a comma-separated list of entity tags. Weak
+/// tags (`W/"…"`) match too, since `If-None-Match` uses weak comparison.
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn if_none_match() {
+        let tag = etag(b"{}");
+        assert!(tag.len() == 18);
+        assert!(tag.starts_with('"') && tag.ends_with('"'));
+        assert!(tag != etag(b"[]"));
+
+        assert!(matches(&tag, &tag));
+        assert!(matches("*", &tag));
+        assert!(matches(&format!("\"other\", W/{tag}"), &tag));
+        assert!(!matches("\"other\"", &tag));
+        assert!(!matches(tag.trim_matches('"'), &tag));
+    }
+}
@@ -3,11 +3,38 @@
 //! This module contains the concrete implementation of the API traits,
 //! including the GitHub OAuth integration.
 
+use super::backoff::Backoff;
+use super::coalesce::Coalescer;
 use super::definition::{
-    ApiBase, LocalContributions, OAuthTokenResponse, RepoYearApi,
+    ApiBase, DeviceCodeResponse, DevicePollResponse, DiffStatsContributions,
+    GitHubStatus, IdentitiesResponse, IngestError, IngestResponse,
+    LocalContributions, MAX_SCAN_PATHS, OAuthError, OAuthTokenResponse,
+    PathScanResult, ReloadError, ReloadResponse, RepoInfo, RepoYearApi,
+    ReposResponse, ScanError, ScanResponse,
 };
-use crate::repos;
+use super::readiness::Readiness;
+use super::reload::{ConfigLoader, LiveConfig, ReloadableConfig};
+use super::sse::ScanEvent;
+use crate::agent::{AgentKey, Report, ReportStore};
+use crate::repos::{RepoOutcome, ScanMetrics, ScanStatus};
+use crate::users::{UserError, Users};
+use crate::{merge, repos};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+use tokio::sync::{Semaphore, broadcast, mpsc};
+use tracing::Instrument;
+
+/// Contributions along with any errors encountered while scanning for them.
+pub type ScanResult = (LocalContributions, Vec<anyhow::Error>);
+
+/// A function that scans one repository for commit times.
+type ScanTimes =
+    fn(&repos::Config, &git2::Repository) -> anyhow::Result<Vec<i64>>;
 
 /// State data for the API (GitHub credentials and HTTP client).
 #[derive(Clone)]
@@ -18,8 +45,45 @@ pub struct AppState {
     pub github_client_secret: String,
     /// HTTP client for making requests to GitHub.
     pub http_client: reqwest::Client,
+    /// How long to wait before trying to reach GitHub again.
+    pub github_backoff: Arc<Backoff>,
+    /// Problems reaching GitHub since the server started.
+    pub github_status: Arc<Mutex<GitHubStatus>>,
+    /// Device flow logins in progress, by device code.
+    pub device_flows: Arc<Mutex<HashMap<String, DeviceFlow>>>,
     /// Configuration for repository scanning.
-    pub scan_config: Option<repos::Config>,
+    pub scan_config: Arc<LiveConfig>,
+    /// API keys for agents allowed to push reports.
+    pub agent_keys: Vec<AgentKey>,
+    /// Latest report from each agent, by agent name.
+    pub reports: Arc<Mutex<BTreeMap<String, Report>>>,
+    /// Where to save reports from agents, if anywhere.
+    pub report_store: Option<ReportStore>,
+    /// Users with their own repositories to scan.
+    pub users: Arc<RwLock<Users>>,
+    /// Reads the configuration files again for [`reload()`](Self::reload).
+    pub config_loader: Arc<ConfigLoader>,
+    /// Token required to reload the configuration over HTTP, if allowed.
+    pub admin_token: Option<String>,
+    /// Scan results kept up to date by watching the repositories, if any.
+    ///
+    /// If this is `None`, repositories are scanned on every request.
+    pub scan_cache: Arc<Mutex<Option<repos::ScanCache>>>,
+    /// Names of repositories rescanned because they changed, for live
+    /// updates.
+    ///
+    /// This is only used while the repositories are being watched.
+    pub contributions_updates: broadcast::Sender<Vec<String>>,
+    /// Shares one scan between concurrent requests for contributions.
+    pub contributions_scan: Arc<Coalescer<Arc<ScanResult>>>,
+    /// Limits how many scans run at once.
+    pub scan_permits: Arc<Semaphore>,
+    /// Number of repositories to scan at once within one scan.
+    pub scan_threads: NonZeroUsize,
+    /// Scan health since the server started.
+    pub scan_status: Arc<Mutex<ScanStatus>>,
+    /// Whether the initial scan has finished.
+    pub readiness: Arc<Readiness>,
 }
 
 /// A request to <https://github.com/login/oauth/access_token>.
@@ -57,13 +121,655 @@ struct GitHubTokenResponse {
     expires_in: Option<u64>,
     /// Number of seconds until the refresh token expires.
     refresh_token_expires_in: Option<u64>,
+    /// New minimum polling interval, for device flow `slow_down` errors.
+    interval: Option<u64>,
+    /// The error code if the request failed.
+    error: Option<String>,
+    /// The error message if the request failed.
+    error_description: Option<String>,
+}
+
+impl GitHubTokenResponse {
+    /// Convert a successful response to send to the client.
+    fn into_token(
+        self,
+        error_context: &str,
+    ) -> Result<OAuthTokenResponse, OAuthError> {
+        if let Some(error) = self.error {
+            tracing::error!("Error in {error_context} response: {error}");
+            return Err(OAuthError::from_github(error, self.error_description));
+        }
+
+        Ok(OAuthTokenResponse {
+            access_token: self.access_token.ok_or_else(|| {
+                tracing::error!("No access token in {error_context} response");
+                OAuthError::BadResponse
+            })?,
+            refresh_token: self.refresh_token,
+            expires_in: self.expires_in,
+            refresh_token_expires_in: self.refresh_token_expires_in,
+        })
+    }
+}
+
+/// A device flow token request to
+/// <https://github.com/login/oauth/access_token>.
+#[derive(Debug, Serialize)]
+struct GitHubDeviceTokenRequest<'a> {
+    /// The GitHub client ID for OAuth.
+    client_id: &'a str,
+    /// The grant type (always [`DEVICE_GRANT_TYPE`]).
+    grant_type: &'a str,
+    /// The device code from GitHub.
+    device_code: &'a str,
+}
+
+/// A request to <https://github.com/login/device/code>.
+#[derive(Debug, Serialize)]
+struct GitHubDeviceCodeRequest<'a> {
+    /// The GitHub client ID for OAuth.
+    client_id: &'a str,
+}
+
+/// A response from <https://github.com/login/device/code>.
+#[derive(Debug, Deserialize)]
+struct GitHubDeviceCodeResponse {
+    /// Code identifying this login.
+    device_code: Option<String>,
+    /// Code for the user to enter on GitHub.
+    user_code: Option<String>,
+    /// Where the user should enter the code.
+    verification_uri: Option<String>,
+    /// Number of seconds until the codes expire.
+    expires_in: Option<u64>,
+    /// Minimum number of seconds between polls.
+    interval: Option<u64>,
     /// The error code if the request failed.
     error: Option<String>,
     /// The error message if the request failed.
     error_description: Option<String>,
 }
 
+/// Grant type for device flow token requests.
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Polling interval to use if GitHub doesn’t specify one.
+const DEVICE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long device codes last if GitHub doesn’t say.
+const DEVICE_EXPIRY: Duration = Duration::from_secs(900);
+
+/// A device flow login in progress.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceFlow {
+    /// Minimum time between polls to GitHub.
+    interval: Duration,
+    /// When the device code expires.
+    expires_at: Instant,
+    /// When GitHub may be polled next.
+    next_poll: Instant,
+}
+
+impl DeviceFlow {
+    /// Start tracking a login.
+    fn new(now: Instant, interval: Duration, expires_in: Duration) -> Self {
+        Self {
+            interval,
+            expires_at: now.checked_add(expires_in).unwrap_or(now),
+            next_poll: now.checked_add(interval).unwrap_or(now),
+        }
+    }
+
+    /// Tell the client to keep waiting.
+    fn pending(&self, now: Instant) -> DevicePollResponse {
+        DevicePollResponse::Pending {
+            interval: self.interval.as_secs(),
+            retry_after: ceil_secs(
+                self.next_poll.saturating_duration_since(now),
+            ),
+            expires_in: self
+                .expires_at
+                .saturating_duration_since(now)
+                .as_secs(),
+        }
+    }
+}
+
+/// Round a duration up to whole seconds.
+fn ceil_secs(duration: Duration) -> u64 {
+    duration
+        .as_secs()
+        .saturating_add(u64::from(duration.subsec_nanos() > 0))
+}
+
 impl AppState {
+    /// Scan the configured repositories for contributions.
+    ///
+    /// Uses the [scan cache](Self::scan_cache) if it’s ready. Contributions
+    /// from agent reports are included, except for repositories that were
+    /// also scanned locally; see [`merge::Merged::drop_local()`]. Returns the
+    /// contributions along with any errors encountered. Repos that could not
+    /// be scanned are left out of the contributions.
+    ///
+    /// This blocks while scanning; from async code, use
+    /// [`shared_scan()`](Self::shared_scan) instead.
+    #[must_use]
+    pub fn scan_contributions(&self) -> ScanResult {
+        let cached = self
+            .scan_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|cache| {
+                (cache.contributions(), cache.remotes(), cache.errors())
+            });
+        let (mut contributions, remotes, errors) =
+            cached.unwrap_or_else(|| {
+                let mut errors = Vec::new();
+                let (contributions, remotes) = self
+                    .scan_config
+                    .get()
+                    .map(|config| {
+                        let (contributions, remotes, metrics) =
+                            Self::scan_repos(
+                                &config,
+                                self.scan_threads,
+                                &mut errors,
+                                &|_| {},
+                            );
+                        self.record_scan(metrics);
+                        (contributions, remotes)
+                    })
+                    .unwrap_or_default();
+                (contributions, remotes, errors)
+            });
+        let reports =
+            self.reports.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut merged = merge::merge(reports.values());
+        drop(reports);
+        for name in merged.drop_local(&contributions, &remotes) {
+            tracing::debug!(
+                repo = name,
+                "Skipped reported repository scanned locally"
+            );
+        }
+        for (repo, times) in merged.contributions() {
+            contributions.entry(repo).or_default().extend(times);
+        }
+        (contributions, errors)
+    }
+
+    /// Read the configuration files again and apply any changes.
+    ///
+    /// A new scan configuration takes effect for the next scan. If the
+    /// repositories are being watched, subscribers to
+    /// [`scan_config`](Self::scan_config) are expected to start over.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration couldn’t be loaded, in which
+    /// case nothing is changed.
+    pub async fn reload(&self) -> anyhow::Result<ReloadResponse> {
+        let loader = self.config_loader.clone();
+        let new = tokio::task::spawn_blocking(move || loader()).await??;
+        let old = ReloadableConfig {
+            scan_config: self.scan_config.get(),
+            users: self
+                .users
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        };
+
+        let changed = old.changes(&new);
+        if changed.is_empty() {
+            tracing::info!("Reloaded configuration; nothing changed");
+        } else {
+            tracing::info!("Reloaded configuration: {}", changed.join(", "));
+            if old.scan_config != new.scan_config {
+                self.scan_config.replace(new.scan_config);
+            }
+            *self.users.write().unwrap_or_else(PoisonError::into_inner) =
+                new.users;
+        }
+        Ok(ReloadResponse { changed })
+    }
+
+    /// Scan for contributions without blocking the async runtime.
+    ///
+    /// Concurrent calls share one scan. See [`Coalescer`].
+    pub async fn shared_scan(&self) -> Arc<ScanResult> {
+        self.contributions_scan
+            .run(|| async {
+                let state = self.clone();
+                let result = self
+                    .run_scan(move || state.scan_contributions())
+                    .await
+                    .unwrap_or_else(|error| {
+                        (LocalContributions::new(), vec![error])
+                    });
+                Arc::new(result)
+            })
+            .await
+    }
+
+    /// Run `scan` on a blocking thread once fewer than the maximum number of
+    /// scans are running.
+    async fn run_scan<T, F>(&self, scan: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let _permit = self.scan_permits.acquire().await?;
+        // Keep logs from the scan in the caller’s span.
+        let caller = tracing::Span::current();
+        Ok(tokio::task::spawn_blocking(move || caller.in_scope(scan)).await?)
+    }
+
+    /// Scan the repositories in `config` using up to `threads` threads,
+    /// adding any errors to `errors`.
+    ///
+    /// `progress` is called with a [`ScanEvent::Repo`] as each repository
+    /// finishes. Threads take the next repository as soon as they finish one,
+    /// so a slow repository only holds up its own thread. Clones of the
+    /// same repository are only counted once; see [`repos::drop_clones()`].
+    ///
+    /// Returns the contributions, the remote URLs of the repositories that
+    /// have one, and the metrics.
+    fn scan_repos(
+        config: &repos::Config,
+        threads: NonZeroUsize,
+        errors: &mut Vec<anyhow::Error>,
+        progress: &(dyn Fn(ScanEvent) + Sync),
+    ) -> (LocalContributions, BTreeMap<String, String>, ScanMetrics) {
+        let (metrics, scan_started) = ScanMetrics::start();
+        let repo_iter = Mutex::new(config.repo_iter());
+        let contributions = Mutex::new(LocalContributions::new());
+        let remotes = Mutex::new(BTreeMap::new());
+        let metrics = Mutex::new(metrics);
+        let scan_errors = Mutex::new(Vec::new());
+        let span = tracing::Span::current();
+        thread::scope(|scope| {
+            for _ in 0..threads.get() {
+                scope.spawn(|| {
+                    let _entered = span.enter();
+                    loop {
+                        // Time spent finding the repository counts towards it.
+                        let started = Instant::now();
+                        let next = repo_iter
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .next();
+                        let (name, repo) = match next {
+                            Some(Ok(found)) => found,
+                            Some(Err(error)) => {
+                                metrics
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .record_error(error.to_string());
+                                scan_errors
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .push(error.into());
+                                continue;
+                            }
+                            None => break,
+                        };
+                        let result = config.scan_repo(&repo);
+                        repos::log_repo_scan(&name, started, &result);
+                        progress(Self::record_repo(
+                            config,
+                            &mut metrics
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner),
+                            &name,
+                            &repo,
+                            &result,
+                        ));
+                        if let Ok(Some(remote)) = repos::remote_url(&repo) {
+                            remotes
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .insert(name.clone(), remote);
+                        }
+                        match result {
+                            Ok(times) => {
+                                contributions
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .insert(name, times);
+                            }
+                            Err(error) => scan_errors
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .push(error),
+                        }
+                    }
+                });
+            }
+        });
+
+        errors.extend(
+            scan_errors
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        let mut metrics =
+            metrics.into_inner().unwrap_or_else(PoisonError::into_inner);
+        metrics.finish(scan_started);
+        let mut contributions = contributions
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        let remotes =
+            remotes.into_inner().unwrap_or_else(PoisonError::into_inner);
+        for name in repos::drop_clones(&mut contributions, &remotes) {
+            tracing::debug!(repo = name, "Skipped clone of another repository");
+        }
+        (contributions, remotes, metrics)
+    }
+
+    /// Record the result of scanning `repo` in `metrics`.
+    ///
+    /// Returns a [`ScanEvent::Repo`] to report progress.
+    fn record_repo(
+        config: &repos::Config,
+        metrics: &mut ScanMetrics,
+        name: &str,
+        repo: &git2::Repository,
+        result: &anyhow::Result<Vec<i64>>,
+    ) -> ScanEvent {
+        let outcome =
+            RepoOutcome::of(repo, config.skip_remotes_for(repo), result);
+        let times = result.as_ref().map_or_else(|_| Vec::new(), Clone::clone);
+        metrics.record_outcome(name, outcome.clone(), times.len());
+        if repo.is_shallow() {
+            metrics.record_shallow(name);
+        }
+        if let Some(group) = config.group_of(repo) {
+            metrics.record_group(name, group);
+        }
+        ScanEvent::Repo {
+            name: name.to_owned(),
+            times,
+            outcome,
+            finished: metrics.repos_discovered,
+        }
+    }
+
+    /// Describe the repositories in `config` without scanning their history.
+    ///
+    /// `outcomes` are from the most recent scan.
+    fn describe_repos(
+        config: &repos::Config,
+        outcomes: &BTreeMap<String, RepoOutcome>,
+    ) -> ReposResponse {
+        let mut response = ReposResponse::default();
+        for result in config.repo_iter() {
+            let (name, repo) = match result {
+                Ok(found) => found,
+                Err(error) => {
+                    response.errors.push(error.to_string());
+                    continue;
+                }
+            };
+            // Listing repositories shouldn’t wait on `git ls-remote`.
+            let branch = config.cached_remote_head(&repo).and_then(|head| {
+                repos::find_default_branch(&repo, head.as_deref())
+            });
+            let (default_branch, last_commit, error) = match branch {
+                Ok((branch, oid)) => (
+                    Some(branch),
+                    repo.find_commit(oid)
+                        .ok()
+                        .map(|commit| commit.time().seconds()),
+                    None,
+                ),
+                Err(error) => (None, None, Some(error.to_string())),
+            };
+            response.repos.push(RepoInfo {
+                path: repo
+                    .workdir()
+                    .unwrap_or_else(|| repo.path())
+                    .display()
+                    .to_string(),
+                outcome: outcomes.get(&name).cloned(),
+                name,
+                default_branch,
+                last_commit,
+                error,
+            });
+        }
+        response.repos.sort_by(|a, b| a.name.cmp(&b.name));
+        response
+    }
+
+    /// Scan the configured repositories, sending progress to `sender`.
+    ///
+    /// Uses the [scan cache](Self::scan_cache) if it’s ready, in which case
+    /// every repository is sent at once.
+    fn stream_scan(&self, sender: &mpsc::UnboundedSender<ScanEvent>) {
+        let send = |event| {
+            // The scan should finish even if nobody is listening.
+            let _ = sender.send(event);
+        };
+        let cached = self
+            .scan_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|cache| (cache.contributions(), cache.metrics()));
+        let (contributions, metrics) = if let Some(cached) = cached {
+            let (contributions, metrics) = &cached;
+            for (finished, (name, outcome)) in
+                metrics.outcomes.iter().enumerate()
+            {
+                send(ScanEvent::Repo {
+                    name: name.clone(),
+                    times: contributions.get(name).cloned().unwrap_or_default(),
+                    outcome: outcome.clone(),
+                    finished: finished.saturating_add(1),
+                });
+            }
+            cached
+        } else if let Some(config) = self.scan_config.get() {
+            let mut errors = Vec::new();
+            let (contributions, _, metrics) = Self::scan_repos(
+                &config,
+                self.scan_threads,
+                &mut errors,
+                &send,
+            );
+            for error in errors {
+                tracing::warn!("{error}");
+            }
+            self.record_scan(metrics.clone());
+            (contributions, metrics)
+        } else {
+            (LocalContributions::new(), ScanMetrics::default())
+        };
+        let dropped = metrics
+            .outcomes
+            .iter()
+            .filter(|(name, outcome)| {
+                !matches!(outcome, RepoOutcome::Error { .. })
+                    && !contributions.contains_key(*name)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        send(ScanEvent::Done { metrics, dropped });
+    }
+
+    /// Scan the repositories in `config` with `scan`, adding any errors to
+    /// `errors`.
+    ///
+    /// Repositories where `scan` finds nothing are left out.
+    fn scan_times(
+        config: &repos::Config,
+        scan: ScanTimes,
+        errors: &mut Vec<anyhow::Error>,
+    ) -> LocalContributions {
+        let mut contributions = LocalContributions::new();
+        for result in config.repo_iter() {
+            match result {
+                Ok((name, repo)) => {
+                    let started = Instant::now();
+                    let result = scan(config, &repo);
+                    repos::log_repo_scan(&name, started, &result);
+                    match result {
+                        Ok(times) if times.is_empty() => {}
+                        Ok(times) => {
+                            contributions.insert(name, times);
+                        }
+                        Err(error) => errors.push(error),
+                    }
+                }
+                Err(error) => errors.push(error.into()),
+            }
+        }
+        contributions
+    }
+
+    /// Run [`scan_times()`](Self::scan_times) if `enabled` is true for the
+    /// scan configuration, logging any errors.
+    async fn scan_times_if(
+        &self,
+        enabled: fn(&repos::Config) -> bool,
+        scan: ScanTimes,
+    ) -> LocalContributions {
+        let Some(config) = self.scan_config.get().filter(enabled) else {
+            return LocalContributions::new();
+        };
+        let result = self
+            .run_scan(move || {
+                let mut errors = Vec::new();
+                let contributions =
+                    Self::scan_times(&config, scan, &mut errors);
+                (contributions, errors)
+            })
+            .await;
+        match result {
+            Ok((contributions, errors)) => {
+                for error in errors {
+                    tracing::warn!("{error}");
+                }
+                contributions
+            }
+            Err(error) => {
+                tracing::warn!("{error}");
+                LocalContributions::new()
+            }
+        }
+    }
+
+    /// Scan the repositories in `config` for commits with diff stats, adding
+    /// any errors to `errors`.
+    fn scan_stats(
+        config: &repos::Config,
+        errors: &mut Vec<anyhow::Error>,
+    ) -> DiffStatsContributions {
+        let mut contributions = DiffStatsContributions::new();
+        for result in config.repo_iter() {
+            match result {
+                Ok((name, repo)) => {
+                    let started = Instant::now();
+                    let result =
+                        config.scan_stats(&repo).map(Option::unwrap_or_default);
+                    repos::log_repo_scan(&name, started, &result);
+                    match result {
+                        Ok(commits) => {
+                            contributions.insert(name, commits);
+                        }
+                        Err(error) => errors.push(error),
+                    }
+                }
+                Err(error) => errors.push(error.into()),
+            }
+        }
+        contributions
+    }
+
+    /// Scan the repositories in `config` for commits by each of its
+    /// identities, adding any errors to `errors`.
+    fn scan_identities(
+        config: &repos::Config,
+        errors: &mut Vec<anyhow::Error>,
+    ) -> HashMap<String, LocalContributions> {
+        let mut identities: HashMap<String, LocalContributions> =
+            HashMap::new();
+        for result in config.repo_iter() {
+            match result {
+                Ok((name, repo)) => {
+                    let started = Instant::now();
+                    let result = config.scan_identities(&repo);
+                    let all = result.as_ref().map(|by_label| {
+                        by_label.values().flatten().collect::<Vec<_>>()
+                    });
+                    repos::log_repo_scan(&name, started, &all);
+                    match result {
+                        Ok(by_label) => {
+                            for (label, times) in by_label {
+                                identities
+                                    .entry(label)
+                                    .or_default()
+                                    .insert(name.clone(), times);
+                            }
+                        }
+                        Err(error) => errors.push(error),
+                    }
+                }
+                Err(error) => errors.push(error.into()),
+            }
+        }
+        identities
+    }
+
+    /// Scan the repository at `path` if `config` covers it.
+    fn scan_path(config: &repos::Config, path: String) -> PathScanResult {
+        let path_ref = std::path::Path::new(&path);
+        if !path_ref.is_absolute() {
+            return PathScanResult {
+                error: Some("Path must be absolute".to_owned()),
+                path,
+                ..PathScanResult::default()
+            };
+        }
+        let Some(name) = config.name_for_path(path_ref) else {
+            return PathScanResult {
+                error: Some("Not a configured repository".to_owned()),
+                path,
+                ..PathScanResult::default()
+            };
+        };
+
+        let started = Instant::now();
+        let result = git2::Repository::open(path_ref)
+            .map_err(anyhow::Error::from)
+            .and_then(|repo| config.scan_repo(&repo));
+        repos::log_repo_scan(&name, started, &result);
+        match result {
+            Ok(times) => PathScanResult {
+                path,
+                name: Some(name),
+                commits: Some(times),
+                error: None,
+            },
+            Err(error) => PathScanResult {
+                path,
+                name: Some(name),
+                commits: None,
+                error: Some(error.to_string()),
+            },
+        }
+    }
+
+    /// Add a finished scan to [`scan_status`](Self::scan_status).
+    fn record_scan(&self, metrics: ScanMetrics) {
+        self.scan_status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(metrics);
+    }
+
     /// Helper function to make OAuth token requests to GitHub.
     ///
     /// This function handles the common logic for both initial token exchange
@@ -71,96 +777,506 @@ impl AppState {
     async fn request_github_token<T: Serialize + Sync>(
         &self,
         request_body: &T,
-        log: &slog::Logger,
         error_context: &str,
-    ) -> Result<OAuthTokenResponse, String> {
-        let token_data = self
-            .http_client
-            .post("https://github.com/login/oauth/access_token")
-            .header(reqwest::header::CONTENT_TYPE, "application/json")
-            .header(reqwest::header::ACCEPT, "application/json")
-            .json(request_body)
-            .send()
-            .await
-            .map_err(|error| {
-                slog::error!(log, "{error_context} request failed: {error}");
-                "Service temporarily unavailable".to_owned()
-            })?
-            .json::<GitHubTokenResponse>()
-            .await
-            .map_err(|error| {
-                slog::error!(
-                    log,
-                    "Failed to parse {error_context} response: {error}"
+    ) -> Result<OAuthTokenResponse, OAuthError> {
+        self.fetch_github::<_, GitHubTokenResponse>(
+            TOKEN_URL,
+            request_body,
+            error_context,
+        )
+        .await?
+        .into_token(error_context)
+    }
+
+    /// Send a request to GitHub and parse the response.
+    async fn fetch_github<T, R>(
+        &self,
+        url: &str,
+        request_body: &T,
+        error_context: &str,
+    ) -> Result<R, OAuthError>
+    where
+        T: Serialize + Sync,
+        R: serde::de::DeserializeOwned,
+    {
+        let body = self
+            .send_github_request(url, request_body, error_context)
+            .await?;
+        serde_json::from_str(&body).map_err(|error| {
+            tracing::error!(
+                "Failed to parse {error_context} response: {error}"
+            );
+            OAuthError::BadResponse
+        })
+    }
+
+    /// Send a request to GitHub and get the response body.
+    ///
+    /// If GitHub is rate limiting requests and asks for a short enough wait
+    /// (up to [`MAX_RATE_LIMIT_WAIT`]), this waits and tries once more.
+    async fn send_github_request<T: Serialize + Sync>(
+        &self,
+        url: &str,
+        request_body: &T,
+        error_context: &str,
+    ) -> Result<String, OAuthError> {
+        if let Some(retry_after) = self.github_backoff.wait() {
+            tracing::warn!(
+                "Not sending {error_context} request; GitHub was unreachable"
+            );
+            return Err(OAuthError::Unavailable { retry_after });
+        }
+
+        let mut retried = false;
+        loop {
+            let response = self
+                .http_client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(reqwest::header::ACCEPT, "application/json")
+                .json(request_body)
+                .send()
+                .await
+                .map_err(|error| {
+                    tracing::error!("{error_context} request failed: {error}");
+                    self.update_github_status(|status| {
+                        status.unavailable =
+                            status.unavailable.saturating_add(1);
+                    });
+                    OAuthError::Unavailable {
+                        retry_after: self.github_backoff.failure(),
+                    }
+                })?;
+            self.github_backoff.success();
+
+            let status = response.status();
+            let wait = rate_limit_wait(
+                status,
+                response.headers(),
+                chrono::Utc::now().timestamp(),
+            );
+            let body = response.text().await.map_err(|error| {
+                tracing::error!(
+                    "Failed to read {error_context} response: {error}"
                 );
-                "Internal server error".to_owned()
+                OAuthError::BadResponse
             })?;
+            let wait = wait.or_else(|| {
+                (status == reqwest::StatusCode::FORBIDDEN
+                    && body.to_lowercase().contains("rate limit"))
+                .then_some(SECONDARY_RATE_LIMIT_WAIT)
+            });
 
-        if let Some(error) = &token_data.error {
-            slog::error!(log, "Error in {error_context} response: {error}");
-            let message = token_data
-                .error_description
-                .clone()
-                .unwrap_or_else(|| format!("{error_context} failed"));
-            return Err(message);
+            let Some(retry_after) = wait else {
+                return Ok(body);
+            };
+            self.update_github_status(|status| {
+                status.rate_limited = status.rate_limited.saturating_add(1);
+            });
+            if retried || retry_after > MAX_RATE_LIMIT_WAIT {
+                tracing::error!(
+                    retry_after = retry_after.as_secs(),
+                    "{error_context} request rate limited by GitHub",
+                );
+                return Err(OAuthError::RateLimited { retry_after });
+            }
+            tracing::warn!(
+                retry_after = retry_after.as_secs(),
+                "{error_context} request rate limited by GitHub; retrying",
+            );
+            tokio::time::sleep(retry_after).await;
+            retried = true;
         }
+    }
 
-        Ok(OAuthTokenResponse {
-            access_token: token_data
-                .access_token
-                .ok_or_else(|| "Internal server error".to_owned())?,
-            refresh_token: token_data.refresh_token,
-            expires_in: token_data.expires_in,
-            refresh_token_expires_in: token_data.refresh_token_expires_in,
-        })
+    /// Update the counts of problems reaching GitHub.
+    fn update_github_status<F: FnOnce(&mut GitHubStatus)>(&self, update: F) {
+        update(
+            &mut self
+                .github_status
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
     }
 }
 
+/// URL to request OAuth tokens from.
+const TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+
+/// URL to request device flow codes from.
+const DEVICE_CODE_URL: &str = "https://github.com/login/device/code";
+
+/// Longest wait requested by GitHub’s rate limiting that we will wait out
+/// before retrying a request ourselves.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(10);
+
+/// How long to wait after hitting a secondary rate limit if GitHub doesn’t
+/// say.
+///
+/// GitHub’s documentation says to wait at least a minute.
+const SECONDARY_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// Check if a response from GitHub means that it’s rate limiting requests.
+///
+/// Returns how long GitHub asked us to wait, based on the `Retry-After` or
+/// `X-RateLimit-Reset` headers. `now` is the current time in seconds since
+/// 1970. Secondary rate limits without either header can only be detected from
+/// the response body, so they aren’t handled here.
+fn rate_limit_wait(
+    status: reqwest::StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    now: i64,
+) -> Option<Duration> {
+    if status != reqwest::StatusCode::FORBIDDEN
+        && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+    {
+        return None;
+    }
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+    };
+    if let Some(seconds) = header("retry-after") {
+        return Some(Duration::from_secs(seconds));
+    }
+    if header("x-ratelimit-remaining") == Some(0)
+        && let Some(reset) = header("x-ratelimit-reset")
+    {
+        let reset = i64::try_from(reset).unwrap_or(i64::MAX);
+        return Some(Duration::from_secs(
+            reset.saturating_sub(now).try_into().unwrap_or(0),
+        ));
+    }
+    (status == reqwest::StatusCode::TOO_MANY_REQUESTS)
+        .then_some(SECONDARY_RATE_LIMIT_WAIT)
+}
+
 impl ApiBase for AppState {
     async fn check_health(&self) -> String {
         "ok".to_owned()
     }
 
+    async fn is_ready(&self) -> bool {
+        self.readiness.is_ready()
+    }
+
     async fn get_version(&self) -> String {
         env!("GIT_VERSION").to_owned()
     }
 
-    async fn get_contributions(
+    async fn get_contributions(&self) -> LocalContributions {
+        // Share the initial scan rather than starting another one.
+        self.readiness.wait().await;
+        let result = self.shared_scan().await;
+        let (contributions, errors) = &*result;
+        for error in errors {
+            tracing::warn!("{error}");
+        }
+        contributions.clone()
+    }
+
+    async fn get_unmerged_contributions(&self) -> LocalContributions {
+        self.scan_times_if(
+            |config| config.unmerged,
+            repos::Config::scan_unmerged,
+        )
+        .await
+    }
+
+    async fn get_reflog_contributions(&self) -> LocalContributions {
+        self.scan_times_if(|config| config.reflog, repos::Config::scan_reflog)
+            .await
+    }
+
+    async fn get_commit_cap(&self) -> Option<usize> {
+        self.scan_config.get().and_then(|config| config.commit_cap)
+    }
+
+    async fn get_diff_stats(&self) -> DiffStatsContributions {
+        let Some(config) = self
+            .scan_config
+            .get()
+            .filter(|config| config.diff_stats.is_some())
+        else {
+            return DiffStatsContributions::new();
+        };
+        let result = self
+            .run_scan(move || {
+                let mut errors = Vec::new();
+                let contributions = Self::scan_stats(&config, &mut errors);
+                (contributions, errors)
+            })
+            .await;
+        match result {
+            Ok((contributions, errors)) => {
+                for error in errors {
+                    tracing::warn!("{error}");
+                }
+                contributions
+            }
+            Err(error) => {
+                tracing::warn!("{error}");
+                DiffStatsContributions::new()
+            }
+        }
+    }
+
+    async fn get_identity_contributions(
         &self,
-        log: &slog::Logger,
-    ) -> LocalContributions {
-        let Some(config) = &self.scan_config else {
-            return LocalContributions::new();
+    ) -> HashMap<String, LocalContributions> {
+        let Some(config) = self
+            .scan_config
+            .get()
+            .filter(|config| !config.identities.is_empty())
+        else {
+            return HashMap::new();
         };
+        let result = self
+            .run_scan(move || {
+                let mut errors = Vec::new();
+                let identities = Self::scan_identities(&config, &mut errors);
+                (identities, errors)
+            })
+            .await;
+        match result {
+            Ok((identities, errors)) => {
+                for error in errors {
+                    tracing::warn!("{error}");
+                }
+                identities
+            }
+            Err(error) => {
+                tracing::warn!("{error}");
+                HashMap::new()
+            }
+        }
+    }
 
-        config
-            .repo_iter()
-            .filter_map(|result| {
-                result
-                    .map_err(anyhow::Error::from) // FIXME?
-                    .and_then(|(name, repo)| {
-                        Ok((name, repos::scan_repo(&repo)?))
-                    })
-                    .inspect_err(|error| {
-                        slog::warn!(log, "{error}");
-                    })
-                    .ok()
+    async fn scan_paths(
+        &self,
+        paths: Vec<String>,
+    ) -> Result<ScanResponse, ScanError> {
+        let Some(config) = self.scan_config.get() else {
+            return Err(ScanError::NotConfigured);
+        };
+        if paths.len() > MAX_SCAN_PATHS {
+            return Err(ScanError::TooManyPaths);
+        }
+        let results = self
+            .run_scan(move || {
+                paths
+                    .into_iter()
+                    .map(|path| Self::scan_path(&config, path))
+                    .collect()
+            })
+            .await
+            .map_err(|error| ScanError::Failed(error.to_string()))?;
+        Ok(ScanResponse { results })
+    }
+
+    async fn stream_contributions(&self) -> mpsc::UnboundedReceiver<ScanEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let state = self.clone();
+        tokio::spawn(
+            async move {
+                let scan = state.clone();
+                if let Err(error) =
+                    state.run_scan(move || scan.stream_scan(&sender)).await
+                {
+                    tracing::warn!("{error}");
+                }
+            }
+            .in_current_span(),
+        );
+        receiver
+    }
+
+    async fn subscribe_contributions(
+        &self,
+    ) -> broadcast::Receiver<Vec<String>> {
+        self.contributions_updates.subscribe()
+    }
+
+    async fn list_repos(&self) -> ReposResponse {
+        let Some(config) = self.scan_config.get() else {
+            return ReposResponse::default();
+        };
+        let outcomes = self
+            .get_scan_status()
+            .await
+            .last
+            .map(|last| last.outcomes)
+            .unwrap_or_default();
+        self.run_scan(move || Self::describe_repos(&config, &outcomes))
+            .await
+            .unwrap_or_else(|error| ReposResponse {
+                repos: Vec::new(),
+                errors: vec![error.to_string()],
             })
-            .collect()
+    }
+
+    async fn get_scan_status(&self) -> ScanStatus {
+        self.scan_status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    async fn get_github_status(&self) -> GitHubStatus {
+        self.github_status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    async fn get_identities(&self) -> IdentitiesResponse {
+        self.scan_config
+            .get()
+            .map(|config| IdentitiesResponse {
+                emails: config.identity.emails,
+                names: config.identity.names,
+                github: config.identity.github,
+            })
+            .unwrap_or_default()
+    }
+
+    async fn get_user_contributions(
+        &self,
+        user: &str,
+        token: Option<&str>,
+    ) -> Result<LocalContributions, UserError> {
+        let user = self
+            .users
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .authenticate(user, token)?
+            .clone();
+        let config = user.scan_config.clone();
+        let threads = self.scan_threads;
+        let span = tracing::info_span!("user", user = %user.name);
+        let (contributions, errors) = match self
+            .run_scan(move || {
+                let mut errors = Vec::new();
+                let found =
+                    Self::scan_repos(&config, threads, &mut errors, &|_| {});
+                (found, errors)
+            })
+            .instrument(span)
+            .await
+        {
+            // User scans are kept out of the server’s scan status, since
+            // that’s visible to everyone.
+            Ok(((contributions, _, metrics), errors)) => {
+                tracing::debug!(
+                    user = user.name,
+                    repos = metrics.repos_discovered,
+                    commits = metrics.commits,
+                    duration_ms = metrics.duration_ms,
+                    "Scanned user repositories",
+                );
+                (contributions, errors)
+            }
+            Err(error) => (LocalContributions::new(), vec![error]),
+        };
+        for error in errors {
+            tracing::warn!("{}: {error}", user.name);
+        }
+        Ok(contributions)
+    }
+
+    async fn reload_config(
+        &self,
+        token: Option<&str>,
+    ) -> Result<ReloadResponse, ReloadError> {
+        let Some(admin_token) = &self.admin_token else {
+            return Err(ReloadError::Disabled);
+        };
+        let authorized = token.is_some_and(|token| {
+            token.as_bytes().ct_eq(admin_token.as_bytes()).into()
+        });
+        if !authorized {
+            tracing::warn!("Rejected request to reload configuration");
+            return Err(ReloadError::Unauthorized);
+        }
+        self.reload()
+            .await
+            .map_err(|error| ReloadError::Failed(error.to_string()))
+    }
+
+    async fn ingest(
+        &self,
+        api_key: &str,
+        report: Report,
+    ) -> Result<IngestResponse, IngestError> {
+        let authorized = self.agent_keys.iter().any(|key| {
+            bool::from(key.key.as_bytes().ct_eq(api_key.as_bytes()))
+                && key.agent == report.agent
+        });
+        if !authorized {
+            tracing::warn!("Rejected report from agent {:?}", report.agent);
+            return Err(IngestError::Unauthorized);
+        }
+        report.validate().map_err(IngestError::Invalid)?;
+
+        let response = IngestResponse {
+            repos: report.repos.len(),
+            commits: report.commit_count(),
+            conflicts: Vec::new(),
+        };
+        tracing::info!(
+            "Received report from agent {:?} with {} commits in {} repos",
+            report.agent,
+            response.commits,
+            response.repos,
+        );
+
+        let (save, conflicts) = {
+            let mut reports =
+                self.reports.lock().unwrap_or_else(PoisonError::into_inner);
+            // Reports may arrive out of order; keep the newest.
+            let newest = reports
+                .get(&report.agent)
+                .is_none_or(|old| old.scanned_at <= report.scanned_at);
+            let save =
+                (newest && self.report_store.is_some()).then(|| report.clone());
+            if newest {
+                reports.insert(report.agent.clone(), report);
+            }
+            (save, merge::merge(reports.values()).conflicts)
+        };
+
+        // Keep the report across restarts.
+        if let (Some(store), Some(report)) = (self.report_store.clone(), save) {
+            let saved =
+                tokio::task::spawn_blocking(move || store.save(&report))
+                    .await
+                    .unwrap_or_else(|error| Err(std::io::Error::other(error)));
+            if let Err(error) = saved {
+                tracing::warn!("Could not save report: {error}");
+            }
+        }
+
+        for conflict in &conflicts {
+            tracing::warn!("{conflict}");
+        }
+        Ok(IngestResponse {
+            conflicts: conflicts.iter().map(ToString::to_string).collect(),
+            ..response
+        })
     }
 
     async fn exchange_oauth_token(
         &self,
         code: &str,
-        log: &slog::Logger,
-    ) -> Result<OAuthTokenResponse, String> {
+    ) -> Result<OAuthTokenResponse, OAuthError> {
         self.request_github_token(
             &GitHubTokenRequest {
                 client_id: &self.github_client_id,
                 client_secret: &self.github_client_secret,
                 code,
             },
-            log,
             "OAuth",
         )
         .await
@@ -169,8 +1285,7 @@ impl ApiBase for AppState {
     async fn refresh_oauth_token(
         &self,
         refresh_token: &str,
-        log: &slog::Logger,
-    ) -> Result<OAuthTokenResponse, String> {
+    ) -> Result<OAuthTokenResponse, OAuthError> {
         self.request_github_token(
             &GitHubRefreshRequest {
                 client_id: &self.github_client_id,
@@ -178,11 +1293,128 @@ impl ApiBase for AppState {
                 grant_type: "refresh_token",
                 refresh_token,
             },
-            log,
             "OAuth refresh",
         )
         .await
     }
+
+    async fn start_device_flow(
+        &self,
+    ) -> Result<DeviceCodeResponse, OAuthError> {
+        let response: GitHubDeviceCodeResponse = self
+            .fetch_github(
+                DEVICE_CODE_URL,
+                &GitHubDeviceCodeRequest { client_id: &self.github_client_id },
+                "Device code",
+            )
+            .await?;
+        if let Some(error) = response.error {
+            tracing::error!("Error in device code response: {error}");
+            return Err(OAuthError::from_github(
+                error,
+                response.error_description,
+            ));
+        }
+        let (Some(device_code), Some(user_code), Some(verification_uri)) = (
+            response.device_code,
+            response.user_code,
+            response.verification_uri,
+        ) else {
+            tracing::error!("Missing fields in device code response");
+            return Err(OAuthError::BadResponse);
+        };
+
+        let interval = response
+            .interval
+            .map_or(DEVICE_INTERVAL, Duration::from_secs);
+        let expires_in = response
+            .expires_in
+            .map_or(DEVICE_EXPIRY, Duration::from_secs);
+        let now = Instant::now();
+        let mut flows = self
+            .device_flows
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        flows.retain(|_, flow| flow.expires_at > now);
+        flows.insert(
+            device_code.clone(),
+            DeviceFlow::new(now, interval, expires_in),
+        );
+        drop(flows);
+
+        Ok(DeviceCodeResponse {
+            device_code,
+            user_code,
+            verification_uri,
+            expires_in: expires_in.as_secs(),
+            interval: interval.as_secs(),
+        })
+    }
+
+    async fn poll_device_flow(
+        &self,
+        device_code: &str,
+    ) -> Result<DevicePollResponse, OAuthError> {
+        let flow = self
+            .device_flows
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(device_code)
+            .copied();
+        let now = Instant::now();
+        if let Some(flow) = flow
+            && flow.next_poll > now
+        {
+            // Polling GitHub too quickly gets a slow_down error.
+            return Ok(flow.pending(now));
+        }
+
+        let token_data: GitHubTokenResponse = self
+            .fetch_github(
+                TOKEN_URL,
+                &GitHubDeviceTokenRequest {
+                    client_id: &self.github_client_id,
+                    grant_type: DEVICE_GRANT_TYPE,
+                    device_code,
+                },
+                "Device flow",
+            )
+            .await?;
+
+        let now = Instant::now();
+        let mut flows = self
+            .device_flows
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Some(code @ ("authorization_pending" | "slow_down")) =
+            token_data.error.as_deref()
+        else {
+            flows.remove(device_code);
+            drop(flows);
+            return Ok(DevicePollResponse::Complete {
+                token: token_data.into_token("Device flow")?,
+            });
+        };
+
+        // The flow may be missing if the server restarted.
+        let flow = flows.entry(device_code.to_owned()).or_insert_with(|| {
+            DeviceFlow::new(now, DEVICE_INTERVAL, DEVICE_EXPIRY)
+        });
+        if code == "slow_down" {
+            flow.interval = token_data.interval.map_or_else(
+                || flow.interval.saturating_add(DEVICE_INTERVAL),
+                Duration::from_secs,
+            );
+            tracing::warn!(
+                interval = flow.interval.as_secs(),
+                "Device flow polled too quickly",
+            );
+        }
+        flow.next_poll = now.checked_add(flow.interval).unwrap_or(now);
+        let response = flow.pending(now);
+        drop(flows);
+        Ok(response)
+    }
 }
 
 /// Implementation type for the `RepoYearApi` trait.
@@ -194,3 +1426,256 @@ pub enum RepoYearApiImpl {}
 impl RepoYearApi for RepoYearApiImpl {
     type Context = AppState;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use reqwest::StatusCode;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use testdir::testdir;
+
+    /// Server state that scans the repositories in `config`, if any.
+    fn state(config: Option<repos::Config>) -> AppState {
+        AppState {
+            github_client_id: String::new(),
+            github_client_secret: String::new(),
+            http_client: reqwest::Client::new(),
+            github_backoff: Arc::default(),
+            github_status: Arc::default(),
+            device_flows: Arc::default(),
+            scan_config: Arc::new(config.into()),
+            agent_keys: Vec::new(),
+            reports: Arc::default(),
+            report_store: None,
+            users: Arc::default(),
+            config_loader: Arc::new(|| Ok(ReloadableConfig::default())),
+            admin_token: None,
+            scan_cache: Arc::default(),
+            contributions_updates: broadcast::Sender::new(16),
+            contributions_scan: Arc::default(),
+            scan_permits: Arc::new(Semaphore::new(2)),
+            scan_threads: NonZeroUsize::MIN,
+            scan_status: Arc::default(),
+            readiness: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn scan_repos_in_parallel() {
+        let home = Home::init(testdir!());
+        for (name, commits) in [("a", 1), ("b", 2), ("c", 3), ("d", 1)] {
+            let repo = home.git_init(format!("root/{name}"));
+            for n in 0..commits {
+                repo.make_commit(n);
+            }
+        }
+        home.write("root/e/.git/HEAD", "broken");
+        let config = repos::Config::with_tree((home.join("root"), Some("x:")));
+
+        for threads in [1, 3, 8] {
+            let mut errors = Vec::new();
+            let finished = Mutex::new(Vec::new());
+            let (contributions, _, metrics) = AppState::scan_repos(
+                &config,
+                NonZeroUsize::new(threads).unwrap(),
+                &mut errors,
+                &|event| {
+                    if let ScanEvent::Repo { finished: n, .. } = event {
+                        finished.lock().unwrap().push(n);
+                    }
+                },
+            );
+            let mut counts: Vec<_> = contributions
+                .into_iter()
+                .map(|(name, times)| (name, times.len()))
+                .collect();
+            counts.sort();
+            assert!(
+                counts
+                    == [
+                        ("x:a".to_owned(), 1),
+                        ("x:b".to_owned(), 2),
+                        ("x:c".to_owned(), 3),
+                        ("x:d".to_owned(), 1),
+                    ]
+            );
+            assert!(metrics.repos_scanned == 4);
+            assert!(metrics.commits == 7);
+            assert!(errors.len() == metrics.repos_errored);
+
+            let mut finished = finished.into_inner().unwrap();
+            finished.sort_unstable();
+            assert!(
+                finished == (1..=metrics.repos_discovered).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn describe_repos() {
+        let home = Home::init(testdir!());
+        home.git_init("root/a").make_commit(0);
+        home.git_init("root/b");
+        let config = repos::Config::with_tree((home.join("root"), Some("x:")));
+        let outcomes =
+            BTreeMap::from([("x:a".to_owned(), RepoOutcome::Scanned)]);
+
+        let response = AppState::describe_repos(&config, &outcomes);
+        assert!(response.errors.is_empty());
+        let [a, b] = &response.repos[..] else {
+            panic!("expected two repos: {:?}", response.repos);
+        };
+        assert!(a.name == "x:a");
+        assert!(a.path == home.join("root/a/").display().to_string());
+        assert!(a.default_branch.as_deref() == Some("main"));
+        assert!(a.last_commit.is_some());
+        assert!(a.outcome == Some(RepoOutcome::Scanned));
+        assert!(a.error == None);
+        assert!(b.name == "x:b");
+        assert!(b.default_branch == None);
+        assert!(b.outcome == None);
+        assert!(b.error.is_some());
+    }
+
+    #[test]
+    fn rate_limit_wait_from_headers() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+
+        let retry_after = headers(&[("retry-after", "30")]);
+        assert!(
+            rate_limit_wait(StatusCode::FORBIDDEN, &retry_after, 0)
+                == Some(Duration::from_secs(30))
+        );
+        assert!(rate_limit_wait(StatusCode::OK, &retry_after, 0).is_none());
+
+        let reset = headers(&[
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", "1000"),
+        ]);
+        assert!(
+            rate_limit_wait(StatusCode::FORBIDDEN, &reset, 990)
+                == Some(Duration::from_secs(10))
+        );
+        assert!(
+            rate_limit_wait(StatusCode::FORBIDDEN, &reset, 2000)
+                == Some(Duration::ZERO)
+        );
+
+        assert!(
+            rate_limit_wait(StatusCode::FORBIDDEN, &HeaderMap::new(), 0)
+                .is_none()
+        );
+        assert!(
+            rate_limit_wait(
+                StatusCode::TOO_MANY_REQUESTS,
+                &HeaderMap::new(),
+                0
+            ) == Some(SECONDARY_RATE_LIMIT_WAIT)
+        );
+    }
+
+    #[test]
+    fn device_flow_pending() {
+        let now = Instant::now();
+        let flow = DeviceFlow::new(now, Duration::from_secs(5), DEVICE_EXPIRY);
+        let later = now.checked_add(Duration::from_millis(2_500)).unwrap();
+        assert!(let DevicePollResponse::Pending {
+            interval: 5,
+            retry_after: 3,
+            expires_in: 897,
+        } = flow.pending(later));
+    }
+
+    #[tokio::test]
+    async fn get_contributions_shares_scan() {
+        let home = Home::init(testdir!());
+        home.git_init("root/a").make_commit(0);
+        let config = repos::Config::with_tree((home.join("root"), Some("x:")));
+        let state = state(Some(config));
+
+        let (a, b, c) = tokio::join!(
+            state.get_contributions(),
+            state.get_contributions(),
+            state.get_contributions(),
+        );
+        assert!(a["x:a"].len() == 1);
+        assert!(a == b);
+        assert!(a == c);
+        assert!(state.get_scan_status().await.scans == 1);
+
+        state.get_contributions().await;
+        assert!(state.get_scan_status().await.scans == 2);
+    }
+
+    #[tokio::test]
+    async fn get_contributions_waits_for_warmup() {
+        let state = AppState {
+            readiness: Arc::new(Readiness::warming_up()),
+            ..state(Some(repos::Config::default()))
+        };
+        assert!(!state.is_ready().await);
+        let request = tokio::spawn({
+            let state = state.clone();
+            async move { state.get_contributions().await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!request.is_finished());
+
+        state.readiness.set_ready();
+        assert!(state.is_ready().await);
+        assert!(request.await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stream_contributions() {
+        let home = Home::init(testdir!());
+        home.git_init("root/a").make_commit(0);
+        let b = home.git_init("root/b");
+        b.make_commit(0);
+        b.make_commit(1);
+        let config = repos::Config::with_tree((home.join("root"), Some("x:")));
+
+        let mut receiver = state(Some(config)).stream_contributions().await;
+        let mut events = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            events.push(event);
+        }
+        let [
+            ScanEvent::Repo { name: a, times: a_times, finished: 1, .. },
+            ScanEvent::Repo { name: b, times: b_times, finished: 2, .. },
+            ScanEvent::Done { metrics, dropped },
+        ] = &events[..]
+        else {
+            panic!("unexpected events: {events:?}");
+        };
+        assert!((a.as_str(), a_times.len()) == ("x:a", 1));
+        assert!((b.as_str(), b_times.len()) == ("x:b", 2));
+        assert!(metrics.repos_scanned == 2);
+        assert!(dropped.is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_identities() {
+        let identities = state(None).get_identities().await;
+        assert!(identities.emails.is_empty());
+        assert!(identities.names.is_empty());
+        assert!(identities.github == None);
+
+        let mut config = repos::Config::default();
+        config.identity.emails = vec!["me@example.com".to_owned()];
+        config.identity.names = vec!["Me".to_owned()];
+        config.identity.github = Some("me".to_owned());
+        let identities = state(Some(config)).get_identities().await;
+        assert!(identities.emails == ["me@example.com"]);
+        assert!(identities.names == ["Me"]);
+        assert!(identities.github.as_deref() == Some("me"));
+    }
+}
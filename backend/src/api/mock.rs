@@ -1,8 +1,17 @@
 //! Mock implementation of the API for testing.
 
 use super::definition::{
-    ApiBase, LocalContributions, OAuthTokenResponse, RepoYearApi,
+    ApiBase, DeviceCodeResponse, DevicePollResponse, DiffStatsContributions,
+    GitHubStatus, IdentitiesResponse, IngestError, IngestResponse,
+    LocalContributions, OAuthError, OAuthTokenResponse, ReloadError,
+    ReloadResponse, RepoYearApi, ReposResponse, ScanError, ScanResponse,
 };
+use super::sse::ScanEvent;
+use crate::agent::Report;
+use crate::repos::{ScanMetrics, ScanStatus};
+use crate::users::UserError;
+use std::collections::HashMap;
+use tokio::sync::{broadcast, mpsc};
 
 /// Mock state for testing that returns predefined responses.
 #[derive(Clone, Debug)]
@@ -13,8 +22,8 @@ pub struct MockAppState {
     pub mock_access_token: Option<String>,
     /// The refresh token to return from OAuth.
     pub mock_refresh_token: Option<String>,
-    /// Error message to return from OAuth (if Some).
-    pub mock_oauth_error: Option<String>,
+    /// Error to return from OAuth (if Some).
+    pub mock_oauth_error: Option<OAuthError>,
 }
 
 impl MockAppState {
@@ -31,7 +40,7 @@ impl MockAppState {
 
     /// Create a mock state that simulates OAuth failure.
     #[must_use]
-    pub fn with_oauth_error(error: String) -> Self {
+    pub fn with_oauth_error(error: OAuthError) -> Self {
         Self {
             health_status: "ok".to_owned(),
             mock_access_token: None,
@@ -52,30 +61,119 @@ impl ApiBase for MockAppState {
         self.health_status.clone()
     }
 
+    async fn is_ready(&self) -> bool {
+        true
+    }
+
     async fn get_version(&self) -> String {
         env!("GIT_VERSION").to_owned()
     }
 
-    async fn get_contributions(
-        &self,
-        _log: &slog::Logger,
-    ) -> LocalContributions {
+    async fn get_contributions(&self) -> LocalContributions {
         // FIXME: better mock data?
         LocalContributions::new()
     }
 
+    async fn get_unmerged_contributions(&self) -> LocalContributions {
+        LocalContributions::new()
+    }
+
+    async fn get_reflog_contributions(&self) -> LocalContributions {
+        LocalContributions::new()
+    }
+
+    async fn get_commit_cap(&self) -> Option<usize> {
+        None
+    }
+
+    async fn get_diff_stats(&self) -> DiffStatsContributions {
+        DiffStatsContributions::new()
+    }
+
+    async fn get_identity_contributions(
+        &self,
+    ) -> HashMap<String, LocalContributions> {
+        HashMap::new()
+    }
+
+    async fn scan_paths(
+        &self,
+        _paths: Vec<String>,
+    ) -> Result<ScanResponse, ScanError> {
+        Err(ScanError::NotConfigured)
+    }
+
+    async fn stream_contributions(&self) -> mpsc::UnboundedReceiver<ScanEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let _ = sender.send(ScanEvent::Done {
+            metrics: ScanMetrics::default(),
+            dropped: Vec::new(),
+        });
+        receiver
+    }
+
+    async fn subscribe_contributions(
+        &self,
+    ) -> broadcast::Receiver<Vec<String>> {
+        // Nothing ever changes.
+        broadcast::channel(1).1
+    }
+
+    async fn list_repos(&self) -> ReposResponse {
+        ReposResponse::default()
+    }
+
+    async fn get_scan_status(&self) -> ScanStatus {
+        ScanStatus::default()
+    }
+
+    async fn get_github_status(&self) -> GitHubStatus {
+        GitHubStatus::default()
+    }
+
+    async fn get_identities(&self) -> IdentitiesResponse {
+        IdentitiesResponse::default()
+    }
+
+    async fn get_user_contributions(
+        &self,
+        _user: &str,
+        _token: Option<&str>,
+    ) -> Result<LocalContributions, UserError> {
+        Err(UserError::NotFound)
+    }
+
+    async fn reload_config(
+        &self,
+        _token: Option<&str>,
+    ) -> Result<ReloadResponse, ReloadError> {
+        Err(ReloadError::Disabled)
+    }
+
+    async fn ingest(
+        &self,
+        _api_key: &str,
+        report: Report,
+    ) -> Result<IngestResponse, IngestError> {
+        report.validate().map_err(IngestError::Invalid)?;
+        Ok(IngestResponse {
+            repos: report.repos.len(),
+            commits: report.commit_count(),
+            conflicts: Vec::new(),
+        })
+    }
+
     async fn exchange_oauth_token(
         &self,
         _code: &str,
-        _log: &slog::Logger,
-    ) -> Result<OAuthTokenResponse, String> {
+    ) -> Result<OAuthTokenResponse, OAuthError> {
         if let Some(error) = &self.mock_oauth_error {
             Err(error.clone())
         } else {
             let access_token = self
                 .mock_access_token
                 .clone()
-                .ok_or_else(|| "No token configured".to_owned())?;
+                .ok_or(OAuthError::BadResponse)?;
 
             Ok(OAuthTokenResponse {
                 access_token,
@@ -86,22 +184,42 @@ impl ApiBase for MockAppState {
         }
     }
 
+    async fn start_device_flow(
+        &self,
+    ) -> Result<DeviceCodeResponse, OAuthError> {
+        Ok(DeviceCodeResponse {
+            device_code: "mock_device_code".to_owned(),
+            user_code: "ABCD-1234".to_owned(),
+            verification_uri: "https://github.com/login/device".to_owned(),
+            expires_in: 900,
+            interval: 5,
+        })
+    }
+
+    async fn poll_device_flow(
+        &self,
+        _device_code: &str,
+    ) -> Result<DevicePollResponse, OAuthError> {
+        Ok(DevicePollResponse::Complete {
+            token: self.exchange_oauth_token("mock_device_code").await?,
+        })
+    }
+
     async fn refresh_oauth_token(
         &self,
         _refresh_token: &str,
-        _log: &slog::Logger,
-    ) -> Result<OAuthTokenResponse, String> {
+    ) -> Result<OAuthTokenResponse, OAuthError> {
         if let Some(error) = &self.mock_oauth_error {
             Err(error.clone())
         } else {
             let access_token = self
                 .mock_access_token
                 .clone()
-                .ok_or_else(|| "No token configured".to_owned())?;
+                .ok_or(OAuthError::BadResponse)?;
             let refresh_token = self
                 .mock_refresh_token
                 .clone()
-                .ok_or_else(|| "No refresh token configured".to_owned())?;
+                .ok_or(OAuthError::BadResponse)?;
 
             Ok(OAuthTokenResponse {
                 access_token,
@@ -123,6 +241,8 @@ impl RepoYearApi for MockApiImpl {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use dropshot::{ErrorStatusCode, HttpError};
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_mock_health() {
@@ -134,8 +254,7 @@ mod tests {
     #[tokio::test]
     async fn test_mock_oauth_success() {
         let mock_state = MockAppState::new();
-        let log = slog::Logger::root(slog::Discard, slog::o!());
-        let result = mock_state.exchange_oauth_token("test_code", &log).await;
+        let result = mock_state.exchange_oauth_token("test_code").await;
         let response = result.unwrap();
         assert_eq!(response.access_token, "mock_token_12345");
         assert_eq!(
@@ -147,9 +266,36 @@ mod tests {
     #[tokio::test]
     async fn test_mock_oauth_failure() {
         let mock_state =
-            MockAppState::with_oauth_error("Invalid code".to_owned());
-        let log = slog::Logger::root(slog::Discard, slog::o!());
-        let result = mock_state.exchange_oauth_token("test_code", &log).await;
-        assert_eq!(result.unwrap_err(), "Invalid code");
+            MockAppState::with_oauth_error(OAuthError::from_github(
+                "bad_verification_code".to_owned(),
+                Some("Invalid code".to_owned()),
+            ));
+        let result = mock_state.exchange_oauth_token("test_code").await;
+        let error = HttpError::from(result.unwrap_err());
+        assert_eq!(error.status_code, ErrorStatusCode::BAD_REQUEST);
+        assert_eq!(error.error_code.as_deref(), Some("bad_verification_code"));
+        assert_eq!(error.external_message, "Invalid code");
+    }
+
+    #[test]
+    fn test_oauth_error_status() {
+        let status = |error: OAuthError| HttpError::from(error).status_code;
+        assert_eq!(
+            status(OAuthError::from_github(
+                "incorrect_client_credentials".to_owned(),
+                None
+            )),
+            ErrorStatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            status(OAuthError::BadResponse),
+            ErrorStatusCode::BAD_GATEWAY
+        );
+
+        let error = HttpError::from(OAuthError::Unavailable {
+            retry_after: Duration::from_millis(1_500),
+        });
+        assert_eq!(error.status_code, ErrorStatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.headers.unwrap()[http::header::RETRY_AFTER], "2");
     }
 }
@@ -0,0 +1,66 @@
+//! Track whether the server has finished warming up.
+
+use tokio::sync::watch;
+
+/// Whether the initial scan has finished, so that requests for contributions
+/// won’t have to wait for a full scan.
+///
+/// The default is ready, for servers that have nothing to warm up.
+#[derive(Debug)]
+pub struct Readiness(watch::Sender<bool>);
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self(watch::Sender::new(true))
+    }
+}
+
+impl Readiness {
+    /// Start out not ready.
+    #[must_use]
+    pub fn warming_up() -> Self {
+        Self(watch::Sender::new(false))
+    }
+
+    /// Check if warmup has finished.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Mark warmup as finished, waking anything waiting for it.
+    pub fn set_ready(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// Wait until warmup has finished.
+    pub async fn wait(&self) {
+        // This can’t fail, since `self` holds the sender.
+        let _ = self.0.subscribe().wait_for(|ready| *ready).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn wait_until_ready() {
+        assert!(Readiness::default().is_ready());
+
+        let readiness = Arc::new(Readiness::warming_up());
+        assert!(!readiness.is_ready());
+        let waiter = tokio::spawn({
+            let readiness = readiness.clone();
+            async move { readiness.wait().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        readiness.set_ready();
+        waiter.await.unwrap();
+        assert!(readiness.is_ready());
+    }
+}
@@ -0,0 +1,161 @@
+//! Reload configuration files while the server is running.
+
+use crate::repos;
+use crate::users::Users;
+use tokio::sync::watch;
+
+/// Reads the configuration files again.
+///
+/// This is set up at startup, since it needs to know which files were used
+/// and which command line options override them.
+pub type ConfigLoader =
+    dyn Fn() -> anyhow::Result<ReloadableConfig> + Send + Sync;
+
+/// Configuration that can change without restarting the server.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReloadableConfig {
+    /// Configuration for repository scanning.
+    pub scan_config: Option<repos::Config>,
+
+    /// Users with their own repositories to scan.
+    pub users: Users,
+}
+
+impl ReloadableConfig {
+    /// Names of the settings that differ between `self` and `new`.
+    #[must_use]
+    pub fn changes(&self, new: &Self) -> Vec<String> {
+        let mut changes = Vec::new();
+        match (&self.scan_config, &new.scan_config) {
+            (Some(old), Some(new)) => {
+                // Destructure so that new settings can’t be forgotten here.
+                let repos::Config {
+                    repos,
+                    single_repos,
+                    deny,
+                    identity,
+                    identities,
+                    cache_dir,
+                    commit_limit,
+                    ls_remote_head,
+                    unmerged,
+                    duplicate_patches,
+                    reflog,
+                    commit_date,
+                    first_parent,
+                    merges,
+                    since,
+                    until,
+                    commit_cap,
+                    skip_remotes,
+                    paths,
+                    diff_stats,
+                    scan,
+                } = old;
+                let mut check = |name: &str, changed: bool| {
+                    if changed {
+                        changes.push(name.to_owned());
+                    }
+                };
+                check(
+                    "repos",
+                    *repos != new.repos || *single_repos != new.single_repos,
+                );
+                check("deny", *deny != new.deny);
+                check("identity", *identity != new.identity);
+                check("identities", *identities != new.identities);
+                check("cache_dir", *cache_dir != new.cache_dir);
+                check("commit_limit", *commit_limit != new.commit_limit);
+                check("ls_remote_head", *ls_remote_head != new.ls_remote_head);
+                check("unmerged", *unmerged != new.unmerged);
+                check(
+                    "duplicate_patches",
+                    *duplicate_patches != new.duplicate_patches,
+                );
+                check("reflog", *reflog != new.reflog);
+                check("commit_date", *commit_date != new.commit_date);
+                check("first_parent", *first_parent != new.first_parent);
+                check("merges", *merges != new.merges);
+                check("since", *since != new.since);
+                check("until", *until != new.until);
+                check("commit_cap", *commit_cap != new.commit_cap);
+                check("skip_remotes", *skip_remotes != new.skip_remotes);
+                check("paths", *paths != new.paths);
+                check("diff_stats", *diff_stats != new.diff_stats);
+                check("scan", *scan != new.scan);
+            }
+            (None, None) => {}
+            _ => changes.push("scan_config".to_owned()),
+        }
+        if self.users != new.users {
+            changes.push("users".to_owned());
+        }
+        changes
+    }
+}
+
+/// The scan configuration, which can be replaced while the server runs.
+///
+/// The default is no configuration.
+#[derive(Debug)]
+pub struct LiveConfig(watch::Sender<Option<repos::Config>>);
+
+impl Default for LiveConfig {
+    fn default() -> Self {
+        Self(watch::Sender::new(None))
+    }
+}
+
+impl From<Option<repos::Config>> for LiveConfig {
+    fn from(config: Option<repos::Config>) -> Self {
+        Self(watch::Sender::new(config))
+    }
+}
+
+impl LiveConfig {
+    /// Get a copy of the current configuration.
+    #[must_use]
+    pub fn get(&self) -> Option<repos::Config> {
+        self.0.borrow().clone()
+    }
+
+    /// Check if there is a configuration.
+    #[must_use]
+    pub fn is_some(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    /// Replace the configuration, waking anything subscribed to it.
+    pub fn replace(&self, config: Option<repos::Config>) {
+        self.0.send_replace(config);
+    }
+
+    /// Get a receiver that is notified when the configuration is replaced.
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<Option<repos::Config>> {
+        self.0.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repos::TreeConfig;
+    use assert2::assert;
+
+    #[test]
+    fn changes() {
+        let old = ReloadableConfig {
+            scan_config: Some(repos::Config::with_tree(("/a", None))),
+            users: Users::default(),
+        };
+        assert!(old.changes(&old).is_empty());
+        assert!(old.changes(&ReloadableConfig::default()) == ["scan_config"]);
+
+        let mut new = old.clone();
+        let config = new.scan_config.as_mut().unwrap();
+        config.repos.push(TreeConfig::from(("/b", None)));
+        config.unmerged = true;
+        assert!(old.changes(&new) == ["repos", "unmerged"]);
+    }
+}
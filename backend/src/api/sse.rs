@@ -0,0 +1,123 @@
+//! Server-sent events for scan progress.
+//!
+//! See the [`EventSource` documentation][mdn] for the format.
+//!
+//! [mdn]: https://developer.mozilla.org/en-US/docs/Web/API/Server-sent_events/Using_server-sent_events
+
+use crate::repos::{RepoOutcome, ScanMetrics};
+use bytes::Bytes;
+use http_body::Frame;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// Progress from a scan streamed by `/api/contributions/stream`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ScanEvent {
+    /// A repository finished scanning. Sent as a `repo` event.
+    Repo {
+        /// Name of the repository.
+        name: String,
+
+        /// Commit times (seconds since epoch). Empty if the repository
+        /// couldn’t be scanned.
+        times: Vec<i64>,
+
+        /// What happened to the repository.
+        outcome: RepoOutcome,
+
+        /// Number of repositories finished so far, including this one.
+        finished: usize,
+    },
+
+    /// The scan finished. Sent as a `done` event, after which the stream
+    /// ends.
+    Done {
+        /// Counts and timing from the scan.
+        metrics: ScanMetrics,
+
+        /// Names of repositories that were sent earlier, but were then left
+        /// out because they’re clones of other repositories.
+        dropped: Vec<String>,
+    },
+}
+
+impl ScanEvent {
+    /// Get the event name.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Repo { .. } => "repo",
+            Self::Done { .. } => "done",
+        }
+    }
+
+    /// Format the event as a server-sent event with JSON data.
+    #[must_use]
+    pub fn to_bytes(&self) -> Bytes {
+        // Serializing to a string can only fail for maps with non-string
+        // keys, which this doesn’t have.
+        let data = serde_json::to_string(self).unwrap_or_default();
+        format!("event: {}\ndata: {data}\n\n", self.name()).into()
+    }
+}
+
+/// A `text/event-stream` response body that sends events as they arrive.
+///
+/// The body ends when the sender is dropped.
+#[derive(Debug)]
+pub struct EventStream(pub mpsc::UnboundedReceiver<ScanEvent>);
+
+impl http_body::Body for EventStream {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        self.0
+            .poll_recv(cx)
+            .map(|event| event.map(|event| Ok(Frame::data(event.to_bytes()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn stream_events() {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        sender
+            .send(ScanEvent::Repo {
+                name: "repo".to_owned(),
+                times: vec![1, 2],
+                outcome: RepoOutcome::Scanned,
+                finished: 1,
+            })
+            .unwrap();
+        sender
+            .send(ScanEvent::Done {
+                metrics: ScanMetrics::default(),
+                dropped: Vec::new(),
+            })
+            .unwrap();
+        drop(sender);
+
+        let body = EventStream(receiver).collect().await.unwrap().to_bytes();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.starts_with(
+            "event: repo\n\
+            data: {\"name\":\"repo\",\"times\":[1,2],\
+            \"outcome\":{\"outcome\":\"scanned\"},\"finished\":1}\n\n\
+            event: done\ndata: {\"metrics\":{"
+        ));
+        assert!(body.ends_with("\"dropped\":[]}\n\n"));
+    }
+}
@@ -0,0 +1,595 @@
+//! Back up and restore the server’s state.
+//!
+//! A backup is a gzipped tar archive containing:
+//!
+//! - `manifest.json`: What’s in the backup. See [`Manifest`].
+//! - `history/`: Scan snapshots. See [`History`](crate::history::History).
+//! - `cache/`: The [cache directory](crate::repos::CacheDir), unless it was
+//!   left out.
+//! - `reports/`: Reports from agents. See
+//!   [`ReportStore`](crate::agent::ReportStore).
+//! - `config/`: The scan configuration files, with their original names. If two
+//!   have the same name, e.g. `/etc/repoyear/config.toml` and
+//!   `~/.config/repoyear/config.toml`, they’re in numbered subdirectories in
+//!   the order they’re merged.
+//! - `secrets.age`: The users configuration and agent keys, encrypted with a
+//!   passphrase by [age](https://age-encryption.org/). This can be decrypted
+//!   with the `age` command line tool.
+
+use crate::agent::AgentKey;
+use age::secrecy::SecretString;
+use anyhow::{Context, anyhow, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Current version of the backup format.
+const VERSION: u32 = 1;
+
+/// Name of the manifest in the archive.
+const MANIFEST: &str = "manifest.json";
+
+/// Directory for history snapshots in the archive.
+const HISTORY: &str = "history";
+
+/// Directory for the cache in the archive.
+const CACHE: &str = "cache";
+
+/// Directory for agent reports in the archive.
+const REPORTS: &str = "reports";
+
+/// Directory for the scan configuration files in the archive.
+const SCAN_CONFIG: &str = "config";
+
+/// Name of the encrypted secrets in the archive.
+const SECRETS: &str = "secrets.age";
+
+/// File name for the restored users configuration.
+const USERS_CONFIG_FILE: &str = "users.toml";
+
+/// File name for the restored agent keys.
+const AGENT_KEYS_FILE: &str = "agent-keys.env";
+
+/// What’s in a backup.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Manifest {
+    /// Version of the backup format.
+    pub version: u32,
+
+    /// When the backup was made (seconds since epoch).
+    pub created_at: i64,
+
+    /// Number of history snapshots.
+    pub history_files: usize,
+
+    /// Number of files from the cache directory.
+    pub cache_files: usize,
+
+    /// Number of agent reports.
+    pub report_files: usize,
+
+    /// Where the scan configuration files came from, in the order they’re
+    /// merged.
+    pub scan_configs: Vec<PathBuf>,
+
+    /// Whether the users configuration is included in the secrets.
+    pub users_config: bool,
+
+    /// Number of agent keys in the secrets.
+    pub agent_keys: usize,
+}
+
+/// Secrets stored encrypted in a backup.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Secrets {
+    /// Contents of the users configuration, which includes user tokens.
+    users_config: Option<String>,
+
+    /// Agent keys as `NAME=KEY`.
+    agent_keys: Vec<String>,
+}
+
+impl Secrets {
+    /// Check if there’s anything to store.
+    const fn is_empty(&self) -> bool {
+        self.users_config.is_none() && self.agent_keys.is_empty()
+    }
+}
+
+/// What to back up.
+#[derive(Clone, Debug, Default)]
+pub struct Sources {
+    /// History directory, if any.
+    pub history: Option<PathBuf>,
+
+    /// Cache directory, if it should be included.
+    pub cache: Option<PathBuf>,
+
+    /// Agent reports directory, if any.
+    pub reports: Option<PathBuf>,
+
+    /// Scan configuration files, in the order they’re merged. See
+    /// [`Config::layers()`](crate::repos::Config::layers).
+    pub scan_configs: Vec<PathBuf>,
+
+    /// Users configuration file, if any.
+    pub users_config: Option<PathBuf>,
+
+    /// Agent keys.
+    pub agent_keys: Vec<AgentKey>,
+}
+
+/// Where to restore a backup to.
+#[derive(Clone, Debug, Default)]
+pub struct Destinations {
+    /// History directory. Snapshots are skipped if this is `None`.
+    pub history: Option<PathBuf>,
+
+    /// Cache directory. The cache is skipped if this is `None`.
+    pub cache: Option<PathBuf>,
+
+    /// Agent reports directory. Reports are skipped if this is `None`.
+    pub reports: Option<PathBuf>,
+
+    /// Directory to write configuration files and agent keys to.
+    pub config_dir: PathBuf,
+
+    /// Whether to overwrite configuration files that already exist.
+    pub overwrite: bool,
+}
+
+/// What was restored from a backup.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Restored {
+    /// Number of history snapshots restored.
+    pub history_files: usize,
+
+    /// Number of cache files restored.
+    pub cache_files: usize,
+
+    /// Number of agent reports restored.
+    pub report_files: usize,
+
+    /// Configuration files written.
+    pub config_files: Vec<PathBuf>,
+}
+
+/// Write a backup of `sources` to `output`.
+///
+/// `passphrase` is required if there are secrets to back up, that is, a users
+/// configuration or agent keys.
+///
+/// # Errors
+///
+/// Returns an error if there are secrets but no passphrase, or if reading the
+/// sources or writing the backup fails.
+pub fn create<W: Write>(
+    sources: &Sources,
+    passphrase: Option<&SecretString>,
+    output: W,
+) -> anyhow::Result<Manifest> {
+    let secrets = Secrets {
+        users_config: sources
+            .users_config
+            .as_ref()
+            .map(|path| {
+                fs::read_to_string(path)
+                    .with_context(|| format!("Could not read {path:?}"))
+            })
+            .transpose()?,
+        agent_keys: sources
+            .agent_keys
+            .iter()
+            .map(|key| format!("{}={}", key.agent, key.key))
+            .collect(),
+    };
+    let encrypted_secrets = if secrets.is_empty() {
+        None
+    } else {
+        let passphrase = passphrase.ok_or_else(|| {
+            anyhow!(
+                "A passphrase is required to back up user tokens and agent keys"
+            )
+        })?;
+        Some(age::encrypt(
+            &age::scrypt::Recipient::new(passphrase.clone()),
+            &serde_json::to_vec(&secrets)?,
+        )?)
+    };
+
+    let history_files = list_files(sources.history.as_deref())?;
+    let cache_files = list_files(sources.cache.as_deref())?;
+    let report_files = list_files(sources.reports.as_deref())?;
+    let manifest = Manifest {
+        version: VERSION,
+        created_at: chrono::Utc::now().timestamp(),
+        history_files: history_files.len(),
+        cache_files: cache_files.len(),
+        report_files: report_files.len(),
+        scan_configs: sources.scan_configs.clone(),
+        users_config: secrets.users_config.is_some(),
+        agent_keys: secrets.agent_keys.len(),
+    };
+
+    let mut archive =
+        tar::Builder::new(GzEncoder::new(output, Compression::default()));
+    append_data(
+        &mut archive,
+        MANIFEST,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    for (prefix, root, files) in [
+        (HISTORY, &sources.history, history_files),
+        (CACHE, &sources.cache, cache_files),
+        (REPORTS, &sources.reports, report_files),
+    ] {
+        let Some(root) = root else { continue };
+        for file in files {
+            archive.append_path_with_name(
+                root.join(&file),
+                Path::new(prefix).join(file),
+            )?;
+        }
+    }
+    for (path, name) in sources
+        .scan_configs
+        .iter()
+        .zip(config_names(&sources.scan_configs)?)
+    {
+        let contents = fs::read(path)
+            .with_context(|| format!("Could not read {path:?}"))?;
+        append_data(&mut archive, &format!("{SCAN_CONFIG}/{name}"), &contents)?;
+    }
+    if let Some(encrypted) = encrypted_secrets {
+        append_data(&mut archive, SECRETS, &encrypted)?;
+    }
+    archive.into_inner()?.finish()?;
+    Ok(manifest)
+}
+
+/// Restore a backup from `input`.
+///
+/// `passphrase` is required if the backup contains secrets.
+///
+/// # Errors
+///
+/// Returns an error if the backup is invalid, if a configuration file already
+/// exists and [`overwrite`](Destinations::overwrite) isn’t set, if the
+/// passphrase is missing or wrong, or if writing fails. Files restored before
+/// the error are left in place.
+pub fn restore<R: Read>(
+    input: R,
+    destinations: &Destinations,
+    passphrase: Option<&SecretString>,
+) -> anyhow::Result<Restored> {
+    let mut archive = tar::Archive::new(GzDecoder::new(input));
+    let mut restored = Restored::default();
+    let mut manifest: Option<Manifest> = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        // Links and devices would be written as plain files, or worse.
+        let kind = entry.header().entry_type();
+        if !kind.is_file() {
+            bail!("Unexpected {kind:?} entry in backup: {path:?}");
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+
+        if path == Path::new(MANIFEST) {
+            let read: Manifest = serde_json::from_slice(&contents)?;
+            if read.version != VERSION {
+                bail!("Unsupported backup version {}", read.version);
+            }
+            if passphrase.is_none()
+                && (read.users_config || read.agent_keys > 0)
+            {
+                bail!(
+                    "A passphrase is required to restore user tokens and agent \
+                    keys"
+                );
+            }
+            manifest = Some(read);
+            continue;
+        }
+        if manifest.is_none() {
+            bail!("Not a backup: {MANIFEST} must come first");
+        }
+
+        if let Ok(relative) = path.strip_prefix(HISTORY) {
+            if let Some(root) = &destinations.history {
+                write_file(&root.join(safe_relative(relative)?), &contents)?;
+                restored.history_files =
+                    restored.history_files.saturating_add(1);
+            }
+        } else if let Ok(relative) = path.strip_prefix(CACHE) {
+            if let Some(root) = &destinations.cache {
+                write_file(&root.join(safe_relative(relative)?), &contents)?;
+                restored.cache_files = restored.cache_files.saturating_add(1);
+            }
+        } else if let Ok(relative) = path.strip_prefix(REPORTS) {
+            if let Some(root) = &destinations.reports {
+                write_file(&root.join(safe_relative(relative)?), &contents)?;
+                restored.report_files = restored.report_files.saturating_add(1);
+            }
+        } else if let Ok(relative) = path.strip_prefix(SCAN_CONFIG) {
+            let path = destinations.config_dir.join(safe_relative(relative)?);
+            write_config(&path, &contents, destinations.overwrite)?;
+            restored.config_files.push(path);
+        } else if path == Path::new(SECRETS) {
+            let passphrase = passphrase.ok_or_else(|| {
+                anyhow!("A passphrase is required to restore user tokens and agent keys")
+            })?;
+            let secrets: Secrets = serde_json::from_slice(&age::decrypt(
+                &age::scrypt::Identity::new(passphrase.clone()),
+                &contents,
+            )?)?;
+            restore_secrets(&secrets, destinations, &mut restored)?;
+        } else {
+            bail!("Unexpected file in backup: {path:?}");
+        }
+    }
+    if manifest.is_none() {
+        bail!("Not a backup: {MANIFEST} is missing");
+    }
+    Ok(restored)
+}
+
+/// Write the users configuration and agent keys.
+fn restore_secrets(
+    secrets: &Secrets,
+    destinations: &Destinations,
+    restored: &mut Restored,
+) -> anyhow::Result<()> {
+    if let Some(users_config) = &secrets.users_config {
+        let path = destinations.config_dir.join(USERS_CONFIG_FILE);
+        write_config(&path, users_config.as_bytes(), destinations.overwrite)?;
+        restored.config_files.push(path);
+    }
+    if !secrets.agent_keys.is_empty() {
+        let path = destinations.config_dir.join(AGENT_KEYS_FILE);
+        let contents = format!("AGENT_KEYS={}\n", secrets.agent_keys.join(","));
+        write_config(&path, contents.as_bytes(), destinations.overwrite)?;
+        restored.config_files.push(path);
+    }
+    Ok(())
+}
+
+/// Get the names to store scan configuration files under in the archive.
+///
+/// Files keep their names unless another file has the same one, in which
+/// case they’re put in a directory named for their position in `paths`.
+fn config_names(paths: &[PathBuf]) -> anyhow::Result<Vec<String>> {
+    let names = paths
+        .iter()
+        .map(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| anyhow!("Unsupported file name: {path:?}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            if names.iter().filter(|other| *other == name).count() > 1 {
+                format!("{i}/{name}")
+            } else {
+                name.clone()
+            }
+        })
+        .collect())
+}
+
+/// List files under `root`, relative to `root`, in a stable order.
+///
+/// Returns nothing if `root` is `None` or doesn’t exist.
+fn list_files(root: Option<&Path>) -> anyhow::Result<Vec<PathBuf>> {
+    let Some(root) = root.filter(|root| root.exists()) else {
+        return Ok(Vec::new());
+    };
+    let mut files = Vec::new();
+    for entry in WalkDir::new(root).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_dir() {
+            files.push(entry.path().strip_prefix(root)?.to_owned());
+        }
+    }
+    Ok(files)
+}
+
+/// Add a file with `contents` to `archive`.
+fn append_data<W: Write>(
+    archive: &mut tar::Builder<W>,
+    path: &str,
+    contents: &[u8],
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(chrono::Utc::now().timestamp().try_into().unwrap_or(0));
+    header.set_cksum();
+    archive.append_data(&mut header, path, contents)
+}
+
+/// Make sure a path from an archive can’t escape the directory it’s restored
+/// to.
+fn safe_relative(path: &Path) -> anyhow::Result<&Path> {
+    if path.as_os_str().is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("Unsafe path in backup: {path:?}");
+    }
+    Ok(path)
+}
+
+/// Write a file, creating its directory if needed.
+fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+/// Write a configuration file that only the owner can read.
+fn write_config(
+    path: &Path,
+    contents: &[u8],
+    overwrite: bool,
+) -> anyhow::Result<()> {
+    if !overwrite && path.exists() {
+        bail!("{path:?} already exists");
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::File::options();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use testdir::testdir;
+
+    fn passphrase() -> SecretString {
+        SecretString::from("correct horse".to_owned())
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = testdir!();
+        fs::create_dir_all(dir.join("history")).unwrap();
+        fs::write(dir.join("history/1.json"), "{}").unwrap();
+        fs::create_dir_all(dir.join("cache/scans")).unwrap();
+        fs::write(dir.join("cache/scans/a.json"), "a").unwrap();
+        fs::create_dir_all(dir.join("reports")).unwrap();
+        fs::write(dir.join("reports/6c6170746f70.json"), "{}").unwrap();
+        fs::create_dir_all(dir.join("etc")).unwrap();
+        fs::write(dir.join("etc/config.toml"), "commit_cap = 1\n").unwrap();
+        fs::create_dir_all(dir.join("xdg")).unwrap();
+        fs::write(dir.join("xdg/config.toml"), "commit_cap = 2\n").unwrap();
+        fs::write(dir.join("scan.yaml"), "repos: []\n").unwrap();
+        fs::write(dir.join("users.toml"), "token = \"secret\"\n").unwrap();
+        let sources = Sources {
+            history: Some(dir.join("history")),
+            cache: Some(dir.join("cache")),
+            reports: Some(dir.join("reports")),
+            scan_configs: vec![
+                dir.join("etc/config.toml"),
+                dir.join("xdg/config.toml"),
+                dir.join("scan.yaml"),
+            ],
+            users_config: Some(dir.join("users.toml")),
+            agent_keys: vec!["laptop=key".parse().unwrap()],
+        };
+
+        assert!(let Err(_) = create(&sources, None, Vec::new()));
+        let mut archive = Vec::new();
+        let manifest =
+            create(&sources, Some(&passphrase()), &mut archive).unwrap();
+        assert!(manifest.history_files == 1);
+        assert!(manifest.cache_files == 1);
+        assert!(manifest.report_files == 1);
+        assert!(manifest.scan_configs == sources.scan_configs);
+        assert!(manifest.agent_keys == 1);
+        assert!(!archive.windows(6).any(|window| window == b"secret"));
+
+        let restore_dir = dir.join("restored");
+        let destinations = Destinations {
+            history: Some(restore_dir.join("history")),
+            cache: None,
+            reports: Some(restore_dir.join("reports")),
+            config_dir: restore_dir.join("config"),
+            overwrite: false,
+        };
+        let restored =
+            restore(archive.as_slice(), &destinations, Some(&passphrase()))
+                .unwrap();
+        assert!(restored.history_files == 1);
+        assert!(restored.cache_files == 0);
+        assert!(restored.report_files == 1);
+        assert!(restored.config_files.len() == 5);
+        assert!(
+            fs::read_to_string(restore_dir.join("history/1.json")).unwrap()
+                == "{}"
+        );
+        assert!(restore_dir.join("reports/6c6170746f70.json").is_file());
+        for (path, contents) in [
+            ("config/0/config.toml", "commit_cap = 1\n"),
+            ("config/1/config.toml", "commit_cap = 2\n"),
+            ("config/scan.yaml", "repos: []\n"),
+        ] {
+            assert!(
+                fs::read_to_string(restore_dir.join(path)).unwrap() == contents
+            );
+        }
+        assert!(
+            fs::read_to_string(restore_dir.join("config/users.toml")).unwrap()
+                == "token = \"secret\"\n"
+        );
+        assert!(
+            fs::read_to_string(restore_dir.join("config/agent-keys.env"))
+                .unwrap()
+                == "AGENT_KEYS=laptop=key\n"
+        );
+
+        // Configuration files aren’t overwritten by default.
+        assert!(let Err(_) = restore(archive.as_slice(), &destinations, Some(&passphrase())));
+    }
+
+    #[test]
+    fn reject_links() {
+        let dir = testdir!();
+        let mut archive = Vec::new();
+        create(&Sources::default(), None, &mut archive).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            Vec::new(),
+            Compression::default(),
+        ));
+        let mut entries = tar::Archive::new(GzDecoder::new(archive.as_slice()));
+        for entry in entries.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut header = entry.header().clone();
+            builder.append(&header, &mut entry).unwrap();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder
+                .append_link(&mut header, "history/1.json", "/etc/passwd")
+                .unwrap();
+        }
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        let destinations = Destinations {
+            history: Some(dir.join("history")),
+            config_dir: dir.join("config"),
+            ..Destinations::default()
+        };
+        let error = restore(archive.as_slice(), &destinations, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Unexpected Symlink entry in backup"));
+        assert!(!dir.join("history/1.json").exists());
+    }
+
+    #[test]
+    fn unsafe_paths() {
+        assert!(let Ok(_) = safe_relative(Path::new("a/b.json")));
+        assert!(let Err(_) = safe_relative(Path::new("../a")));
+        assert!(let Err(_) = safe_relative(Path::new("/etc/passwd")));
+        assert!(let Err(_) = safe_relative(Path::new("")));
+    }
+}
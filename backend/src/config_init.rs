@@ -0,0 +1,385 @@
+//! Interactive `config init` wizard.
+
+use crate::params::ConfigInitParams;
+use anyhow::bail;
+use repoyear_backend::repos::{Identity, TreeConfig};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Directories under the home directory where repositories are often kept.
+const CANDIDATES: &[&str] = &[
+    "src",
+    "source",
+    "git",
+    "code",
+    "dev",
+    "projects",
+    "Projects",
+    "repos",
+    "work",
+    "workspace",
+    "Developer",
+];
+
+/// Ask some questions and write a starter configuration file.
+///
+/// # Errors
+///
+/// Returns an error if the file already exists (unless `--force` was passed),
+/// if no directories were entered, or if reading or writing fails.
+pub fn run(init_params: &ConfigInitParams) -> anyhow::Result<()> {
+    let path = &init_params.config;
+    if !init_params.force && path.exists() {
+        bail!(
+            "{} already exists; pass --force to overwrite",
+            path.display()
+        );
+    }
+
+    let answers = interview(&mut Prompt {
+        input: io::stdin().lock(),
+        output: io::stdout(),
+    })?;
+    fs::write(path, answers.render())?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Ask the questions.
+///
+/// # Errors
+///
+/// Returns an error if no directories were entered, or if reading or writing
+/// fails.
+fn interview<R: BufRead, W: Write>(
+    prompt: &mut Prompt<R, W>,
+) -> anyhow::Result<Answers> {
+    let found = std::env::home_dir()
+        .map(|home| probe_roots(&home))
+        .unwrap_or_default();
+    if found.is_empty() {
+        prompt.say("Didn’t find any repositories in the usual places.\n")?;
+    } else {
+        prompt.say("Found repositories in:\n")?;
+        for (root, count) in &found {
+            prompt.say(&format!("  {} ({count})\n", root.display()))?;
+        }
+    }
+
+    let default_roots = found
+        .iter()
+        .map(|(root, _)| root.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let roots: Vec<PathBuf> = prompt
+        .ask(
+            "Where are your repositories? (separate with commas)",
+            &default_roots,
+        )?
+        .split(',')
+        .map(str::trim)
+        .filter(|root| !root.is_empty())
+        .map(std::path::absolute)
+        .collect::<io::Result<_>>()?;
+    if roots.is_empty() {
+        bail!("No directories to scan");
+    }
+
+    let mut answers = Answers {
+        trees: roots
+            .into_iter()
+            .map(|root| Tree { root, label: None })
+            .collect(),
+        identity: Identity::detect().unwrap_or_default(),
+    };
+
+    if prompt.confirm("Split repositories into work and personal?", false)? {
+        for tree in &mut answers.trees {
+            let guess = if tree.root.to_string_lossy().contains("work") {
+                "work"
+            } else {
+                "personal"
+            };
+            let label = prompt.ask(
+                &format!("Is {} work or personal?", tree.root.display()),
+                guess,
+            )?;
+            tree.label = Some(format!("{label}/"));
+        }
+    } else if prompt.confirm("Hide local paths in repository names?", true)? {
+        for tree in &mut answers.trees {
+            tree.label = Some(default_label(&tree.root));
+        }
+    }
+
+    let emails = prompt.ask(
+        "Which emails do you commit as? (separate with commas)",
+        &answers.identity.emails.join(", "),
+    )?;
+    answers.identity.emails = emails
+        .split(',')
+        .map(str::trim)
+        .filter(|email| !email.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    Ok(answers)
+}
+
+/// Find directories under `home` that contain repositories.
+///
+/// Returns the directories from [`CANDIDATES`] that exist, along with the
+/// number of repositories in each.
+fn probe_roots(home: &Path) -> Vec<(PathBuf, usize)> {
+    CANDIDATES
+        .iter()
+        .map(|name| home.join(name))
+        .filter(|root| root.is_dir())
+        .map(|root| {
+            let count = TreeConfig::from(root.as_path())
+                .repo_iter()
+                .flatten()
+                .count();
+            (root, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+/// Prefix to hide the local path of `root` in names, e.g. `src/`.
+fn default_label(root: &Path) -> String {
+    root.file_name().map_or_else(
+        || "repos/".to_owned(),
+        |name| format!("{}/", name.to_string_lossy()),
+    )
+}
+
+/// Answers to the wizard’s questions.
+#[derive(Debug)]
+struct Answers {
+    /// Directory trees to scan.
+    trees: Vec<Tree>,
+
+    /// Who to count commits for.
+    identity: Identity,
+}
+
+/// A directory tree to scan.
+#[derive(Debug)]
+struct Tree {
+    /// Path under which to look for repos.
+    root: PathBuf,
+
+    /// What to replace `root` with in names, if anything.
+    label: Option<String>,
+}
+
+impl Answers {
+    /// Write a commented configuration file.
+    fn render(&self) -> String {
+        let mut output = String::from(
+            "# RepoYear scan configuration.\n\
+            #\n\
+            # Use with `repoyear-backend scan` or `serve --scan-config`.\n\n\
+            # Ask `origin` for its default branch when the repository doesn’t\n\
+            # know it.\n\
+            # ls_remote_head = true\n\n\
+            # Also report commits on local branches that aren’t merged.\n\
+            # unmerged = true\n\n\
+            # Don’t count unmerged commits twice if they were rebased or\n\
+            # cherry-picked onto another branch.\n\
+            # duplicate_patches = \"skip\"\n\n\
+            # Also report activity from the `HEAD` reflog, e.g. commits that\n\
+            # were amended or rebased away.\n\
+            # reflog = true\n\n\
+            # Count commits on the date they were last rewritten, e.g. by a\n\
+            # rebase, rather than the date they were written.\n\
+            # commit_date = \"committer\"\n\n\
+            # Only count commits in a range of dates.\n\
+            # since = \"2024-01-01\"\n\
+            # until = \"2024-12-31\"\n\n\
+            # Only count the newest commits in each repository.\n\
+            # commit_cap = 10000\n\n\
+            # Only count commits that haven’t been pushed to remotes matching\n\
+            # these patterns.\n\
+            # skip_remotes = [\"github.com/*\"]\n\n\
+            # Directory trees to search for repositories. Only commits that\n\
+            # haven’t been pushed to GitHub are counted, since GitHub already\n\
+            # counts the rest.\n",
+        );
+        for tree in &self.trees {
+            output.push_str("\n[[repos]]\n");
+            let _ = writeln!(
+                output,
+                "root = {}",
+                quote(&tree.root.to_string_lossy())
+            );
+            match &tree.label {
+                Some(label) => {
+                    output.push_str(
+                        "# Replace the local path in names, e.g. `work/repo`.\n",
+                    );
+                    let _ = writeln!(output, "replace_root = {}", quote(label));
+                }
+                None => output.push_str(
+                    "# Uncomment to hide the local path in names.\n\
+                    # replace_root = \"repos/\"\n",
+                ),
+            }
+        }
+
+        output.push_str(
+            "\n# Who to count commits for. If this is empty, `user.email` and\n\
+            # `user.name` from your global git configuration are used.\n\
+            [identity]\n",
+        );
+        let emails: Vec<_> = self
+            .identity
+            .emails
+            .iter()
+            .map(|email| quote(email))
+            .collect();
+        let _ = writeln!(output, "emails = [{}]", emails.join(", "));
+        if !self.identity.names.is_empty() {
+            let names: Vec<_> =
+                self.identity.names.iter().map(|name| quote(name)).collect();
+            let _ = writeln!(output, "names = [{}]", names.join(", "));
+        }
+        output.push_str(
+            "# Also count commits by the email and name configured in each\n\
+            # repository.\n\
+            # repo_config = true\n",
+        );
+        output
+    }
+}
+
+/// Format a string as a TOML value.
+fn quote(value: &str) -> String {
+    toml::Value::String(value.to_owned()).to_string()
+}
+
+/// Ask questions on a terminal.
+struct Prompt<R, W> {
+    /// Where answers come from.
+    input: R,
+
+    /// Where questions go.
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    /// Print a message.
+    fn say(&mut self, message: &str) -> io::Result<()> {
+        self.output.write_all(message.as_bytes())
+    }
+
+    /// Ask a question. An empty answer, or end of input, means `default`.
+    fn ask(&mut self, question: &str, default: &str) -> io::Result<String> {
+        if default.is_empty() {
+            write!(self.output, "{question} ")?;
+        } else {
+            write!(self.output, "{question} [{default}] ")?;
+        }
+        self.output.flush()?;
+
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            // End of input; finish the line.
+            writeln!(self.output)?;
+        }
+        let answer = answer.trim();
+        Ok(if answer.is_empty() { default } else { answer }.to_owned())
+    }
+
+    /// Ask a yes or no question.
+    fn confirm(&mut self, question: &str, default: bool) -> io::Result<bool> {
+        let choices = if default { "Y/n" } else { "y/N" };
+        loop {
+            let answer = self.ask(&format!("{question} ({choices})"), "")?;
+            match answer.to_lowercase().as_str() {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => self.say("Please answer yes or no.\n")?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use repoyear_backend::repos::{CommitDate, Config};
+    use testdir::testdir;
+
+    #[test]
+    fn prompt_defaults() {
+        let mut prompt = Prompt {
+            input: io::Cursor::new("\nanswer\nmaybe\nn\n"),
+            output: Vec::new(),
+        };
+        assert!(prompt.ask("Q?", "default").unwrap() == "default");
+        assert!(prompt.ask("Q?", "default").unwrap() == "answer");
+        assert!(!prompt.confirm("Ok?", true).unwrap());
+        // End of input.
+        assert!(prompt.confirm("Ok?", true).unwrap());
+        assert!(
+            String::from_utf8(prompt.output).unwrap()
+                == "Q? [default] Q? [default] Ok? (Y/n) Please answer yes or \
+                no.\nOk? (Y/n) Ok? (Y/n) \n"
+        );
+    }
+
+    #[test]
+    fn probe() {
+        let home = testdir!();
+        git2::Repository::init(home.join("src/a")).unwrap();
+        git2::Repository::init(home.join("src/b/c")).unwrap();
+        fs::create_dir_all(home.join("code/empty")).unwrap();
+        assert!(probe_roots(&home) == [(home.join("src"), 2)]);
+    }
+
+    #[test]
+    fn render_parses() {
+        let answers = Answers {
+            trees: vec![
+                Tree { root: PathBuf::from("/home/me/work"), label: None },
+                Tree {
+                    root: PathBuf::from("/home/me/src \"quoted\""),
+                    label: Some("personal/".to_owned()),
+                },
+            ],
+            identity: Identity {
+                emails: vec!["me@example.com".to_owned()],
+                ..Identity::default()
+            },
+        };
+        let config = Config::parse(&answers.render()).unwrap();
+        assert!(
+            config.repos
+                == [
+                    TreeConfig::from(("/home/me/work", None)),
+                    TreeConfig::from((
+                        "/home/me/src \"quoted\"",
+                        Some("personal/")
+                    )),
+                ]
+        );
+        assert!(config.identity.emails == ["me@example.com"]);
+
+        // Commented options are in the right section.
+        let uncommented = answers
+            .render()
+            .replace("# ls_remote_head", "ls_remote_head")
+            .replace("# commit_date", "commit_date")
+            .replace("# repo_config", "repo_config");
+        let config = Config::parse(&uncommented).unwrap();
+        assert!(config.ls_remote_head);
+        assert!(config.commit_date == CommitDate::Committer);
+        assert!(config.identity.repo_config);
+    }
+}
@@ -0,0 +1,77 @@
+//! Formats of files read by other tools.
+//!
+//! The `schema` subcommand prints JSON Schemas for the JSON formats so that
+//! tools that consume the files can validate them. See [`parquet`] for
+//! exporting commits for analysis.
+
+pub mod parquet;
+
+use schemars::JsonSchema;
+use schemars::r#gen::SchemaGenerator;
+use schemars::schema::Schema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Output of the `scan` command.
+///
+/// Maps repository names to the times of commits in them (seconds since
+/// 1970). This is also the format of `assets/local.json` in exported bundles.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ScanOutput(pub BTreeMap<String, Vec<i64>>);
+
+// The derived schema for a transparent struct is just the schema of the
+// inner type, which has no title or description.
+impl JsonSchema for ScanOutput {
+    fn schema_name() -> String {
+        "ScanOutput".to_owned()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let mut schema =
+            BTreeMap::<String, Vec<i64>>::json_schema(generator).into_object();
+        schema.metadata().description = Some(
+            "Maps repository names to the times of commits in them (seconds \
+            since 1970)."
+                .to_owned(),
+        );
+        schema.into()
+    }
+}
+
+/// Contributions from GitHub, stored as `assets/github.json` in exported
+/// bundles.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StaticData {
+    /// Version of this format. Currently 2.
+    pub schema_version: u32,
+
+    /// SHA-256 hash of the GraphQL query used to get `contributions`, as hex.
+    ///
+    /// If the query changes, the data must be regenerated.
+    pub query_hash: String,
+
+    /// When the data was generated (ISO 8601).
+    pub generated_at: String,
+
+    /// GitHub `contributionsCollection` results, one per year.
+    ///
+    /// See `CONTRIBUTIONS_QUERY_TEMPLATE` in `src/github/api.ts` for the
+    /// fields included.
+    pub contributions: Vec<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn scan_output_is_a_map() {
+        let output = ScanOutput(BTreeMap::from([("a".to_owned(), vec![1])]));
+        let json = serde_json::to_string(&output).unwrap();
+        assert!(json == r#"{"a":[1]}"#);
+        assert!(serde_json::from_str::<ScanOutput>(&json).unwrap() == output);
+    }
+}
@@ -0,0 +1,211 @@
+//! Export commits as a Parquet file for analysis tools like DuckDB or pandas.
+//!
+//! The file has one row per commit, with these columns:
+//!
+//! - `repo` (string): Repository name.
+//! - `timestamp` (timestamp, milliseconds, UTC): Author or committer date,
+//!   depending on [`Config::commit_date`](crate::repos::Config::commit_date).
+//! - `category` (string): Either `merged` for commits on the default branch, or
+//!   `unmerged` for commits only on branches that haven’t been merged.
+//! - `insertions` (integer, optional): Lines inserted.
+//! - `deletions` (integer, optional): Lines deleted.
+//!
+//! `insertions` and `deletions` are null unless diff stats were computed. See
+//! [`Config::diff_stats`](crate::repos::Config::diff_stats).
+
+use crate::repos::DiffStats;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Parquet schema of the file.
+const SCHEMA: &str = "
+    message contributions {
+        required binary repo (STRING);
+        required int64 timestamp (TIMESTAMP(MILLIS, true));
+        required binary category (STRING);
+        optional int64 insertions;
+        optional int64 deletions;
+    }
+";
+
+/// Which commits a row belongs to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Category {
+    /// Commits on the default branch.
+    Merged,
+
+    /// Commits only on branches that haven’t been merged.
+    Unmerged,
+}
+
+impl Category {
+    /// Value of the `category` column.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Merged => "merged",
+            Self::Unmerged => "unmerged",
+        }
+    }
+}
+
+/// One commit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Row {
+    /// Repository name.
+    pub repo: String,
+
+    /// Commit date (seconds since 1970).
+    pub time: i64,
+
+    /// Which commits this belongs to.
+    pub category: Category,
+
+    /// Lines changed, if known.
+    pub stats: Option<DiffStats>,
+}
+
+/// Write `rows` as a Parquet file.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub fn write<W: Write + Send>(
+    rows: &[Row],
+    output: W,
+) -> Result<(), ParquetError> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let properties = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build(),
+    );
+    let mut writer = SerializedFileWriter::new(output, schema, properties)?;
+
+    let repos: Vec<ByteArray> =
+        rows.iter().map(|row| row.repo.as_str().into()).collect();
+    let timestamps: Vec<i64> = rows
+        .iter()
+        .map(|row| row.time.saturating_mul(1000))
+        .collect();
+    let categories: Vec<ByteArray> = rows
+        .iter()
+        .map(|row| row.category.as_str().into())
+        .collect();
+    let stats = |count: fn(&DiffStats) -> usize| -> (Vec<i64>, Vec<i16>) {
+        let values = rows
+            .iter()
+            .filter_map(|row| row.stats.as_ref())
+            .map(|stats| i64::try_from(count(stats)).unwrap_or(i64::MAX))
+            .collect();
+        let levels = rows
+            .iter()
+            .map(|row| i16::from(row.stats.is_some()))
+            .collect();
+        (values, levels)
+    };
+
+    let mut group = writer.next_row_group()?;
+    if let Some(mut column) = group.next_column()? {
+        column
+            .typed::<ByteArrayType>()
+            .write_batch(&repos, None, None)?;
+        column.close()?;
+    }
+    if let Some(mut column) = group.next_column()? {
+        column
+            .typed::<Int64Type>()
+            .write_batch(&timestamps, None, None)?;
+        column.close()?;
+    }
+    if let Some(mut column) = group.next_column()? {
+        column
+            .typed::<ByteArrayType>()
+            .write_batch(&categories, None, None)?;
+        column.close()?;
+    }
+    for (values, levels) in [
+        stats(|stats| stats.insertions),
+        stats(|stats| stats.deletions),
+    ] {
+        if let Some(mut column) = group.next_column()? {
+            column.typed::<Int64Type>().write_batch(
+                &values,
+                Some(&levels),
+                None,
+            )?;
+            column.close()?;
+        }
+    }
+    group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+    use std::fs;
+    use testdir::testdir;
+
+    #[test]
+    fn round_trip() {
+        let rows = [
+            Row {
+                repo: "a".to_owned(),
+                time: 1_700_000_000,
+                category: Category::Merged,
+                stats: Some(DiffStats { insertions: 3, deletions: 1 }),
+            },
+            Row {
+                repo: "b".to_owned(),
+                time: 10,
+                category: Category::Unmerged,
+                stats: None,
+            },
+        ];
+        let path = testdir!().join("commits.parquet");
+        write(&rows, fs::File::create(&path).unwrap()).unwrap();
+
+        let reader =
+            SerializedFileReader::new(fs::File::open(&path).unwrap()).unwrap();
+        assert!(reader.metadata().file_metadata().num_rows() == 2);
+        let read: Vec<Vec<Field>> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .get_column_iter()
+                    .map(|(_, field)| field.clone())
+                    .collect()
+            })
+            .collect();
+        assert!(
+            read == [
+                vec![
+                    Field::Str("a".to_owned()),
+                    Field::TimestampMillis(1_700_000_000_000),
+                    Field::Str("merged".to_owned()),
+                    Field::Long(3),
+                    Field::Long(1),
+                ],
+                vec![
+                    Field::Str("b".to_owned()),
+                    Field::TimestampMillis(10_000),
+                    Field::Str("unmerged".to_owned()),
+                    Field::Null,
+                    Field::Null,
+                ],
+            ]
+        );
+    }
+}
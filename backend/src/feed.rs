@@ -0,0 +1,176 @@
+//! Feeds of contribution activity.
+//!
+//! This module is organized into:
+//! - [`atom`] - Atom feeds for feed readers
+//! - [`ics`] - iCalendar feeds for calendar apps
+
+pub mod atom;
+pub mod ics;
+
+use crate::api::definition::LocalContributions;
+use crate::repos::{Calendar, week_start};
+use chrono::{NaiveDate, TimeZone, Weekday};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Default number of entries in a feed.
+pub const DEFAULT_LIMIT: usize = 30;
+
+/// How much time each feed entry covers.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    /// One entry per day.
+    #[default]
+    Day,
+
+    /// One entry per week, starting on Sunday.
+    Week,
+}
+
+impl Period {
+    /// Find the first day of the period containing `date`.
+    #[must_use]
+    pub fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => date,
+            Self::Week => week_start(date, Weekday::Sun),
+        }
+    }
+}
+
+/// Contributions during one [`Period`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    /// First day of the period.
+    pub start: NaiveDate,
+
+    /// Time of the last contribution (seconds since the epoch).
+    pub updated: i64,
+
+    /// Number of contributions by repository name.
+    pub repos: BTreeMap<String, usize>,
+}
+
+impl Entry {
+    /// Total number of contributions.
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.repos.values().sum()
+    }
+}
+
+/// Group contributions into entries, most recent first.
+///
+/// Only periods with contributions get entries. Days are calculated in `tz`.
+#[must_use]
+pub fn entries<Tz: TimeZone>(
+    contributions: &LocalContributions,
+    tz: &Tz,
+    period: Period,
+    limit: usize,
+) -> Vec<Entry> {
+    let calendar = Calendar::new(tz.clone());
+    let mut entries: BTreeMap<NaiveDate, Entry> = BTreeMap::new();
+    for (repo, times) in contributions {
+        for &time in times {
+            let Some(date) = calendar.date(time) else {
+                continue;
+            };
+            let start = period.start(date);
+            let entry = entries.entry(start).or_insert_with(|| Entry {
+                start,
+                updated: time,
+                repos: BTreeMap::new(),
+            });
+            entry.updated = entry.updated.max(time);
+            entry
+                .repos
+                .entry(repo.clone())
+                .and_modify(|count| *count = count.saturating_add(1))
+                .or_insert(1);
+        }
+    }
+
+    entries.into_values().rev().take(limit).collect()
+}
+
+/// Escape text for XML.
+#[must_use]
+pub fn escape_xml(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use chrono::{DateTime, Utc};
+
+    fn seconds(input: &str) -> i64 {
+        input.parse::<DateTime<Utc>>().unwrap().timestamp()
+    }
+
+    fn date(input: &str) -> NaiveDate {
+        input.parse().unwrap()
+    }
+
+    fn contributions() -> LocalContributions {
+        LocalContributions::from([
+            (
+                "a".to_owned(),
+                vec![
+                    seconds("2024-01-02T12:00:00Z"),
+                    seconds("2024-01-03T12:00:00Z"),
+                ],
+            ),
+            ("b".to_owned(), vec![seconds("2024-01-03T13:00:00Z")]),
+            ("c".to_owned(), vec![seconds("2024-01-08T13:00:00Z")]),
+        ])
+    }
+
+    #[test]
+    fn daily_entries() {
+        let entries = entries(&contributions(), &Utc, Period::Day, 2);
+        assert!(
+            entries
+                == [
+                    Entry {
+                        start: date("2024-01-08"),
+                        updated: seconds("2024-01-08T13:00:00Z"),
+                        repos: BTreeMap::from([("c".to_owned(), 1)]),
+                    },
+                    Entry {
+                        start: date("2024-01-03"),
+                        updated: seconds("2024-01-03T13:00:00Z"),
+                        repos: BTreeMap::from([
+                            ("a".to_owned(), 1),
+                            ("b".to_owned(), 1),
+                        ]),
+                    },
+                ]
+        );
+    }
+
+    #[test]
+    fn weekly_entries() {
+        let entries = entries(&contributions(), &Utc, Period::Week, 10);
+        assert!(entries.len() == 2);
+        assert!(entries[0].start == date("2024-01-07"));
+        assert!(entries[1].start == date("2023-12-31"));
+        assert!(entries[1].total() == 3);
+    }
+
+    #[test]
+    fn escape() {
+        assert!(escape_xml(r#"<a & "b">"#) == "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
@@ -0,0 +1,107 @@
+//! Render feed entries as an Atom feed.
+
+use super::{Entry, Period, escape_xml};
+use chrono::{DateTime, SecondsFormat};
+use std::fmt;
+
+/// Render entries as an Atom feed.
+///
+/// `entries` should be sorted with the most recent first.
+#[must_use]
+pub fn render(entries: &[Entry], period: Period) -> String {
+    Feed { entries, period }.to_string()
+}
+
+/// An Atom feed.
+struct Feed<'a> {
+    /// Entries, most recent first.
+    entries: &'a [Entry],
+
+    /// How much time each entry covers.
+    period: Period,
+}
+
+impl fmt::Display for Feed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(f, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#)?;
+        writeln!(f, "<title>RepoYear contributions</title>")?;
+        writeln!(f, "<id>urn:repoyear:contributions</id>")?;
+        writeln!(
+            f,
+            "<updated>{}</updated>",
+            timestamp(self.entries.first().map_or(0, |entry| entry.updated)),
+        )?;
+        writeln!(f, "<author><name>RepoYear</name></author>")?;
+        writeln!(f, "<generator>RepoYear</generator>")?;
+
+        for entry in self.entries {
+            let total = entry.total();
+            let plural = if total == 1 { "" } else { "s" };
+            let period = match self.period {
+                Period::Day => "on",
+                Period::Week => "in the week of",
+            };
+            writeln!(f, "<entry>")?;
+            writeln!(
+                f,
+                "<title>{total} contribution{plural} {period} {}</title>",
+                entry.start,
+            )?;
+            writeln!(f, "<id>urn:repoyear:contributions:{}</id>", entry.start)?;
+            writeln!(f, "<updated>{}</updated>", timestamp(entry.updated))?;
+            write!(f, r#"<content type="text">"#)?;
+            for (repo, count) in &entry.repos {
+                writeln!(f, "{}: {count}", escape_xml(repo))?;
+            }
+            writeln!(f, "</content>")?;
+            writeln!(f, "</entry>")?;
+        }
+
+        writeln!(f, "</feed>")
+    }
+}
+
+/// Format seconds since the epoch as an RFC 3339 timestamp.
+fn timestamp(seconds: i64) -> String {
+    DateTime::from_timestamp(seconds, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn render_atom() {
+        let entries = [Entry {
+            start: "2024-01-03".parse().unwrap(),
+            updated: 1_704_286_800,
+            repos: BTreeMap::from([("a&b".to_owned(), 2), ("c".to_owned(), 1)]),
+        }];
+        assert!(
+            render(&entries, Period::Day)
+                == "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\">
+<title>RepoYear contributions</title>
+<id>urn:repoyear:contributions</id>
+<updated>2024-01-03T13:00:00Z</updated>
+<author><name>RepoYear</name></author>
+<generator>RepoYear</generator>
+<entry>
+<title>3 contributions on 2024-01-03</title>
+<id>urn:repoyear:contributions:2024-01-03</id>
+<updated>2024-01-03T13:00:00Z</updated>
+<content type=\"text\">a&amp;b: 2
+c: 1
+</content>
+</entry>
+</feed>
+"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Render feed entries as an iCalendar (RFC 5545) calendar.
+
+use super::Entry;
+use chrono::{DateTime, Days};
+use std::fmt;
+
+/// Maximum length of a content line in octets, excluding the line break.
+const MAX_LINE: usize = 75;
+
+/// Render daily entries as a calendar with one all-day event per entry.
+#[must_use]
+pub fn render(entries: &[Entry]) -> String {
+    Calendar { entries }.to_string()
+}
+
+/// An iCalendar calendar.
+struct Calendar<'a> {
+    /// Daily entries.
+    entries: &'a [Entry],
+}
+
+impl fmt::Display for Calendar<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        line(f, "BEGIN:VCALENDAR")?;
+        line(f, "VERSION:2.0")?;
+        line(f, "PRODID:-//RepoYear//Contributions//EN")?;
+        line(f, "CALSCALE:GREGORIAN")?;
+        line(f, "X-WR-CALNAME:RepoYear contributions")?;
+
+        for entry in self.entries {
+            let total = entry.total();
+            let plural = if total == 1 { "" } else { "s" };
+            let end = entry
+                .start
+                .checked_add_days(Days::new(1))
+                .unwrap_or(entry.start);
+            let description = entry
+                .repos
+                .iter()
+                .map(|(repo, count)| format!("{repo}: {count}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            line(f, "BEGIN:VEVENT")?;
+            line(f, &format!("UID:{}@repoyear", entry.start))?;
+            line(f, &format!("DTSTAMP:{}", timestamp(entry.updated)))?;
+            line(
+                f,
+                &format!("DTSTART;VALUE=DATE:{}", entry.start.format("%Y%m%d")),
+            )?;
+            line(f, &format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")))?;
+            line(f, &format!("SUMMARY:{total} contribution{plural}"))?;
+            line(f, &format!("DESCRIPTION:{}", escape_text(&description)))?;
+            line(f, "TRANSP:TRANSPARENT")?;
+            line(f, "END:VEVENT")?;
+        }
+
+        line(f, "END:VCALENDAR")
+    }
+}
+
+/// Write a content line, folding it if it’s too long.
+fn line(f: &mut fmt::Formatter<'_>, content: &str) -> fmt::Result {
+    let mut length = 0_usize;
+    for c in content.chars() {
+        if length.saturating_add(c.len_utf8()) > MAX_LINE {
+            f.write_str("\r\n ")?;
+            length = 1;
+        }
+        write!(f, "{c}")?;
+        length = length.saturating_add(c.len_utf8());
+    }
+    f.write_str("\r\n")
+}
+
+/// Escape a `TEXT` value.
+fn escape_text(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Format seconds since the epoch as a UTC date-time.
+fn timestamp(seconds: i64) -> String {
+    DateTime::from_timestamp(seconds, 0)
+        .unwrap_or_default()
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn render_ics() {
+        let entries = [Entry {
+            start: "2024-01-03".parse().unwrap(),
+            updated: 1_704_286_800,
+            repos: BTreeMap::from([("a,b".to_owned(), 2), ("c".to_owned(), 1)]),
+        }];
+        assert!(
+            render(&entries)
+                == "BEGIN:VCALENDAR\r\n\
+                    VERSION:2.0\r\n\
+                    PRODID:-//RepoYear//Contributions//EN\r\n\
+                    CALSCALE:GREGORIAN\r\n\
+                    X-WR-CALNAME:RepoYear contributions\r\n\
+                    BEGIN:VEVENT\r\n\
+                    UID:2024-01-03@repoyear\r\n\
+                    DTSTAMP:20240103T130000Z\r\n\
+                    DTSTART;VALUE=DATE:20240103\r\n\
+                    DTEND;VALUE=DATE:20240104\r\n\
+                    SUMMARY:3 contributions\r\n\
+                    DESCRIPTION:a\\,b: 2\\nc: 1\r\n\
+                    TRANSP:TRANSPARENT\r\n\
+                    END:VEVENT\r\n\
+                    END:VCALENDAR\r\n"
+        );
+    }
+
+    #[test]
+    fn fold_long_lines() {
+        let entries = [Entry {
+            start: "2024-01-03".parse().unwrap(),
+            updated: 1_704_286_800,
+            repos: BTreeMap::from([("x".repeat(100), 1)]),
+        }];
+        let output = render(&entries);
+        assert!(output.lines().all(|line| line.len() <= MAX_LINE));
+        assert!(output.contains(&format!("\r\n {}", "x".repeat(12))));
+    }
+}
@@ -0,0 +1,184 @@
+//! Keep snapshots of past scans.
+//!
+//! Every scan sees only the repositories that exist now. If a repository is
+//! deleted, or a tree is dropped from the configuration by mistake, its
+//! commits quietly disappear from the results. Comparing how a period looked
+//! in past snapshots makes that kind of loss visible.
+//!
+//! Unlike the [cache](crate::repos::CacheDir), snapshots can’t be recomputed,
+//! so they are kept in the data directory rather than the cache directory.
+
+use crate::repos::{Calendar, DateRange};
+use chrono::TimeZone;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Results of one scan.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Snapshot {
+    /// When the scan finished (seconds since epoch).
+    pub scanned_at: i64,
+
+    /// Commit times (seconds since epoch) by repository name.
+    pub repos: BTreeMap<String, Vec<i64>>,
+}
+
+impl Snapshot {
+    /// Count commits and repositories with commits within `range`.
+    ///
+    /// Days are calculated in the time zone `tz`.
+    #[must_use]
+    pub fn totals<Tz: TimeZone>(&self, range: &DateRange, tz: &Tz) -> Totals {
+        let calendar = Calendar::new(tz.clone());
+        let mut totals = Totals::default();
+        for times in self.repos.values() {
+            let commits = times
+                .iter()
+                .filter_map(|&time| calendar.date(time))
+                .filter(|&date| range.contains(date))
+                .count();
+            if commits > 0 {
+                totals.commits = totals.commits.saturating_add(commits);
+                totals.repos = totals.repos.saturating_add(1);
+            }
+        }
+        totals
+    }
+}
+
+/// Number of commits and repositories in a period.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Totals {
+    /// Number of commits.
+    pub commits: usize,
+
+    /// Number of repositories with commits.
+    pub repos: usize,
+}
+
+/// Snapshots stored as files in a directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct History {
+    /// Directory containing snapshots.
+    pub path: PathBuf,
+}
+
+impl History {
+    /// Use a specific directory.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Get the default history directory, `$XDG_DATA_HOME/repoyear/history`
+    /// or `~/.local/share/repoyear/history`.
+    ///
+    /// Returns `None` if neither `$XDG_DATA_HOME` nor the home directory are
+    /// set.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| {
+                std::env::home_dir().map(|home| home.join(".local/share"))
+            })
+            .map(|data| data.join("repoyear/history"))
+    }
+
+    /// Store a snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot could not be written.
+    pub fn record(&self, snapshot: &Snapshot) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.path)?;
+        let contents = serde_json::to_vec(snapshot)?;
+        let mut n = 0_u32;
+        loop {
+            let path = self
+                .path
+                .join(format!("{:020}-{n}.json", snapshot.scanned_at));
+            match fs::File::create_new(&path) {
+                Ok(mut file) => {
+                    file.write_all(&contents)?;
+                    return Ok(path);
+                }
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    n = n.checked_add(1).ok_or(error)?;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Read all snapshots, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or a snapshot could not be read.
+    pub fn snapshots(&self) -> io::Result<Vec<Snapshot>> {
+        let mut paths = match fs::read_dir(&self.path) {
+            Ok(entries) => entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .filter(|path| {
+                    path.as_ref().map_or(true, |path| {
+                        path.extension().is_some_and(|ext| ext == "json")
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| Ok(serde_json::from_slice(&fs::read(path)?)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use chrono::{DateTime, Utc};
+    use testdir::testdir;
+
+    fn seconds(input: &str) -> i64 {
+        input.parse::<DateTime<Utc>>().unwrap().timestamp()
+    }
+
+    #[test]
+    fn record_and_count() {
+        let history = History::new(testdir!().join("history"));
+        assert!(history.snapshots().unwrap().is_empty());
+
+        let old = Snapshot {
+            scanned_at: 200,
+            repos: BTreeMap::from([
+                ("a".to_owned(), vec![seconds("2024-03-01T12:00:00Z")]),
+                (
+                    "b".to_owned(),
+                    vec![
+                        seconds("2024-03-31T12:00:00Z"),
+                        seconds("2024-04-01T12:00:00Z"),
+                    ],
+                ),
+            ]),
+        };
+        let mut new = old.clone();
+        new.scanned_at = 300;
+        new.repos.remove("a");
+
+        history.record(&new).unwrap();
+        history.record(&old).unwrap();
+        assert!(history.snapshots().unwrap() == [old.clone(), new.clone()]);
+
+        let march = "2024-03".parse().unwrap();
+        assert!(old.totals(&march, &Utc) == Totals { commits: 2, repos: 2 });
+        assert!(new.totals(&march, &Utc) == Totals { commits: 1, repos: 1 });
+    }
+}
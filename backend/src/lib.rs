@@ -1,8 +1,19 @@
 //! RepoYear backend.
 #![expect(clippy::doc_markdown, reason = "app name")]
 
+pub mod agent;
 pub mod api;
+pub mod backup;
+pub mod export;
+pub mod feed;
+pub mod history;
+pub mod merge;
+pub mod notify;
+pub mod render;
 pub mod repos;
+pub mod schedule;
+pub mod stats;
+pub mod users;
 
 #[cfg(test)]
 pub mod test;
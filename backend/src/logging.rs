@@ -1,27 +1,146 @@
 //! Various logging functions.
 
 use anyhow::bail;
-use slog::{Drain, Level, Logger};
+use std::fmt::{self, Write};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
 
 /// Initialize logging for the executable.
 ///
-/// Creates and returns a slog logger configured based on the verbosity level.
-pub fn init(verbose: u8) -> anyhow::Result<Logger> {
+/// Installs a `tracing` subscriber configured based on the verbosity level.
+/// `RUST_LOG` can be used to override the level for specific modules, e.g.
+/// `RUST_LOG=repoyear_backend::repos=debug`.
+///
+/// Returns an slog logger that forwards to `tracing`, since Dropshot needs
+/// one.
+pub fn init(verbose: u8) -> anyhow::Result<slog::Logger> {
     let level = match verbose {
         4.. => bail!("-v is only allowed up to 3 times."),
-        3 => Level::Trace,
-        2 => Level::Debug,
-        1 => Level::Info,
-        0 => Level::Warning,
+        3 => LevelFilter::TRACE,
+        2 => LevelFilter::DEBUG,
+        1 => LevelFilter::INFO,
+        0 => LevelFilter::WARN,
     };
 
-    let decorator = slog_term::TermDecorator::new().build();
-    let drain = slog_term::FullFormat::new(decorator).build().fuse();
-    let drain = slog_async::Async::new(drain)
-        .chan_size(256)
-        .build()
-        .filter_level(level)
-        .fuse();
+    let filter = EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env()?;
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .try_init()
+        .map_err(|error| anyhow::anyhow!(error))?;
+
+    Ok(slog::Logger::root(TracingDrain, slog::o!()))
+}
+
+/// Forward slog records to `tracing`.
+///
+/// Key-value pairs are appended to the message as `key=value`.
+struct TracingDrain;
+
+impl slog::Drain for TracingDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &slog::Record<'_>,
+        values: &slog::OwnedKVList,
+    ) -> Result<(), slog::Never> {
+        let message = record.msg();
+        let fields = format_fields(record, values);
+        match record.level() {
+            slog::Level::Critical | slog::Level::Error => {
+                tracing::error!(target: "dropshot", "{message}{fields}");
+            }
+            slog::Level::Warning => {
+                tracing::warn!(target: "dropshot", "{message}{fields}");
+            }
+            slog::Level::Info => {
+                tracing::info!(target: "dropshot", "{message}{fields}");
+            }
+            slog::Level::Debug => {
+                tracing::debug!(target: "dropshot", "{message}{fields}");
+            }
+            slog::Level::Trace => {
+                tracing::trace!(target: "dropshot", "{message}{fields}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format the key-value pairs of a slog record as ` key=value`, starting with
+/// the record’s own.
+fn format_fields(
+    record: &slog::Record<'_>,
+    values: &slog::OwnedKVList,
+) -> String {
+    let mut fields = Fields::default();
+    // Fields only fails if writing to a string fails, which it doesn’t.
+    let _ = slog::KV::serialize(&record.kv(), record, &mut fields);
+    let _ = slog::KV::serialize(values, record, &mut fields);
+    fields.0
+}
+
+/// Collects slog key-value pairs as ` key=value`.
+#[derive(Default)]
+struct Fields(String);
+
+impl slog::Serializer for Fields {
+    fn emit_arguments(
+        &mut self,
+        key: slog::Key,
+        value: &fmt::Arguments<'_>,
+    ) -> slog::Result {
+        write!(self.0, " {key}={value}")
+            .map_err(|_| slog::Error::Fmt(fmt::Error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use std::sync::{Arc, Mutex};
+
+    /// Drain that keeps formatted messages.
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl slog::Drain for Capture {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(
+            &self,
+            record: &slog::Record<'_>,
+            values: &slog::OwnedKVList,
+        ) -> Result<(), slog::Never> {
+            let fields = format_fields(record, values);
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{}{fields}", record.msg()));
+            Ok(())
+        }
+    }
 
-    Ok(Logger::root(drain, slog::o!()))
+    #[test]
+    fn fields() {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let log = slog::Logger::root(
+            Capture(messages.clone()),
+            slog::o!("local" => "[::1]:80"),
+        );
+        slog::info!(log, "listening"; "tls" => false);
+        slog::info!(log, "plain");
+        assert!(
+            *messages.lock().unwrap()
+                == [
+                    "listening tls=false local=[::1]:80",
+                    "plain local=[::1]:80"
+                ]
+        );
+    }
 }
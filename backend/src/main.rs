@@ -1,15 +1,39 @@
 //! repoyear-backend executable.
 
-use anyhow::anyhow;
-use repoyear_backend::{api, repos};
+use age::secrecy::SecretString;
+use anyhow::{anyhow, bail};
+use repoyear_backend::agent::{Agent, Queue, ReportStore};
+use repoyear_backend::api::AppState;
+use repoyear_backend::api::readiness::Readiness;
+use repoyear_backend::api::reload::ReloadableConfig;
+use repoyear_backend::export::{ScanOutput, StaticData, parquet};
+use repoyear_backend::history::{self, History, Snapshot};
+use repoyear_backend::notify::{
+    Notifier, email::EmailConfig, webhook::WebhookConfig,
+};
+use repoyear_backend::repos::{CacheDir, TreeConfig};
+use repoyear_backend::users::Users;
+use repoyear_backend::{api, backup, repos};
 use std::collections::BTreeMap;
 use std::fs;
+use std::io::{self, Write as _};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::{Semaphore, broadcast};
 
+mod access_log;
+mod config_init;
 mod logging;
 mod params;
+mod serve_static;
 mod server;
+mod watch;
 
+use access_log::AccessLog;
 use params::{Command, Params, Parser};
 
 /// Wrapper to handle errors.
@@ -41,44 +65,23 @@ fn cli(params: &Params) -> anyhow::Result<ExitCode> {
     let log = logging::init(params.verbose)?;
 
     match &params.command {
-        Command::Serve(serve_params) => {
-            let scan_config = if let Some(path) = &serve_params.scan_config {
-                Some(repos::Config::parse(&fs::read_to_string(path)?)?)
-            } else {
-                None
-            };
-
-            server::serve(
-                &serve_params.bind,
-                &serve_params.github_client_id,
-                &serve_params.github_client_secret,
-                scan_config,
+        Command::Serve(serve_params) => serve(serve_params, &log)?,
+        Command::Agent(agent_params) => run_agent(agent_params)?,
+        Command::ServeStatic(serve_static_params) => {
+            serve_static::serve(
+                &serve_static_params.bind,
+                serve_static_params.directory.clone(),
+                serve_static_params.basic_auth.clone(),
                 &log,
             )?;
         }
-        Command::Scan(scan_params) => {
-            let result = repos::Config::parse(&fs::read_to_string(
-                &scan_params.config,
-            )?)?
-            .repo_iter()
-            .filter_map(|result| {
-                result
-                    .map_err(anyhow::Error::from) // FIXME?
-                    .and_then(|(name, repo)| {
-                        Ok((name, repos::scan_repo(&repo)?))
-                    })
-                    .inspect_err(|error| {
-                        params.warn(format!("Warning: {error}\n")).unwrap();
-                    })
-                    .ok()
-            })
-            .collect::<BTreeMap<_, _>>();
-            println!("{}", serde_json::to_string(&result)?);
-        }
+        Command::Scan(scan_params) => scan(params, scan_params)?,
         Command::ScanRepo(scan_repo_params) => {
+            let options =
+                repos::ScanOptions::for_identity(repos::Identity::detect()?);
             let mut result = BTreeMap::new();
             for path in &scan_repo_params.repositories {
-                match repos::scan_repo_path(path) {
+                match repos::scan_repo_path(path, &options) {
                     Ok(times) => {
                         result.insert(path, times);
                     }
@@ -89,9 +92,24 @@ fn cli(params: &Params) -> anyhow::Result<ExitCode> {
             }
             println!("{}", serde_json::to_string(&result)?);
         }
+        Command::History(history_params) => history(history_params)?,
+        Command::Backup(backup_params) => backup(backup_params)?,
+        Command::Restore(restore_params) => restore(restore_params)?,
+        Command::Cache(cache_command) => cache(cache_command)?,
+        Command::Identity(identity_command) => {
+            identity(params, identity_command)?;
+        }
+        Command::Repos(repos_command) => repos(params, repos_command)?,
+        Command::Config(params::ConfigCommand::Init(init_params)) => {
+            config_init::run(init_params)?;
+        }
+        Command::Config(params::ConfigCommand::Check(check_params)) => {
+            return config_check(params, check_params);
+        }
         Command::Openapi(openapi_params) => {
             generate_openapi(openapi_params)?;
         }
+        Command::Schema(schema_params) => generate_schema(schema_params)?,
         Command::Version => {
             println!("{}", env!("GIT_VERSION"));
         }
@@ -99,6 +117,1062 @@ fn cli(params: &Params) -> anyhow::Result<ExitCode> {
     Ok(ExitCode::SUCCESS)
 }
 
+/// Scan the repositories in a configuration file.
+///
+/// # Errors
+///
+/// Returns an error if the configuration cannot be loaded or the output
+/// cannot be written. Problems with individual repositories are reported as
+/// warnings.
+fn scan(
+    params: &Params,
+    scan_params: &params::ScanParams,
+) -> anyhow::Result<()> {
+    let paths = config_layers(scan_params.config.as_deref())?;
+    let config = load_scan_config(&paths, scan_params.cache_dir.as_ref())?;
+    let parquet = scan_params.format == params::ScanFormat::Parquet;
+    let summaries = scan_params.format == params::ScanFormat::Commits;
+    let by_identity = scan_params.format == params::ScanFormat::Identities;
+    let by_group = scan_params.format == params::ScanFormat::Groups;
+    let mut merged = BTreeMap::new();
+    let mut unmerged = BTreeMap::new();
+    let mut stats = BTreeMap::new();
+    let mut commit_summaries = BTreeMap::new();
+    let mut grouped: Grouped = BTreeMap::new();
+    let mut outcomes = BTreeMap::new();
+    for result in config.repo_iter() {
+        let (name, repo) = match result {
+            Ok(found) => found,
+            Err(error) => {
+                params.warn(format!("Warning: {error}\n"))?;
+                continue;
+            }
+        };
+        if repo.is_shallow() {
+            params.warn(format!(
+                "Warning: {name} is a shallow clone, so older commits aren’t \
+                counted\n"
+            ))?;
+        }
+        config.fetch_if_configured(&repo);
+        let mut scan_one = || -> anyhow::Result<()> {
+            let commits = if parquet {
+                config.scan_stats(&repo)?
+            } else {
+                None
+            };
+            if let Some(commits) = commits {
+                let times = commits.iter().map(|commit| commit.time);
+                merged.insert(name.clone(), times.collect());
+                stats.insert(name.clone(), commits);
+            } else if summaries {
+                let commits = config
+                    .scan_summaries(&repo, scan_params.verify_signatures)?;
+                let times = commits.iter().map(|commit| commit.time);
+                merged.insert(name.clone(), times.collect());
+                commit_summaries.insert(name.clone(), commits);
+            } else {
+                merged.insert(name.clone(), config.scan_repo(&repo)?);
+            }
+            if by_identity {
+                for (label, times) in config.scan_identities(&repo)? {
+                    grouped
+                        .entry(label)
+                        .or_default()
+                        .insert(name.clone(), times);
+                }
+            }
+            if by_group {
+                let group = config.group_of(&repo).unwrap_or_default();
+                let times = merged.get(&name).cloned().unwrap_or_default();
+                grouped
+                    .entry(group.to_owned())
+                    .or_default()
+                    .insert(name.clone(), times);
+            }
+            if parquet && config.unmerged {
+                unmerged.insert(name.clone(), config.scan_unmerged(&repo)?);
+            }
+            Ok(())
+        };
+        let result = scan_one();
+        let outcome = repos::RepoOutcome::of(
+            &repo,
+            config.skip_remotes_for(&repo),
+            &result,
+        );
+        if let repos::RepoOutcome::Error { message } = &outcome {
+            params.warn(format!("Warning: {name}: {message}\n"))?;
+        }
+        outcomes.insert(name, outcome);
+    }
+    report_outcomes(params, &outcomes)?;
+
+    if scan_params.record {
+        history_dir(scan_params.history_dir.as_ref())?.record(&Snapshot {
+            scanned_at: chrono::Utc::now().timestamp(),
+            repos: merged.clone(),
+        })?;
+    }
+
+    write_scan_output(
+        scan_params,
+        merged,
+        unmerged,
+        stats,
+        &commit_summaries,
+        &grouped,
+    )?;
+
+    if let Some(hours) = scan_params.date_divergence {
+        report_date_divergence(params, &config, hours)?;
+    }
+    Ok(())
+}
+
+/// Commit times by identity or group, then by repository name.
+type Grouped = BTreeMap<String, BTreeMap<String, Vec<i64>>>;
+
+/// Write the results of [`scan()`] in the format in `scan_params`.
+///
+/// # Errors
+///
+/// Returns an error if the output cannot be written.
+fn write_scan_output(
+    scan_params: &params::ScanParams,
+    merged: BTreeMap<String, Vec<i64>>,
+    unmerged: BTreeMap<String, Vec<i64>>,
+    stats: BTreeMap<String, Vec<repos::CommitStats>>,
+    commit_summaries: &BTreeMap<String, Vec<repos::CommitSummary>>,
+    grouped: &Grouped,
+) -> anyhow::Result<()> {
+    let mut output: Box<dyn io::Write + Send> = match &scan_params.output {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    match scan_params.format {
+        params::ScanFormat::Json => {
+            serde_json::to_writer(&mut output, &ScanOutput(merged))?;
+            writeln!(output)?;
+        }
+        params::ScanFormat::Parquet => {
+            write_parquet(merged, unmerged, stats, output)?;
+        }
+        params::ScanFormat::Commits => {
+            serde_json::to_writer(&mut output, commit_summaries)?;
+            writeln!(output)?;
+            report_signatures(commit_summaries);
+        }
+        params::ScanFormat::Identities | params::ScanFormat::Groups => {
+            serde_json::to_writer(&mut output, grouped)?;
+            writeln!(output)?;
+        }
+    }
+    Ok(())
+}
+
+/// Report what happened to the repositories in a scan on standard error.
+///
+/// Repositories that couldn’t be scanned are always counted. With `-v`, the
+/// outcome of every repository is listed.
+///
+/// # Errors
+///
+/// Returns an error if the report cannot be written.
+fn report_outcomes(
+    params: &Params,
+    outcomes: &BTreeMap<String, repos::RepoOutcome>,
+) -> io::Result<()> {
+    if params.verbose > 0 {
+        for (name, outcome) in outcomes {
+            eprintln!("{name}: {outcome}");
+        }
+    }
+    let errors = outcomes
+        .values()
+        .filter(|outcome| matches!(outcome, repos::RepoOutcome::Error { .. }))
+        .count();
+    if errors > 0 {
+        params.warn(format!(
+            "Warning: {errors} of {} repositories could not be scanned, so \
+            their commits are missing\n",
+            outcomes.len(),
+        ))?;
+    }
+    Ok(())
+}
+
+/// Write scan results as Parquet.
+///
+/// Commits with diff stats in `stats` are written with them.
+///
+/// # Errors
+///
+/// Returns an error if the output cannot be written.
+fn write_parquet(
+    merged: BTreeMap<String, Vec<i64>>,
+    unmerged: BTreeMap<String, Vec<i64>>,
+    mut stats: BTreeMap<String, Vec<repos::CommitStats>>,
+    output: Box<dyn io::Write + Send>,
+) -> anyhow::Result<()> {
+    let row = |repo: &String, time, category, stats| parquet::Row {
+        repo: repo.clone(),
+        time,
+        category,
+        stats,
+    };
+    let mut rows = Vec::new();
+    for (repo, times) in merged {
+        if let Some(commits) = stats.remove(&repo) {
+            rows.extend(commits.into_iter().map(|commit| {
+                row(
+                    &repo,
+                    commit.time,
+                    parquet::Category::Merged,
+                    Some(commit.stats),
+                )
+            }));
+        } else {
+            rows.extend(
+                times.into_iter().map(|time| {
+                    row(&repo, time, parquet::Category::Merged, None)
+                }),
+            );
+        }
+    }
+    for (repo, times) in unmerged {
+        rows.extend(
+            times.into_iter().map(|time| {
+                row(&repo, time, parquet::Category::Unmerged, None)
+            }),
+        );
+    }
+    parquet::write(&rows, output)?;
+    Ok(())
+}
+
+/// Find the history directory.
+///
+/// # Errors
+///
+/// Returns an error if there is no default and none was passed.
+fn history_dir(path: Option<&PathBuf>) -> anyhow::Result<History> {
+    path.cloned()
+        .or_else(History::default_path)
+        .map(History::new)
+        .ok_or_else(|| anyhow!("Could not find history directory"))
+}
+
+/// Save the server’s state to an archive.
+///
+/// # Errors
+///
+/// Returns an error if the backup fails.
+fn backup(backup_params: &params::BackupParams) -> anyhow::Result<()> {
+    let sources = backup::Sources {
+        history: backup_params
+            .history_dir
+            .clone()
+            .or_else(History::default_path),
+        cache: if backup_params.no_cache {
+            None
+        } else {
+            backup_params
+                .cache_dir
+                .clone()
+                .or_else(CacheDir::default_path)
+        },
+        reports: backup_params
+            .reports_dir
+            .clone()
+            .or_else(ReportStore::default_path),
+        scan_configs: repos::Config::layers(
+            backup_params.scan_config.as_deref(),
+        ),
+        users_config: backup_params.users_config.clone(),
+        agent_keys: backup_params.agent_keys.clone(),
+    };
+    let passphrase = backup_params.passphrase.clone().map(SecretString::from);
+    let path = &backup_params.archive;
+    let manifest =
+        backup::create(&sources, passphrase.as_ref(), fs::File::create(path)?)
+            .inspect_err(|_| {
+                // Don’t leave a partial archive behind.
+                let _ = fs::remove_file(path);
+            })?;
+    println!(
+        "Saved {} snapshots, {} cache files, {} agent reports, {} scan \
+        configuration files, and {} agent keys to {}",
+        manifest.history_files,
+        manifest.cache_files,
+        manifest.report_files,
+        manifest.scan_configs.len(),
+        manifest.agent_keys,
+        path.display(),
+    );
+    Ok(())
+}
+
+/// Restore the server’s state from an archive.
+///
+/// # Errors
+///
+/// Returns an error if the restore fails.
+fn restore(restore_params: &params::RestoreParams) -> anyhow::Result<()> {
+    let destinations = backup::Destinations {
+        history: restore_params
+            .history_dir
+            .clone()
+            .or_else(History::default_path),
+        cache: if restore_params.no_cache {
+            None
+        } else {
+            restore_params
+                .cache_dir
+                .clone()
+                .or_else(CacheDir::default_path)
+        },
+        reports: restore_params
+            .reports_dir
+            .clone()
+            .or_else(ReportStore::default_path),
+        config_dir: restore_params.config_dir.clone(),
+        overwrite: restore_params.force,
+    };
+    let passphrase = restore_params.passphrase.clone().map(SecretString::from);
+    let restored = backup::restore(
+        fs::File::open(&restore_params.archive)?,
+        &destinations,
+        passphrase.as_ref(),
+    )?;
+    println!(
+        "Restored {} snapshots, {} cache files, and {} agent reports",
+        restored.history_files, restored.cache_files, restored.report_files,
+    );
+    for path in &restored.config_files {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// Show the totals for a period in each snapshot.
+///
+/// # Errors
+///
+/// Returns an error if the snapshots could not be read.
+fn history(history_params: &params::HistoryParams) -> anyhow::Result<()> {
+    let history = history_dir(history_params.history_dir.as_ref())?;
+    let snapshots = history.snapshots()?;
+    if snapshots.is_empty() {
+        bail!(
+            "No snapshots in {}; use `scan --record` to save some",
+            history.path.display()
+        );
+    }
+
+    let range = &history_params.period;
+    println!("Commits in {range} as of each snapshot:");
+    let mut previous: Option<history::Totals> = None;
+    for snapshot in &snapshots {
+        let totals = snapshot.totals(range, &chrono::Local);
+        let lost = previous
+            .map(|previous| previous.commits.saturating_sub(totals.commits))
+            .filter(|&lost| lost > 0)
+            .map(|lost| format!("  ({lost} fewer than before)"))
+            .unwrap_or_default();
+        println!(
+            "{}  {:>6} commits in {:>3} repos{lost}",
+            format_time(snapshot.scanned_at),
+            totals.commits,
+            totals.repos,
+        );
+        previous = Some(totals);
+    }
+    Ok(())
+}
+
+/// Report commits whose author and commit dates are more than `hours` apart.
+///
+/// # Errors
+///
+/// Returns an error if writing to stderr fails.
+fn report_date_divergence(
+    params: &Params,
+    config: &repos::Config,
+    hours: u64,
+) -> anyhow::Result<()> {
+    let threshold = Duration::from_secs(hours.saturating_mul(3600));
+    let mut total = repos::DateDivergence::default();
+    for (name, repo) in config.repo_iter().filter_map(Result::ok) {
+        let divergence = match config.scan_date_divergence(&repo, threshold) {
+            Ok(divergence) => divergence,
+            Err(error) => {
+                params.warn(format!("Error in {name}: {error}\n"))?;
+                continue;
+            }
+        };
+        total.commits = total.commits.saturating_add(divergence.commits);
+        if let Some(max_gap) = divergence.max_gap() {
+            eprintln!(
+                "{name}: {} of {} commits diverge by more than {hours}h \
+                (up to {:.0}h)",
+                divergence.divergent.len(),
+                divergence.commits,
+                max_gap.as_secs_f64() / 3600.0,
+            );
+        }
+        if params.verbose > 0 {
+            for commit in &divergence.divergent {
+                eprintln!(
+                    "  {}  authored {}  committed {}",
+                    commit.oid,
+                    format_time(commit.author_time),
+                    format_time(commit.commit_time),
+                );
+            }
+        }
+        total.divergent.extend(divergence.divergent);
+    }
+    eprintln!(
+        "{} of {} commits have author and commit dates more than {hours}h \
+        apart",
+        total.divergent.len(),
+        total.commits,
+    );
+    Ok(())
+}
+
+/// Format seconds since 1970 as a UTC date and time.
+fn format_time(time: i64) -> String {
+    chrono::DateTime::from_timestamp(time, 0).map_or_else(
+        || time.to_string(),
+        |time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+    )
+}
+
+/// Load a scan configuration and fill in its identity and cache directory.
+///
+/// `paths` are merged with [`repos::Config::load_layered()`]. See
+/// [`prepare_scan_config()`].
+///
+/// # Errors
+///
+/// Returns an error if the configuration cannot be read or parsed, or if the
+/// git configuration cannot be read.
+fn load_scan_config(
+    paths: &[PathBuf],
+    cache_dir: Option<&PathBuf>,
+) -> anyhow::Result<repos::Config> {
+    prepare_scan_config(repos::Config::load_layered(paths)?, cache_dir)
+}
+
+/// Load a scan configuration file merged over the default configuration
+/// files, the same way `scan` and `serve` do.
+///
+/// Unlike [`load_scan_config()`], this doesn’t fill anything in.
+///
+/// # Errors
+///
+/// Returns an error if the configuration cannot be read or parsed.
+fn load_config_layers(path: &Path) -> anyhow::Result<repos::Config> {
+    Ok(repos::Config::load_layered(&config_layers(Some(path))?)?)
+}
+
+/// Get the scan configuration files to merge, lowest precedence first.
+///
+/// See [`repos::Config::layers()`].
+///
+/// # Errors
+///
+/// Returns an error if `path` isn’t passed and no configuration file exists.
+fn config_layers(path: Option<&Path>) -> anyhow::Result<Vec<PathBuf>> {
+    let names = |paths: &[PathBuf]| {
+        let names: Vec<_> = paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        names.join(", ")
+    };
+    let layers = repos::Config::layers(path);
+    if layers.is_empty() {
+        bail!(
+            "No configuration file passed or found in {}",
+            names(&repos::Config::default_paths())
+        );
+    }
+    tracing::info!("Using configuration files {}", names(&layers));
+    Ok(layers)
+}
+
+/// Fill in the identity and cache directory of a scan configuration.
+///
+/// A `cache_dir` passed on the command line overrides the configuration file.
+///
+/// # Errors
+///
+/// Returns an error if the git configuration cannot be read.
+fn prepare_scan_config(
+    config: repos::Config,
+    cache_dir: Option<&PathBuf>,
+) -> anyhow::Result<repos::Config> {
+    let mut config = config.detect_identity()?;
+    config.cache_dir = cache_dir
+        .cloned()
+        .or(config.cache_dir)
+        .or_else(CacheDir::default_path);
+    Ok(config)
+}
+
+/// Configuration files for the server, along with the command line options
+/// that add to them.
+#[derive(Clone, Debug)]
+struct ConfigFiles {
+    /// Scan configuration files to merge, lowest precedence first.
+    scan_config: Vec<PathBuf>,
+
+    /// Extra directory trees to scan.
+    repo_roots: Vec<PathBuf>,
+
+    /// Cache directory that overrides the scan configuration.
+    cache_dir: Option<PathBuf>,
+
+    /// Users configuration file.
+    users_config: Option<PathBuf>,
+}
+
+impl ConfigFiles {
+    /// Read the configuration files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a configuration file cannot be loaded.
+    fn load(&self) -> anyhow::Result<ReloadableConfig> {
+        let cache_dir = self.cache_dir.as_ref();
+        let mut scan_config = (!self.scan_config.is_empty())
+            .then(|| load_scan_config(&self.scan_config, cache_dir))
+            .transpose()?;
+        let roots = self.repo_roots.iter().cloned().map(TreeConfig::from);
+        if let Some(config) = &mut scan_config {
+            config.repos.extend(roots);
+        } else if !self.repo_roots.is_empty() {
+            let config = repos::Config {
+                repos: roots.collect(),
+                ..repos::Config::default()
+            };
+            scan_config = Some(prepare_scan_config(config, cache_dir)?);
+        }
+
+        let users = if let Some(path) = &self.users_config {
+            Users::parse(&fs::read_to_string(path)?)?
+        } else {
+            Users::default()
+        };
+        Ok(ReloadableConfig { scan_config, users })
+    }
+}
+
+/// Start the API server.
+///
+/// # Errors
+///
+/// Returns an error if a configuration file cannot be loaded or the server
+/// fails.
+fn serve(
+    serve_params: &params::ServeParams,
+    log: &slog::Logger,
+) -> anyhow::Result<()> {
+    let files = ConfigFiles {
+        scan_config: repos::Config::layers(serve_params.scan_config.as_deref()),
+        repo_roots: serve_params.repo_roots.clone(),
+        cache_dir: serve_params.cache_dir.clone(),
+        users_config: serve_params.users_config.clone(),
+    };
+    let ReloadableConfig { scan_config, users } = files.load()?;
+
+    let notifier = Notifier {
+        email: serve_params
+            .smtp_url
+            .as_ref()
+            .zip(serve_params.summary_email_to.as_ref())
+            .map(|(smtp_url, to)| EmailConfig {
+                smtp_url: smtp_url.clone(),
+                from: serve_params.summary_email_from.clone(),
+                to: to.clone(),
+            }),
+        webhook: serve_params.webhook_url.as_ref().map(|url| WebhookConfig {
+            url: url.clone(),
+            template: serve_params.webhook_template.clone(),
+            http_client: reqwest::Client::new(),
+        }),
+        summary_schedule: serve_params.summary_schedule,
+        check_schedule: serve_params.check_schedule,
+    };
+
+    let report_store = serve_params
+        .reports_dir
+        .clone()
+        .or_else(ReportStore::default_path)
+        .map(|directory| ReportStore { directory });
+    let mut reports = report_store
+        .as_ref()
+        .map(ReportStore::load)
+        .transpose()?
+        .unwrap_or_default();
+    // Ignore reports from agents that are no longer allowed.
+    reports.retain(|agent, _| {
+        serve_params
+            .agent_keys
+            .iter()
+            .any(|key| key.agent == *agent)
+    });
+
+    let readiness = if scan_config.is_some() {
+        Readiness::warming_up()
+    } else {
+        Readiness::default()
+    };
+    let state = AppState {
+        github_client_id: serve_params.github_client_id.clone(),
+        github_client_secret: serve_params.github_client_secret.clone(),
+        http_client: reqwest::Client::new(),
+        github_backoff: Arc::default(),
+        github_status: Arc::default(),
+        device_flows: Arc::default(),
+        scan_config: Arc::new(scan_config.into()),
+        agent_keys: serve_params.agent_keys.clone(),
+        reports: Arc::new(Mutex::new(reports)),
+        report_store,
+        users: Arc::new(RwLock::new(users)),
+        config_loader: Arc::new(move || files.load()),
+        admin_token: serve_params.admin_token.clone(),
+        scan_cache: Arc::default(),
+        contributions_updates: broadcast::Sender::new(16),
+        contributions_scan: Arc::default(),
+        scan_status: Arc::default(),
+        scan_permits: Arc::new(Semaphore::new(serve_params.max_scans.into())),
+        scan_threads: serve_params
+            .scan_threads
+            .or_else(|| thread::available_parallelism().ok())
+            .unwrap_or(NonZeroUsize::MIN),
+        readiness: Arc::new(readiness),
+    };
+
+    let warmup_timeout = Duration::from_secs(serve_params.warmup_timeout);
+    let access_log = serve_params
+        .access_log
+        .as_deref()
+        .map(|path| {
+            AccessLog::open(path, serve_params.access_log_format).map_err(
+                |error| anyhow!("Could not open access log {path:?}: {error}"),
+            )
+        })
+        .transpose()?;
+    server::serve(
+        &serve_params.bind,
+        state,
+        notifier,
+        warmup_timeout,
+        access_log,
+        log,
+    )
+}
+
+/// Scan on a schedule and report to a central server, forever.
+///
+/// # Errors
+///
+/// Returns an error if the scan configuration cannot be loaded or the runtime
+/// cannot be started.
+fn run_agent(agent_params: &params::AgentParams) -> anyhow::Result<()> {
+    let config =
+        load_scan_config(&config_layers(Some(&agent_params.config))?, None)?;
+    let agent = Agent {
+        server_url: agent_params.server.clone(),
+        api_key: agent_params.api_key.clone(),
+        queue: Queue { directory: agent_params.queue_dir.clone() },
+        max_retries: agent_params.max_retries,
+        http_client: reqwest::Client::new(),
+    };
+    tokio::runtime::Runtime::new()?.block_on(agent.run(
+        &agent_params.name,
+        &config,
+        Duration::from_secs(agent_params.interval),
+    ));
+    Ok(())
+}
+
+/// Inspect or clear the cache directory.
+///
+/// # Errors
+///
+/// Returns an error if there is no cache directory or it cannot be read or
+/// cleared.
+fn cache(command: &params::CacheCommand) -> anyhow::Result<()> {
+    use params::CacheCommand;
+
+    let (CacheCommand::Status(cache_params)
+    | CacheCommand::Clear(cache_params)) = command;
+    let cache = cache_params
+        .cache_dir
+        .clone()
+        .or_else(CacheDir::default_path)
+        .map(CacheDir::new)
+        .ok_or_else(|| anyhow!("Could not find cache directory"))?;
+
+    match command {
+        CacheCommand::Status(_) => print!("{}", cache.status()?),
+        CacheCommand::Clear(_) => {
+            let files = cache.clear()?;
+            println!("Deleted {files} files from {}", cache.path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Manage the identity in a scan configuration.
+///
+/// # Errors
+///
+/// Returns an error if the configuration cannot be read, parsed, or written.
+fn identity(
+    params: &Params,
+    command: &params::IdentityCommand,
+) -> anyhow::Result<()> {
+    use params::IdentityCommand;
+
+    match command {
+        IdentityCommand::List(list_params) => {
+            let config = load_config_layers(&list_params.config)?;
+            let identity = if config.identity.is_empty() {
+                println!("# No identity configured; using git configuration.");
+                config.identity.or_detect()?
+            } else {
+                config.identity
+            };
+            for email in &identity.emails {
+                println!("email: {email}");
+            }
+            for name in &identity.names {
+                println!("name: {name}");
+            }
+            if let Some(github) = &identity.github {
+                println!("github: {github}");
+            }
+        }
+        IdentityCommand::Add(edit_params)
+        | IdentityCommand::Remove(edit_params) => {
+            let path = &edit_params.config;
+            if repos::ConfigFormat::from_path(path) != repos::ConfigFormat::Toml
+            {
+                bail!(
+                    "Only TOML configs can be edited; edit the identity in {} \
+                    by hand",
+                    path.display()
+                );
+            }
+            let mut identity = repos::Config::load(path)?.identity;
+            if let IdentityCommand::Add(_) = command {
+                for email in &edit_params.emails {
+                    if !identity
+                        .emails
+                        .iter()
+                        .any(|known| known.eq_ignore_ascii_case(email.trim()))
+                    {
+                        identity.emails.push(email.trim().to_owned());
+                    }
+                }
+                for name in &edit_params.names {
+                    if !identity.names.contains(name) {
+                        identity.names.push(name.trim().to_owned());
+                    }
+                }
+            } else {
+                identity.emails.retain(|known| {
+                    !edit_params
+                        .emails
+                        .iter()
+                        .any(|email| known.eq_ignore_ascii_case(email.trim()))
+                });
+                identity
+                    .names
+                    .retain(|known| !edit_params.names.contains(known));
+            }
+            let input = fs::read_to_string(path)?;
+            fs::write(path, identity.update_config(&input)?)?;
+        }
+        IdentityCommand::Detect(detect_params) => {
+            let config =
+                load_config_layers(&detect_params.config)?.detect_identity()?;
+            let mut counts = BTreeMap::new();
+            for result in config.repo_iter() {
+                if let Err(error) =
+                    result.map_err(anyhow::Error::from).and_then(|(_, repo)| {
+                        config.identity.count_unclaimed(&repo, &mut counts)
+                    })
+                {
+                    params.warn(format!("Warning: {error}\n"))?;
+                }
+            }
+
+            let mut authors: Vec<_> = counts.into_values().collect();
+            authors.sort_by(|a, b| {
+                b.commits
+                    .cmp(&a.commits)
+                    .then_with(|| a.email.cmp(&b.email))
+            });
+            for author in authors.iter().take(detect_params.limit) {
+                println!(
+                    "{:>6}  {} ({})",
+                    author.commits, author.email, author.name
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// List commits with their authors as JSON lines for `repos commits`.
+///
+/// # Errors
+///
+/// Returns an error if the configuration can’t be loaded, or if writing
+/// output fails.
+fn list_commits(
+    params: &Params,
+    commits_params: &params::ReposCommitsParams,
+) -> anyhow::Result<()> {
+    let config =
+        load_config_layers(&commits_params.config)?.detect_identity()?;
+    let anonymizer = commits_params
+        .anonymize_salt
+        .as_deref()
+        .map(repos::Anonymizer::new);
+    for result in config.repo_iter() {
+        let result =
+            result
+                .map_err(anyhow::Error::from)
+                .and_then(|(name, repo)| {
+                    let commits = repos::scan_authored_commits(
+                        &repo,
+                        &config.scan_options(&repo)?,
+                    )?;
+                    Ok((name, commits))
+                });
+        let (name, commits) = match result {
+            Ok(result) => result,
+            Err(error) => {
+                params.warn(format!("Warning: {error}\n"))?;
+                continue;
+            }
+        };
+        for mut commit in commits {
+            if let Some(anonymizer) = &anonymizer {
+                commit = commit.anonymize(anonymizer);
+            }
+            let mut line = serde_json::to_value(commit)?;
+            line["repo"] = name.clone().into();
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+/// Report how many commits were signed on standard error.
+fn report_signatures(summaries: &BTreeMap<String, Vec<repos::CommitSummary>>) {
+    let commits = summaries.values().flatten();
+    let signatures: Vec<_> = commits
+        .clone()
+        .filter_map(|commit| commit.signature.as_ref())
+        .collect();
+    let verified = signatures
+        .iter()
+        .filter(|signature| signature.verified == Some(true))
+        .count();
+    eprint!(
+        "{} of {} commits are signed",
+        signatures.len(),
+        commits.count()
+    );
+    if signatures
+        .iter()
+        .any(|signature| signature.verified.is_some())
+    {
+        eprint!(" ({verified} verified)");
+    }
+    eprintln!();
+}
+
+/// Handle the `repos commit-graph` subcommand.
+///
+/// # Errors
+///
+/// Returns an error if the configuration cannot be read.
+fn write_commit_graphs(
+    params: &Params,
+    graph_params: &params::ReposCommitGraphParams,
+) -> anyhow::Result<()> {
+    let config = load_config_layers(&graph_params.config)?;
+    for result in config.repo_iter() {
+        let (name, repo) = match result {
+            Ok(result) => result,
+            Err(error) => {
+                params.warn(format!("Warning: {error}\n"))?;
+                continue;
+            }
+        };
+        if !graph_params.force && repos::has_commit_graph(&repo) {
+            println!("exists  {name}");
+            continue;
+        }
+        match repos::write_commit_graph(&repo) {
+            Ok(()) => println!("wrote   {name}"),
+            Err(error) => {
+                params.warn(format!("Error in {name}: {error}\n"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Handle the `repos` subcommands.
+///
+/// # Errors
+///
+/// Returns an error if the configuration or stdin cannot be read.
+fn repos(
+    params: &Params,
+    command: &params::ReposCommand,
+) -> anyhow::Result<()> {
+    use params::ReposCommand;
+
+    match command {
+        ReposCommand::List(list_params) => {
+            let config = load_config_layers(&list_params.config)?;
+            for result in config.repo_iter() {
+                match result {
+                    Ok((name, repo)) => {
+                        match repos::remote_kind(
+                            &repo,
+                            config.skip_remotes_for(&repo),
+                        ) {
+                            Ok(kind) => {
+                                let action =
+                                    if kind == repos::RemoteKind::Skipped {
+                                        "unpushed"
+                                    } else {
+                                        "all"
+                                    };
+                                let path = repo
+                                    .workdir()
+                                    .unwrap_or_else(|| repo.path());
+                                println!(
+                                    "{action:<8}  {kind:<7}  {name}  {}",
+                                    path.display()
+                                );
+                            }
+                            Err(error) => {
+                                params.warn(format!(
+                                    "Error in {name}: {error}\n"
+                                ))?;
+                            }
+                        }
+                    }
+                    Err(error) => params.warn(format!("Warning: {error}\n"))?,
+                }
+            }
+        }
+        ReposCommand::Name(name_params) => {
+            let config = load_config_layers(&name_params.config)?;
+            let paths = if name_params.paths.is_empty() {
+                io::stdin()
+                    .lines()
+                    .filter(|line| {
+                        line.as_ref().is_ok_and(|line| !line.is_empty())
+                    })
+                    .map(|line| line.map(PathBuf::from))
+                    .collect::<io::Result<_>>()?
+            } else {
+                name_params.paths.clone()
+            };
+            for path in paths {
+                let path = std::path::absolute(&path)?;
+                let names: Vec<_> = config
+                    .repos
+                    .iter()
+                    .filter_map(|tree| tree.name(&path))
+                    .chain(
+                        config
+                            .single_repos
+                            .iter()
+                            .filter(|repo| {
+                                std::path::absolute(&repo.path)
+                                    .is_ok_and(|repo_path| repo_path == path)
+                            })
+                            .map(|repo| repo.name.clone()),
+                    )
+                    .collect();
+                if names.is_empty() {
+                    params.warn(format!(
+                        "{}: not under any configured root\n",
+                        path.display()
+                    ))?;
+                }
+                for name in names {
+                    println!("{}  {name}", path.display());
+                }
+            }
+        }
+        ReposCommand::Commits(commits_params) => {
+            list_commits(params, commits_params)?;
+        }
+        ReposCommand::CommitGraph(graph_params) => {
+            write_commit_graphs(params, graph_params)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check a scan configuration file and report what it would scan.
+///
+/// Each tree’s root must be readable, and each repository found in it must
+/// open. Individual repositories must open, and there must be an identity to
+/// count commits for.
+///
+/// # Errors
+///
+/// Returns an error if the configuration cannot be loaded or output cannot
+/// be written. Other problems are reported as warnings, and make this return
+/// [`ExitCode::FAILURE`].
+fn config_check(
+    params: &Params,
+    check_params: &params::ConfigCheckParams,
+) -> anyhow::Result<ExitCode> {
+    let config = load_config_layers(&check_params.config)?;
+    let mut problems = 0_usize;
+    for check in config.check() {
+        match check {
+            repos::Check::Tree { root, found } => {
+                println!("{found:>6} repositories  {}", root.display());
+            }
+            repos::Check::Repo { path } => {
+                println!("{:>6} repository    {}", 1, path.display());
+            }
+            repos::Check::Problem(message) => {
+                problems = problems.saturating_add(1);
+                params.warn(message + "\n")?;
+            }
+        }
+    }
+
+    if problems > 0 {
+        params.warn(format!("Found {problems} problem(s)\n"))?;
+        Ok(ExitCode::FAILURE)
+    } else {
+        println!("Configuration OK");
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
 /// Generate `OpenAPI` specification.
 ///
 /// Uses the trait-based API stub to generate the spec without requiring
@@ -131,3 +1205,69 @@ fn generate_openapi(params: &params::OpenapiParams) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Generate JSON Schema for a file format.
+///
+/// # Errors
+///
+/// Returns an error if the schema cannot be written.
+fn generate_schema(params: &params::SchemaParams) -> anyhow::Result<()> {
+    let schema = match params.format {
+        params::SchemaFormat::Scan => schemars::schema_for!(ScanOutput),
+        params::SchemaFormat::Github => schemars::schema_for!(StaticData),
+        params::SchemaFormat::Config => schemars::schema_for!(repos::Config),
+    };
+    let json_string = serde_json::to_string_pretty(&schema)?;
+
+    if let Some(output_path) = &params.output {
+        std::fs::write(output_path, format!("{json_string}\n"))?;
+    } else {
+        println!("{json_string}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use testdir::testdir;
+
+    /// Get the roots of the trees in a scan configuration.
+    fn roots(config: &ReloadableConfig) -> Vec<PathBuf> {
+        config
+            .scan_config
+            .iter()
+            .flat_map(|config| &config.repos)
+            .map(|tree| tree.root.clone())
+            .collect()
+    }
+
+    #[test]
+    fn config_files_repo_roots() {
+        let dir = testdir!();
+        let files = ConfigFiles {
+            scan_config: Vec::new(),
+            repo_roots: Vec::new(),
+            cache_dir: Some(dir.join("cache")),
+            users_config: None,
+        };
+        assert!(files.load().unwrap().scan_config == None);
+
+        let files = ConfigFiles { repo_roots: vec![dir.join("a")], ..files };
+        let config = files.load().unwrap();
+        assert!(roots(&config) == [dir.join("a")]);
+        assert!(
+            config.scan_config.unwrap().cache_dir == Some(dir.join("cache"))
+        );
+
+        let path = dir.join("config.toml");
+        fs::write(&path, "[[repos]]\nroot = '/b'\n").unwrap();
+        let files = ConfigFiles { scan_config: vec![path], ..files };
+        assert!(
+            roots(&files.load().unwrap())
+                == [PathBuf::from("/b"), dir.join("a")]
+        );
+    }
+}
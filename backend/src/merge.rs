@@ -0,0 +1,374 @@
+//! Merge reports from multiple agents.
+//!
+//! Different machines often have clones of the same repository. Repositories
+//! are matched by remote URL when they have one, and by name otherwise. Their
+//! commits are combined and deduplicated by commit ID, so a commit that exists
+//! on several machines is only counted once.
+//!
+//! Merging is deterministic: reports are applied from oldest to newest scan
+//! (ties are broken by agent name), and the newest report wins when reports
+//! disagree. Disagreements are returned as [`Conflict`]s.
+//!
+//! The same thing happens on one machine when a repository is cloned under
+//! more than one root. [`drop_clones()`](crate::repos::drop_clones) keeps only
+//! one of the clones.
+
+use crate::agent::Report;
+use crate::api::definition::LocalContributions;
+use crate::repos::{Commit, normalize_remote};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A repository combined from one or more reports.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MergedRepo {
+    /// Name from the newest report.
+    pub name: String,
+
+    /// Remote URL from the newest report.
+    pub remote: Option<String>,
+
+    /// When the newest report including this repository was scanned.
+    pub scanned_at: i64,
+
+    /// Agents that reported this repository.
+    pub agents: Vec<String>,
+
+    /// Commit times (seconds since epoch) by commit ID.
+    pub commits: BTreeMap<String, i64>,
+}
+
+/// The result of merging reports.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Merged {
+    /// Repositories by key (normalized remote URL, or name).
+    pub repos: BTreeMap<String, MergedRepo>,
+
+    /// Disagreements between reports.
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Merged {
+    /// Get commit times for each repository by name.
+    ///
+    /// Different repositories with the same name are combined, but a commit
+    /// that appears in more than one of them (e.g. in a fork) is only counted
+    /// once.
+    #[must_use]
+    pub fn contributions(&self) -> LocalContributions {
+        let mut by_name: BTreeMap<&str, BTreeMap<&str, i64>> = BTreeMap::new();
+        for repo in self.repos.values() {
+            by_name.entry(&repo.name).or_default().extend(
+                repo.commits.iter().map(|(oid, time)| (oid.as_str(), *time)),
+            );
+        }
+        by_name
+            .into_iter()
+            .map(|(name, commits)| {
+                (name.to_owned(), commits.into_values().collect())
+            })
+            .collect()
+    }
+
+    /// Drop repositories that were also scanned locally.
+    ///
+    /// Local scans only produce commit times, so commits can’t be matched
+    /// with reports by ID. Instead, repositories are matched the same way
+    /// reports are matched with each other: by [normalized](normalize_remote)
+    /// remote URL, or by name if the local repository has no remote. The
+    /// local scan wins, since it’s current.
+    ///
+    /// `remotes` maps local repository names to remote URLs, as in
+    /// [`drop_clones()`](crate::repos::drop_clones).
+    ///
+    /// Returns the names of the repositories that were dropped, sorted.
+    pub fn drop_local(
+        &mut self,
+        local: &LocalContributions,
+        remotes: &BTreeMap<String, String>,
+    ) -> Vec<String> {
+        let mut dropped: Vec<String> = local
+            .keys()
+            .filter_map(|name| {
+                let key = remotes
+                    .get(name)
+                    .map_or_else(|| name.clone(), |url| normalize_remote(url));
+                self.repos.remove(&key).map(|repo| repo.name)
+            })
+            .collect();
+        dropped.sort();
+        dropped
+    }
+}
+
+/// A disagreement between reports.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Conflict {
+    /// The same remote was reported under different names.
+    Name {
+        /// Remote URL.
+        remote: String,
+        /// Name from the older report.
+        old: String,
+        /// Name from the newer report, which is used.
+        new: String,
+    },
+
+    /// Different remotes were reported under the same name.
+    Remote {
+        /// Repository name.
+        name: String,
+        /// Remote URLs.
+        remotes: Vec<String>,
+    },
+
+    /// The same commit was reported with different times.
+    Time {
+        /// Repository name.
+        name: String,
+        /// Commit ID.
+        oid: String,
+        /// Time from the older report.
+        old: i64,
+        /// Time from the newer report, which is used.
+        new: i64,
+    },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name { remote, old, new } => write!(
+                f,
+                "Remote {remote:?} reported as both {old:?} and {new:?}",
+            ),
+            Self::Remote { name, remotes } => write!(
+                f,
+                "Repository {name:?} reported with different remotes: {}",
+                remotes.join(", "),
+            ),
+            Self::Time { name, oid, old, new } => write!(
+                f,
+                "Commit {oid} in {name:?} reported with times {old} and {new}",
+            ),
+        }
+    }
+}
+
+/// Merge reports.
+///
+/// The order of `reports` doesn’t matter.
+#[must_use]
+pub fn merge<'a, I>(reports: I) -> Merged
+where
+    I: IntoIterator<Item = &'a Report>,
+{
+    let mut reports: Vec<&Report> = reports.into_iter().collect();
+    reports.sort_by(|a, b| {
+        (a.scanned_at, &a.agent).cmp(&(b.scanned_at, &b.agent))
+    });
+
+    let mut merged = Merged::default();
+    for report in reports {
+        for (name, repo_report) in &report.repos {
+            let key = repo_report
+                .remote
+                .as_deref()
+                .map_or_else(|| name.clone(), normalize_remote);
+            let repo = merged.repos.entry(key).or_insert_with(|| MergedRepo {
+                name: name.clone(),
+                remote: repo_report.remote.clone(),
+                scanned_at: report.scanned_at,
+                agents: Vec::new(),
+                commits: BTreeMap::new(),
+            });
+
+            if repo.name != *name {
+                merged.conflicts.push(Conflict::Name {
+                    remote: repo_report.remote.clone().unwrap_or_default(),
+                    old: repo.name.clone(),
+                    new: name.clone(),
+                });
+            }
+            repo.name.clone_from(name);
+            repo.remote.clone_from(&repo_report.remote);
+            repo.scanned_at = report.scanned_at;
+            if !repo.agents.contains(&report.agent) {
+                repo.agents.push(report.agent.clone());
+            }
+
+            for Commit { oid, time } in &repo_report.commits {
+                if let Some(old) = repo.commits.insert(oid.clone(), *time)
+                    && old != *time
+                {
+                    merged.conflicts.push(Conflict::Time {
+                        name: name.clone(),
+                        oid: oid.clone(),
+                        old,
+                        new: *time,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut remotes_by_name: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for repo in merged.repos.values() {
+        remotes_by_name
+            .entry(&repo.name)
+            .or_default()
+            .push(repo.remote.clone().unwrap_or_default());
+    }
+    for (name, remotes) in remotes_by_name {
+        if remotes.len() > 1 {
+            merged
+                .conflicts
+                .push(Conflict::Remote { name: name.to_owned(), remotes });
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RepoReport;
+    use assert2::assert;
+
+    /// Repository name, remote, and commits as `(n, time)`.
+    type TestRepo<'a> = (&'a str, Option<&'a str>, &'a [(u8, i64)]);
+
+    fn oid(n: u8) -> String {
+        format!("{n:040x}")
+    }
+
+    fn report(agent: &str, scanned_at: i64, repos: &[TestRepo<'_>]) -> Report {
+        Report {
+            agent: agent.to_owned(),
+            scanned_at,
+            repos: repos
+                .iter()
+                .map(|(name, remote, commits)| {
+                    (
+                        (*name).to_owned(),
+                        RepoReport {
+                            remote: remote.map(ToOwned::to_owned),
+                            commits: commits
+                                .iter()
+                                .map(|&(n, time)| Commit { oid: oid(n), time })
+                                .collect(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn union_dedups_by_oid() {
+        let a = report("a", 100, &[("repo", None, &[(1, 10), (2, 20)])]);
+        let b = report("b", 200, &[("repo", None, &[(2, 20), (3, 30)])]);
+        let merged = merge([&a, &b]);
+
+        assert!(merged.conflicts.is_empty());
+        let repo = &merged.repos["repo"];
+        assert!(repo.agents == ["a", "b"]);
+        assert!(repo.scanned_at == 200);
+        assert!(repo.commits.len() == 3);
+        assert!(merged.contributions()["repo"].len() == 3);
+    }
+
+    #[test]
+    fn match_by_remote_newest_name_wins() {
+        let a = report(
+            "a",
+            200,
+            &[("new-name", Some("git@example.com:repo.git"), &[(1, 10)])],
+        );
+        let b = report(
+            "b",
+            100,
+            &[("old-name", Some("https://example.com/repo"), &[(2, 20)])],
+        );
+
+        // Order of the input doesn’t matter.
+        let merged = merge([&a, &b]);
+        assert!(merge([&b, &a]) == merged);
+
+        assert!(merged.repos.len() == 1);
+        let repo = &merged.repos["example.com/repo"];
+        assert!(repo.name == "new-name");
+        assert!(repo.commits.len() == 2);
+        assert!(
+            merged.conflicts
+                == [Conflict::Name {
+                    remote: "git@example.com:repo.git".to_owned(),
+                    old: "old-name".to_owned(),
+                    new: "new-name".to_owned(),
+                }]
+        );
+    }
+
+    #[test]
+    fn conflicting_times() {
+        let a = report("a", 100, &[("repo", None, &[(1, 10)])]);
+        let b = report("b", 200, &[("repo", None, &[(1, 11)])]);
+        let merged = merge([&b, &a]);
+
+        assert!(merged.repos["repo"].commits[&oid(1)] == 11);
+        assert!(let [Conflict::Time { old: 10, new: 11, .. }] = merged.conflicts.as_slice());
+    }
+
+    #[test]
+    fn same_name_different_remotes() {
+        let a =
+            report("a", 100, &[("repo", Some("https://a.test/r"), &[(1, 10)])]);
+        let b =
+            report("b", 100, &[("repo", Some("https://b.test/r"), &[(2, 20)])]);
+        let merged = merge([&a, &b]);
+
+        assert!(merged.repos.len() == 2);
+        assert!(let [Conflict::Remote { .. }] = merged.conflicts.as_slice());
+        assert!(merged.contributions()["repo"].len() == 2);
+    }
+
+    #[test]
+    fn same_name_shared_commits() {
+        let a = report(
+            "a",
+            100,
+            &[("repo", Some("https://a.test/r"), &[(1, 10), (2, 20)])],
+        );
+        let b =
+            report("b", 100, &[("repo", Some("https://b.test/r"), &[(2, 20)])]);
+        assert!(merge([&a, &b]).contributions()["repo"] == [10, 20]);
+    }
+
+    #[test]
+    fn drop_locally_scanned() {
+        let report = report(
+            "a",
+            100,
+            &[
+                ("remote", Some("git@example.com:repo.git"), &[(1, 10)]),
+                ("plain", None, &[(2, 20)]),
+                ("other", Some("https://example.com/other"), &[(3, 30)]),
+            ],
+        );
+        let mut merged = merge([&report]);
+        let local = LocalContributions::from([
+            ("renamed".to_owned(), vec![10]),
+            ("plain".to_owned(), vec![20]),
+            ("unreported".to_owned(), vec![40]),
+        ]);
+        let remotes = BTreeMap::from([(
+            "renamed".to_owned(),
+            "https://example.com/repo".to_owned(),
+        )]);
+
+        assert!(merged.drop_local(&local, &remotes) == ["plain", "remote"]);
+        let contributions = merged.contributions();
+        assert!(contributions.keys().collect::<Vec<_>>() == ["other"]);
+    }
+}
@@ -0,0 +1,318 @@
+//! Notifications about contribution activity.
+//!
+//! This module is organized into:
+//! - [`email`] - Sending notifications over SMTP
+//! - [`webhook`] - Sending notifications to chat services
+
+pub mod email;
+pub mod webhook;
+
+use crate::api::definition::LocalContributions;
+use crate::repos::Calendar;
+use crate::schedule::Schedule;
+use chrono::{DateTime, Days, NaiveDate, TimeZone};
+use std::collections::BTreeSet;
+use std::{fmt, iter};
+
+/// Where and when to send notifications.
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    /// Email settings, if email notifications are enabled.
+    pub email: Option<email::EmailConfig>,
+
+    /// Webhook settings, if webhook notifications are enabled.
+    pub webhook: Option<webhook::WebhookConfig>,
+
+    /// When to send a [`Summary`].
+    pub summary_schedule: Schedule,
+
+    /// When to check for an [`Alert`].
+    pub check_schedule: Schedule,
+}
+
+impl Notifier {
+    /// Whether any notification targets are configured.
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.email.is_some() || self.webhook.is_some()
+    }
+
+    /// Send a notification to all configured targets.
+    ///
+    /// Failures are logged rather than returned so that one broken target
+    /// doesn’t prevent notifications from reaching the others.
+    pub async fn send(&self, subject: &str, body: &str) {
+        if let Some(email) = &self.email
+            && let Err(error) = email.send(subject, body).await
+        {
+            tracing::error!("Could not send email notification: {error}");
+        }
+
+        if let Some(webhook) = &self.webhook
+            && let Err(error) = webhook.send(subject, body).await
+        {
+            tracing::error!("Could not send webhook notification: {error}");
+        }
+    }
+}
+
+/// A problem that needs attention.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Alert {
+    /// Some repositories could not be scanned.
+    ScanFailed(Vec<String>),
+
+    /// There is a streak, but no commits yet today.
+    StreakEnding(usize),
+}
+
+impl Alert {
+    /// Find alerts based on the results of a scan.
+    ///
+    /// `errors` are the messages from repositories that could not be scanned.
+    #[must_use]
+    pub fn check(summary: &Summary, errors: Vec<String>) -> Vec<Self> {
+        let mut alerts = Vec::new();
+        if !errors.is_empty() {
+            alerts.push(Self::ScanFailed(errors));
+        }
+        if summary.streak > 0 && !summary.active_today {
+            alerts.push(Self::StreakEnding(summary.streak));
+        }
+        alerts
+    }
+
+    /// A subject line for this alert.
+    #[must_use]
+    pub fn subject(&self) -> String {
+        match self {
+            Self::ScanFailed(errors) => {
+                format!("RepoYear: scan failed for {} repos", errors.len())
+            }
+            Self::StreakEnding(days) => {
+                format!("RepoYear: {days} day streak about to break")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ScanFailed(errors) => {
+                writeln!(f, "Some repositories could not be scanned:\n")?;
+                for error in errors {
+                    writeln!(f, "  {error}")?;
+                }
+                Ok(())
+            }
+            Self::StreakEnding(days) => writeln!(
+                f,
+                "You have committed every day for {days} days, but haven’t \
+                committed anything today."
+            ),
+        }
+    }
+}
+
+/// Number of repositories listed in a [`Summary`].
+const TOP_REPOS: usize = 3;
+
+/// Summary of contributions over the week before a given day.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Summary {
+    /// First day of the week summarized.
+    pub start: NaiveDate,
+
+    /// Last day of the week summarized.
+    pub end: NaiveDate,
+
+    /// Number of commits during the week.
+    pub commits: usize,
+
+    /// Number of consecutive days with commits ending today or yesterday.
+    pub streak: usize,
+
+    /// Whether there have been commits today.
+    pub active_today: bool,
+
+    /// The most active repositories during the week, with commit counts.
+    pub top_repos: Vec<(String, usize)>,
+}
+
+impl Summary {
+    /// Summarize the seven days before `now`.
+    ///
+    /// Days are calculated in the time zone of `now`.
+    #[must_use]
+    pub fn new<Tz: TimeZone>(
+        contributions: &LocalContributions,
+        now: &DateTime<Tz>,
+    ) -> Self {
+        let tz = now.timezone();
+        let today = now.date_naive();
+        let end = today.pred_opt().unwrap_or(NaiveDate::MIN);
+        let start = today
+            .checked_sub_days(Days::new(7))
+            .unwrap_or(NaiveDate::MIN);
+
+        let mut active_days = BTreeSet::new();
+        let mut top_repos = Vec::new();
+        for (repo, times) in contributions {
+            let dates: Vec<_> = times
+                .iter()
+                .filter_map(|&time| local_date(&tz, time))
+                .collect();
+            let count = dates
+                .iter()
+                .filter(|date| (start..=end).contains(*date))
+                .count();
+            if count > 0 {
+                top_repos.push((repo.clone(), count));
+            }
+            active_days.extend(dates);
+        }
+
+        let active_today = active_days.contains(&today);
+        let streak = iter::successors(
+            Some(if active_today { today } else { end }),
+            NaiveDate::pred_opt,
+        )
+        .take_while(|day| active_days.contains(day))
+        .count();
+
+        top_repos.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let commits = top_repos.iter().map(|(_, count)| count).sum();
+        top_repos.truncate(TOP_REPOS);
+
+        Self { start, end, commits, streak, active_today, top_repos }
+    }
+
+    /// A subject line for this summary.
+    #[must_use]
+    pub fn subject(&self) -> String {
+        format!("RepoYear summary for {} to {}", self.start, self.end)
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} commits from {} to {}.",
+            self.commits, self.start, self.end
+        )?;
+
+        match (self.streak, self.active_today) {
+            (0, _) => writeln!(f, "No current streak.")?,
+            (days, true) => writeln!(f, "Current streak: {days} days.")?,
+            (days, false) => writeln!(
+                f,
+                "Current streak: {days} days (no commits yet today)."
+            )?,
+        }
+
+        if !self.top_repos.is_empty() {
+            writeln!(f, "\nMost active repositories:")?;
+            for (repo, count) in &self.top_repos {
+                writeln!(f, "  {repo}: {count} commits")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert seconds since the epoch to a date in `tz`.
+fn local_date<Tz: TimeZone>(tz: &Tz, seconds: i64) -> Option<NaiveDate> {
+    Calendar::new(tz.clone()).date(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use chrono::Utc;
+
+    fn seconds(input: &str) -> i64 {
+        input.parse::<DateTime<Utc>>().unwrap().timestamp()
+    }
+
+    fn date(input: &str) -> NaiveDate {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn summary_empty() {
+        let now: DateTime<Utc> = "2024-03-11T08:00:00Z".parse().unwrap();
+        assert!(
+            Summary::new(&LocalContributions::new(), &now)
+                == Summary {
+                    start: date("2024-03-04"),
+                    end: date("2024-03-10"),
+                    commits: 0,
+                    streak: 0,
+                    active_today: false,
+                    top_repos: vec![],
+                }
+        );
+    }
+
+    #[test]
+    fn summary_week() {
+        let now: DateTime<Utc> = "2024-03-11T08:00:00Z".parse().unwrap();
+        let contributions = LocalContributions::from([
+            (
+                "a".to_owned(),
+                vec![
+                    seconds("2024-03-03T12:00:00Z"), // Before the week.
+                    seconds("2024-03-09T12:00:00Z"),
+                    seconds("2024-03-10T12:00:00Z"),
+                ],
+            ),
+            (
+                "b".to_owned(),
+                vec![
+                    seconds("2024-03-04T00:00:00Z"),
+                    seconds("2024-03-04T01:00:00Z"),
+                    seconds("2024-03-10T23:59:59Z"),
+                ],
+            ),
+            ("c".to_owned(), vec![seconds("2024-03-11T07:00:00Z")]),
+        ]);
+
+        assert!(
+            Summary::new(&contributions, &now)
+                == Summary {
+                    start: date("2024-03-04"),
+                    end: date("2024-03-10"),
+                    commits: 5,
+                    streak: 3,
+                    active_today: true,
+                    top_repos: vec![("b".to_owned(), 3), ("a".to_owned(), 2)],
+                }
+        );
+    }
+
+    #[test]
+    fn alerts() {
+        let summary = Summary {
+            start: date("2024-03-04"),
+            end: date("2024-03-10"),
+            commits: 5,
+            streak: 3,
+            active_today: false,
+            top_repos: vec![],
+        };
+        assert!(
+            Alert::check(&summary, vec!["repo: error".to_owned()])
+                == [
+                    Alert::ScanFailed(vec!["repo: error".to_owned()]),
+                    Alert::StreakEnding(3),
+                ]
+        );
+
+        let summary = Summary { active_today: true, ..summary };
+        assert!(Alert::check(&summary, vec![]) == []);
+    }
+}
@@ -0,0 +1,70 @@
+//! Send notifications to chat services like Slack or Discord via webhooks.
+
+/// Default body template, which works with Slack.
+pub const DEFAULT_TEMPLATE: &str = r#"{"text": {message}}"#;
+
+/// Webhook settings for notifications.
+#[derive(Clone, Debug)]
+pub struct WebhookConfig {
+    /// URL to `POST` notifications to.
+    pub url: String,
+
+    /// Template for the JSON body of the request.
+    ///
+    /// `{message}` is replaced with the notification as a JSON string,
+    /// including quotes. For example, Discord needs `{"content": {message}}`.
+    pub template: String,
+
+    /// HTTP client for making requests.
+    pub http_client: reqwest::Client,
+}
+
+impl WebhookConfig {
+    /// Render the request body for a notification.
+    #[must_use]
+    #[expect(
+        clippy::literal_string_with_formatting_args,
+        reason = "template placeholder"
+    )]
+    pub fn render(&self, subject: &str, body: &str) -> String {
+        let message = serde_json::Value::from(format!("{subject}\n\n{body}"));
+        self.template.replace("{message}", &message.to_string())
+    }
+
+    /// Send a notification.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server returns an error
+    /// status.
+    pub async fn send(&self, subject: &str, body: &str) -> anyhow::Result<()> {
+        self.http_client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(self.render(subject, body))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn render_escapes_message() {
+        let webhook = WebhookConfig {
+            url: "https://example.com/hook".to_owned(),
+            template: r#"{"content": {message}, "username": "RepoYear"}"#
+                .to_owned(),
+            http_client: reqwest::Client::new(),
+        };
+        assert!(
+            webhook.render("Subject \"1\"", "Line 1\nLine 2")
+                == r#"{"content": "Subject \"1\"\n\nLine 1\nLine 2", "username": "RepoYear"}"#
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Render contribution calendars without the frontend.
+//!
+//! This module is organized into:
+//! - [`html`] - Rendering to HTML pages for embedding
+//! - [`png`] - Rendering to PNG images
+
+pub mod html;
+pub mod png;
+
+use crate::api::definition::LocalContributions;
+use crate::repos::{Calendar, week_start};
+use chrono::{Datelike, NaiveDate, TimeZone, Weekday};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Number of intensity levels for days with contributions.
+pub const LEVELS: usize = 4;
+
+/// A calendar heat map of contributions for one year.
+///
+/// Like GitHub’s, this is laid out with one column per week and one row per
+/// day of the week, starting with Sunday.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Heatmap {
+    /// The year displayed.
+    pub year: i32,
+
+    /// Number of contributions by day.
+    pub counts: BTreeMap<NaiveDate, usize>,
+
+    /// The highest number of contributions on any day of the year.
+    pub max: usize,
+}
+
+impl Heatmap {
+    /// Create a heat map for `year`.
+    ///
+    /// Days are calculated in `tz`. Returns `None` if `year` is out of range.
+    #[must_use]
+    pub fn new<Tz: TimeZone>(
+        contributions: &LocalContributions,
+        year: i32,
+        tz: &Tz,
+    ) -> Option<Self> {
+        NaiveDate::from_yo_opt(year, 1)?;
+        let counts: BTreeMap<NaiveDate, usize> = Calendar::new(tz.clone())
+            .contributions(contributions)
+            .into_iter()
+            .filter(|(date, _)| date.year() == year)
+            .map(|(date, count)| {
+                (date, usize::try_from(count).unwrap_or(usize::MAX))
+            })
+            .collect();
+
+        let max = counts.values().copied().max().unwrap_or(0);
+        Some(Self { year, counts, max })
+    }
+
+    /// The Sunday on or before January 1, which is the top left cell.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the year is out of range, which [`Heatmap::new()`] prevents.
+    #[must_use]
+    pub fn first_day(&self) -> NaiveDate {
+        NaiveDate::from_yo_opt(self.year, 1)
+            .map(|date| week_start(date, Weekday::Sun))
+            .expect("year should be in range")
+    }
+
+    /// Number of weeks (columns) in the heat map.
+    #[must_use]
+    pub fn weeks(&self) -> u32 {
+        self.cells()
+            .last()
+            .map_or(0, |cell| cell.week.saturating_add(1))
+    }
+
+    /// All the days in the year with their positions and levels.
+    pub fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        let first_day = self.first_day();
+        first_day
+            .iter_days()
+            .skip_while(|date| date.year() < self.year)
+            .take_while(|date| date.year() == self.year)
+            .map(move |date| {
+                let count = self.counts.get(&date).copied().unwrap_or(0);
+                Cell {
+                    date,
+                    week: date
+                        .signed_duration_since(first_day)
+                        .num_weeks()
+                        .try_into()
+                        .unwrap_or(0),
+                    weekday: date.weekday().num_days_from_sunday(),
+                    count,
+                    level: self.level(count),
+                }
+            })
+    }
+
+    /// Intensity level for a number of contributions.
+    ///
+    /// Returns 0 for no contributions, otherwise 1 through [`LEVELS`].
+    #[must_use]
+    pub fn level(&self, count: usize) -> usize {
+        if count == 0 || self.max == 0 {
+            0
+        } else {
+            count
+                .saturating_mul(LEVELS)
+                .div_ceil(self.max)
+                .clamp(1, LEVELS)
+        }
+    }
+}
+
+/// Default size of a day cell in pixels.
+pub const DEFAULT_CELL_SIZE: u32 = 10;
+
+/// Largest allowed size of a day cell in pixels.
+pub const MAX_CELL_SIZE: u32 = 32;
+
+/// Pixel dimensions for drawing a [`Heatmap`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Layout {
+    /// Width and height of each day.
+    pub cell_size: u32,
+
+    /// Space between days and around the edges.
+    pub gap: u32,
+
+    /// Total width.
+    pub width: u32,
+
+    /// Total height.
+    pub height: u32,
+}
+
+impl Layout {
+    /// Calculate the layout for a heat map.
+    ///
+    /// `cell_size` is clamped to between 1 and [`MAX_CELL_SIZE`].
+    #[must_use]
+    pub fn new(heatmap: &Heatmap, cell_size: u32) -> Self {
+        let cell_size = cell_size.clamp(1, MAX_CELL_SIZE);
+        let gap = cell_size.div_ceil(5);
+        let pitch = cell_size.saturating_add(gap);
+        Self {
+            cell_size,
+            gap,
+            width: heatmap.weeks().saturating_mul(pitch).saturating_add(gap),
+            height: pitch.saturating_mul(7).saturating_add(gap),
+        }
+    }
+
+    /// Position of the top left corner of a cell.
+    #[must_use]
+    pub const fn position(&self, cell: &Cell) -> (u32, u32) {
+        let pitch = self.cell_size.saturating_add(self.gap);
+        (
+            cell.week.saturating_mul(pitch).saturating_add(self.gap),
+            cell.weekday.saturating_mul(pitch).saturating_add(self.gap),
+        )
+    }
+}
+
+/// One day in a [`Heatmap`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cell {
+    /// The date.
+    pub date: NaiveDate,
+
+    /// Column, starting from 0.
+    pub week: u32,
+
+    /// Row, starting from 0 for Sunday.
+    pub weekday: u32,
+
+    /// Number of contributions.
+    pub count: usize,
+
+    /// Intensity level (see [`Heatmap::level()`]).
+    pub level: usize,
+}
+
+/// Color scheme.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Dark cells on a light background.
+    #[default]
+    Light,
+
+    /// Light cells on a dark background.
+    Dark,
+}
+
+impl Theme {
+    /// Background color.
+    #[must_use]
+    pub const fn background(self) -> Rgb {
+        match self {
+            Self::Light => Rgb(0xff, 0xff, 0xff),
+            Self::Dark => Rgb(0x0d, 0x11, 0x17),
+        }
+    }
+
+    /// Cell color for an intensity level (see [`Heatmap::level()`]).
+    #[must_use]
+    pub const fn level(self, level: usize) -> Rgb {
+        match (self, level) {
+            (Self::Light, 0) => Rgb(0xeb, 0xed, 0xf0),
+            (Self::Light, 1) => Rgb(0x9b, 0xe9, 0xa8),
+            (Self::Light, 2) => Rgb(0x40, 0xc4, 0x63),
+            (Self::Light, 3) => Rgb(0x30, 0xa1, 0x4e),
+            (Self::Light, _) => Rgb(0x21, 0x6e, 0x39),
+            (Self::Dark, 0) => Rgb(0x16, 0x1b, 0x22),
+            (Self::Dark, 1) => Rgb(0x0e, 0x44, 0x29),
+            (Self::Dark, 2) => Rgb(0x00, 0x6d, 0x32),
+            (Self::Dark, 3) => Rgb(0x26, 0xa6, 0x41),
+            (Self::Dark, _) => Rgb(0x39, 0xd3, 0x53),
+        }
+    }
+}
+
+/// An RGB color.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+/// Format as a CSS hex color.
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use chrono::{DateTime, Utc};
+
+    fn seconds(input: &str) -> i64 {
+        input.parse::<DateTime<Utc>>().unwrap().timestamp()
+    }
+
+    fn date(input: &str) -> NaiveDate {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn layout() {
+        let heatmap =
+            Heatmap::new(&LocalContributions::new(), 2024, &Utc).unwrap();
+        // 2024-01-01 is a Monday.
+        assert!(heatmap.first_day() == date("2023-12-31"));
+        assert!(heatmap.weeks() == 53);
+
+        let cells: Vec<_> = heatmap.cells().collect();
+        assert!(cells.len() == 366);
+        assert!(cells[0].date == date("2024-01-01"));
+        assert!((cells[0].week, cells[0].weekday) == (0, 1));
+        assert!(cells[365].date == date("2024-12-31"));
+        assert!((cells[365].week, cells[365].weekday) == (52, 2));
+    }
+
+    #[test]
+    fn counts_and_levels() {
+        let contributions = LocalContributions::from([
+            (
+                "a".to_owned(),
+                vec![
+                    seconds("2023-12-31T12:00:00Z"),
+                    seconds("2024-01-02T12:00:00Z"),
+                    seconds("2024-01-03T12:00:00Z"),
+                ],
+            ),
+            (
+                "b".to_owned(),
+                vec![
+                    seconds("2024-01-03T13:00:00Z"),
+                    seconds("2024-01-03T14:00:00Z"),
+                    seconds("2024-01-03T15:00:00Z"),
+                ],
+            ),
+        ]);
+        let heatmap = Heatmap::new(&contributions, 2024, &Utc).unwrap();
+
+        assert!(heatmap.max == 4);
+        assert!(
+            heatmap.counts
+                == BTreeMap::from([
+                    (date("2024-01-02"), 1),
+                    (date("2024-01-03"), 4),
+                ])
+        );
+        assert!(heatmap.level(0) == 0);
+        assert!(heatmap.level(1) == 1);
+        assert!(heatmap.level(3) == 3);
+        assert!(heatmap.level(4) == 4);
+    }
+
+    #[test]
+    fn year_out_of_range() {
+        assert!(
+            Heatmap::new(&LocalContributions::new(), 1_000_000, &Utc).is_none()
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Render heat maps as self-contained HTML pages for embedding.
+
+use super::{Heatmap, Layout, Theme};
+use std::fmt;
+
+/// Render a heat map as a minimal HTML page containing an inline SVG.
+///
+/// The page has no external dependencies, so it works well in an `<iframe>`.
+/// `cell_size` is the width and height of each day in pixels (see
+/// [`Layout::new()`]).
+#[must_use]
+pub fn render(heatmap: &Heatmap, cell_size: u32, theme: Theme) -> String {
+    Page { heatmap, layout: Layout::new(heatmap, cell_size), theme }.to_string()
+}
+
+/// An HTML page displaying a [`Heatmap`].
+struct Page<'a> {
+    /// The heat map to display.
+    heatmap: &'a Heatmap,
+
+    /// Pixel dimensions.
+    layout: Layout,
+
+    /// Color scheme.
+    theme: Theme,
+}
+
+impl fmt::Display for Page<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Layout { width, height, cell_size, gap } = self.layout;
+        let title = format!("Contributions in {}", self.heatmap.year);
+
+        writeln!(f, "<!DOCTYPE html>")?;
+        writeln!(f, r#"<html lang="en">"#)?;
+        writeln!(f, "<head>")?;
+        writeln!(f, r#"<meta charset="utf-8">"#)?;
+        writeln!(f, "<title>{title}</title>")?;
+        writeln!(
+            f,
+            "<style>html, body {{ margin: 0; background: {}; }}</style>",
+            self.theme.background(),
+        )?;
+        writeln!(f, "</head>")?;
+        writeln!(f, "<body>")?;
+        writeln!(
+            f,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" role="img" aria-label="{title}">"#,
+        )?;
+        for cell in self.heatmap.cells() {
+            let (x, y) = self.layout.position(&cell);
+            writeln!(
+                f,
+                r#"<rect x="{x}" y="{y}" width="{cell_size}" height="{cell_size}" rx="{}" fill="{}"><title>{} on {}</title></rect>"#,
+                f64::from(gap) / 2.0,
+                self.theme.level(cell.level),
+                Contributions(cell.count),
+                cell.date,
+            )?;
+        }
+        writeln!(f, "</svg>")?;
+        writeln!(f, "</body>")?;
+        writeln!(f, "</html>")
+    }
+}
+
+/// Format a number of contributions, e.g. “1 contribution”.
+struct Contributions(usize);
+
+impl fmt::Display for Contributions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => write!(f, "No contributions"),
+            1 => write!(f, "1 contribution"),
+            count => write!(f, "{count} contributions"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::definition::LocalContributions;
+    use assert2::assert;
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn render_html() {
+        let time: DateTime<Utc> = "2024-01-03T12:00:00Z".parse().unwrap();
+        let contributions = LocalContributions::from([(
+            "a".to_owned(),
+            vec![time.timestamp()],
+        )]);
+        let heatmap = Heatmap::new(&contributions, 2024, &Utc).unwrap();
+        let html = render(&heatmap, 10, Theme::Light);
+
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.matches("<rect ").count() == 366);
+        assert!(html.contains(
+            r##"<rect x="2" y="38" width="10" height="10" rx="1" fill="#216e39"><title>1 contribution on 2024-01-03</title></rect>"##
+        ));
+    }
+}
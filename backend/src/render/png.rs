@@ -0,0 +1,92 @@
+//! Render heat maps as PNG images.
+
+use super::{Heatmap, Layout, Rgb, Theme};
+
+/// Render a heat map as a PNG image.
+///
+/// `cell_size` is the width and height of each day in pixels (see
+/// [`Layout::new()`]).
+///
+/// # Errors
+///
+/// Returns an error if the image could not be encoded.
+pub fn render(
+    heatmap: &Heatmap,
+    cell_size: u32,
+    theme: Theme,
+) -> Result<Vec<u8>, ::png::EncodingError> {
+    let layout = Layout::new(heatmap, cell_size);
+    let mut canvas =
+        Canvas::new(layout.width, layout.height, theme.background());
+    for cell in heatmap.cells() {
+        let (x, y) = layout.position(&cell);
+        canvas.fill(x, y, layout.cell_size, theme.level(cell.level));
+    }
+
+    let mut output = Vec::new();
+    let mut encoder =
+        ::png::Encoder::new(&mut output, layout.width, layout.height);
+    encoder.set_color(::png::ColorType::Rgb);
+    encoder.set_depth(::png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&canvas.pixels)?;
+    writer.finish()?;
+    Ok(output)
+}
+
+/// An RGB image buffer.
+struct Canvas {
+    /// Width in pixels.
+    width: usize,
+
+    /// RGB bytes, row by row.
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// Create a canvas filled with `background`.
+    fn new(width: u32, height: u32, background: Rgb) -> Self {
+        let width = width as usize;
+        let pixels = [background.0, background.1, background.2]
+            .repeat(width.saturating_mul(height as usize));
+        Self { width, pixels }
+    }
+
+    /// Fill a square with its top left corner at `(x, y)`.
+    fn fill(&mut self, x: u32, y: u32, size: u32, color: Rgb) {
+        let (x, y, size) = (x as usize, y as usize, size as usize);
+        for row in y..y.saturating_add(size) {
+            let start = row.saturating_mul(self.width).saturating_add(x);
+            let end = start.saturating_add(size);
+            if let Some(pixels) = self
+                .pixels
+                .get_mut(start.saturating_mul(3)..end.saturating_mul(3))
+            {
+                for pixel in pixels.chunks_exact_mut(3) {
+                    pixel.copy_from_slice(&[color.0, color.1, color.2]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::definition::LocalContributions;
+    use assert2::assert;
+
+    #[test]
+    fn render_png() {
+        let heatmap =
+            Heatmap::new(&LocalContributions::new(), 2024, &chrono::Utc)
+                .unwrap();
+        let output = render(&heatmap, 10, Theme::Dark).unwrap();
+
+        let decoder = ::png::Decoder::new(std::io::Cursor::new(output));
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        // 53 weeks of 10 pixel cells with 2 pixel gaps.
+        assert!((info.width, info.height) == (53 * 12 + 2, 7 * 12 + 2));
+    }
+}
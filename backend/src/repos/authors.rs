@@ -0,0 +1,179 @@
+//! List commits with their authors, optionally anonymized.
+
+use super::ScanOptions;
+use git2::Repository;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// A commit with its author.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct AuthoredCommit {
+    /// Commit ID as a hex string.
+    pub oid: String,
+
+    /// Commit date (seconds since 1970). See [`ScanOptions::date`].
+    pub time: i64,
+
+    /// Author email.
+    pub email: String,
+
+    /// Author name.
+    pub name: String,
+
+    /// Whether the author matches the identity, i.e. whether the commit is
+    /// counted.
+    pub mine: bool,
+}
+
+impl AuthoredCommit {
+    /// Replace the email and name with salted hashes.
+    ///
+    /// [`mine`](Self::mine) is left alone, so it’s still possible to see
+    /// which commits are counted.
+    #[must_use]
+    pub fn anonymize(self, anonymizer: &Anonymizer) -> Self {
+        Self {
+            // Emails are compared case-insensitively.
+            email: anonymizer.hash(&self.email.to_lowercase()),
+            name: anonymizer.hash(&self.name),
+            ..self
+        }
+    }
+}
+
+/// Replaces identifying strings with salted hashes.
+///
+/// The same salt always produces the same hash for the same string, so
+/// output from different runs can be compared without revealing who the
+/// authors are. Anyone who knows the salt can check guesses, so keep it
+/// secret.
+#[derive(Clone, Debug)]
+pub struct Anonymizer {
+    /// Salt mixed into every hash.
+    salt: String,
+}
+
+impl Anonymizer {
+    /// Create an anonymizer with `salt`.
+    #[must_use]
+    pub fn new<S: Into<String>>(salt: S) -> Self {
+        Self { salt: salt.into() }
+    }
+
+    /// Hash `value` with the salt.
+    ///
+    /// Returns the first 16 hex digits of the SHA-256 hash.
+    #[must_use]
+    pub fn hash(&self, value: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.salt.as_bytes())
+            .chain_update([0])
+            .chain_update(value.as_bytes())
+            .finalize();
+        digest.iter().take(8).fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+    }
+}
+
+/// List commits on the default branch of `repo` along with their authors.
+///
+/// This walks the same commits as [`scan_repo_commits()`], but includes
+/// commits by everyone and marks the ones counted by `options`.
+/// [`ScanOptions::limit`] doesn’t apply here.
+///
+/// [`scan_repo_commits()`]: super::scan_repo_commits
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn scan_authored_commits(
+    repo: &Repository,
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<AuthoredCommit>> {
+    let revwalk = options.default_branch_walk(repo)?;
+    let identity = options.identity.for_repo(repo)?;
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if options.stops_at(&commit) {
+            break;
+        }
+        if options.skips(&commit) {
+            continue;
+        }
+        let author = commit.author();
+        commits.push(AuthoredCommit {
+            oid: commit.id().to_string(),
+            time: options.date.of(&commit),
+            email: author.email().unwrap_or_default().to_owned(),
+            name: author.name().unwrap_or_default().to_owned(),
+            mine: identity.matches_commit(&commit),
+        });
+    }
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repos::Identity;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use testdir::testdir;
+
+    #[test]
+    fn hash_depends_on_salt() {
+        let a = Anonymizer::new("a");
+        assert!(a.hash("me@example.com").len() == 16);
+        assert!(a.hash("me@example.com") == a.hash("me@example.com"));
+        assert!(a.hash("me@example.com") != a.hash("you@example.com"));
+        assert!(
+            a.hash("me@example.com")
+                != Anonymizer::new("b").hash("me@example.com")
+        );
+    }
+
+    #[test]
+    fn anonymized_authors() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.write("a", "other");
+        repo.git(["commit", "-am", "other", "--author", "O <O@example.com>"]);
+
+        let options = ScanOptions::for_identity(Identity {
+            emails: vec!["name@example.com".to_owned()],
+            ..Identity::default()
+        });
+        let anonymizer = Anonymizer::new("salt");
+        let mut commits: Vec<_> = scan_authored_commits(
+            &Repository::open(repo.path()).unwrap(),
+            &options,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|commit| commit.anonymize(&anonymizer))
+        .map(|commit| (commit.email, commit.name, commit.mine))
+        .collect();
+        // Both commits may have the same time, so the order isn’t certain.
+        commits.sort_by_key(|(_, _, mine)| *mine);
+        assert!(
+            commits
+                == [
+                    (
+                        anonymizer.hash("o@example.com"),
+                        anonymizer.hash("O"),
+                        false
+                    ),
+                    (
+                        anonymizer.hash("name@example.com"),
+                        anonymizer.hash("Name"),
+                        true
+                    ),
+                ]
+        );
+    }
+}
@@ -0,0 +1,380 @@
+//! Keep scan results in memory and update them as repositories change.
+
+use super::{
+    Config, LocalContributions, RepoOutcome, ScanMetrics, drop_clones,
+    log_repo_scan, remote_url,
+};
+use git2::Repository;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Scan results for every repository in a [`Config`].
+///
+/// [`ScanCache::update()`] takes a list of changed paths (e.g. from a
+/// filesystem watcher) and rescans only the repositories they affect.
+#[derive(Clone, Debug, Default)]
+pub struct ScanCache {
+    /// Results by git directory.
+    repos: BTreeMap<PathBuf, CachedRepo>,
+
+    /// Errors from finding repositories.
+    errors: Vec<String>,
+
+    /// When the most recent update started and how long it took.
+    timing: ScanMetrics,
+}
+
+/// Scan results for one repository.
+#[derive(Clone, Debug)]
+struct CachedRepo {
+    /// Repository name.
+    name: String,
+
+    /// Working directory, if the repository isn’t bare.
+    workdir: Option<PathBuf>,
+
+    /// Main remote URL, if any.
+    remote: Option<String>,
+
+    /// Commit times, or an error message if the scan failed.
+    times: Result<Vec<i64>, String>,
+
+    /// Whether the repository has a skipped remote, so only unpushed commits
+    /// were counted. See [`Config::skip_remotes`].
+    unpushed_only: bool,
+
+    /// Whether the repository is a shallow clone.
+    shallow: bool,
+
+    /// The repository’s group, if any. See [`Config::group_of()`].
+    group: Option<String>,
+}
+
+impl ScanCache {
+    /// Find and scan every repository in `config`.
+    ///
+    /// Repositories in trees that set [`fetch`](super::TreeConfig::fetch) are
+    /// fetched first, here and in [`update()`](Self::update).
+    #[must_use]
+    pub fn scan(config: &Config) -> Self {
+        let (timing, started) = ScanMetrics::start();
+        let mut cache = Self { timing, ..Self::default() };
+        cache.discover(config);
+        cache.timing.finish(started);
+        cache
+    }
+
+    /// Get commit times for each repository by name.
+    ///
+    /// Clones of the same repository are only counted once; see
+    /// [`drop_clones()`].
+    #[must_use]
+    pub fn contributions(&self) -> LocalContributions {
+        let mut contributions = LocalContributions::new();
+        for repo in self.repos.values() {
+            if let Ok(times) = &repo.times {
+                contributions
+                    .entry(repo.name.clone())
+                    .or_default()
+                    .extend(times);
+            }
+        }
+        drop_clones(&mut contributions, &self.remotes());
+        contributions
+    }
+
+    /// Get the remote URL of each repository that has one, by name.
+    #[must_use]
+    pub fn remotes(&self) -> BTreeMap<String, String> {
+        self.repos
+            .values()
+            .filter_map(|repo| {
+                repo.remote
+                    .clone()
+                    .map(|remote| (repo.name.clone(), remote))
+            })
+            .collect()
+    }
+
+    /// Get errors from the most recent scans.
+    #[must_use]
+    pub fn errors(&self) -> Vec<anyhow::Error> {
+        self.errors
+            .iter()
+            .cloned()
+            .chain(self.repos.values().filter_map(|repo| {
+                repo.times
+                    .as_ref()
+                    .err()
+                    .map(|error| format!("{}: {error}", repo.name))
+            }))
+            .map(anyhow::Error::msg)
+            .collect()
+    }
+
+    /// Describe the repositories in the cache.
+    ///
+    /// The timing is for the most recent update.
+    #[must_use]
+    pub fn metrics(&self) -> ScanMetrics {
+        let mut metrics = ScanMetrics {
+            started_at: self.timing.started_at,
+            duration_ms: self.timing.duration_ms,
+            ..ScanMetrics::default()
+        };
+        for error in &self.errors {
+            metrics.record_error(error.clone());
+        }
+        for repo in self.repos.values() {
+            let (outcome, commits) = match &repo.times {
+                Ok(times) if repo.unpushed_only => {
+                    (RepoOutcome::UnpushedOnly, times.len())
+                }
+                Ok(times) => (RepoOutcome::Scanned, times.len()),
+                Err(message) => {
+                    (RepoOutcome::Error { message: message.clone() }, 0)
+                }
+            };
+            metrics.record_outcome(&repo.name, outcome, commits);
+            if repo.shallow {
+                metrics.record_shallow(&repo.name);
+            }
+            if let Some(group) = &repo.group {
+                metrics.record_group(&repo.name, group);
+            }
+        }
+        metrics.shallow_repos.sort();
+        metrics
+    }
+
+    /// Update the cache after the files at `paths` changed.
+    ///
+    /// Repositories whose refs, `HEAD`, or configuration changed are rescanned.
+    /// Changes outside of any known repository may mean a repository was added,
+    /// so they trigger a search for new repositories.
+    ///
+    /// Returns the names of the repositories that were rescanned or added.
+    pub fn update<I, P>(&mut self, config: &Config, paths: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let (timing, started) = ScanMetrics::start();
+        let mut rescan = BTreeSet::new();
+        let mut discover = false;
+        for path in paths {
+            let path = path.as_ref();
+            if let Some(git_dir) =
+                self.repos.keys().find(|git_dir| path.starts_with(git_dir))
+            {
+                if path.strip_prefix(git_dir).is_ok_and(is_ref_change) {
+                    rescan.insert(git_dir.clone());
+                }
+            } else if !self.repos.values().any(|repo| {
+                repo.workdir
+                    .as_ref()
+                    .is_some_and(|workdir| path.starts_with(workdir))
+            }) {
+                discover = true;
+            }
+        }
+
+        let mut updated = Vec::new();
+        for git_dir in rescan {
+            if !git_dir.exists() {
+                // Removed. If it was moved, discovery will find it again.
+                self.repos.remove(&git_dir);
+                discover = true;
+            } else if let Some(repo) = self.repos.get_mut(&git_dir) {
+                let started = Instant::now();
+                (repo.times, repo.unpushed_only) =
+                    match Repository::open(&git_dir) {
+                        Ok(repository) => {
+                            repo.remote =
+                                remote_url(&repository).ok().flatten();
+                            repo.shallow = repository.is_shallow();
+                            scan(config, &repo.name, &repository, started)
+                        }
+                        Err(error) => (Err(error.to_string()), false),
+                    };
+                updated.push(repo.name.clone());
+            }
+        }
+
+        if discover {
+            updated.extend(self.discover(config));
+        }
+        if !updated.is_empty() {
+            self.timing = timing;
+            self.timing.finish(started);
+        }
+        updated
+    }
+
+    /// Scan repositories in `config` that aren’t already cached, and drop
+    /// repositories that no longer exist.
+    ///
+    /// Returns the names of the new repositories.
+    fn discover(&mut self, config: &Config) -> Vec<String> {
+        self.errors.clear();
+        let mut found = BTreeSet::new();
+        let mut added = Vec::new();
+        let mut started = Instant::now();
+        for result in config.repo_iter() {
+            match result {
+                Ok((name, repository)) => {
+                    let git_dir = repository.path().to_owned();
+                    found.insert(git_dir.clone());
+                    if self.repos.contains_key(&git_dir) {
+                        started = Instant::now();
+                        continue;
+                    }
+                    let (times, unpushed_only) =
+                        scan(config, &name, &repository, started);
+                    let workdir = repository.workdir().map(Path::to_owned);
+                    let remote = remote_url(&repository).ok().flatten();
+                    added.push(name.clone());
+                    self.repos.insert(
+                        git_dir,
+                        CachedRepo {
+                            name,
+                            workdir,
+                            remote,
+                            times,
+                            unpushed_only,
+                            shallow: repository.is_shallow(),
+                            group: config
+                                .group_of(&repository)
+                                .map(str::to_owned),
+                        },
+                    );
+                }
+                Err(error) => self.errors.push(error.to_string()),
+            }
+            started = Instant::now();
+        }
+        self.repos.retain(|git_dir, _| found.contains(git_dir));
+        added
+    }
+}
+
+/// Fetch a repository if it’s configured to be fetched, then scan it and
+/// check whether only unpushed commits were counted.
+///
+/// See [`log_repo_scan()`] for `started`.
+fn scan(
+    config: &Config,
+    name: &str,
+    repository: &Repository,
+    started: Instant,
+) -> (Result<Vec<i64>, String>, bool) {
+    config.fetch_if_configured(repository);
+    let result = config.scan_repo(repository);
+    log_repo_scan(name, started, &result);
+    let unpushed_only = result.is_ok()
+        && config
+            .skip_remotes_for(repository)
+            .matches_repo(repository)
+            .unwrap_or(false);
+    (result.map_err(|error| error.to_string()), unpushed_only)
+}
+
+/// Whether a change to `path` within a git directory could change the commits
+/// found by a scan.
+fn is_ref_change(path: &Path) -> bool {
+    path.components().next().is_some_and(|first| {
+        ["HEAD", "packed-refs", "refs", "config", "shallow"]
+            .iter()
+            .any(|name| first.as_os_str() == *name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use testdir::testdir;
+
+    /// Count commits in each repository, sorted by name.
+    fn counts(cache: &ScanCache) -> Vec<(String, usize)> {
+        let mut counts: Vec<_> = cache
+            .contributions()
+            .into_iter()
+            .map(|(name, times)| (name, times.len()))
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    #[test]
+    fn rescan_changed_repo() {
+        let home = Home::init(testdir!());
+        let a = home.git_init("root/a");
+        a.make_commit(0);
+        let b = home.git_init("root/b");
+        b.make_commit(0);
+        let config = Config::with_tree((home.join("root"), Some("x:")));
+
+        let mut cache = ScanCache::scan(&config);
+        assert!(
+            counts(&cache) == [("x:a".to_owned(), 1), ("x:b".to_owned(), 1)]
+        );
+
+        a.make_commit(1);
+        b.make_commit(1);
+        let a_git = Repository::open(a.path()).unwrap().path().to_owned();
+
+        // Changes to the working directory are ignored.
+        assert!(cache.update(&config, [a.join("a")]).is_empty());
+
+        assert!(
+            cache.update(&config, [a_git.join("refs/heads/main")]) == ["x:a"]
+        );
+        assert!(
+            counts(&cache) == [("x:a".to_owned(), 2), ("x:b".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn discover_new_repo() {
+        let home = Home::init(testdir!());
+        let a = home.git_init("root/a");
+        a.make_commit(0);
+        let config = Config::with_tree((home.join("root"), Some("x:")));
+
+        let mut cache = ScanCache::scan(&config);
+        home.git_init("root/c").make_commit(0);
+        assert!(
+            cache.update(&config, [home.join("root/c/.git/HEAD")]) == ["x:c"]
+        );
+        assert!(
+            counts(&cache) == [("x:a".to_owned(), 1), ("x:c".to_owned(), 1)]
+        );
+        assert!(cache.errors().is_empty());
+
+        let metrics = cache.metrics();
+        assert!(metrics.repos_discovered == 2);
+        assert!(metrics.repos_scanned == 2);
+        assert!(metrics.commits == 2);
+    }
+
+    #[test]
+    fn metrics_outcomes() {
+        let home = Home::init(testdir!());
+        home.git_init("root/a").make_commit(0);
+        home.git_init("root/b");
+        let c = home.git_init("root/c");
+        c.make_commit(0);
+        c.git(["remote", "add", "origin", "git@github.com:u/c.git"]);
+        let config = Config::with_tree((home.join("root"), Some("x:")));
+
+        let metrics = ScanCache::scan(&config).metrics();
+        let outcome = |name: &str| metrics.outcomes.get(name).cloned();
+        assert!(outcome("x:a") == Some(RepoOutcome::Scanned));
+        assert!(let Some(RepoOutcome::Error { .. }) = outcome("x:b"));
+        assert!(outcome("x:c") == Some(RepoOutcome::UnpushedOnly));
+        assert!(metrics.repos_errored == 1);
+    }
+}
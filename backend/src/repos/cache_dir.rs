@@ -0,0 +1,359 @@
+//! Keep data on disk between runs.
+//!
+//! The cache directory holds scan results for each repository so that they
+//! don’t need to be recomputed unless the repository changes, and the default
+//! branches of remotes if [`ls_remote_head`] is enabled. It is marked
+//! with a [`CACHEDIR.TAG`](https://bford.info/cachedir/) file so that backup
+//! tools skip it, and so that [`CacheDir::clear()`] can tell that it’s safe to
+//! delete its contents.
+//!
+//! [`ls_remote_head`]: super::Config::ls_remote_head
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// Name of the file that marks a cache directory.
+const TAG_NAME: &str = "CACHEDIR.TAG";
+
+/// Contents of the file that marks a cache directory.
+const TAG_CONTENTS: &str = "Signature: 8a477f597d28d172789f06886806bc55\n\
+    # This file is a cache directory tag created by repoyear.\n\
+    # For information about cache directory tags see https://bford.info/cachedir/\n";
+
+/// A directory to cache data in.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CacheDir {
+    /// Path to the directory.
+    pub path: PathBuf,
+}
+
+/// Scan results for one repository, as stored in the cache.
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedScan {
+    /// Path to the git directory.
+    git_dir: PathBuf,
+
+    /// Everything the scan depends on. If this changes, the scan is stale.
+    key: String,
+
+    /// Commit times (seconds since epoch).
+    times: Vec<i64>,
+}
+
+/// The branch `HEAD` points to on a remote, as stored in the cache.
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedRemoteHead {
+    /// Path to the git directory.
+    git_dir: PathBuf,
+
+    /// URL of the remote.
+    url: String,
+
+    /// Branch name, or `None` if the remote didn’t report one.
+    head: Option<String>,
+}
+
+impl CacheDir {
+    /// Use a specific directory.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Get the default cache directory, `$XDG_CACHE_HOME/repoyear` or
+    /// `~/.cache/repoyear`.
+    ///
+    /// Returns `None` if neither `$XDG_CACHE_HOME` nor the home directory are
+    /// set.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| std::env::home_dir().map(|home| home.join(".cache")))
+            .map(|cache| cache.join("repoyear"))
+    }
+
+    /// Get cached scan results for a repository.
+    ///
+    /// Returns `None` if there are no results for `git_dir`, or if they were
+    /// stored with a different `key`.
+    #[must_use]
+    pub fn read_scan(&self, git_dir: &Path, key: &str) -> Option<Vec<i64>> {
+        let contents = fs::read(self.scan_path(git_dir)).ok()?;
+        let cached: CachedScan = serde_json::from_slice(&contents).ok()?;
+        (cached.git_dir == git_dir && cached.key == key).then_some(cached.times)
+    }
+
+    /// Store scan results for a repository.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the results could not be written.
+    pub fn write_scan(
+        &self,
+        git_dir: &Path,
+        key: &str,
+        times: &[i64],
+    ) -> io::Result<()> {
+        let cached = CachedScan {
+            git_dir: git_dir.to_owned(),
+            key: key.to_owned(),
+            times: times.to_vec(),
+        };
+        self.write(&self.scan_path(git_dir), &serde_json::to_vec(&cached)?)
+    }
+
+    /// Get the cached branch that `HEAD` points to on a remote.
+    ///
+    /// Returns `None` if nothing is cached for `git_dir`, or if the remote URL
+    /// has changed. Returns `Some(None)` if the remote didn’t report a branch.
+    #[must_use]
+    pub fn read_remote_head(
+        &self,
+        git_dir: &Path,
+        url: &str,
+    ) -> Option<Option<String>> {
+        let contents = fs::read(self.remote_head_path(git_dir)).ok()?;
+        let cached: CachedRemoteHead =
+            serde_json::from_slice(&contents).ok()?;
+        (cached.git_dir == git_dir && cached.url == url).then_some(cached.head)
+    }
+
+    /// Store the branch that `HEAD` points to on a remote.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if it could not be written.
+    pub fn write_remote_head(
+        &self,
+        git_dir: &Path,
+        url: &str,
+        head: Option<&str>,
+    ) -> io::Result<()> {
+        let cached = CachedRemoteHead {
+            git_dir: git_dir.to_owned(),
+            url: url.to_owned(),
+            head: head.map(ToOwned::to_owned),
+        };
+        self.write(
+            &self.remote_head_path(git_dir),
+            &serde_json::to_vec(&cached)?,
+        )
+    }
+
+    /// Summarize the contents of the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory could not be read.
+    pub fn status(&self) -> io::Result<CacheStatus> {
+        let mut status = CacheStatus {
+            path: self.path.clone(),
+            files: 0,
+            bytes: 0,
+            oldest: None,
+            newest: None,
+        };
+        if !self.path.exists() {
+            return Ok(status);
+        }
+
+        for entry in WalkDir::new(&self.path) {
+            let entry = entry?;
+            if entry.file_type().is_dir() || entry.file_name() == TAG_NAME {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let modified = metadata.modified()?;
+            status.files = status.files.saturating_add(1);
+            status.bytes = status.bytes.saturating_add(metadata.len());
+            status.oldest = Some(
+                status
+                    .oldest
+                    .map_or(modified, |oldest| oldest.min(modified)),
+            );
+            status.newest = status.newest.max(Some(modified));
+        }
+        Ok(status)
+    }
+
+    /// Delete everything in the cache.
+    ///
+    /// To avoid deleting the wrong thing, this refuses to touch a directory
+    /// that isn’t marked with a `CACHEDIR.TAG` file. Returns the number of
+    /// files deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory isn’t a cache directory or if its
+    /// contents could not be deleted.
+    pub fn clear(&self) -> io::Result<usize> {
+        let entries = match fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(0);
+            }
+            Err(error) => return Err(error),
+        };
+        if !self.path.join(TAG_NAME).is_file() {
+            return Err(io::Error::other(format!(
+                "{:?} is not a cache directory (no {TAG_NAME})",
+                self.path
+            )));
+        }
+
+        let files = self.status()?.files;
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_name() == TAG_NAME {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(files)
+    }
+
+    /// Get the path to the scan results for a repository.
+    fn scan_path(&self, git_dir: &Path) -> PathBuf {
+        self.repo_path("scans", git_dir)
+    }
+
+    /// Get the path to the cached remote `HEAD` for a repository.
+    fn remote_head_path(&self, git_dir: &Path) -> PathBuf {
+        self.repo_path("remote-heads", git_dir)
+    }
+
+    /// Get the path to a file in `subdir` about a repository.
+    fn repo_path(&self, subdir: &str, git_dir: &Path) -> PathBuf {
+        // Use git’s SHA-1 implementation to get a stable file name.
+        let hash = git2::Oid::hash_object(
+            git2::ObjectType::Blob,
+            git_dir.as_os_str().as_encoded_bytes(),
+        )
+        .map_or_else(|_| "invalid".to_owned(), |oid| oid.to_string());
+        self.path.join(subdir).join(format!("{hash}.json"))
+    }
+
+    /// Write a file atomically, creating the cache directory if needed.
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let tag = self.path.join(TAG_NAME);
+        if !tag.exists() {
+            fs::create_dir_all(&self.path)?;
+            fs::write(tag, TAG_CONTENTS)?;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(temporary, path)
+    }
+}
+
+/// Summary of what’s in a [`CacheDir`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CacheStatus {
+    /// Path to the directory.
+    pub path: PathBuf,
+
+    /// Number of files.
+    pub files: usize,
+
+    /// Total size of the files.
+    pub bytes: u64,
+
+    /// Modification time of the oldest file.
+    pub oldest: Option<SystemTime>,
+
+    /// Modification time of the newest file.
+    pub newest: Option<SystemTime>,
+}
+
+impl fmt::Display for CacheStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// Format a time as local time.
+        fn local(time: SystemTime) -> impl fmt::Display {
+            chrono::DateTime::<chrono::Local>::from(time).format("%F %T")
+        }
+
+        writeln!(f, "Cache directory: {}", self.path.display())?;
+        writeln!(f, "Files: {} ({} bytes)", self.files, self.bytes)?;
+        if let Some(oldest) = self.oldest {
+            writeln!(f, "Oldest: {}", local(oldest))?;
+        }
+        if let Some(newest) = self.newest {
+            writeln!(f, "Newest: {}", local(newest))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use testdir::testdir;
+
+    #[test]
+    fn scan_round_trip() {
+        let cache = CacheDir::new(testdir!().join("cache"));
+        let git_dir = Path::new("/src/repo/.git");
+        assert!(cache.read_scan(git_dir, "key").is_none());
+
+        cache.write_scan(git_dir, "key", &[1, 2]).unwrap();
+        assert!(cache.read_scan(git_dir, "key") == Some(vec![1, 2]));
+        assert!(cache.read_scan(git_dir, "other").is_none());
+        assert!(
+            cache
+                .read_scan(Path::new("/src/other/.git"), "key")
+                .is_none()
+        );
+
+        let status = cache.status().unwrap();
+        assert!(status.files == 1);
+        assert!(status.oldest.is_some());
+
+        assert!(cache.clear().unwrap() == 1);
+        assert!(cache.read_scan(git_dir, "key").is_none());
+        assert!(cache.path.join(TAG_NAME).is_file());
+    }
+
+    #[test]
+    fn remote_head_round_trip() {
+        let cache = CacheDir::new(testdir!().join("cache"));
+        let git_dir = Path::new("/src/repo/.git");
+        assert!(cache.read_remote_head(git_dir, "url").is_none());
+
+        cache
+            .write_remote_head(git_dir, "url", Some("trunk"))
+            .unwrap();
+        assert!(
+            cache.read_remote_head(git_dir, "url")
+                == Some(Some("trunk".to_owned()))
+        );
+        assert!(cache.read_remote_head(git_dir, "other").is_none());
+
+        cache.write_remote_head(git_dir, "url", None).unwrap();
+        assert!(cache.read_remote_head(git_dir, "url") == Some(None));
+    }
+
+    #[test]
+    fn clear_requires_tag() {
+        let path = testdir!();
+        fs::write(path.join("important"), "data").unwrap();
+        assert!(let Err(_) = CacheDir::new(&path).clear());
+        assert!(path.join("important").is_file());
+
+        assert!(CacheDir::new(path.join("missing")).clear().unwrap() == 0);
+    }
+}
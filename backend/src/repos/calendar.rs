@@ -0,0 +1,132 @@
+//! Count commits by day.
+//!
+//! Scans produce commit times as seconds since 1970. Anything displayed to a
+//! person needs those grouped into days in some time zone, and sometimes into
+//! weeks.
+
+use super::LocalContributions;
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeZone, Weekday};
+use std::collections::BTreeMap;
+
+/// How to divide commit times into days and weeks.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Calendar<Tz: TimeZone> {
+    /// Time zone that days are calculated in.
+    pub tz: Tz,
+
+    /// First day of the week.
+    pub week_start: Weekday,
+}
+
+impl<Tz: TimeZone> Calendar<Tz> {
+    /// Calculate days in `tz`, with weeks starting on Sunday like GitHub’s
+    /// contribution calendar.
+    #[must_use]
+    pub const fn new(tz: Tz) -> Self {
+        Self { tz, week_start: Weekday::Sun }
+    }
+
+    /// Get the day that `time` (seconds since 1970) falls on.
+    ///
+    /// Returns `None` if `time` is out of range.
+    #[must_use]
+    pub fn date(&self, time: i64) -> Option<NaiveDate> {
+        DateTime::from_timestamp(time, 0)
+            .map(|time| time.with_timezone(&self.tz).date_naive())
+    }
+
+    /// Get the first day of the week containing `date`.
+    #[must_use]
+    pub fn week(&self, date: NaiveDate) -> NaiveDate {
+        week_start(date, self.week_start)
+    }
+
+    /// Count commits on each day.
+    ///
+    /// Days without commits are left out.
+    #[must_use]
+    pub fn days<'a, I>(&self, times: I) -> BTreeMap<NaiveDate, u32>
+    where
+        I: IntoIterator<Item = &'a i64>,
+    {
+        let mut counts = BTreeMap::new();
+        for date in times.into_iter().filter_map(|&time| self.date(time)) {
+            counts
+                .entry(date)
+                .and_modify(|count: &mut u32| *count = count.saturating_add(1))
+                .or_insert(1);
+        }
+        counts
+    }
+
+    /// Count commits in all repositories on each day.
+    ///
+    /// See [`days()`](Self::days).
+    #[must_use]
+    pub fn contributions(
+        &self,
+        contributions: &LocalContributions,
+    ) -> BTreeMap<NaiveDate, u32> {
+        self.days(contributions.values().flatten())
+    }
+}
+
+/// Get the first day of the week containing `date`, where weeks start on
+/// `first`.
+#[must_use]
+pub fn week_start(date: NaiveDate, first: Weekday) -> NaiveDate {
+    date.checked_sub_days(Days::new(date.weekday().days_since(first).into()))
+        .unwrap_or(date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use chrono::{FixedOffset, Utc};
+
+    fn date(input: &str) -> NaiveDate {
+        input.parse().unwrap()
+    }
+
+    fn seconds(input: &str) -> i64 {
+        input.parse::<DateTime<Utc>>().unwrap().timestamp()
+    }
+
+    #[test]
+    fn days_in_time_zone() {
+        let times = [
+            seconds("2024-03-01T12:00:00Z"),
+            seconds("2024-03-01T23:30:00Z"),
+            seconds("2024-03-02T01:00:00Z"),
+        ];
+        assert!(
+            Calendar::new(Utc).days(&times)
+                == BTreeMap::from([
+                    (date("2024-03-01"), 2),
+                    (date("2024-03-02"), 1),
+                ])
+        );
+
+        let east = FixedOffset::east_opt(2 * 3600).unwrap();
+        assert!(
+            Calendar::new(east).days(&times)
+                == BTreeMap::from([
+                    (date("2024-03-01"), 1),
+                    (date("2024-03-02"), 2),
+                ])
+        );
+    }
+
+    #[test]
+    fn weeks() {
+        let wednesday = date("2024-03-06");
+        let mut calendar = Calendar::new(Utc);
+        assert!(calendar.week(wednesday) == date("2024-03-03"));
+        assert!(calendar.week(date("2024-03-03")) == date("2024-03-03"));
+
+        calendar.week_start = Weekday::Mon;
+        assert!(calendar.week(wednesday) == date("2024-03-04"));
+        assert!(calendar.week(date("2024-03-03")) == date("2024-02-26"));
+    }
+}
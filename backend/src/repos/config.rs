@@ -1,23 +1,371 @@
 //! Repository configuration parsing.
+#![expect(
+    clippy::same_name_method,
+    reason = "`Config` derives `deserialize()` as an inherent method so that \
+    its `Deserialize` implementation can apply `[scan]` afterwards"
+)]
 
-use super::Result;
+use super::{
+    CacheDir, CommitDate, CommitLimit, CommitStats, CommitSummary,
+    DateDivergence, DateRange, DiffStatsConfig, Error, Identity, MergeCommits,
+    PatchDuplicates, RemotePatterns, RenameRule, Result, ScanOptions,
+    ScanPaths, fetch, glob_match, has_remote_head, ls_remote_head,
+    scan_date_divergence, scan_key, scan_reflog, scan_repo,
+    scan_repo_identities, scan_repo_stats, scan_repo_summaries,
+    scan_unmerged_commits,
+};
 use git2::{ErrorCode, Repository};
+use schemars::JsonSchema;
 use serde::Deserialize;
-use std::path::{Path, PathBuf};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf, Prefix};
 use std::slice;
+use std::time::Duration;
 use walkdir::{DirEntry, WalkDir};
 
 /// Configuration.
-#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+///
+/// The default is the same as an empty configuration file.
+#[derive(Debug, Clone, Default, Deserialize, Eq, JsonSchema, PartialEq)]
+#[serde(remote = "Self")]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "these are independent options in the configuration file"
+)]
 pub struct Config {
     /// Directory trees to search for repos.
+    #[serde(default)]
     pub repos: Vec<TreeConfig>,
+
+    /// Individual repositories to scan without searching for them.
+    ///
+    /// These are `[[repo]]` entries in TOML.
+    #[serde(default, rename = "repo")]
+    pub single_repos: Vec<RepoConfig>,
+
+    /// Repositories to leave out, even though they were found.
+    ///
+    /// ```toml
+    /// deny = ["**/archive/**", "old-project"]
+    /// ```
+    ///
+    /// These are globs matched against both the name of each repository and
+    /// the path of its working directory (or git directory, if it’s bare).
+    /// `*` matches any number of characters, including `/`.
+    #[serde(default)]
+    pub deny: Vec<String>,
+
+    /// Who to count commits for.
+    #[serde(default)]
+    pub identity: Identity,
+
+    /// Other identities to count commits for separately, by label.
+    ///
+    /// This makes it possible to show e.g. work and personal contributions
+    /// separately:
+    ///
+    /// ```toml
+    /// [identities.work]
+    /// emails = ["daniel@work.example"]
+    ///
+    /// [identities.personal]
+    /// emails = ["daniel@example.com"]
+    /// ```
+    ///
+    /// These don’t affect [`identity`](Self::identity). See
+    /// [`Config::scan_identities()`].
+    #[serde(default)]
+    pub identities: BTreeMap<String, Identity>,
+
+    /// Directory to cache scan results in.
+    ///
+    /// If this isn’t set, every scan starts from scratch.
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Limit on the number of commits to scan in each repository.
+    #[serde(default)]
+    pub commit_limit: Option<CommitLimit>,
+
+    /// Ask `origin` for its default branch when the repository doesn’t know.
+    ///
+    /// Repositories without `refs/remotes/origin/HEAD` otherwise fall back to
+    /// guessing `main` or `master`. This contacts the remote, so the result
+    /// is saved in [`cache_dir`](Self::cache_dir) if it’s set.
+    #[serde(default)]
+    pub ls_remote_head: bool,
+
+    /// Also report commits on local branches that aren’t merged into the
+    /// default branch.
+    ///
+    /// These are reported separately from other commits. See
+    /// [`Config::scan_unmerged()`].
+    #[serde(default)]
+    pub unmerged: bool,
+
+    /// Whether to report unmerged commits that were rebased or cherry-picked
+    /// onto the default branch or another unmerged branch.
+    ///
+    /// See [`PatchDuplicates`].
+    #[serde(default)]
+    pub duplicate_patches: PatchDuplicates,
+
+    /// Also report activity from the `HEAD` reflog of each repository.
+    ///
+    /// This catches work that never reached a branch, e.g. commits that were
+    /// amended, rebased, or on branches that were deleted. It’s reported
+    /// separately from commits. See [`Config::scan_reflog()`].
+    #[serde(default)]
+    pub reflog: bool,
+
+    /// Which date to count commits on.
+    #[serde(default)]
+    pub commit_date: CommitDate,
+
+    /// Only follow the first parent of merge commits on the default branch.
+    ///
+    /// See [`ScanOptions::first_parent`].
+    #[serde(default)]
+    pub first_parent: bool,
+
+    /// Whether to count merge commits.
+    #[serde(default)]
+    pub merges: MergeCommits,
+
+    /// Only count commits from the start of this period on, e.g. `"2024"` or
+    /// `"2024-03-05"`. Days start at midnight UTC.
+    #[serde(default)]
+    pub since: Option<DateRange>,
+
+    /// Only count commits through the end of this period, e.g. `"2024"` or
+    /// `"2024-12-31"`. Days end at midnight UTC.
+    #[serde(default)]
+    pub until: Option<DateRange>,
+
+    /// Maximum number of commits to count in each repository. Only the
+    /// newest are counted. See [`ScanOptions::commit_cap`].
+    #[serde(default)]
+    pub commit_cap: Option<usize>,
+
+    /// Only count commits that haven’t been pushed to remotes matching these
+    /// patterns.
+    ///
+    /// Defaults to GitHub, which already counts commits pushed to it. See
+    /// [`RemotePatterns`]. Trees can override this with
+    /// [`TreeConfig::skip_remotes`].
+    #[serde(default)]
+    pub skip_remotes: RemotePatterns,
+
+    /// Only count commits that touch these paths.
+    ///
+    /// This is off unless there’s a `[paths]` section. See [`ScanPaths`].
+    #[serde(default)]
+    pub paths: ScanPaths,
+
+    /// Count the lines inserted and deleted by each commit.
+    ///
+    /// This is much slower than scanning for commit times, so it’s off unless
+    /// there’s a `[diff_stats]` section. See [`Config::scan_stats()`].
+    #[serde(default)]
+    pub diff_stats: Option<DiffStatsConfig>,
+
+    /// Scan options in a `[scan]` table.
+    ///
+    /// These are applied to the other fields when the configuration is
+    /// loaded. See [`ScanSection`].
+    #[serde(default)]
+    pub scan: ScanSection,
+}
+
+// `remote = "Self"` makes the derived `deserialize()` inherent, so that this
+// can apply `[scan]` after it.
+impl<'de> Deserialize<'de> for Config {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> ::std::result::Result<Self, D::Error> {
+        let mut config = Self::deserialize(deserializer)?;
+        config.scan.clone().apply(&mut config);
+        Ok(config)
+    }
+}
+
+/// Default scan options for both `scan` and `serve`, as a `[scan]` table.
+///
+/// This is another way to set the top-level options that control what each
+/// scan counts:
+///
+/// ```toml
+/// [scan]
+/// authors = ["daniel@example.com", "Daniel Parks"]
+/// since = "2024"
+/// include_merges = false
+/// first_parent = true
+/// ```
+///
+/// Options set here take precedence over the same options at the top level.
+#[derive(Debug, Clone, Default, Deserialize, Eq, JsonSchema, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ScanSection {
+    /// Authors to count commits for. Entries with an `@` are emails, and
+    /// anything else is a name. This replaces `emails` and `names` in
+    /// [`identity`](Config::identity).
+    pub authors: Option<Vec<String>>,
+
+    /// See [`Config::since`].
+    pub since: Option<DateRange>,
+
+    /// See [`Config::until`].
+    pub until: Option<DateRange>,
+
+    /// Whether to count merge commits. See [`Config::merges`].
+    pub include_merges: Option<bool>,
+
+    /// See [`Config::first_parent`].
+    pub first_parent: Option<bool>,
+
+    /// See [`Config::commit_date`].
+    pub commit_date: Option<CommitDate>,
+
+    /// See [`Config::commit_cap`].
+    pub commit_cap: Option<usize>,
+
+    /// See [`Config::commit_limit`].
+    pub commit_limit: Option<CommitLimit>,
+
+    /// See [`Config::skip_remotes`].
+    pub skip_remotes: Option<RemotePatterns>,
+
+    /// See [`Config::paths`].
+    pub paths: Option<ScanPaths>,
+}
+
+impl ScanSection {
+    /// Set the options in `config` that this sets.
+    fn apply(self, config: &mut Config) {
+        let Self {
+            authors,
+            since,
+            until,
+            include_merges,
+            first_parent,
+            commit_date,
+            commit_cap,
+            commit_limit,
+            skip_remotes,
+            paths,
+        } = self;
+        if let Some(authors) = authors {
+            let (emails, names) =
+                authors.into_iter().partition(|author| author.contains('@'));
+            config.identity.emails = emails;
+            config.identity.names = names;
+        }
+        if since.is_some() {
+            config.since = since;
+        }
+        if until.is_some() {
+            config.until = until;
+        }
+        if let Some(include_merges) = include_merges {
+            config.merges = if include_merges {
+                MergeCommits::Count
+            } else {
+                MergeCommits::Skip
+            };
+        }
+        if let Some(first_parent) = first_parent {
+            config.first_parent = first_parent;
+        }
+        if let Some(commit_date) = commit_date {
+            config.commit_date = commit_date;
+        }
+        if commit_cap.is_some() {
+            config.commit_cap = commit_cap;
+        }
+        if commit_limit.is_some() {
+            config.commit_limit = commit_limit;
+        }
+        if let Some(skip_remotes) = skip_remotes {
+            config.skip_remotes = skip_remotes;
+        }
+        if let Some(paths) = paths {
+            config.paths = paths;
+        }
+    }
 }
 
 impl Config {
     /// Create a configuration with a single tree.
     pub fn with_tree<T: Into<TreeConfig>>(tree: T) -> Self {
-        Self { repos: vec![tree.into()] }
+        Self { repos: vec![tree.into()], ..Self::default() }
+    }
+
+    /// Get the paths searched for a configuration file when none is given,
+    /// in order of precedence.
+    ///
+    /// These are `$XDG_CONFIG_HOME/repoyear/config.toml` (or
+    /// `~/.config/repoyear/config.toml` if `$XDG_CONFIG_HOME` isn’t set), then
+    /// `/etc/repoyear/config.toml`.
+    #[must_use]
+    pub fn default_paths() -> Vec<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| std::env::home_dir().map(|home| home.join(".config")))
+            .into_iter()
+            .chain([PathBuf::from("/etc")])
+            .map(|dir| dir.join("repoyear").join("config.toml"))
+            .collect()
+    }
+
+    /// Get the configuration files to merge with
+    /// [`load_layered()`](Self::load_layered), lowest precedence first.
+    ///
+    /// These are the [`default_paths()`](Self::default_paths) that exist,
+    /// starting with `/etc/repoyear/config.toml`, followed by `path` if it’s
+    /// passed. Returns an empty `Vec` if there are none.
+    #[must_use]
+    pub fn layers(path: Option<&Path>) -> Vec<PathBuf> {
+        let mut layers: Vec<_> = Self::default_paths()
+            .into_iter()
+            .rev()
+            .filter(|path| path.is_file())
+            .collect();
+        layers.extend(path.map(Path::to_owned));
+        layers
+    }
+
+    /// Read configuration files and merge them, with later files taking
+    /// precedence over earlier ones.
+    ///
+    /// Tables are merged key by key, so a later file only needs to set the
+    /// keys it changes. Everything else, including arrays like `[[repos]]`,
+    /// is replaced. With only one file, this is the same as
+    /// [`load()`](Self::load).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file can’t be read or parsed, or if the merged
+    /// configuration isn’t valid.
+    pub fn load_layered(paths: &[PathBuf]) -> Result<Self> {
+        if let [path] = paths {
+            return Self::load(path);
+        }
+        let mut merged = serde_json::Value::Object(serde_json::Map::new());
+        for path in paths {
+            let input = fs::read_to_string(path).map_err(|source| {
+                Error::Read { path: path.to_owned(), source }
+            })?;
+            let layer = match ConfigFormat::from_path(path) {
+                ConfigFormat::Toml => toml::from_str(&input)?,
+                ConfigFormat::Json => serde_json::from_str(&input)?,
+                ConfigFormat::Yaml => serde_norway::from_str(&input)?,
+            };
+            merge_layer(&mut merged, layer);
+        }
+        Ok(serde_json::from_value(merged)?)
     }
 
     /// Parse a TOML configuration.
@@ -26,7 +374,9 @@ impl Config {
     ///
     /// ```
     /// use assert2::assert;
-    /// use repoyear_backend::repos::{Config, TreeConfig};
+    /// use repoyear_backend::repos::{
+    ///     Config, Identity, RepoConfig, TreeConfig,
+    /// };
     /// use std::path::PathBuf;
     ///
     /// assert!(
@@ -38,20 +388,39 @@ impl Config {
     ///
     ///         [[repos]]
     ///         root = "/home/daniel/special-repo"
+    ///
+    ///         [[repo]]
+    ///         path = "/mnt/slow/project"
+    ///         name = "project"
+    ///
+    ///         [identity]
+    ///         emails = ["daniel@example.com"]
     ///         "#
     ///     )
     ///     .unwrap()
     ///         == Config {
     ///             repos: vec![
     ///                 TreeConfig {
-    ///                     root: PathBuf::from("/srv/git"),
     ///                     replace_root: Some("oxidized.org:git".to_owned()),
+    ///                     ..TreeConfig::new("/srv/git".into())
     ///                 },
-    ///                 TreeConfig {
-    ///                     root: PathBuf::from("/home/daniel/special-repo"),
-    ///                     replace_root: None,
-    ///                 },
+    ///                 TreeConfig::new("/home/daniel/special-repo".into()),
     ///             ],
+    ///             single_repos: vec![RepoConfig {
+    ///                 path: PathBuf::from("/mnt/slow/project"),
+    ///                 name: "project".to_owned(),
+    ///                 refs: vec![],
+    ///             }],
+    ///             deny: vec![],
+    ///             identity: Identity {
+    ///                 emails: vec!["daniel@example.com".to_owned()],
+    ///                 names: vec![],
+    ///                 repo_config: false,
+    ///                 github: None,
+    ///                 co_authors: false,
+    ///                 mailmap: false,
+    ///             },
+    ///             ..Config::default()
     ///         },
     /// );
     /// ```
@@ -60,7 +429,383 @@ impl Config {
     ///
     /// Returns an error if it can’t parse the configuration.
     pub fn parse(input: &str) -> Result<Self> {
-        Ok(toml::from_str(input)?)
+        Self::parse_as(input, ConfigFormat::Toml)
+    }
+
+    /// Parse a configuration in `format`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if it can’t parse the configuration.
+    pub fn parse_as(input: &str, format: ConfigFormat) -> Result<Self> {
+        Ok(match format {
+            ConfigFormat::Toml => toml::from_str(input)?,
+            ConfigFormat::Json => serde_json::from_str(input)?,
+            ConfigFormat::Yaml => serde_norway::from_str(input)?,
+        })
+    }
+
+    /// Read a configuration file.
+    ///
+    /// The format is determined by the file’s extension. See
+    /// [`ConfigFormat::from_path()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can’t be read or parsed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let format = ConfigFormat::from_path(path);
+        let input = fs::read_to_string(path)
+            .map_err(|source| Error::Read { path: path.to_owned(), source })?;
+        Self::parse_as(&input, format)
+    }
+
+    /// Use the identity from the global git configuration if none is set.
+    ///
+    /// See [`Identity::or_detect()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the git configuration could not be read.
+    pub fn detect_identity(
+        mut self,
+    ) -> ::std::result::Result<Self, git2::Error> {
+        self.identity = self.identity.or_detect()?;
+        Ok(self)
+    }
+
+    /// Scan a repository for commits by [`identity`](Self::identity).
+    ///
+    /// This never fetches; see
+    /// [`fetch_if_configured()`](Self::fetch_if_configured).
+    ///
+    /// If [`cache_dir`](Self::cache_dir) is set, results are read from the
+    /// cache when the repository hasn’t changed, and written to it otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository or the
+    /// results could not be cached.
+    pub fn scan_repo(&self, repo: &Repository) -> anyhow::Result<Vec<i64>> {
+        let options = self.scan_options(repo)?;
+        let Some(cache_dir) = &self.cache_dir else {
+            return scan_repo(repo, &options);
+        };
+
+        let cache = CacheDir::new(cache_dir);
+        let key = scan_key(repo, &options)?;
+        if let Some(times) = cache.read_scan(repo.path(), &key) {
+            return Ok(times);
+        }
+        let times = scan_repo(repo, &options)?;
+        cache.write_scan(repo.path(), &key, &times)?;
+        Ok(times)
+    }
+
+    /// Scan a repository for commits by [`identity`](Self::identity) that
+    /// are only on branches that haven’t been merged.
+    ///
+    /// See [`scan_unmerged_commits()`]. These results aren’t cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn scan_unmerged(&self, repo: &Repository) -> anyhow::Result<Vec<i64>> {
+        Ok(scan_unmerged_commits(repo, &self.scan_options(repo)?)?
+            .into_iter()
+            .map(|commit| commit.time)
+            .collect())
+    }
+
+    /// Scan a repository for commits by each of the
+    /// [`identities`](Self::identities), by label.
+    ///
+    /// See [`scan_repo_identities()`]. These results aren’t cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn scan_identities(
+        &self,
+        repo: &Repository,
+    ) -> anyhow::Result<BTreeMap<String, Vec<i64>>> {
+        scan_repo_identities(repo, &self.scan_options(repo)?, &self.identities)
+    }
+
+    /// Scan the `HEAD` reflog of a repository for activity by
+    /// [`identity`](Self::identity).
+    ///
+    /// See [`scan_reflog()`]. These results aren’t cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn scan_reflog(&self, repo: &Repository) -> anyhow::Result<Vec<i64>> {
+        scan_reflog(repo, &self.scan_options(repo)?)
+    }
+
+    /// Scan a repository for commits by [`identity`](Self::identity) along
+    /// with their summary lines and signatures.
+    ///
+    /// See [`scan_repo_summaries()`]. These results aren’t cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn scan_summaries(
+        &self,
+        repo: &Repository,
+        verify_signatures: bool,
+    ) -> anyhow::Result<Vec<CommitSummary>> {
+        scan_repo_summaries(repo, &self.scan_options(repo)?, verify_signatures)
+    }
+
+    /// Scan a repository for commits by [`identity`](Self::identity) along
+    /// with the number of lines they changed.
+    ///
+    /// Returns `Ok(None)` if [`diff_stats`](Self::diff_stats) isn’t set. See
+    /// [`scan_repo_stats()`]. These results aren’t cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository or an
+    /// exclude pattern is invalid.
+    pub fn scan_stats(
+        &self,
+        repo: &Repository,
+    ) -> anyhow::Result<Option<Vec<CommitStats>>> {
+        let Some(diff_stats) = &self.diff_stats else {
+            return Ok(None);
+        };
+        let filter = diff_stats.path_filter()?;
+        Ok(Some(scan_repo_stats(
+            repo,
+            &self.scan_options(repo)?,
+            &filter,
+        )?))
+    }
+
+    /// Find commits by [`identity`](Self::identity) whose author and commit
+    /// dates differ by more than `threshold`.
+    ///
+    /// See [`scan_date_divergence()`]. These results aren’t cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn scan_date_divergence(
+        &self,
+        repo: &Repository,
+        threshold: Duration,
+    ) -> anyhow::Result<DateDivergence> {
+        scan_date_divergence(repo, &self.scan_options(repo)?, threshold)
+    }
+
+    /// Get the options for scanning `repo`.
+    ///
+    /// This may contact the remote to find its default branch; see
+    /// [`remote_head()`](Self::remote_head).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn scan_options(
+        &self,
+        repo: &Repository,
+    ) -> anyhow::Result<ScanOptions> {
+        Ok(ScanOptions {
+            identity: self.identity.clone(),
+            limit: self.commit_limit.clone(),
+            remote_head: self.remote_head(repo)?,
+            date: self.commit_date,
+            first_parent: self.first_parent,
+            merges: self.merges,
+            since: self.since.as_ref().map(DateRange::start_timestamp),
+            until: self.until.as_ref().map(DateRange::end_timestamp),
+            commit_cap: self.commit_cap,
+            skip_remotes: self.skip_remotes_for(repo).clone(),
+            duplicate_patches: self.duplicate_patches,
+            refs: self.refs_for(repo).to_vec(),
+            paths: self.paths.clone(),
+        })
+    }
+
+    /// Get the refs to scan in `repo` instead of the default branch.
+    ///
+    /// These come from the repository’s `[[repo]]` entry if it has one, or
+    /// else from the first tree it’s in. Returns an empty slice if neither
+    /// sets [`refs`](TreeConfig::refs).
+    fn refs_for(&self, repo: &Repository) -> &[String] {
+        let paths = canonical_paths(repo);
+        let is_repo = |path: &Path| {
+            path.canonicalize().is_ok_and(|path| paths.contains(&path))
+        };
+        if let Some(single) = self
+            .single_repos
+            .iter()
+            .find(|single| is_repo(&single.path))
+        {
+            return &single.refs;
+        }
+        self.tree_for(&paths).map_or(&[], |tree| &tree.refs)
+    }
+
+    /// Find the first tree that contains any of `paths`.
+    ///
+    /// `paths` should be canonical. See [`canonical_paths()`].
+    fn tree_for(&self, paths: &[PathBuf]) -> Option<&TreeConfig> {
+        self.repos.iter().find(|tree| {
+            tree.root.canonicalize().is_ok_and(|root| {
+                paths.iter().any(|path| path.starts_with(&root))
+            })
+        })
+    }
+
+    /// Get the remote patterns to skip for `repo`.
+    ///
+    /// These come from the first tree it’s in if that sets
+    /// [`skip_remotes`](TreeConfig::skip_remotes), or else from
+    /// [`skip_remotes`](Self::skip_remotes).
+    #[must_use]
+    pub fn skip_remotes_for(&self, repo: &Repository) -> &RemotePatterns {
+        self.tree_for(&canonical_paths(repo))
+            .and_then(|tree| tree.skip_remotes.as_ref())
+            .unwrap_or(&self.skip_remotes)
+    }
+
+    /// Get the [`group`](TreeConfig::group) of the first tree `repo` is in.
+    #[must_use]
+    pub fn group_of(&self, repo: &Repository) -> Option<&str> {
+        self.tree_for(&canonical_paths(repo))
+            .and_then(|tree| tree.group.as_deref())
+    }
+
+    /// Fetch `repo` if the first tree it’s in sets
+    /// [`fetch`](TreeConfig::fetch).
+    ///
+    /// Failures are logged rather than returned, so that a remote that can’t
+    /// be reached doesn’t prevent scanning what’s already there.
+    ///
+    /// Scanning never fetches on its own, since that could hold up a request
+    /// for as long as [`fetch_timeout`](TreeConfig::fetch_timeout). Instead,
+    /// this is called before scans that run in the background: the `scan`
+    /// command, the agent, and the server’s repository watcher.
+    pub fn fetch_if_configured(&self, repo: &Repository) {
+        let Some(tree) = self
+            .tree_for(&canonical_paths(repo))
+            .filter(|tree| tree.fetch)
+        else {
+            return;
+        };
+        let timeout = Duration::from_secs(tree.fetch_timeout);
+        if let Err(error) = fetch(repo, timeout) {
+            tracing::warn!(repo = ?repo.path(), "{error}");
+        }
+    }
+
+    /// Get the branch that `HEAD` points to on `origin`, if it’s needed.
+    ///
+    /// This only asks the remote if [`ls_remote_head`](Self::ls_remote_head)
+    /// is set and the repository doesn’t already know the answer. Repositories
+    /// with [skipped remotes](Self::skip_remotes) are never asked, since only
+    /// their unpushed commits are counted.
+    ///
+    /// If the remote can’t be reached, this returns `Ok(None)` so that the
+    /// scan can fall back to guessing. The failure isn’t cached, so the next
+    /// scan tries again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository or the
+    /// result could not be cached.
+    pub fn remote_head(
+        &self,
+        repo: &Repository,
+    ) -> anyhow::Result<Option<String>> {
+        self.find_remote_head(repo, true)
+    }
+
+    /// Get the branch that `HEAD` points to on `origin` without contacting
+    /// the remote.
+    ///
+    /// This is like [`remote_head()`](Self::remote_head), but only returns
+    /// what an earlier call cached. It’s meant for things like listing
+    /// repositories, where waiting on the network would be surprising.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn cached_remote_head(
+        &self,
+        repo: &Repository,
+    ) -> anyhow::Result<Option<String>> {
+        self.find_remote_head(repo, false)
+    }
+
+    /// Get the branch that `HEAD` points to on `origin`, only asking the
+    /// remote if `online` is set.
+    ///
+    /// See [`remote_head()`](Self::remote_head).
+    fn find_remote_head(
+        &self,
+        repo: &Repository,
+        online: bool,
+    ) -> anyhow::Result<Option<String>> {
+        if !self.ls_remote_head
+            || has_remote_head(repo, "origin")?
+            || self.skip_remotes_for(repo).matches_repo(repo)?
+        {
+            return Ok(None);
+        }
+        let url = match repo.find_remote("origin") {
+            Ok(remote) => remote.url().unwrap_or_default().to_owned(),
+            Err(error) if error.code() == ErrorCode::NotFound => {
+                return Ok(None);
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let cache = self.cache_dir.as_ref().map(CacheDir::new);
+        if let Some(head) = cache
+            .as_ref()
+            .and_then(|cache| cache.read_remote_head(repo.path(), &url))
+        {
+            return Ok(head);
+        }
+        if !online {
+            return Ok(None);
+        }
+        let Ok(head) = ls_remote_head(repo, "origin") else {
+            return Ok(None);
+        };
+        if let Some(cache) = cache {
+            cache.write_remote_head(repo.path(), &url, head.as_deref())?;
+        }
+        Ok(head)
+    }
+
+    /// Get the name of the repository at `path` if it’s covered by this
+    /// configuration.
+    ///
+    /// `path` is covered if it’s under the root of a tree, or if it’s one of
+    /// the [single repositories](Self::single_repos). Paths are compared
+    /// after resolving symlinks and `..`, so a path can’t escape its tree.
+    ///
+    /// Returns `None` if `path` isn’t covered or doesn’t exist.
+    #[must_use]
+    pub fn name_for_path(&self, path: &Path) -> Option<String> {
+        let path = path.canonicalize().ok()?;
+        self.single_repos
+            .iter()
+            .find(|repo| repo.path.canonicalize().is_ok_and(|p| p == path))
+            .map(|repo| repo.name.clone())
+            .or_else(|| {
+                self.repos.iter().find_map(|tree| {
+                    let root = tree.root.canonicalize().ok()?;
+                    let suffix = path.strip_prefix(root).ok()?;
+                    tree.name(&tree.root.join(suffix))
+                })
+            })
     }
 
     /// Find repos in the directory trees defined in this configuration.
@@ -74,7 +819,91 @@ impl Config {
         let tree_iter = config_iter
             .next()
             .map(|tree_config| tree_config.repo_iter());
-        ConfigRepoIter { config_iter, tree_iter }
+        ConfigRepoIter {
+            config_iter,
+            tree_iter,
+            single_repos: self.single_repos.iter(),
+            deny: &self.deny,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Check that everything in this configuration can be found.
+    ///
+    /// This looks for repositories in every tree, opens every single
+    /// repository, and makes sure there’s an identity to scan for, either
+    /// configured or from the git configuration. It never touches the
+    /// network.
+    ///
+    /// Returns what was found in the order it was found.
+    #[must_use]
+    pub fn check(&self) -> Vec<Check> {
+        let mut checks = Vec::new();
+        for tree in &self.repos {
+            if let Err(error) = fs::read_dir(&tree.root) {
+                checks.push(Check::problem(&tree.root, error));
+                continue;
+            }
+            let mut found = 0_usize;
+            for result in tree.repo_iter() {
+                match result {
+                    Ok(_) => found = found.saturating_add(1),
+                    Err(error) => {
+                        checks.push(Check::problem(&tree.root, error));
+                    }
+                }
+            }
+            checks.push(Check::Tree { root: tree.root.clone(), found });
+        }
+
+        for repo in &self.single_repos {
+            match repo.open() {
+                Ok(_) => checks.push(Check::Repo { path: repo.path.clone() }),
+                Err(error) => checks.push(Check::problem(&repo.path, error)),
+            }
+        }
+
+        match self.identity.clone().or_detect() {
+            Ok(identity) if identity.is_empty() => {
+                checks.push(Check::Problem(
+                    "No identity configured, and none in the git configuration"
+                        .to_owned(),
+                ));
+            }
+            Ok(_) => {}
+            Err(error) => checks.push(Check::Problem(format!(
+                "Could not detect identity: {error}"
+            ))),
+        }
+        checks
+    }
+}
+
+/// Something found by [`Config::check()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Check {
+    /// A tree was searched and this many repositories were found in it.
+    Tree {
+        /// The root of the tree.
+        root: PathBuf,
+        /// The number of repositories found.
+        found: usize,
+    },
+
+    /// A single repository was opened.
+    Repo {
+        /// The path to the repository.
+        path: PathBuf,
+    },
+
+    /// Something is wrong with the configuration.
+    Problem(String),
+}
+
+impl Check {
+    /// Make a [`Check::Problem`] about `path`.
+    fn problem<E: std::fmt::Display>(path: &Path, error: E) -> Self {
+        Self::Problem(format!("{}: {error}", path.display()))
     }
 }
 
@@ -86,7 +915,10 @@ where
     TreeConfig: for<'a> From<&'a T>,
 {
     fn from(slice: &[T]) -> Self {
-        Self { repos: slice.iter().map(TreeConfig::from).collect() }
+        Self {
+            repos: slice.iter().map(TreeConfig::from).collect(),
+            ..Self::default()
+        }
     }
 }
 
@@ -98,7 +930,43 @@ where
     TreeConfig: for<'a> From<&'a T>,
 {
     fn from(arr: [T; N]) -> Self {
-        Self { repos: arr.iter().map(TreeConfig::from).collect() }
+        Self {
+            repos: arr.iter().map(TreeConfig::from).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+/// The format of a configuration file.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ConfigFormat {
+    /// [TOML](https://toml.io/). This is what `config init` writes.
+    #[default]
+    Toml,
+
+    /// JSON, with the same structure as the TOML.
+    Json,
+
+    /// YAML, with the same structure as the TOML.
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Determine the format of the configuration file at `path` from its
+    /// extension.
+    ///
+    /// `.json` files are JSON and `.yaml` or `.yml` files are YAML. Everything
+    /// else, including files without an extension, is TOML.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("json") => Self::Json,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Toml,
+        }
     }
 }
 
@@ -114,14 +982,34 @@ pub struct ConfigRepoIter<'a> {
 
     /// The internal iterator over a configured search tree.
     tree_iter: Option<TreeRepoIter<'a>>,
-}
 
-impl Iterator for ConfigRepoIter<'_> {
-    type Item = ::std::result::Result<(String, Repository), RepoIterError>;
+    /// Individual repositories, which are yielded after the trees.
+    single_repos: slice::Iter<'a, RepoConfig>,
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Patterns for repositories not to yield. See [`Config::deny`].
+    deny: &'a [String],
+
+    /// Canonical git directories of repositories already yielded from trees.
+    ///
+    /// A symlink in one tree may point into another tree (or elsewhere in the
+    /// same tree), so the same repository can be found more than once.
+    seen: HashSet<PathBuf>,
+}
+
+impl ConfigRepoIter<'_> {
+    /// Get the next repository found, whether or not it’s denied.
+    fn next_found(
+        &mut self,
+    ) -> Option<::std::result::Result<(String, Repository), RepoIterError>>
+    {
         while let Some(tree_iter) = &mut self.tree_iter {
             if let Some(item) = tree_iter.next() {
+                if let Ok((_, repo)) = &item
+                    && let Ok(path) = repo.path().canonicalize()
+                    && !self.seen.insert(path)
+                {
+                    continue;
+                }
                 return Some(item);
             }
 
@@ -130,12 +1018,75 @@ impl Iterator for ConfigRepoIter<'_> {
                 .next()
                 .map(|tree_config| tree_config.repo_iter());
         }
-        None
+        self.single_repos.next().map(RepoConfig::open)
+    }
+
+    /// Check if the repository `name` matches a pattern in
+    /// [`Config::deny`].
+    fn is_denied(&self, name: &str, repo: &Repository) -> bool {
+        if self.deny.is_empty() {
+            return false;
+        }
+        let path = path_name(repo.workdir().unwrap_or_else(|| repo.path()));
+        self.deny.iter().any(|pattern| {
+            glob_match(pattern, name) || glob_match(pattern, &path)
+        })
+    }
+}
+
+impl Iterator for ConfigRepoIter<'_> {
+    type Item = ::std::result::Result<(String, Repository), RepoIterError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = self.next_found()?;
+            if let Ok((name, repo)) = &item
+                && self.is_denied(name, repo)
+            {
+                continue;
+            }
+            return Some(item);
+        }
+    }
+}
+
+/// Configuration for a single repository.
+///
+/// # Example
+///
+/// ```toml
+/// [[repo]]
+/// path = "/mnt/slow/project"
+/// name = "project"
+/// ```
+#[derive(Debug, Clone, Deserialize, Eq, JsonSchema, PartialEq)]
+pub struct RepoConfig {
+    /// Path to the repository.
+    pub path: PathBuf,
+
+    /// Name to use for the repository in the output.
+    pub name: String,
+
+    /// Refs to scan instead of the default branch. See [`TreeConfig::refs`].
+    #[serde(default)]
+    pub refs: Vec<String>,
+}
+
+impl RepoConfig {
+    /// Open the repository.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the repository can’t be opened.
+    pub fn open(
+        &self,
+    ) -> ::std::result::Result<(String, Repository), RepoIterError> {
+        Ok((self.name.clone(), Repository::open(&self.path)?))
     }
 }
 
 /// Configuration for a repo search tree.
-#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Eq, JsonSchema, PartialEq)]
 pub struct TreeConfig {
     /// Path under which to look for repos.
     pub root: PathBuf,
@@ -153,10 +1104,225 @@ pub struct TreeConfig {
     /// If there is a repo at `/home/daniel/git/repo`, it will be called
     /// `oxidized.org:/repo` in the output.
     pub replace_root: Option<String>,
+
+    /// Whether to also scan initialized submodules of repositories found in
+    /// the tree, recursively.
+    ///
+    /// Submodules are named like any other repository under `root`.
+    #[serde(default)]
+    pub submodules: bool,
+
+    /// Refs to scan instead of the default branch, for repositories with
+    /// unusual branch layouts.
+    ///
+    /// ```toml
+    /// [[repos]]
+    /// root = "/home/daniel/releases"
+    /// refs = ["refs/heads/release/*", "refs/tags/v*"]
+    /// ```
+    ///
+    /// These are globs matched against full ref names. See
+    /// [`ScanOptions::refs`].
+    #[serde(default)]
+    pub refs: Vec<String>,
+
+    /// Whether to fetch repositories in the tree before scanning them.
+    ///
+    /// This is meant for mirrors, e.g. made with `git clone --mirror`, that
+    /// aren’t otherwise kept up to date:
+    ///
+    /// ```toml
+    /// [[repos]]
+    /// root = "/srv/git"
+    /// fetch = true
+    /// ```
+    ///
+    /// See [`fetch()`](super::fetch). If the fetch fails, the repository is
+    /// scanned as it is. Scans for requests to the server don’t fetch; see
+    /// [`Config::fetch_if_configured()`].
+    #[serde(default)]
+    pub fetch: bool,
+
+    /// How many seconds to wait for a fetch before giving up on it.
+    #[serde(default = "TreeConfig::default_fetch_timeout")]
+    pub fetch_timeout: u64,
+
+    /// Globs for directories not to search for repositories, e.g. vendored
+    /// checkouts and build directories.
+    ///
+    /// ```toml
+    /// [[repos]]
+    /// root = "/home/daniel/git"
+    /// exclude = ["**/node_modules/**", "**/target/**"]
+    /// ```
+    ///
+    /// Globs are matched against directory paths relative to `root` with `/`
+    /// at both ends, e.g. `/project/node_modules/`. `*` matches any number of
+    /// characters, including `/`, so `**` is the same as `*`. See
+    /// [`is_excluded()`](Self::is_excluded).
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// How many directories deep to search for repositories under `root`.
+    ///
+    /// ```toml
+    /// [[repos]]
+    /// root = "/home/daniel"
+    /// max_depth = 2
+    /// ```
+    ///
+    /// With a depth of 1 only repositories directly in `root`, like
+    /// `/home/daniel/repo`, are found; with 2, `/home/daniel/git/repo` is
+    /// also found. If this isn’t set the whole tree is searched.
+    pub max_depth: Option<usize>,
+
+    /// Whether to follow symbolic links to directories when searching for
+    /// repositories. This is on by default.
+    ///
+    /// Turn this off if links in the tree could loop or lead somewhere slow,
+    /// like a network mount.
+    #[serde(default = "TreeConfig::default_follow_symlinks")]
+    pub follow_symlinks: bool,
+
+    /// Rules to rename repositories, applied in order after
+    /// [`replace_root`](Self::replace_root).
+    ///
+    /// ```toml
+    /// [[repos]]
+    /// root = "/srv/git"
+    /// replace_root = ""
+    /// rename = [{ pattern = '\.git$', replace = "" }]
+    /// ```
+    ///
+    /// If there is a repo at `/srv/git/team-x/foo.git`, it will be called
+    /// `team-x/foo` in the output. See [`RenameRule`].
+    #[serde(default)]
+    pub rename: Vec<RenameRule>,
+
+    /// Group to report repositories in this tree under, e.g. to show work and
+    /// personal repositories in separate calendars.
+    ///
+    /// ```toml
+    /// [[repos]]
+    /// root = "/home/daniel/work"
+    /// group = "work"
+    /// ```
+    pub group: Option<String>,
+
+    /// Remote patterns to skip for repositories in this tree, instead of the
+    /// top-level [`skip_remotes`](Config::skip_remotes).
+    ///
+    /// For example, to count every commit in a tree of GitHub mirrors:
+    ///
+    /// ```toml
+    /// [[repos]]
+    /// root = "/srv/mirrors"
+    /// skip_remotes = []
+    /// ```
+    pub skip_remotes: Option<RemotePatterns>,
 }
 
 impl TreeConfig {
-    /// Find repositories in this directory tree.
+    /// Make a configuration for the tree at `root`.
+    ///
+    /// Everything else is the same as a `[[repos]]` entry with only `root`
+    /// set.
+    #[must_use]
+    pub const fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            replace_root: None,
+            submodules: false,
+            refs: Vec::new(),
+            fetch: false,
+            fetch_timeout: Self::default_fetch_timeout(),
+            exclude: Vec::new(),
+            max_depth: None,
+            follow_symlinks: Self::default_follow_symlinks(),
+            rename: Vec::new(),
+            group: None,
+            skip_remotes: None,
+        }
+    }
+
+    /// Default for [`fetch_timeout`](Self::fetch_timeout).
+    const fn default_fetch_timeout() -> u64 {
+        60
+    }
+
+    /// Default for [`follow_symlinks`](Self::follow_symlinks).
+    const fn default_follow_symlinks() -> bool {
+        true
+    }
+
+    /// Get the name of the repository at `path`.
+    ///
+    /// Returns `None` if `path` is not under `root`. The
+    /// [`rename`](Self::rename) rules are applied last.
+    ///
+    /// Names always use `/` as a separator, even on Windows. On Windows, `root`
+    /// is matched without regard to case, and verbatim paths (`\\?\C:\`)
+    /// match their normal equivalents (`C:\`).
+    #[must_use]
+    pub fn name(&self, path: &Path) -> Option<String> {
+        let suffix = strip_root(&self.root, path)?;
+        let name = match &self.replace_root {
+            Some(prefix) => format!("{prefix}{}", path_name(suffix)),
+            None => path_name(path),
+        };
+        Some(
+            self.rename
+                .iter()
+                .fold(name, |name, rule| rule.apply(&name)),
+        )
+    }
+
+    /// Check if the directory at `path` matches a glob in
+    /// [`exclude`](Self::exclude).
+    ///
+    /// `root` itself is never excluded.
+    #[must_use]
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let Some(suffix) = strip_root(&self.root, path) else {
+            return false;
+        };
+        if self.exclude.is_empty() || suffix.as_os_str().is_empty() {
+            return false;
+        }
+        let path = format!("/{}/", path_name(suffix));
+        self.exclude
+            .iter()
+            .any(|pattern| glob_match(pattern, &path))
+    }
+
+    /// Add the initialized submodules of `repo` to `found`, recursively.
+    fn find_submodules(
+        &self,
+        repo: &Repository,
+        found: &mut Vec<
+            ::std::result::Result<(String, PathBuf), RepoIterError>,
+        >,
+    ) {
+        let submodules = match repo.submodules() {
+            Ok(submodules) => submodules,
+            Err(error) => return found.push(Err(error.into())),
+        };
+        for submodule in submodules {
+            // Submodules that haven’t been initialized can’t be opened.
+            let Ok(submodule) = submodule.open() else {
+                continue;
+            };
+            let path = submodule.workdir().unwrap_or_else(|| submodule.path());
+            let Some(name) = self.name(path) else {
+                continue;
+            };
+            let index = found.len();
+            self.find_submodules(&submodule, found);
+            found.insert(index, Ok((name, submodule.path().to_owned())));
+        }
+    }
+
+    /// Find repositories in this directory tree, in order by path.
     ///
     /// Returns an iterator that yields
     /// `Result<(String, Repository), RepoIterError>`.
@@ -164,13 +1330,122 @@ impl TreeConfig {
         fn is_dir(entry: &DirEntry) -> bool {
             entry.file_type().is_dir()
         }
+        let mut walker = WalkDir::new(&self.root)
+            .follow_links(self.follow_symlinks)
+            .sort_by_file_name();
+        if let Some(depth) = self.max_depth {
+            walker = walker.max_depth(depth);
+        }
         TreeRepoIter {
-            walker: WalkDir::new(&self.root)
-                .follow_links(true)
-                .into_iter()
-                .filter_entry(is_dir),
+            walker: walker.into_iter().filter_entry(is_dir),
             tree_config: self,
+            worktree_mains: HashSet::new(),
+            submodules: Vec::new(),
+        }
+    }
+}
+
+/// Files that mark a directory to be skipped when searching trees.
+///
+/// Putting one of these in a directory skips it and everything under it,
+/// including a repository in it, without changing the configuration.
+pub const IGNORE_MARKERS: [&str; 2] = [".repoyearignore", ".norepoyear"];
+
+/// Check if the directory at `path` contains one of [`IGNORE_MARKERS`].
+fn has_ignore_marker(path: &Path) -> bool {
+    IGNORE_MARKERS
+        .iter()
+        .any(|marker| path.join(marker).exists())
+}
+
+/// Merge `layer` into `base` for [`Config::load_layered()`].
+fn merge_layer(base: &mut serde_json::Value, layer: serde_json::Value) {
+    match (base, layer) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_layer(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Get the canonical paths of the working directory, if any, and git
+/// directory of `repo`.
+fn canonical_paths(repo: &Repository) -> Vec<PathBuf> {
+    repo.workdir()
+        .into_iter()
+        .chain([repo.path()])
+        .filter_map(|path| path.canonicalize().ok())
+        .collect()
+}
+
+/// Remove `root` from the start of `path`.
+///
+/// See [`TreeConfig::name()`] for how this differs on Windows.
+fn strip_root<'a>(root: &Path, path: &'a Path) -> Option<&'a Path> {
+    let mut components = path.components();
+    for expected in root.components() {
+        let actual = component_name(components.next()?);
+        let expected = component_name(expected);
+        let matches = if cfg!(windows) {
+            actual.to_lowercase() == expected.to_lowercase()
+        } else {
+            actual == expected
+        };
+        if !matches {
+            return None;
+        }
+    }
+    Some(components.as_path())
+}
+
+/// Format a path as a name using `/` as the separator.
+fn path_name(path: &Path) -> String {
+    let mut name = String::new();
+    let mut separate = false;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => separate = false,
+            _ if separate => name.push('/'),
+            _ => separate = true,
         }
+        name.push_str(&component_name(component));
+    }
+    name
+}
+
+/// Format one component of a path for [`path_name()`].
+///
+/// Verbatim prefixes (`\\?\`) are formatted like normal prefixes.
+fn component_name(component: Component<'_>) -> Cow<'_, str> {
+    match component {
+        Component::Prefix(prefix) => match prefix.kind() {
+            Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                format!("{}:", char::from(letter)).into()
+            }
+            Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                format!(
+                    "//{}/{}",
+                    server.to_string_lossy(),
+                    share.to_string_lossy()
+                )
+                .into()
+            }
+            Prefix::DeviceNS(device) => {
+                format!("//./{}", device.to_string_lossy()).into()
+            }
+            Prefix::Verbatim(name) => name.to_string_lossy(),
+        },
+        Component::RootDir => "/".into(),
+        Component::CurDir => ".".into(),
+        Component::ParentDir => "..".into(),
+        Component::Normal(name) => name.to_string_lossy(),
     }
 }
 
@@ -179,10 +1454,7 @@ impl TreeConfig {
 /// Convenience for writing tests.
 impl From<(&str, Option<&str>)> for TreeConfig {
     fn from((root, replace_root): (&str, Option<&str>)) -> Self {
-        Self {
-            root: root.into(),
-            replace_root: replace_root.map(str::to_owned),
-        }
+        Self::from((PathBuf::from(root), replace_root))
     }
 }
 
@@ -191,10 +1463,7 @@ impl From<(&str, Option<&str>)> for TreeConfig {
 /// Convenience for writing tests.
 impl From<(&Path, Option<&str>)> for TreeConfig {
     fn from((root, replace_root): (&Path, Option<&str>)) -> Self {
-        Self {
-            root: root.into(),
-            replace_root: replace_root.map(str::to_owned),
-        }
+        Self::from((root.to_owned(), replace_root))
     }
 }
 
@@ -203,7 +1472,10 @@ impl From<(&Path, Option<&str>)> for TreeConfig {
 /// Convenience for writing tests.
 impl From<(PathBuf, Option<&str>)> for TreeConfig {
     fn from((root, replace_root): (PathBuf, Option<&str>)) -> Self {
-        Self { root, replace_root: replace_root.map(str::to_owned) }
+        Self {
+            replace_root: replace_root.map(str::to_owned),
+            ..Self::new(root)
+        }
     }
 }
 
@@ -212,7 +1484,7 @@ impl From<(PathBuf, Option<&str>)> for TreeConfig {
 /// Convenience for writing tests.
 impl From<&Path> for TreeConfig {
     fn from(root: &Path) -> Self {
-        Self { root: root.into(), replace_root: None }
+        Self::new(root.to_owned())
     }
 }
 
@@ -221,7 +1493,7 @@ impl From<&Path> for TreeConfig {
 /// Convenience for writing tests.
 impl From<PathBuf> for TreeConfig {
     fn from(root: PathBuf) -> Self {
-        Self { root, replace_root: None }
+        Self::new(root)
     }
 }
 
@@ -238,47 +1510,87 @@ pub struct TreeRepoIter<'a> {
 
     /// The tree config.
     tree_config: &'a TreeConfig,
+
+    /// Main repositories outside of the tree that have already been yielded
+    /// for their linked worktrees.
+    worktree_mains: HashSet<PathBuf>,
+
+    /// Names and git directories of submodules waiting to be yielded, last
+    /// first. See [`TreeConfig::submodules`].
+    submodules: Vec<::std::result::Result<(String, PathBuf), RepoIterError>>,
+}
+
+impl TreeRepoIter<'_> {
+    /// Get the repository to scan in place of a linked worktree.
+    ///
+    /// A worktree shares its history with its main repository, so scanning
+    /// both would count commits twice. If the main repository is in the tree
+    /// it’s found on its own, and this returns `Ok(None)`. Otherwise this
+    /// returns the main repository the first time one of its worktrees is
+    /// found.
+    fn worktree_main(
+        &mut self,
+        worktree: &Repository,
+    ) -> Result<Option<Repository>, git2::Error> {
+        let main = Repository::open(worktree.commondir())?;
+        let main_path = main.workdir().unwrap_or_else(|| main.path());
+        if self.tree_config.name(main_path).is_some()
+            || !self.worktree_mains.insert(main.path().to_owned())
+        {
+            return Ok(None);
+        }
+        Ok(Some(main))
+    }
 }
 
 impl Iterator for TreeRepoIter<'_> {
     type Item = ::std::result::Result<(String, Repository), RepoIterError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        fn get_name(
-            root: &Path,
-            replace_root: Option<&str>,
-            path: &Path,
-        ) -> String {
-            #[expect(
-                clippy::match_wild_err_arm,
-                reason = "better panic message"
-            )]
-            match replace_root {
-                Some(prefix) => match path.strip_prefix(root) {
-                    Ok(suffix) => {
-                        format!("{prefix}{}", suffix.display())
-                    }
-                    Err(_) => panic!(
-                        "{path:?} found under {root:?}, but does not have it \
-                        as a prefix",
-                    ),
-                },
-                None => path.to_string_lossy().into_owned(),
-            }
+        if let Some(item) = self.submodules.pop() {
+            return Some(
+                item.and_then(|(name, path)| {
+                    Ok((name, Repository::open(path)?))
+                }),
+            );
         }
-
         loop {
             match self.walker.next() {
                 None => return None,
                 Some(Err(error)) => return Some(Err(error.into())),
+                Some(Ok(entry))
+                    if self.tree_config.is_excluded(entry.path())
+                        || has_ignore_marker(entry.path()) =>
+                {
+                    self.walker.skip_current_dir();
+                }
                 Some(Ok(entry)) => match Repository::open(entry.path()) {
                     Ok(repository) => {
                         self.walker.skip_current_dir();
-                        let name = get_name(
-                            &self.tree_config.root,
-                            self.tree_config.replace_root.as_deref(),
-                            entry.path(),
-                        );
+                        let repository = if repository.is_worktree() {
+                            match self.worktree_main(&repository) {
+                                Ok(Some(main)) => main,
+                                Ok(None) => continue,
+                                Err(error) => return Some(Err(error.into())),
+                            }
+                        } else {
+                            repository
+                        };
+                        let Some(name) = self.tree_config.name(entry.path())
+                        else {
+                            panic!(
+                                "{:?} found under {:?}, but does not have it \
+                                as a prefix",
+                                entry.path(),
+                                self.tree_config.root,
+                            );
+                        };
+                        if self.tree_config.submodules {
+                            let mut found = Vec::new();
+                            self.tree_config
+                                .find_submodules(&repository, &mut found);
+                            self.submodules.extend(found.into_iter().rev());
+                        }
                         return Some(Ok((name, repository)));
                     }
                     Err(error) if error.code() == ErrorCode::NotFound => {}
@@ -339,6 +1651,237 @@ mod tests {
         );
     }
 
+    #[test]
+    fn default_paths() {
+        let paths = Config::default_paths();
+        assert!(
+            paths.last() == Some(&PathBuf::from("/etc/repoyear/config.toml"))
+        );
+        assert!(
+            paths
+                .iter()
+                .all(|path| path.ends_with("repoyear/config.toml"))
+        );
+    }
+
+    #[test]
+    fn json_schema() {
+        let schema =
+            serde_json::to_value(schemars::schema_for!(Config)).unwrap();
+        let definitions = &schema["definitions"];
+        assert!(
+            definitions["TreeConfig"]["required"]
+                == serde_json::json!(["root"])
+        );
+        assert!(definitions["DateRange"]["type"] == "string");
+        assert!(schema["properties"]["skip_remotes"]["type"] == "array");
+    }
+
+    #[test]
+    fn load_layered() {
+        let dir = testdir!();
+        let system = dir.join("system.toml");
+        std::fs::write(
+            &system,
+            "unmerged = true\ncommit_cap = 10\n\
+            [[repos]]\nroot = \"/srv/git\"\n\
+            [identity]\nemails = [\"a@example.com\"]\nmailmap = true\n",
+        )
+        .unwrap();
+        let user = dir.join("user.json");
+        std::fs::write(
+            &user,
+            r#"{"commit_cap": 20, "identity": {"emails": ["b@example.com"]}}"#,
+        )
+        .unwrap();
+
+        let config = Config::load_layered(&[system, user]).unwrap();
+        assert!(config.unmerged);
+        assert!(config.commit_cap == Some(20));
+        assert!(config.repos.len() == 1);
+        assert!(config.identity.emails == ["b@example.com"]);
+        assert!(config.identity.mailmap);
+    }
+
+    #[test]
+    fn check() {
+        let home = Home::init(testdir!());
+        home.git_init("tree/a").make_commit(0);
+        home.git_init("tree/b").make_commit(0);
+        home.git_init("single").make_commit(0);
+        let config = Config::parse(&format!(
+            "[[repos]]\nroot = '{tree}'\n\
+            [[repos]]\nroot = '{missing}'\n\
+            [[repo]]\npath = '{single}'\nname = 'single'\n\
+            [[repo]]\npath = '{missing}'\nname = 'missing'\n\
+            [identity]\nemails = ['name@example.com']\n",
+            tree = home.join("tree").display(),
+            missing = home.join("missing").display(),
+            single = home.join("single").display(),
+        ))
+        .unwrap();
+
+        let checks = config.check();
+        assert!(checks.len() == 4);
+        assert!(checks[0] == Check::Tree { root: home.join("tree"), found: 2 });
+        assert!(let Check::Problem(_) = &checks[1]);
+        assert!(checks[2] == Check::Repo { path: home.join("single") });
+        assert!(let Check::Problem(_) = &checks[3]);
+    }
+
+    #[test]
+    fn scan_section() {
+        let config = Config::parse(
+            "since = \"2020\"\ncommit_cap = 5\n\
+            [identity]\nemails = [\"old@example.com\"]\nmailmap = true\n\
+            [scan]\n\
+            authors = [\"a@example.com\", \"A Person\"]\n\
+            since = \"2024\"\nuntil = \"2024-06\"\n\
+            include_merges = false\nfirst_parent = true\n\
+            commit_date = \"committer\"\nskip_remotes = []\n",
+        )
+        .unwrap();
+        assert!(config.identity.emails == ["a@example.com"]);
+        assert!(config.identity.names == ["A Person"]);
+        assert!(config.identity.mailmap);
+        assert!(config.since == Some("2024".parse().unwrap()));
+        assert!(config.until == Some("2024-06".parse().unwrap()));
+        assert!(config.merges == MergeCommits::Skip);
+        assert!(config.first_parent);
+        assert!(config.commit_date == CommitDate::Committer);
+        assert!(config.commit_cap == Some(5));
+        assert!(config.skip_remotes == RemotePatterns(Vec::new()));
+
+        let yaml = Config::parse_as(
+            "scan:\n  include_merges: true\n  commit_cap: 1\n",
+            ConfigFormat::Yaml,
+        )
+        .unwrap();
+        assert!(yaml.merges == MergeCommits::Count);
+        assert!(yaml.commit_cap == Some(1));
+
+        assert!(let Err(_) = Config::parse("[scan]\nunknown = 1\n"));
+    }
+
+    #[test]
+    fn scan_section_layered() {
+        let dir = testdir!();
+        let system = dir.join("system.toml");
+        std::fs::write(&system, "[scan]\nsince = \"2020\"\n").unwrap();
+        let user = dir.join("user.toml");
+        std::fs::write(&user, "[scan]\nuntil = \"2024\"\n").unwrap();
+
+        let config = Config::load_layered(&[system, user]).unwrap();
+        assert!(config.since == Some("2020".parse().unwrap()));
+        assert!(config.until == Some("2024".parse().unwrap()));
+    }
+
+    #[test]
+    fn default_is_empty_file() {
+        assert!(Config::parse("").unwrap() == Config::default());
+        assert!(
+            Config::with_tree(Path::new("/src"))
+                == Config {
+                    repos: vec![TreeConfig::from(Path::new("/src"))],
+                    ..Config::default()
+                }
+        );
+    }
+
+    #[test]
+    fn load_formats() {
+        let dir = testdir!();
+        let toml = dir.join("scan.toml");
+        std::fs::write(
+            &toml,
+            "unmerged = true\n[[repos]]\nroot = \"/src\"\nmax_depth = 2\n",
+        )
+        .unwrap();
+        let json = dir.join("scan.JSON");
+        std::fs::write(
+            &json,
+            r#"{"unmerged": true, "repos": [{"root": "/src", "max_depth": 2}]}"#,
+        )
+        .unwrap();
+        let config = Config::load(&toml).unwrap();
+        assert!(config.unmerged);
+        assert!(config.repos[0].max_depth == Some(2));
+        assert!(Config::load(&json).unwrap() == config);
+
+        let yaml = dir.join("scan.yml");
+        std::fs::write(
+            &yaml,
+            "unmerged: true\nrepos:\n  - root: /src\n    max_depth: 2\n",
+        )
+        .unwrap();
+        assert!(Config::load(&yaml).unwrap() == config);
+
+        let broken = dir.join("broken.yaml");
+        std::fs::write(&broken, "unmerged: [\n").unwrap();
+        assert!(let Err(Error::Yaml(_)) = Config::load(&broken));
+        assert!(let Err(Error::Read { .. }) = Config::load(&dir.join("no.toml")));
+    }
+
+    #[test]
+    fn parse_formats() {
+        let toml = Config::parse(
+            "deny = [\"tmp/*\"]\n\
+            [[repos]]\nroot = \"/src\"\nrefs = [\"main\"]\n\
+            [[repo]]\npath = \"/opt/x\"\nname = \"x\"\n\
+            [identity]\nemails = [\"a@example.com\"]\nnames = [\"A\"]\n",
+        )
+        .unwrap();
+        let json = Config::parse_as(
+            r#"{
+                "deny": ["tmp/*"],
+                "repos": [{"root": "/src", "refs": ["main"]}],
+                "repo": [{"path": "/opt/x", "name": "x"}],
+                "identity": {"emails": ["a@example.com"], "names": ["A"]}
+            }"#,
+            ConfigFormat::Json,
+        )
+        .unwrap();
+        let yaml = Config::parse_as(
+            "deny: [tmp/*]\n\
+            repos:\n  - root: /src\n    refs: [main]\n\
+            repo:\n  - path: /opt/x\n    name: x\n\
+            identity:\n  emails: [a@example.com]\n  names: [A]\n",
+            ConfigFormat::Yaml,
+        )
+        .unwrap();
+        assert!(toml.single_repos[0].name == "x");
+        assert!(toml.identity.names == ["A"]);
+        assert!(json == toml);
+        assert!(yaml == toml);
+
+        assert!(let Err(Error::Json(_)) = Config::parse_as("{", ConfigFormat::Json));
+        assert!(
+            let Err(Error::Yaml(_)) =
+                Config::parse_as("repos: 1\n", ConfigFormat::Yaml)
+        );
+        assert!(
+            Config::parse_as("", ConfigFormat::Yaml).unwrap()
+                == Config::default()
+        );
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert!(
+            ConfigFormat::from_path(Path::new("a.toml")) == ConfigFormat::Toml
+        );
+        assert!(ConfigFormat::from_path(Path::new("a")) == ConfigFormat::Toml);
+        assert!(
+            ConfigFormat::from_path(Path::new("a.json")) == ConfigFormat::Json
+        );
+        assert!(
+            ConfigFormat::from_path(Path::new("a.YAML")) == ConfigFormat::Yaml
+        );
+        assert!(
+            ConfigFormat::from_path(Path::new("a.yml")) == ConfigFormat::Yaml
+        );
+    }
+
     #[test]
     fn tree_no_repos() {
         let home = Home::init(testdir!());
@@ -376,6 +1919,397 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tree_worktrees() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("tree/repo");
+        repo.make_commit(0);
+        repo.git(["worktree", "add", "../repo-feature"]);
+        let outside = home.git_init("outside");
+        outside.make_commit(0);
+        outside.git(["worktree", "add", "../tree/a"]);
+        outside.git(["worktree", "add", "../tree/b"]);
+
+        assert!(
+            summarize_config(Config::with_tree((
+                home.join("tree"),
+                Some("x:")
+            ))) == [
+                Ok(("x:a".to_owned(), outside.join(".git/"))),
+                Ok(("x:repo".to_owned(), repo.join(".git"))),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn trees_symlinked_repo() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("a/repo");
+        repo.make_commit(0);
+        home.mkdir("b");
+        std::os::unix::fs::symlink(repo.path(), home.join("b/link")).unwrap();
+        std::os::unix::fs::symlink(repo.path(), home.join("a/same")).unwrap();
+
+        let mut config = Config::with_tree((home.join("a"), Some("a:")));
+        config.repos.push((home.join("b"), Some("b:")).into());
+        assert!(
+            summarize_config(config)
+                == [Ok(("a:repo".to_owned(), repo.join(".git")))]
+        );
+    }
+
+    #[test]
+    fn tree_exclude() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("tree/repo");
+        repo.make_commit(0);
+        home.git_init("tree/node_modules/dep").make_commit(0);
+        home.git_init("tree/web/node_modules/dep").make_commit(0);
+        home.git_init("tree/vendor/lib").make_commit(0);
+
+        let mut tree = TreeConfig::from((home.join("tree"), Some("x:")));
+        assert!(summarize_config(Config::with_tree(tree.clone())).len() == 4);
+
+        tree.exclude =
+            vec!["**/node_modules/**".to_owned(), "/vendor/".to_owned()];
+        assert!(
+            summarize_config(Config::with_tree(tree))
+                == [Ok(("x:repo".to_owned(), repo.join(".git")))]
+        );
+    }
+
+    #[test]
+    fn tree_ignore_markers() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("tree/repo");
+        repo.make_commit(0);
+        let ignored = home.git_init("tree/ignored");
+        ignored.make_commit(0);
+        ignored.write(".norepoyear", "");
+        home.git_init("tree/scratch/a").make_commit(0);
+        home.write("tree/scratch/.repoyearignore", "");
+
+        assert!(
+            summarize_config(Config::with_tree((
+                home.join("tree"),
+                Some("x:")
+            ))) == [Ok(("x:repo".to_owned(), repo.join(".git")))]
+        );
+    }
+
+    #[test]
+    fn tree_max_depth() {
+        let home = Home::init(testdir!());
+        let shallow = home.git_init("tree/shallow");
+        shallow.make_commit(0);
+        let deep = home.git_init("tree/a/deep");
+        deep.make_commit(0);
+        home.git_init("tree/a/b/deeper").make_commit(0);
+
+        let mut tree = TreeConfig::from((home.join("tree"), Some("x:")));
+        tree.max_depth = Some(2);
+        assert!(
+            summarize_config(Config::with_tree(tree.clone()))
+                == [
+                    Ok(("x:a/deep".to_owned(), deep.join(".git"))),
+                    Ok(("x:shallow".to_owned(), shallow.join(".git"))),
+                ]
+        );
+
+        tree.max_depth = Some(1);
+        assert!(
+            summarize_config(Config::with_tree(tree))
+                == [Ok(("x:shallow".to_owned(), shallow.join(".git")))]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tree_follow_symlinks() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        home.mkdir("tree");
+        std::os::unix::fs::symlink(repo.path(), home.join("tree/link"))
+            .unwrap();
+
+        let mut tree = TreeConfig::from((home.join("tree"), Some("x:")));
+        assert!(
+            summarize_config(Config::with_tree(tree.clone()))
+                == [Ok(("x:link".to_owned(), repo.join(".git")))]
+        );
+
+        tree.follow_symlinks = false;
+        assert!(summarize_config(Config::with_tree(tree)).is_empty());
+    }
+
+    #[test]
+    fn tree_submodules() {
+        let home = Home::init(testdir!());
+        let lib = home.git_init("lib");
+        lib.make_commit(0);
+        let repo = home.git_init("tree/repo");
+        repo.make_commit(0);
+        repo.git([
+            "-c",
+            "protocol.file.allow=always",
+            "submodule",
+            "add",
+            lib.path().to_str().unwrap(),
+            "lib",
+        ]);
+
+        let mut tree = TreeConfig::from((home.join("tree"), Some("x:")));
+        assert!(summarize_config(Config::with_tree(tree.clone())).len() == 1);
+
+        tree.submodules = true;
+        assert!(
+            summarize_config(Config::with_tree(tree))
+                == [
+                    Ok(("x:repo".to_owned(), repo.join(".git"))),
+                    Ok((
+                        "x:repo/lib".to_owned(),
+                        repo.join(".git/modules/lib")
+                    )),
+                ]
+        );
+    }
+
+    #[test]
+    fn single_repos() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("deep/inside/repo");
+        let config = Config::parse(&format!(
+            "[[repo]]\npath = {:?}\nname = \"special\"\n\n\
+            [[repo]]\npath = {:?}\nname = \"missing\"\n",
+            repo.path(),
+            home.join("missing"),
+        ))
+        .unwrap();
+
+        let results = summarize_config(config);
+        assert!(results[0] == Ok(("special".to_owned(), repo.join(".git"))));
+        assert!(results.len() == 2);
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn deny_repos() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("tree/repo");
+        home.git_init("tree/archive/old");
+        home.git_init("tree/embarrassing");
+        let single = home.git_init("single");
+        let mut config = Config::with_tree((home.join("tree"), Some("x:")));
+        config.single_repos.push(RepoConfig {
+            path: single.path().to_owned(),
+            name: "single".to_owned(),
+            refs: Vec::new(),
+        });
+        config.deny =
+            vec!["**/archive/**".to_owned(), "x:embarrassing".to_owned()];
+
+        assert!(
+            summarize_config(config)
+                == [
+                    Ok(("single".to_owned(), single.join(".git"))),
+                    Ok(("x:repo".to_owned(), repo.join(".git"))),
+                ]
+        );
+    }
+
+    #[test]
+    fn refs_for_repo() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("tree/repo");
+        repo.make_commit(0);
+        repo.git(["tag", "v1"]);
+        repo.make_commit(1);
+        let single = home.git_init("single");
+        let mut config = Config::with_tree(home.join("tree"));
+        config.repos[0].refs = vec!["refs/tags/v*".to_owned()];
+        config.single_repos.push(RepoConfig {
+            path: single.path().to_owned(),
+            name: "single".to_owned(),
+            refs: vec!["refs/heads/*".to_owned()],
+        });
+
+        let repository = Repository::open(repo.path()).unwrap();
+        let options = config.scan_options(&repository).unwrap();
+        assert!(options.refs == ["refs/tags/v*"]);
+        assert!(let Ok([_]) = scan_repo(&repository, &options).as_deref());
+
+        let repository = Repository::open(single.path()).unwrap();
+        assert!(
+            config.scan_options(&repository).unwrap().refs == ["refs/heads/*"]
+        );
+
+        let repository =
+            Repository::open(home.git_init("other").path()).unwrap();
+        assert!(config.scan_options(&repository).unwrap().refs.is_empty());
+    }
+
+    #[test]
+    fn group_of_repo() {
+        let home = Home::init(testdir!());
+        let work = home.git_init("work/repo");
+        let personal = home.git_init("personal/repo");
+        let mut config = Config::with_tree((home.join("work"), Some("w:")));
+        config.repos[0].group = Some("work".to_owned());
+        config
+            .repos
+            .push((home.join("personal"), Some("p:")).into());
+
+        let work = Repository::open(work.path()).unwrap();
+        let personal = Repository::open(personal.path()).unwrap();
+        assert!(config.group_of(&work) == Some("work"));
+        assert!(config.group_of(&personal).is_none());
+    }
+
+    #[test]
+    fn skip_remotes_config() {
+        assert!(Config::default().skip_remotes == RemotePatterns::default());
+        let config = Config::parse(
+            "skip_remotes = [\"gitea.example.com/*\"]\n\
+            [[repos]]\nroot = \"/srv/mirrors\"\nskip_remotes = []\n",
+        )
+        .unwrap();
+        assert!(config.skip_remotes.0 == ["gitea.example.com/*"]);
+        assert!(config.repos[0].skip_remotes == Some(RemotePatterns(vec![])));
+        assert!(let Err(_) = Config::parse("skip_remotes = \"github.com/*\"\n"));
+    }
+
+    #[test]
+    fn skip_remotes_for_tree() {
+        let home = Home::init(testdir!());
+        let mirror = home.git_init("mirrors/repo");
+        mirror.git(["remote", "add", "origin", "https://github.com/a/b"]);
+        let other = home.git_init("other/repo");
+        other.git(["remote", "add", "origin", "https://github.com/a/c"]);
+        let mut config = Config::with_tree((home.join("mirrors"), Some("m:")));
+        config.repos[0].skip_remotes = Some(RemotePatterns(Vec::new()));
+        config.repos.push((home.join("other"), Some("o:")).into());
+
+        let mirror = Repository::open(mirror.path()).unwrap();
+        let other = Repository::open(other.path()).unwrap();
+        assert!(
+            !config
+                .skip_remotes_for(&mirror)
+                .matches_repo(&mirror)
+                .unwrap()
+        );
+        assert!(
+            config
+                .skip_remotes_for(&other)
+                .matches_repo(&other)
+                .unwrap()
+        );
+        assert!(
+            config
+                .scan_options(&mirror)
+                .unwrap()
+                .skip_remotes
+                .0
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn scan_options_identity() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.write("a", "other");
+        repo.git(["commit", "-am", "other", "--author", "O <o@example.com>"]);
+        let repo = Repository::open(repo.path()).unwrap();
+
+        assert!(let Ok([_, _]) = Config::default().scan_repo(&repo).as_deref());
+
+        let config =
+            Config::parse("[identity]\nemails = ['name@example.com']\n")
+                .unwrap();
+        let options = config.scan_options(&repo).unwrap();
+        assert!(options.identity.emails == ["name@example.com"]);
+        assert!(let Ok([_]) = config.scan_repo(&repo).as_deref());
+    }
+
+    #[test]
+    fn fetch_mirror() {
+        let home = Home::init(testdir!());
+        let upstream = home.git_init("upstream");
+        upstream.make_commit(0);
+        home.mkdir("tree");
+        home.git(
+            "tree",
+            [
+                "clone".as_ref(),
+                "--mirror".as_ref(),
+                upstream.path().as_os_str(),
+                "mirror.git".as_ref(),
+            ],
+        );
+        upstream.make_commit(1);
+        let repository =
+            Repository::open(home.join("tree/mirror.git")).unwrap();
+
+        let mut config = Config::with_tree(home.join("tree"));
+        assert!(let Ok([_]) = config.scan_repo(&repository).as_deref());
+
+        config.repos[0].fetch = true;
+        assert!(let Ok([_]) = config.scan_repo(&repository).as_deref());
+        config.fetch_if_configured(&repository);
+        assert!(let Ok([_, _]) = config.scan_repo(&repository).as_deref());
+    }
+
+    #[test]
+    fn cached_remote_head() {
+        let home = Home::init(testdir!());
+        let bare_repo = home.git_init_bare("bare_repo");
+        let repo = bare_repo.clone("repo");
+        repo.make_commit(0);
+        repo.git(["push", "origin", "main:trunk"]);
+        home.git(
+            bare_repo.path(),
+            ["symbolic-ref", "HEAD", "refs/heads/trunk"],
+        );
+        let clone = bare_repo.clone("tree/clone");
+        clone.git(["remote", "set-head", "origin", "--delete"]);
+        let repository = Repository::open(clone.path()).unwrap();
+
+        let mut config = Config::with_tree(home.join("tree"));
+        config.ls_remote_head = true;
+        config.cache_dir = Some(home.join("cache"));
+        assert!(config.cached_remote_head(&repository).unwrap().is_none());
+        assert!(
+            config.remote_head(&repository).unwrap().as_deref()
+                == Some("trunk")
+        );
+
+        // Now it’s cached, so it doesn’t matter if the remote is gone.
+        std::fs::remove_dir_all(bare_repo.path()).unwrap();
+        assert!(
+            config.cached_remote_head(&repository).unwrap().as_deref()
+                == Some("trunk")
+        );
+    }
+
+    #[test]
+    fn name_for_path() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("tree/repo");
+        home.git_init("outside");
+        let config = Config::with_tree((home.join("tree"), Some("x:")));
+
+        assert!(config.name_for_path(repo.path()) == Some("x:repo".to_owned()));
+        assert!(
+            config.name_for_path(&home.join("tree/../tree/repo"))
+                == Some("x:repo".to_owned())
+        );
+        assert!(config.name_for_path(&home.join("tree/../outside")) == None);
+        assert!(config.name_for_path(&home.join("tree/missing")) == None);
+    }
+
     #[test]
     fn tree_contains_repos_named() {
         let home = Home::init(testdir!());
@@ -423,4 +2357,97 @@ mod tests {
             ))) == [Ok(("BASElink".to_owned(), repo.join(".git")))]
         );
     }
+
+    #[test]
+    fn tree_name() {
+        let tree = TreeConfig::from(("/src", Some("host:")));
+        assert!(
+            tree.name(Path::new("/src/a/b")).as_deref() == Some("host:a/b")
+        );
+        assert!(tree.name(Path::new("/srcs/a")).is_none());
+
+        let tree = TreeConfig::from(Path::new("/src"));
+        assert!(tree.name(Path::new("/src/a")).as_deref() == Some("/src/a"));
+    }
+
+    #[test]
+    fn tree_name_rename() {
+        let mut tree = TreeConfig::from(("/srv/git", Some("")));
+        tree.rename = vec![
+            RenameRule::new(r"\.git$", "").unwrap(),
+            RenameRule::new(r"^team-(?<team>\w+)/", "${team}:").unwrap(),
+        ];
+        assert!(
+            tree.name(Path::new("/srv/git/team-x/foo.git")).as_deref()
+                == Some("x:foo")
+        );
+        assert!(
+            tree.name(Path::new("/srv/git/other/bar")).as_deref()
+                == Some("other/bar")
+        );
+
+        assert!(let Err(_) = Config::parse(
+            "[[repos]]\nroot = \"/srv/git\"\n\
+            rename = [{ pattern = '(', replace = '' }]\n",
+        ));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn tree_name_windows() {
+        let tree = TreeConfig::from((r"C:\src", Some("host:")));
+        assert!(
+            tree.name(Path::new(r"c:\SRC\a\b")).as_deref() == Some("host:a/b")
+        );
+        assert!(
+            tree.name(Path::new(r"\\?\C:\src\a")).as_deref() == Some("host:a")
+        );
+        assert!(tree.name(Path::new(r"D:\src\a")).is_none());
+
+        let tree = TreeConfig::from((r"\\Server\Share\src", Some("host:")));
+        assert!(
+            tree.name(Path::new(r"\\server\share\src\a")).as_deref()
+                == Some("host:a")
+        );
+
+        let tree = TreeConfig::from(Path::new(r"C:\src"));
+        assert!(
+            tree.name(Path::new(r"C:\src\a")).as_deref() == Some("C:/src/a")
+        );
+        let tree = TreeConfig::from(Path::new(r"\\server\share"));
+        assert!(
+            tree.name(Path::new(r"\\server\share\a")).as_deref()
+                == Some("//server/share/a")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn tree_contains_repos_windows() {
+        let home = Home::init(testdir!());
+        home.git_init(r"repos\one");
+
+        // Match the root even if it’s written with different case and
+        // separators.
+        let root = home
+            .path()
+            .to_string_lossy()
+            .to_uppercase()
+            .replace('\\', "/");
+        let names = |config: Config| -> Vec<_> {
+            config
+                .repo_iter()
+                .map(|result| result.map(|(name, _)| name).ok())
+                .collect()
+        };
+
+        assert!(
+            names(Config::with_tree((PathBuf::from(&root), Some("BASE"))))
+                == [Some("BASErepos/one".to_owned())]
+        );
+        assert!(
+            names(Config::with_tree(PathBuf::from(&root)))
+                == [Some(format!("{root}/repos/one"))]
+        );
+    }
 }
@@ -0,0 +1,148 @@
+//! Ranges of days.
+
+use chrono::{NaiveDate, NaiveTime};
+use schemars::JsonSchema;
+use schemars::r#gen::SchemaGenerator;
+use schemars::schema::Schema;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// A range of days, inclusive.
+///
+/// This deserializes from a string; see [`from_str()`](Self::from_str).
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub struct DateRange {
+    /// First day.
+    pub start: NaiveDate,
+
+    /// Last day.
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    /// Check if `date` is in the range.
+    #[must_use]
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+
+    /// Get the start of the first day in UTC (seconds since epoch).
+    #[must_use]
+    pub const fn start_timestamp(&self) -> i64 {
+        self.start.and_time(NaiveTime::MIN).and_utc().timestamp()
+    }
+
+    /// Get the end of the last day in UTC (seconds since epoch, exclusive).
+    #[must_use]
+    pub const fn end_timestamp(&self) -> i64 {
+        self.end
+            .and_time(NaiveTime::MIN)
+            .and_utc()
+            .timestamp()
+            .saturating_add(86_400)
+    }
+}
+
+// This deserializes from a string, so the derived schema would be wrong.
+impl JsonSchema for DateRange {
+    fn schema_name() -> String {
+        "DateRange".to_owned()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(generator).into_object();
+        schema.metadata().description = Some(
+            "A year (`2024`), a month (`2024-03`), or a day (`2024-03-05`)."
+                .to_owned(),
+        );
+        schema.into()
+    }
+}
+
+impl FromStr for DateRange {
+    type Err = ParseDateRangeError;
+
+    /// Parse a year (`2024`), a month (`2024-03`), or a day (`2024-03-05`).
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<u32> = input
+            .split('-')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| ParseDateRangeError)?;
+        let date = |year: u32, month, day| {
+            NaiveDate::from_ymd_opt(year.try_into().ok()?, month, day)
+        };
+        let range = match *parts.as_slice() {
+            [year] => date(year, 1, 1).zip(date(year, 12, 31)),
+            [year, month] => date(year, month, 1).and_then(|start| {
+                let end = start
+                    .checked_add_months(chrono::Months::new(1))?
+                    .pred_opt()?;
+                Some((start, end))
+            }),
+            [year, month, day] => date(year, month, day).map(|day| (day, day)),
+            _ => None,
+        };
+        range
+            .map(|(start, end)| Self { start, end })
+            .ok_or(ParseDateRangeError)
+    }
+}
+
+impl TryFrom<String> for DateRange {
+    type Error = ParseDateRangeError;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        input.parse()
+    }
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{} to {}", self.start, self.end)
+        }
+    }
+}
+
+/// Error returned when a [`DateRange`] can’t be parsed.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid period (expected YYYY, YYYY-MM, or YYYY-MM-DD)")]
+pub struct ParseDateRangeError;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    fn range(start: &str, end: &str) -> DateRange {
+        DateRange { start: start.parse().unwrap(), end: end.parse().unwrap() }
+    }
+
+    #[test]
+    fn parse_range() {
+        assert!(
+            "2024".parse::<DateRange>().unwrap()
+                == range("2024-01-01", "2024-12-31")
+        );
+        assert!(
+            "2024-02".parse::<DateRange>().unwrap()
+                == range("2024-02-01", "2024-02-29")
+        );
+        assert!(
+            "2024-12".parse::<DateRange>().unwrap()
+                == range("2024-12-01", "2024-12-31")
+        );
+        assert!(
+            "2024-03-05".parse::<DateRange>().unwrap()
+                == range("2024-03-05", "2024-03-05")
+        );
+        assert!(let Err(_) = "2024-13".parse::<DateRange>());
+        assert!(let Err(_) = "March".parse::<DateRange>());
+        assert!(let Err(_) = "2024-03-05-01".parse::<DateRange>());
+    }
+}
@@ -0,0 +1,230 @@
+//! Count lines inserted and deleted by commits.
+//!
+//! This is much slower than only reading commit times, since every commit has
+//! to be diffed against its parent. It’s only done when
+//! [`Config::diff_stats`](super::Config::diff_stats) is set.
+
+use super::PathFilter;
+use git2::{Patch, Repository};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Configuration for diff stats.
+///
+/// ```toml
+/// [diff_stats]
+/// exclude = ["package-lock.json", "vendor/"]
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq)]
+pub struct DiffStatsConfig {
+    /// Paths to leave out, as git pathspecs.
+    ///
+    /// Paths marked `linguist-vendored` or `linguist-generated` in
+    /// `.gitattributes` are always left out. See [`PathFilter`].
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl DiffStatsConfig {
+    /// Get the filter for paths that don’t count.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern in [`exclude`](Self::exclude) is invalid.
+    pub fn path_filter(&self) -> Result<PathFilter, git2::Error> {
+        PathFilter::new(&self.exclude)
+    }
+}
+
+/// Lines inserted and deleted by a commit.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    JsonSchema,
+    PartialEq,
+    Serialize,
+)]
+pub struct DiffStats {
+    /// Number of lines inserted.
+    pub insertions: usize,
+
+    /// Number of lines deleted.
+    pub deletions: usize,
+}
+
+/// Lines changed in each type of file, keyed by [`file_type()`].
+pub type FileTypeStats = BTreeMap<String, DiffStats>;
+
+impl DiffStats {
+    /// Count lines changed by `commit` compared to its first parent.
+    ///
+    /// Merge commits count as no changes, since their changes were already
+    /// counted in the commits being merged. Binary files and paths excluded by
+    /// `filter` are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn for_commit(
+        repo: &Repository,
+        commit: &git2::Commit<'_>,
+        filter: &PathFilter,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::total(
+            Self::by_file_type(repo, commit, filter)?.values(),
+        ))
+    }
+
+    /// Count lines changed by `commit` in each type of file.
+    ///
+    /// This skips the same changes as [`for_commit()`](Self::for_commit).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn by_file_type(
+        repo: &Repository,
+        commit: &git2::Commit<'_>,
+        filter: &PathFilter,
+    ) -> anyhow::Result<FileTypeStats> {
+        let mut file_types = FileTypeStats::new();
+        if commit.parent_count() > 1 {
+            return Ok(file_types);
+        }
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let diff = repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            None,
+        )?;
+
+        for (index, delta) in diff.deltas().enumerate() {
+            let path =
+                delta.new_file().path().or_else(|| delta.old_file().path());
+            if let Some(path) = path
+                && filter.excludes(repo, path)?
+            {
+                continue;
+            }
+            if let Some(patch) = Patch::from_diff(&diff, index)? {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                file_types
+                    .entry(path.map_or_else(String::new, file_type))
+                    .or_default()
+                    .add(Self { insertions, deletions });
+            }
+        }
+        Ok(file_types)
+    }
+
+    /// Add up stats.
+    pub fn total<'a, I: IntoIterator<Item = &'a Self>>(stats: I) -> Self {
+        let mut total = Self::default();
+        for stats in stats {
+            total.add(*stats);
+        }
+        total
+    }
+
+    /// Add `other` to these stats.
+    pub const fn add(&mut self, other: Self) {
+        self.insertions = self.insertions.saturating_add(other.insertions);
+        self.deletions = self.deletions.saturating_add(other.deletions);
+    }
+}
+
+/// Get the type of file at `path` for breaking down diff stats.
+///
+/// This is the lowercase extension, e.g. `rs` for `src/main.rs`, or the file
+/// name if there isn’t an extension, e.g. `Makefile`.
+#[must_use]
+pub fn file_type(path: &Path) -> String {
+    match path.extension() {
+        Some(extension) => extension.to_string_lossy().to_lowercase(),
+        None => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+/// A commit found while scanning, with its diff stats.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct CommitStats {
+    /// Commit ID as a hex string.
+    pub oid: String,
+
+    /// Commit date as seconds since 1970. See [`ScanOptions::date`].
+    ///
+    /// [`ScanOptions::date`]: super::ScanOptions::date
+    pub time: i64,
+
+    /// Lines changed.
+    #[serde(flatten)]
+    pub stats: DiffStats,
+
+    /// Lines changed in each type of file. See [`file_type()`].
+    pub file_types: FileTypeStats,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use testdir::testdir;
+
+    #[test]
+    fn count_lines() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.write(".gitattributes", "*.lock linguist-generated\n");
+        repo.write("a", "1\n2\n3\n");
+        repo.write("Cargo.lock", "x\n");
+        repo.write("skip.json", "x\n");
+        repo.git(["add", "."]);
+        repo.git(["commit", "-m", "first"]);
+        repo.write("a", "1\nchanged\n3\n4\n");
+        repo.write("Cargo.lock", "y\n");
+        repo.git(["commit", "-am", "second"]);
+
+        let repository = Repository::open(repo.path()).unwrap();
+        let filter = PathFilter::new(["skip.json"]).unwrap();
+        let stats = |rev: &str| {
+            let commit = repository
+                .revparse_single(rev)
+                .unwrap()
+                .peel_to_commit()
+                .unwrap();
+            DiffStats::for_commit(&repository, &commit, &filter).unwrap()
+        };
+        assert!(stats("HEAD~") == DiffStats { insertions: 4, deletions: 0 });
+        assert!(stats("HEAD") == DiffStats { insertions: 2, deletions: 1 });
+
+        let commit = repository.head().unwrap().peel_to_commit().unwrap();
+        assert!(
+            DiffStats::by_file_type(&repository, &commit, &filter).unwrap()
+                == FileTypeStats::from([(
+                    "a".to_owned(),
+                    DiffStats { insertions: 2, deletions: 1 }
+                )])
+        );
+    }
+
+    #[test]
+    fn file_types() {
+        assert!(file_type(Path::new("src/main.RS")) == "rs");
+        assert!(file_type(Path::new("Cargo.toml")) == "toml");
+        assert!(file_type(Path::new("dir/Makefile")) == "Makefile");
+        assert!(file_type(Path::new(".gitignore")) == ".gitignore");
+    }
+}
@@ -0,0 +1,126 @@
+//! Compare author dates with commit dates.
+//!
+//! Scans count commits by their author dates, which rebasing and cherry-picking
+//! leave alone. This finds how often the commit dates tell a different story.
+
+use super::ScanOptions;
+use git2::Repository;
+use std::time::Duration;
+
+/// Commits whose author and commit dates are far apart.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DateDivergence {
+    /// Number of commits checked.
+    pub commits: usize,
+
+    /// Commits with dates further apart than the threshold, newest first.
+    pub divergent: Vec<DivergentCommit>,
+}
+
+/// A commit whose author and commit dates are far apart.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DivergentCommit {
+    /// Commit ID as a hex string.
+    pub oid: String,
+
+    /// Author date (seconds since 1970).
+    pub author_time: i64,
+
+    /// Commit date (seconds since 1970).
+    pub commit_time: i64,
+}
+
+impl DivergentCommit {
+    /// How far apart the dates are.
+    #[must_use]
+    pub const fn gap(&self) -> Duration {
+        Duration::from_secs(self.commit_time.abs_diff(self.author_time))
+    }
+}
+
+impl DateDivergence {
+    /// The largest gap between author and commit dates, if any commits
+    /// diverge.
+    #[must_use]
+    pub fn max_gap(&self) -> Option<Duration> {
+        self.divergent.iter().map(DivergentCommit::gap).max()
+    }
+}
+
+/// Find commits counted by `options` whose author and commit dates differ by
+/// more than `threshold`.
+///
+/// This checks the same commits as [`scan_repo_commits()`]: those on the
+/// default branch that haven’t been pushed to [skipped remotes].
+/// [`ScanOptions::limit`] doesn’t apply here.
+///
+/// [`scan_repo_commits()`]: super::scan_repo_commits
+/// [skipped remotes]: ScanOptions::skip_remotes
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn scan_date_divergence(
+    repo: &Repository,
+    options: &ScanOptions,
+    threshold: Duration,
+) -> anyhow::Result<DateDivergence> {
+    let revwalk = options.default_branch_walk(repo)?;
+    let mut result = DateDivergence::default();
+    let identity = options.identity.for_repo(repo)?;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if options.stops_at(&commit) {
+            break;
+        }
+        if options.skips(&commit) || !identity.matches_commit(&commit) {
+            continue;
+        }
+        result.commits = result.commits.saturating_add(1);
+        let divergent = DivergentCommit {
+            oid: commit.id().to_string(),
+            author_time: commit.author().when().seconds(),
+            commit_time: commit.committer().when().seconds(),
+        };
+        if divergent.gap() > threshold {
+            result.divergent.push(divergent);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use testdir::testdir;
+
+    #[test]
+    fn rebased_commits() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.git([
+            "commit",
+            "--allow-empty",
+            "-m",
+            "rebased",
+            "--date=2000-01-01T00:00:00Z",
+        ]);
+        let repository = Repository::open(repo.path()).unwrap();
+
+        let result = scan_date_divergence(
+            &repository,
+            &ScanOptions::default(),
+            Duration::from_secs(86400),
+        )
+        .unwrap();
+        assert!(result.commits == 2);
+        let [divergent] = result.divergent.as_slice() else {
+            panic!("expected one divergent commit: {result:?}");
+        };
+        assert!(divergent.author_time == 946_684_800);
+        assert!(result.max_gap() == Some(divergent.gap()));
+    }
+}
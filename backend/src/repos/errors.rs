@@ -1,6 +1,7 @@
 //! Errors encountered by repo scanner.
 
-use std::result;
+use std::path::PathBuf;
+use std::{io, result};
 
 /// `Result` type for `Error`.
 pub type Result<T, E = Error> = result::Result<T, E>;
@@ -11,4 +12,30 @@ pub enum Error {
     /// An error encountered parsing the TOML configuration.
     #[error(transparent)]
     Toml(#[from] toml::de::Error),
+
+    /// An error encountered parsing the JSON configuration.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// An error encountered reading the configuration file.
+    #[error("Could not read {path:?}: {source}")]
+    Read {
+        /// The configuration file.
+        path: PathBuf,
+
+        /// What went wrong.
+        source: io::Error,
+    },
+
+    /// An error encountered parsing the YAML configuration.
+    #[error(transparent)]
+    Yaml(#[from] serde_norway::Error),
+
+    /// An error encountered parsing the TOML configuration for editing.
+    #[error(transparent)]
+    TomlEdit(#[from] toml_edit::TomlError),
+
+    /// A key in the configuration has the wrong type.
+    #[error("`{0}` in configuration must be a table")]
+    NotTable(&'static str),
 }
@@ -0,0 +1,86 @@
+//! Simple glob patterns for repository names and remote URLs.
+
+/// Check if `name` matches a simple glob `pattern`.
+///
+/// `*` matches any number of characters (including `/`) and `?` matches
+/// exactly one. Everything else matches itself.
+#[must_use]
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    glob_match_slice(&pattern, &name, '*', '?')
+}
+
+/// Check if `name`, which might not be UTF-8, matches a simple glob
+/// `pattern`.
+///
+/// This is like [`glob_match()`], except that `?` matches exactly one byte.
+#[must_use]
+pub fn glob_match_bytes(pattern: &str, name: &[u8]) -> bool {
+    glob_match_slice(pattern.as_bytes(), name, b'*', b'?')
+}
+
+/// Check if `name` matches `pattern`, where `star` matches any number of
+/// items and `any` matches exactly one.
+fn glob_match_slice<T: Copy + PartialEq>(
+    pattern: &[T],
+    name: &[T],
+    star: T,
+    any: T,
+) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` in the pattern and where it started matching.
+    let mut last_star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(&c) if c == star => {
+                last_star = Some((p, n));
+                p = p.saturating_add(1);
+            }
+            Some(&c) if c == any || Some(&c) == name.get(n) => {
+                p = p.saturating_add(1);
+                n = n.saturating_add(1);
+            }
+            _ => {
+                // Backtrack: let the last `*` match one more character.
+                let Some((star_p, star_n)) = last_star else {
+                    return false;
+                };
+                let star_n = star_n.saturating_add(1);
+                last_star = Some((star_p, star_n));
+                p = star_p.saturating_add(1);
+                n = star_n;
+            }
+        }
+    }
+    pattern
+        .get(p..)
+        .is_some_and(|rest| rest.iter().all(|&c| c == star))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("repo", "repo"));
+        assert!(!glob_match("repo", "repo2"));
+        assert!(glob_match("host:*", "host:src/repo"));
+        assert!(glob_match("*/repo", "host:src/repo"));
+        assert!(glob_match("*r*o*", "host:src/repo"));
+        assert!(glob_match("rep?", "repo"));
+        assert!(!glob_match("rep?", "rep"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a*b", "aab/c"));
+    }
+
+    #[test]
+    fn glob_bytes() {
+        assert!(glob_match_bytes("host/*", b"host/\xff/repo"));
+        assert!(glob_match_bytes("host/?/repo", b"host/\xff/repo"));
+        assert!(!glob_match_bytes("host/?/repo", "host/é/repo".as_bytes()));
+        assert!(!glob_match_bytes("other/*", b"host/\xff"));
+    }
+}
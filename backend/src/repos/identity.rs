@@ -0,0 +1,607 @@
+//! Decide which commits are “mine”.
+
+use super::{Error, Result, get_default_branch};
+use git2::{ConfigLevel, ErrorCode, Mailmap, Repository, Signature};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Deref;
+
+/// The emails and names that identify one person.
+///
+/// Commits are counted only if their author matches one of the emails or
+/// names. If both lists are empty, every commit is counted.
+///
+/// # Example
+///
+/// ```toml
+/// [identity]
+/// emails = ["daniel@example.com", "daniel@old-job.example"]
+/// names = ["Daniel Parks", "Daneil Parks"]
+/// github = "danielparks"
+/// repo_config = true
+/// co_authors = true
+/// mailmap = true
+/// ```
+///
+/// If no identity is configured, [`Identity::detect()`] reads `user.email` and
+/// `user.name` from the global git configuration.
+#[derive(Debug, Clone, Default, Deserialize, Eq, JsonSchema, PartialEq)]
+pub struct Identity {
+    /// Email addresses. These are compared case-insensitively.
+    #[serde(default)]
+    pub emails: Vec<String>,
+
+    /// Names. These must match exactly.
+    #[serde(default)]
+    pub names: Vec<String>,
+
+    /// GitHub login, if known.
+    #[serde(default)]
+    pub github: Option<String>,
+
+    /// Also count commits by `user.email` and `user.name` from each
+    /// repository’s own git configuration.
+    #[serde(default)]
+    pub repo_config: bool,
+
+    /// Also count commits that credit this person in a `Co-authored-by:`
+    /// trailer, like GitHub does.
+    #[serde(default)]
+    pub co_authors: bool,
+
+    /// Map authors through each repository’s `.mailmap` before matching them,
+    /// like `git log` does.
+    ///
+    /// This lets a mailmap entry like `Daniel Parks <daniel@example.com>
+    /// <old@example.com>` claim commits by `old@example.com` without listing
+    /// it in [`emails`](Self::emails).
+    #[serde(default)]
+    pub mailmap: bool,
+}
+
+impl Identity {
+    /// Whether or not any emails or names are configured.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.emails.is_empty() && self.names.is_empty()
+    }
+
+    /// Read `user.email` and `user.name` from the global git configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the git configuration could not be read.
+    pub fn detect() -> Result<Self, git2::Error> {
+        Self::from_git_config(&git2::Config::open_default()?)
+    }
+
+    /// Read `user.email` and `user.name` from a git configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the git configuration could not be read.
+    pub fn from_git_config(config: &git2::Config) -> Result<Self, git2::Error> {
+        let get = |name| match config.get_string(name) {
+            Ok(value) if !value.trim().is_empty() => {
+                Ok(Some(value.trim().to_owned()))
+            }
+            Ok(_) => Ok(None),
+            Err(error) if error.code() == ErrorCode::NotFound => Ok(None),
+            Err(error) => Err(error),
+        };
+        Ok(Self {
+            emails: get("user.email")?.into_iter().collect(),
+            names: get("user.name")?.into_iter().collect(),
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        })
+    }
+
+    /// Fill in an empty identity from the global git configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the git configuration could not be read.
+    pub fn or_detect(self) -> Result<Self, git2::Error> {
+        if self.is_empty() {
+            Ok(Self {
+                repo_config: self.repo_config,
+                github: self.github,
+                co_authors: self.co_authors,
+                mailmap: self.mailmap,
+                ..Self::detect()?
+            })
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Get the identity to use for a repository.
+    ///
+    /// If [`repo_config`](Self::repo_config) is set, this adds the identity
+    /// from the repository’s own git configuration, if it has one. If
+    /// [`mailmap`](Self::mailmap) is set, this loads the repository’s mailmap.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the repository’s configuration or mailmap could not
+    /// be read.
+    pub fn for_repo(
+        &self,
+        repo: &Repository,
+    ) -> Result<RepoIdentity<'_>, git2::Error> {
+        Ok(RepoIdentity {
+            identity: self.with_repo_config(repo)?,
+            mailmap: if self.mailmap {
+                Some(repo.mailmap()?)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Add the identity from a repository’s git configuration if
+    /// [`repo_config`](Self::repo_config) is set.
+    fn with_repo_config(
+        &self,
+        repo: &Repository,
+    ) -> Result<Cow<'_, Self>, git2::Error> {
+        if !self.repo_config || self.is_empty() {
+            return Ok(Cow::Borrowed(self));
+        }
+
+        let local = match repo.config()?.open_level(ConfigLevel::Local) {
+            Ok(config) => Self::from_git_config(&config)?,
+            Err(error) if error.code() == ErrorCode::NotFound => {
+                return Ok(Cow::Borrowed(self));
+            }
+            Err(error) => return Err(error),
+        };
+        let mut identity = self.clone();
+        identity.emails.extend(local.emails);
+        identity.names.extend(local.names);
+        Ok(Cow::Owned(identity))
+    }
+
+    /// Whether or not an author with `name` and `email` is this person.
+    ///
+    /// Always true if the identity [is empty](Self::is_empty).
+    #[must_use]
+    pub fn matches(&self, name: Option<&str>, email: Option<&str>) -> bool {
+        self.is_empty()
+            || email.is_some_and(|email| {
+                self.emails
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(email.trim()))
+            })
+            || name.is_some_and(|name| {
+                self.names.iter().any(|known| known == name.trim())
+            })
+    }
+
+    /// Whether or not the author of `commit` is this person.
+    ///
+    /// This ignores [`mailmap`](Self::mailmap); use
+    /// [`for_repo()`](Self::for_repo) to get an identity that uses it.
+    #[must_use]
+    pub fn matches_author(&self, commit: &git2::Commit<'_>) -> bool {
+        self.matches_author_with(commit, None)
+    }
+
+    /// Whether or not `commit` counts for this person.
+    ///
+    /// It counts if they wrote it, or if [`co_authors`](Self::co_authors) is
+    /// set and they’re credited as a co-author.
+    ///
+    /// This ignores [`mailmap`](Self::mailmap); use
+    /// [`for_repo()`](Self::for_repo) to get an identity that uses it.
+    #[must_use]
+    pub fn matches_commit(&self, commit: &git2::Commit<'_>) -> bool {
+        self.matches_commit_with(commit, None)
+    }
+
+    /// Whether or not the author of `commit`, after mapping it through
+    /// `mailmap`, is this person.
+    fn matches_author_with(
+        &self,
+        commit: &git2::Commit<'_>,
+        mailmap: Option<&Mailmap>,
+    ) -> bool {
+        let author = author(commit, mailmap);
+        self.matches(author.name(), author.email())
+    }
+
+    /// Whether or not `commit` counts for this person after mapping authors
+    /// through `mailmap`.
+    fn matches_commit_with(
+        &self,
+        commit: &git2::Commit<'_>,
+        mailmap: Option<&Mailmap>,
+    ) -> bool {
+        self.matches_author_with(commit, mailmap)
+            || (self.co_authors
+                && co_authors(commit).iter().any(|(name, email)| match mailmap
+                    .and_then(|mailmap| resolve(mailmap, name, email))
+                {
+                    Some(signature) => {
+                        self.matches(signature.name(), signature.email())
+                    }
+                    None => self.matches(Some(name), Some(email)),
+                }))
+    }
+
+    /// Write [`emails`](Self::emails) and [`names`](Self::names) into the
+    /// `[identity]` section of a TOML configuration.
+    ///
+    /// Everything else in the configuration, including comments and
+    /// formatting, is left alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration can’t be parsed, or if it has an
+    /// `identity` key that isn’t a table.
+    pub fn update_config(&self, input: &str) -> Result<String> {
+        let mut document: toml_edit::DocumentMut = input.parse()?;
+        let table = document
+            .entry("identity")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or(Error::NotTable("identity"))?;
+
+        for (key, list) in [("emails", &self.emails), ("names", &self.names)] {
+            if list.is_empty() {
+                table.remove(key);
+            } else {
+                let array: toml_edit::Array = list.iter().collect();
+                table.insert(key, toml_edit::value(array));
+            }
+        }
+
+        Ok(document.to_string())
+    }
+
+    /// Count commits on the default branch of `repo` by authors who aren’t
+    /// this person.
+    ///
+    /// Counts are added to `counts`, which is keyed by lowercase email. If
+    /// the identity [is empty](Self::is_empty), every author is counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn count_unclaimed(
+        &self,
+        repo: &Repository,
+        counts: &mut BTreeMap<String, AuthorCount>,
+    ) -> anyhow::Result<()> {
+        let identity = self.for_repo(repo)?;
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(get_default_branch(repo, None)?)?;
+
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            let author = identity.author(&commit);
+            let Some(email) = author.email() else {
+                continue;
+            };
+            if !identity.is_empty()
+                && identity.matches(author.name(), Some(email))
+            {
+                continue;
+            }
+
+            let count = counts
+                .entry(email.trim().to_lowercase())
+                .or_insert_with(|| AuthorCount {
+                    email: email.trim().to_owned(),
+                    name: author.name().unwrap_or_default().trim().to_owned(),
+                    commits: 0,
+                });
+            count.commits = count.commits.saturating_add(1);
+        }
+        Ok(())
+    }
+}
+
+/// An [`Identity`] prepared for one repository.
+///
+/// See [`Identity::for_repo()`].
+pub struct RepoIdentity<'a> {
+    /// The identity, including any from the repository’s git configuration.
+    identity: Cow<'a, Identity>,
+
+    /// The repository’s mailmap, if [`Identity::mailmap`] is set.
+    mailmap: Option<Mailmap>,
+}
+
+impl RepoIdentity<'_> {
+    /// Get the author of `commit`, mapped through the mailmap if there is one.
+    #[must_use]
+    pub fn author(&self, commit: &git2::Commit<'_>) -> Signature<'static> {
+        author(commit, self.mailmap.as_ref())
+    }
+
+    /// Whether or not the author of `commit` is this person.
+    #[must_use]
+    pub fn matches_author(&self, commit: &git2::Commit<'_>) -> bool {
+        self.identity
+            .matches_author_with(commit, self.mailmap.as_ref())
+    }
+
+    /// Whether or not `commit` counts for this person.
+    ///
+    /// See [`Identity::matches_commit()`].
+    #[must_use]
+    pub fn matches_commit(&self, commit: &git2::Commit<'_>) -> bool {
+        self.identity
+            .matches_commit_with(commit, self.mailmap.as_ref())
+    }
+}
+
+impl Deref for RepoIdentity<'_> {
+    type Target = Identity;
+
+    fn deref(&self) -> &Identity {
+        &self.identity
+    }
+}
+
+/// Get the author of `commit`, mapped through `mailmap` if there is one.
+fn author(
+    commit: &git2::Commit<'_>,
+    mailmap: Option<&Mailmap>,
+) -> Signature<'static> {
+    mailmap
+        .and_then(|mailmap| commit.author_with_mailmap(mailmap).ok())
+        .unwrap_or_else(|| commit.author().to_owned())
+}
+
+/// Map `name` and `email` through `mailmap`.
+///
+/// Returns `None` if they can’t be made into a signature, e.g. because one is
+/// empty.
+fn resolve(
+    mailmap: &Mailmap,
+    name: &str,
+    email: &str,
+) -> Option<Signature<'static>> {
+    let signature = Signature::new(name, email, &git2::Time::new(0, 0)).ok()?;
+    mailmap.resolve_signature(&signature).ok()
+}
+
+/// Get the names and emails in `Co-authored-by:` trailers of `commit`.
+///
+/// Trailers that aren’t in the form `Name <email>` are ignored.
+fn co_authors(commit: &git2::Commit<'_>) -> Vec<(String, String)> {
+    let Some(trailers) = commit
+        .message()
+        .and_then(|message| git2::message_trailers_strs(message).ok())
+    else {
+        return Vec::new();
+    };
+    trailers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("co-authored-by"))
+        .filter_map(|(_, value)| {
+            let (name, email) = value.split_once('<')?;
+            let email = email.trim().strip_suffix('>')?;
+            Some((name.trim().to_owned(), email.trim().to_owned()))
+        })
+        .collect()
+}
+
+/// Number of commits by an author who isn’t claimed by an [`Identity`].
+///
+/// See [`Identity::count_unclaimed()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuthorCount {
+    /// Email as it first appeared.
+    pub email: String,
+
+    /// Name from the first commit found with this email.
+    pub name: String,
+
+    /// Number of commits.
+    pub commits: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use testdir::testdir;
+
+    #[test]
+    fn empty_matches_everything() {
+        let identity = Identity::default();
+        assert!(identity.matches(Some("Anyone"), Some("any@example.com")));
+        assert!(identity.matches(None, None));
+    }
+
+    #[test]
+    fn match_email_or_name() {
+        let identity = Identity {
+            emails: vec!["me@example.com".to_owned()],
+            names: vec!["Me Myself".to_owned()],
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        };
+        assert!(identity.matches(Some("Other"), Some("ME@example.com")));
+        assert!(identity.matches(Some("Me Myself"), Some("x@example.com")));
+        assert!(!identity.matches(Some("me myself"), Some("x@example.com")));
+        assert!(!identity.matches(None, None));
+    }
+
+    #[test]
+    fn match_co_author() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.write("a", "paired");
+        repo.git(["add", "a"]);
+        repo.git([
+            "commit",
+            "-m",
+            "paired\n\nCo-authored-by: Me <ME@example.com>\n\
+                co-authored-by: Not An Email",
+            "--author",
+            "O <o@example.com>",
+        ]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let commit = repository.head().unwrap().peel_to_commit().unwrap();
+
+        let mut identity = Identity {
+            emails: vec!["me@example.com".to_owned()],
+            ..Identity::default()
+        };
+        assert!(!identity.matches_commit(&commit));
+        identity.co_authors = true;
+        assert!(identity.matches_commit(&commit));
+
+        identity.emails = vec!["you@example.com".to_owned()];
+        assert!(!identity.matches_commit(&commit));
+    }
+
+    #[test]
+    fn match_mailmap() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.write(".mailmap", "Me <me@example.com> <old@example.com>\n");
+        repo.git(["add", ".mailmap"]);
+        repo.git([
+            "commit",
+            "-m",
+            "old\n\nCo-authored-by: Old <old@example.com>",
+            "--author",
+            "Old <old@example.com>",
+        ]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let commit = repository.head().unwrap().peel_to_commit().unwrap();
+
+        let mut identity = Identity {
+            emails: vec!["me@example.com".to_owned()],
+            ..Identity::default()
+        };
+        assert!(
+            !identity
+                .for_repo(&repository)
+                .unwrap()
+                .matches_author(&commit)
+        );
+
+        identity.mailmap = true;
+        let mapped = identity.for_repo(&repository).unwrap();
+        assert!(mapped.matches_author(&commit));
+        assert!(mapped.author(&commit).name() == Some("Me"));
+        assert!(!identity.matches_author(&commit));
+
+        identity.emails = vec!["other@example.com".to_owned()];
+        identity.names = vec!["Me".to_owned()];
+        identity.co_authors = true;
+        assert!(
+            identity
+                .for_repo(&repository)
+                .unwrap()
+                .matches_commit(&commit)
+        );
+    }
+
+    #[test]
+    fn read_git_config() {
+        let home = Home::init(testdir!());
+        let config = git2::Config::open(&home.join(".gitconfig")).unwrap();
+        assert!(
+            Identity::from_git_config(&config).unwrap()
+                == Identity {
+                    emails: vec!["name@example.com".to_owned()],
+                    names: vec!["Name".to_owned()],
+                    repo_config: false,
+                    github: None,
+                    co_authors: false,
+                    mailmap: false,
+                }
+        );
+    }
+
+    #[test]
+    fn update_config_keeps_formatting() {
+        let input = "# Scan everything.\n[[repos]]\nroot = \"/src\" # mine\n\n\
+            [identity]\nnames = [\"Old\"]\nrepo_config = true\n";
+        let identity = Identity {
+            emails: vec!["me@example.com".to_owned()],
+            names: vec![],
+            repo_config: true,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        };
+        assert!(
+            identity.update_config(input).unwrap()
+                == "# Scan everything.\n[[repos]]\nroot = \"/src\" # mine\n\n\
+                    [identity]\nrepo_config = true\n\
+                    emails = [\"me@example.com\"]\n"
+        );
+    }
+
+    #[test]
+    fn update_config_adds_section() {
+        let identity = Identity {
+            emails: vec![],
+            names: vec!["Me".to_owned()],
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        };
+        let output = identity.update_config("repos = []\n").unwrap();
+        assert!(output == "repos = []\n\n[identity]\nnames = [\"Me\"]\n");
+        assert!(let Err(Error::NotTable(_)) = identity.update_config("identity = 1"));
+    }
+
+    #[test]
+    fn count_unclaimed_authors() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        for n in 0..2 {
+            repo.write("a", &format!("other {n}"));
+            repo.git([
+                "commit",
+                "-am",
+                "other",
+                "--author",
+                "O <O@example.com>",
+            ]);
+        }
+
+        let identity = Identity {
+            emails: vec!["name@example.com".to_owned()],
+            names: vec![],
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        };
+        let mut counts = BTreeMap::new();
+        identity
+            .count_unclaimed(
+                &Repository::open(repo.path()).unwrap(),
+                &mut counts,
+            )
+            .unwrap();
+        assert!(
+            counts.into_values().collect::<Vec<_>>()
+                == [AuthorCount {
+                    email: "O@example.com".to_owned(),
+                    name: "O".to_owned(),
+                    commits: 2,
+                }]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Decide which paths don’t count towards diff stats.
+//!
+//! A committed `package-lock.json` or vendored library can add tens of
+//! thousands of lines in one commit. Like GitHub’s linguist, this skips paths
+//! marked `linguist-vendored` or `linguist-generated` in `.gitattributes`, as
+//! well as paths matching configured patterns.
+
+use git2::{AttrCheckFlags, AttrValue, Pathspec, PathspecFlags, Repository};
+use std::path::Path;
+
+/// Attributes that mark a path as not written by hand.
+const ATTRIBUTES: &[&str] = &["linguist-vendored", "linguist-generated"];
+
+/// Paths to leave out of diff stats.
+pub struct PathFilter {
+    /// Configured patterns, if any.
+    patterns: Option<Pathspec>,
+}
+
+impl PathFilter {
+    /// Create a filter that excludes paths matching `patterns` in addition to
+    /// paths with linguist attributes.
+    ///
+    /// Patterns are git pathspecs, e.g. `vendor/` or `*.min.js`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern is invalid.
+    pub fn new<I, S>(patterns: I) -> Result<Self, git2::Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns: Vec<String> = patterns
+            .into_iter()
+            .map(|pattern| pattern.as_ref().to_owned())
+            .collect();
+        Ok(Self {
+            patterns: if patterns.is_empty() {
+                None
+            } else {
+                Some(Pathspec::new(patterns)?)
+            },
+        })
+    }
+
+    /// Check if `path` (relative to the root of `repo`) should be left out.
+    ///
+    /// Attributes are read from `.gitattributes` in the working directory,
+    /// falling back to the index, so bare repositories only use
+    /// `info/attributes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attributes couldn’t be read.
+    pub fn excludes(
+        &self,
+        repo: &Repository,
+        path: &Path,
+    ) -> Result<bool, git2::Error> {
+        if self.patterns.as_ref().is_some_and(|patterns| {
+            patterns.matches_path(path, PathspecFlags::DEFAULT)
+        }) {
+            return Ok(true);
+        }
+        for attribute in ATTRIBUTES {
+            let value = repo.get_attr(
+                path,
+                attribute,
+                AttrCheckFlags::FILE_THEN_INDEX,
+            )?;
+            match AttrValue::from_string(value) {
+                AttrValue::True => return Ok(true),
+                AttrValue::String(value) if value != "false" => {
+                    return Ok(true);
+                }
+                _ => {}
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use testdir::testdir;
+
+    #[test]
+    fn attributes_and_patterns() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.write(
+            ".gitattributes",
+            "vendor/** linguist-vendored\n\
+            *.lock linguist-generated=true\n\
+            vendor/mine.c -linguist-vendored\n",
+        );
+        let repository = Repository::open(repo.path()).unwrap();
+        let filter = PathFilter::new(["package-lock.json"]).unwrap();
+        let excludes =
+            |path: &str| filter.excludes(&repository, Path::new(path)).unwrap();
+
+        assert!(excludes("vendor/lib/a.c"));
+        assert!(!excludes("vendor/mine.c"));
+        assert!(excludes("Cargo.lock"));
+        assert!(excludes("package-lock.json"));
+        assert!(!excludes("src/main.rs"));
+
+        let filter = PathFilter::new([""; 0]).unwrap();
+        assert!(!filter.excludes(&repository, Path::new("a.json")).unwrap());
+    }
+}
@@ -0,0 +1,414 @@
+//! Measure scans.
+
+use super::RemotePatterns;
+use git2::Repository;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Counts and timing from one scan.
+#[derive(Clone, Debug, Default, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct ScanMetrics {
+    /// When the scan started (seconds since epoch).
+    pub started_at: i64,
+
+    /// How long the scan took in milliseconds.
+    pub duration_ms: u64,
+
+    /// Number of repositories found.
+    pub repos_discovered: usize,
+
+    /// Number of repositories scanned successfully.
+    pub repos_scanned: usize,
+
+    /// Number of repositories scanned with skipped remotes, so that only
+    /// commits that haven’t been pushed to them were counted. See
+    /// [`RemotePatterns`].
+    pub repos_unpushed_only: usize,
+
+    /// Number of repositories that could not be found or scanned.
+    pub repos_errored: usize,
+
+    /// Number of commits counted.
+    pub commits: usize,
+
+    /// Names of repositories that are shallow clones, sorted. Their older
+    /// history is missing, so their commits are undercounted.
+    pub shallow_repos: Vec<String>,
+
+    /// What happened to each repository found, by name.
+    pub outcomes: BTreeMap<String, RepoOutcome>,
+
+    /// Groups of repositories that are in a group, by name. See
+    /// [`TreeConfig::group`](super::TreeConfig::group).
+    pub groups: BTreeMap<String, String>,
+
+    /// Errors finding repositories. These are counted in
+    /// [`repos_errored`](Self::repos_errored), but since the repositories
+    /// weren’t found they don’t have names or [`outcomes`](Self::outcomes).
+    pub errors: Vec<String>,
+}
+
+/// What happened to one repository in a scan.
+#[derive(Clone, Debug, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum RepoOutcome {
+    /// The repository was scanned.
+    Scanned,
+
+    /// The repository was scanned, but it has a remote matching
+    /// [`RemotePatterns`], so only commits that haven’t been pushed to it were
+    /// counted.
+    UnpushedOnly,
+
+    /// The repository could not be scanned, so its commits are missing.
+    Error {
+        /// What went wrong.
+        message: String,
+    },
+}
+
+impl RepoOutcome {
+    /// Get the outcome of scanning `repo`, where only unpushed commits are
+    /// counted if it has a remote matching `skip`.
+    #[must_use]
+    pub fn of<T>(
+        repo: &Repository,
+        skip: &RemotePatterns,
+        result: &anyhow::Result<T>,
+    ) -> Self {
+        match result {
+            Ok(_) if skip.matches_repo(repo).unwrap_or(false) => {
+                Self::UnpushedOnly
+            }
+            Ok(_) => Self::Scanned,
+            Err(error) => Self::Error { message: error.to_string() },
+        }
+    }
+}
+
+impl fmt::Display for RepoOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Scanned => f.write_str("scanned"),
+            Self::UnpushedOnly => f.write_str("unpushed only"),
+            Self::Error { message } => write!(f, "error: {message}"),
+        }
+    }
+}
+
+impl ScanMetrics {
+    /// Start measuring a scan.
+    ///
+    /// Pass the returned [`Instant`] to [`finish()`](Self::finish).
+    #[must_use]
+    pub fn start() -> (Self, Instant) {
+        let metrics = Self {
+            started_at: chrono::Utc::now().timestamp(),
+            ..Self::default()
+        };
+        (metrics, Instant::now())
+    }
+
+    /// Record the result of scanning `repo`, where only unpushed commits are
+    /// counted if it has a remote matching `skip`.
+    pub fn record(
+        &mut self,
+        name: &str,
+        repo: &Repository,
+        skip: &RemotePatterns,
+        result: &anyhow::Result<Vec<i64>>,
+    ) {
+        let commits = result.as_ref().map_or(0, Vec::len);
+        self.record_outcome(name, RepoOutcome::of(repo, skip, result), commits);
+    }
+
+    /// Record what happened to the repository `name`, and how many commits
+    /// were found in it.
+    pub fn record_outcome(
+        &mut self,
+        name: &str,
+        outcome: RepoOutcome,
+        commits: usize,
+    ) {
+        self.repos_discovered = self.repos_discovered.saturating_add(1);
+        let count = match outcome {
+            RepoOutcome::Scanned => &mut self.repos_scanned,
+            RepoOutcome::UnpushedOnly => &mut self.repos_unpushed_only,
+            RepoOutcome::Error { .. } => &mut self.repos_errored,
+        };
+        *count = count.saturating_add(1);
+        self.commits = self.commits.saturating_add(commits);
+        self.outcomes.insert(name.to_owned(), outcome);
+    }
+
+    /// Record a repository that could not be found or opened.
+    pub fn record_error(&mut self, error: String) {
+        self.repos_errored = self.repos_errored.saturating_add(1);
+        self.errors.push(error);
+    }
+
+    /// Record that the repository `name` is a shallow clone.
+    pub fn record_shallow(&mut self, name: &str) {
+        self.shallow_repos.push(name.to_owned());
+    }
+
+    /// Record that the repository `name` is in `group`.
+    pub fn record_group(&mut self, name: &str, group: &str) {
+        self.groups.insert(name.to_owned(), group.to_owned());
+    }
+
+    /// Record how long the scan took.
+    pub fn finish(&mut self, started: Instant) {
+        self.duration_ms = duration_ms(started.elapsed());
+        self.shallow_repos.sort();
+    }
+}
+
+/// Log how long it took to find and scan one repository.
+///
+/// `started` should be when the search for the repository began, so that time
+/// spent walking the directory tree is attributed to the repository it found.
+pub fn log_repo_scan<T, E: fmt::Display>(
+    name: &str,
+    started: Instant,
+    result: &Result<Vec<T>, E>,
+) {
+    let duration_ms = duration_ms(started.elapsed());
+    match result {
+        Ok(times) => tracing::debug!(
+            repo = name,
+            commits = times.len(),
+            duration_ms,
+            "Scanned repository",
+        ),
+        Err(error) => tracing::debug!(
+            repo = name,
+            %error,
+            duration_ms,
+            "Failed to scan repository",
+        ),
+    }
+}
+
+/// Convert a [`Duration`] to whole milliseconds, saturating.
+fn duration_ms(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+/// Scan health since the server started.
+#[derive(Clone, Debug, Default, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct ScanStatus {
+    /// Number of scans finished.
+    pub scans: u64,
+
+    /// Total number of repositories that could not be scanned, over all
+    /// scans.
+    pub errors: u64,
+
+    /// Total time spent scanning in milliseconds.
+    pub total_duration_ms: u64,
+
+    /// The most recent scan.
+    pub last: Option<ScanMetrics>,
+}
+
+impl ScanStatus {
+    /// Add a finished scan.
+    pub fn record(&mut self, metrics: ScanMetrics) {
+        self.scans = self.scans.saturating_add(1);
+        self.errors = self.errors.saturating_add(
+            metrics.repos_errored.try_into().unwrap_or(u64::MAX),
+        );
+        self.total_duration_ms =
+            self.total_duration_ms.saturating_add(metrics.duration_ms);
+        self.last = Some(metrics);
+    }
+
+    /// Format as Prometheus metrics.
+    ///
+    /// See the [exposition format documentation][format].
+    ///
+    /// [format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+    #[must_use]
+    pub fn prometheus(&self) -> String {
+        let mut output = String::new();
+        let mut metric =
+            |name: &str, kind: &str, help: &str, values: &[(&str, String)]| {
+                write_metric(&mut output, name, kind, help, values);
+            };
+
+        metric(
+            "scans_total",
+            "counter",
+            "Number of scans finished.",
+            &[("", self.scans.to_string())],
+        );
+        metric(
+            "scan_errors_total",
+            "counter",
+            "Repositories that could not be scanned.",
+            &[("", self.errors.to_string())],
+        );
+        metric(
+            "scan_duration_seconds_total",
+            "counter",
+            "Time spent scanning.",
+            &[("", seconds(self.total_duration_ms))],
+        );
+
+        if let Some(last) = &self.last {
+            metric(
+                "last_scan_timestamp_seconds",
+                "gauge",
+                "When the most recent scan started.",
+                &[("", last.started_at.to_string())],
+            );
+            metric(
+                "last_scan_duration_seconds",
+                "gauge",
+                "How long the most recent scan took.",
+                &[("", seconds(last.duration_ms))],
+            );
+            metric(
+                "last_scan_repos",
+                "gauge",
+                "Repositories in the most recent scan by outcome.",
+                &[
+                    (
+                        r#"{state="discovered"}"#,
+                        last.repos_discovered.to_string(),
+                    ),
+                    (r#"{state="scanned"}"#, last.repos_scanned.to_string()),
+                    (
+                        r#"{state="unpushed_only"}"#,
+                        last.repos_unpushed_only.to_string(),
+                    ),
+                    (r#"{state="errored"}"#, last.repos_errored.to_string()),
+                ],
+            );
+            metric(
+                "last_scan_commits",
+                "gauge",
+                "Commits counted in the most recent scan.",
+                &[("", last.commits.to_string())],
+            );
+        }
+
+        output
+    }
+}
+
+/// Add a metric in the Prometheus text format to `output`.
+///
+/// `name` is prefixed with `repoyear_`. Each value is paired with its labels,
+/// e.g. `{state="scanned"}`, or `""` for no labels.
+pub fn write_metric(
+    output: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    values: &[(&str, String)],
+) {
+    // Writing to a String can’t fail.
+    let _ = writeln!(output, "# HELP repoyear_{name} {help}");
+    let _ = writeln!(output, "# TYPE repoyear_{name} {kind}");
+    for (labels, value) in values {
+        let _ = writeln!(output, "repoyear_{name}{labels} {value}");
+    }
+}
+
+/// Format milliseconds as seconds.
+fn seconds(ms: u64) -> String {
+    format!("{:.3}", Duration::from_millis(ms).as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    /// Writer that keeps everything written to it.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn log_repo_scans() {
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .without_time()
+            .with_writer({
+                let capture = capture.clone();
+                move || capture.clone()
+            })
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            log_repo_scan::<i64, &str>("x:a", Instant::now(), &Ok(vec![1, 2]));
+            log_repo_scan::<i64, &str>("x:b", Instant::now(), &Err("broken"));
+        });
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone());
+        let output = output.unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        let [a, b] = &lines[..] else {
+            panic!("expected two lines: {lines:?}");
+        };
+        assert!(a.contains(
+            "Scanned repository repo=\"x:a\" commits=2 duration_ms="
+        ));
+        assert!(b.contains(
+            "Failed to scan repository repo=\"x:b\" error=broken duration_ms="
+        ));
+    }
+
+    #[test]
+    fn record_and_format() {
+        let mut status = ScanStatus::default();
+        assert!(!status.prometheus().contains("last_scan"));
+
+        status.record(ScanMetrics {
+            started_at: 1_700_000_000,
+            duration_ms: 1_250,
+            repos_discovered: 4,
+            repos_scanned: 2,
+            repos_unpushed_only: 1,
+            repos_errored: 1,
+            commits: 30,
+            shallow_repos: Vec::new(),
+            outcomes: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            errors: Vec::new(),
+        });
+        status
+            .record(ScanMetrics { repos_errored: 2, ..ScanMetrics::default() });
+        assert!(status.scans == 2);
+        assert!(status.errors == 3);
+
+        let output = status.prometheus();
+        assert!(output.contains("\nrepoyear_scans_total 2\n"));
+        assert!(
+            output.contains("\nrepoyear_scan_duration_seconds_total 1.250\n")
+        );
+        assert!(
+            output
+                .contains("\nrepoyear_last_scan_repos{state=\"errored\"} 2\n")
+        );
+    }
+}
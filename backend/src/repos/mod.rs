@@ -1,10 +1,55 @@
 //! Deal with local git repos.
 
+mod authors;
+pub use authors::*;
+
+mod cache;
+pub use cache::*;
+
+mod cache_dir;
+pub use cache_dir::*;
+
+mod calendar;
+pub use calendar::*;
+
 mod config;
 pub use config::*;
 
+mod date_range;
+pub use date_range::*;
+
+mod diffstat;
+pub use diffstat::*;
+
+mod divergence;
+pub use divergence::*;
+
 mod errors;
 pub use errors::*;
 
+mod glob;
+pub use glob::*;
+
+mod identity;
+pub use identity::*;
+
+mod linguist;
+pub use linguist::*;
+
+mod metrics;
+pub use metrics::*;
+
+mod paths;
+pub use paths::*;
+
+mod remotes;
+pub use remotes::*;
+
+mod rename;
+pub use rename::*;
+
 mod scan;
 pub use scan::*;
+
+mod signature;
+pub use signature::*;
@@ -0,0 +1,160 @@
+//! Only count commits that touch certain paths.
+//!
+//! Commits that only change generated code or vendored libraries usually
+//! aren’t interesting contributions. Finding the paths a commit touches
+//! requires diffing it against its parent, so this is much slower than only
+//! reading commit times.
+
+use git2::{Pathspec, PathspecFlags, Repository};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Paths that commits must touch to be counted.
+///
+/// ```toml
+/// [paths]
+/// include = ["src/", "Cargo.toml"]
+/// exclude = ["src/generated/"]
+/// ```
+///
+/// Patterns are git pathspecs, e.g. `vendor/` or `*.min.js`. A commit counts
+/// if it changes at least one path that matches `include`, or any path if
+/// `include` is empty, and doesn’t match `exclude`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq)]
+pub struct ScanPaths {
+    /// Paths to count changes to. If empty, every path counts.
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Paths to ignore changes to.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl ScanPaths {
+    /// Whether or not any patterns are configured.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Compile the patterns.
+    ///
+    /// Returns `None` if there aren’t any, so that commits don’t need to be
+    /// diffed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern is invalid.
+    pub fn matcher(&self) -> Result<Option<PathMatcher>, git2::Error> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let pathspec = |patterns: &[String]| {
+            if patterns.is_empty() {
+                Ok(None)
+            } else {
+                Pathspec::new(patterns).map(Some)
+            }
+        };
+        Ok(Some(PathMatcher {
+            include: pathspec(&self.include)?,
+            exclude: pathspec(&self.exclude)?,
+        }))
+    }
+}
+
+/// Compiled [`ScanPaths`].
+pub struct PathMatcher {
+    /// Paths to count, or `None` to count every path.
+    include: Option<Pathspec>,
+
+    /// Paths to ignore.
+    exclude: Option<Pathspec>,
+}
+
+impl PathMatcher {
+    /// Check if changes to `path` count.
+    #[must_use]
+    pub fn matches(&self, path: &Path) -> bool {
+        let matches = |pathspec: &Pathspec| {
+            pathspec.matches_path(path, PathspecFlags::DEFAULT)
+        };
+        self.include.as_ref().is_none_or(matches)
+            && !self.exclude.as_ref().is_some_and(matches)
+    }
+
+    /// Check if `commit` changes any paths that count.
+    ///
+    /// Commits are compared to their first parent, so a merge commit touches
+    /// everything that was merged in.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn touches(
+        &self,
+        repo: &Repository,
+        commit: &git2::Commit<'_>,
+    ) -> Result<bool, git2::Error> {
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let diff = repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            None,
+        )?;
+        Ok(diff.deltas().any(|delta| {
+            [delta.new_file().path(), delta.old_file().path()]
+                .into_iter()
+                .flatten()
+                .any(|path| self.matches(path))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use testdir::testdir;
+
+    #[test]
+    fn touches_paths() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.write("src/main.rs", "fn main() {}\n");
+        repo.git(["add", "."]);
+        repo.git(["commit", "-m", "main"]);
+        repo.write("src/generated/a.rs", "a\n");
+        repo.git(["add", "."]);
+        repo.git(["commit", "-m", "generated"]);
+        repo.write("README.md", "readme\n");
+        repo.git(["add", "."]);
+        repo.git(["commit", "-m", "readme"]);
+
+        let repository = Repository::open(repo.path()).unwrap();
+        let paths = ScanPaths {
+            include: vec!["src/".to_owned()],
+            exclude: vec!["src/generated/".to_owned()],
+        };
+        let matcher = paths.matcher().unwrap().unwrap();
+        let touches = |rev: &str| {
+            let commit = repository
+                .revparse_single(rev)
+                .unwrap()
+                .peel_to_commit()
+                .unwrap();
+            matcher.touches(&repository, &commit).unwrap()
+        };
+        assert!(touches("HEAD~2"));
+        assert!(!touches("HEAD~"));
+        assert!(!touches("HEAD"));
+
+        assert!(let Ok(None) = ScanPaths::default().matcher());
+    }
+}
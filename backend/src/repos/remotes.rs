@@ -0,0 +1,165 @@
+//! Recognize clones of the same repository by their remote URLs.
+
+use super::LocalContributions;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+
+/// Drop local repositories that are clones of another one.
+///
+/// Repositories are clones if their remote URLs match after
+/// [normalization](normalize_remote). Only the clone with the most commits is
+/// kept, since it’s probably the most up to date; ties go to the first name.
+///
+/// `remotes` maps repository names to remote URLs. Repositories without a
+/// remote are always kept.
+///
+/// Returns the names of the repositories that were dropped, sorted.
+pub fn drop_clones(
+    contributions: &mut LocalContributions,
+    remotes: &BTreeMap<String, String>,
+) -> Vec<String> {
+    let mut kept: BTreeMap<String, (&String, usize)> = BTreeMap::new();
+    let mut dropped = Vec::new();
+    for (name, remote) in remotes {
+        let Some(commits) = contributions.get(name).map(Vec::len) else {
+            continue;
+        };
+        match kept.entry(normalize_remote(remote)) {
+            Entry::Vacant(entry) => {
+                entry.insert((name, commits));
+            }
+            Entry::Occupied(mut entry) => {
+                if commits > entry.get().1 {
+                    dropped.push(entry.insert((name, commits)).0.clone());
+                } else {
+                    dropped.push(name.clone());
+                }
+            }
+        }
+    }
+
+    for name in &dropped {
+        contributions.remove(name);
+    }
+    dropped.sort();
+    dropped
+}
+
+/// Normalize a remote URL so that different ways of writing it match.
+///
+/// For example, `git@github.com:user/repo.git` and
+/// `https://github.com/user/repo` both become `github.com/user/repo`.
+#[must_use]
+pub fn normalize_remote(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let url = if let Some((_, rest)) = url.split_once("://") {
+        // Drop the user name, if any.
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+        format!("{host}/{path}")
+    } else if let Some((host, path)) = url.split_once(':') {
+        // scp-like syntax: [user@]host:path
+        let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+        format!("{host}/{}", path.trim_start_matches('/'))
+    } else {
+        url.to_owned()
+    };
+    url.to_lowercase()
+}
+
+/// Normalize a remote URL that might not be UTF-8.
+///
+/// UTF-8 URLs are normalized with [`normalize_remote()`]. Otherwise the same
+/// changes are made to the bytes, but only ASCII letters are lowercased.
+#[must_use]
+pub fn normalize_remote_bytes(url: &[u8]) -> Vec<u8> {
+    if let Ok(url) = str::from_utf8(url) {
+        return normalize_remote(url).into_bytes();
+    }
+    let find = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+    let drop_user = |host: &[u8]| match host.iter().rposition(|&b| b == b'@') {
+        Some(at) => host[at.saturating_add(1)..].to_vec(),
+        None => host.to_vec(),
+    };
+
+    let mut url = url.trim_ascii();
+    while let Some(rest) = url.strip_suffix(b"/") {
+        url = rest;
+    }
+    let url = url.strip_suffix(b".git").unwrap_or(url);
+    let mut url = if let Some(scheme) = find(url, b"://") {
+        let rest = &url[scheme.saturating_add(3)..];
+        let slash = rest.iter().position(|&b| b == b'/');
+        let (host, path) = slash.map_or((rest, &b""[..]), |slash| {
+            (&rest[..slash], &rest[slash.saturating_add(1)..])
+        });
+        [drop_user(host), b"/".to_vec(), path.to_vec()].concat()
+    } else if let Some(colon) = url.iter().position(|&b| b == b':') {
+        let mut path = &url[colon.saturating_add(1)..];
+        while let Some(rest) = path.strip_prefix(b"/") {
+            path = rest;
+        }
+        [drop_user(&url[..colon]), b"/".to_vec(), path.to_vec()].concat()
+    } else {
+        url.to_vec()
+    };
+    url.make_ascii_lowercase();
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn drop_local_clones() {
+        let mut contributions = LocalContributions::from([
+            ("a".to_owned(), vec![1, 2]),
+            ("b".to_owned(), vec![1, 2, 3]),
+            ("c".to_owned(), vec![1, 2, 3]),
+            ("d".to_owned(), vec![1]),
+            ("e".to_owned(), vec![1]),
+        ]);
+        let remotes = BTreeMap::from([
+            ("a".to_owned(), "git@example.com:repo.git".to_owned()),
+            ("b".to_owned(), "https://example.com/repo".to_owned()),
+            ("c".to_owned(), "ssh://git@example.com/repo".to_owned()),
+            ("d".to_owned(), "git@example.com:other.git".to_owned()),
+            ("missing".to_owned(), "git@example.com:repo.git".to_owned()),
+        ]);
+        assert!(drop_clones(&mut contributions, &remotes) == ["a", "c"]);
+        let mut names: Vec<_> = contributions.keys().cloned().collect();
+        names.sort();
+        assert!(names == ["b", "d", "e"]);
+    }
+
+    #[test]
+    fn normalize() {
+        let expected = "github.com/user/repo";
+        assert!(normalize_remote("git@github.com:user/repo.git") == expected);
+        assert!(normalize_remote("https://github.com/user/repo/") == expected);
+        assert!(normalize_remote("ssh://git@GitHub.com/user/repo") == expected);
+        assert!(normalize_remote("/srv/git/repo.git") == "/srv/git/repo");
+    }
+
+    #[test]
+    fn normalize_remotes_bytes() {
+        assert!(
+            normalize_remote_bytes(b"git@GitHub.com:user/\xff.git")
+                == b"github.com/user/\xff"
+        );
+        assert!(
+            normalize_remote_bytes(b"ssh://git@host/\xff/") == b"host/\xff"
+        );
+        assert!(
+            normalize_remote_bytes(b"https://github.com/user/repo")
+                == b"github.com/user/repo"
+        );
+    }
+}
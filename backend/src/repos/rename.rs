@@ -0,0 +1,126 @@
+//! Rename repositories with regular expressions.
+
+use regex_automata::meta::{BuildError, Regex};
+use schemars::JsonSchema;
+use schemars::r#gen::SchemaGenerator;
+use schemars::schema::Schema;
+use serde::Deserialize;
+
+/// A rule to rename repositories found in a tree. See
+/// [`TreeConfig::rename`](super::TreeConfig::rename).
+///
+/// # Example
+///
+/// ```
+/// use assert2::assert;
+/// use repoyear_backend::repos::RenameRule;
+///
+/// let rule = RenameRule::new(r"^/srv/git/(.*)\.git$", "$1").unwrap();
+/// assert!(rule.apply("/srv/git/team-x/foo.git") == "team-x/foo");
+/// assert!(rule.apply("/srv/git/team-x/bar") == "/srv/git/team-x/bar");
+/// ```
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq)]
+pub struct RenameRule {
+    /// Regular expression to look for in the name.
+    pub pattern: NamePattern,
+
+    /// What to replace each match with.
+    ///
+    /// `$1` or `${1}` is replaced with the first capture group, and `$name`
+    /// or `${name}` with the group named `name`. Use `$$` for a literal `$`.
+    pub replace: String,
+}
+
+impl RenameRule {
+    /// Create a rule that replaces `pattern` with `replace`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` isn’t a valid regular expression.
+    pub fn new(pattern: &str, replace: &str) -> Result<Self, Box<BuildError>> {
+        Ok(Self {
+            pattern: NamePattern::new(pattern)?,
+            replace: replace.to_owned(),
+        })
+    }
+
+    /// Replace every match of the pattern in `name`.
+    #[must_use]
+    pub fn apply(&self, name: &str) -> String {
+        let mut renamed = String::new();
+        let mut end = 0;
+        for captures in self.pattern.regex.captures_iter(name) {
+            let Some(found) = captures.get_match() else {
+                continue;
+            };
+            renamed.push_str(&name[end..found.start()]);
+            captures.interpolate_string_into(name, &self.replace, &mut renamed);
+            end = found.end();
+        }
+        renamed.push_str(&name[end..]);
+        renamed
+    }
+}
+
+/// A regular expression in a [`RenameRule`].
+///
+/// This is compiled when the configuration is read, so invalid patterns are
+/// reported then.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct NamePattern {
+    /// The pattern as written.
+    source: String,
+
+    /// The compiled pattern.
+    regex: Regex,
+}
+
+impl NamePattern {
+    /// Compile a regular expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `source` isn’t a valid regular expression.
+    pub fn new(source: &str) -> Result<Self, Box<BuildError>> {
+        Self::try_from(source.to_owned())
+    }
+
+    /// Get the pattern as written.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+// This deserializes from a string, so the derived schema would be wrong.
+impl JsonSchema for NamePattern {
+    fn schema_name() -> String {
+        "NamePattern".to_owned()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(generator).into_object();
+        schema.metadata().description = Some(
+            "A regular expression to match in repository names.".to_owned(),
+        );
+        schema.into()
+    }
+}
+
+impl TryFrom<String> for NamePattern {
+    type Error = Box<BuildError>;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Ok(Self { regex: Regex::new(&source)?, source })
+    }
+}
+
+/// Patterns are equal if they were written the same way.
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for NamePattern {}
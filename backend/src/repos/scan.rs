@@ -1,59 +1,1160 @@
 //! Scan repos for contribution data.
 
-use git2::{ErrorCode, Oid, Repository};
+use super::{
+    CommitSignature, CommitStats, DiffStats, Identity, PathFilter,
+    RepoIdentity, ScanPaths, glob_match, glob_match_bytes, normalize_remote,
+    normalize_remote_bytes,
+};
+use git2::{ErrorCode, Oid, ReferenceType, Repository, Revwalk};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
 use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
 // FIXME use super::Error and super::Result
 
+/// Local contributions (map repo name to commit times as seconds since epoch).
+pub type LocalContributions = HashMap<String, Vec<i64>>;
+
+/// Options that control which commits a scan counts.
+///
+/// Usually this comes from [`Config::scan_options()`].
+///
+/// [`Config::scan_options()`]: super::Config::scan_options
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ScanOptions {
+    /// Only count commits by this author. If it’s
+    /// [empty](Identity::is_empty), every commit is counted.
+    pub identity: Identity,
+
+    /// Limit on the number of commits to scan. See [`CommitLimit`].
+    pub limit: Option<CommitLimit>,
+
+    /// The branch that `HEAD` points to on `origin`, if known. See
+    /// [`get_default_branch()`].
+    pub remote_head: Option<String>,
+
+    /// Which date to count commits on.
+    pub date: CommitDate,
+
+    /// Only follow the first parent of merge commits.
+    ///
+    /// This leaves out commits that were merged in from other branches, e.g.
+    /// by a bot updating dependencies.
+    pub first_parent: bool,
+
+    /// Whether to count merge commits.
+    pub merges: MergeCommits,
+
+    /// Only count commits from this time on (seconds since 1970).
+    ///
+    /// Walks stop as soon as they reach older commits, so this makes scanning
+    /// long histories much faster.
+    pub since: Option<i64>,
+
+    /// Only count commits from before this time (seconds since 1970).
+    pub until: Option<i64>,
+
+    /// Maximum number of commits on the default branch to count. Only the
+    /// newest are counted, and the walk stops once it finds this many.
+    ///
+    /// Unlike [`limit`](Self::limit), this doesn’t depend on how many
+    /// commits are walked, only on how many are counted.
+    pub commit_cap: Option<usize>,
+
+    /// Only count commits that haven’t been pushed to these remotes.
+    pub skip_remotes: RemotePatterns,
+
+    /// Whether to count unmerged commits with the same changes more than
+    /// once. See [`PatchDuplicates`].
+    pub duplicate_patches: PatchDuplicates,
+
+    /// Refs to walk instead of the default branch, e.g.
+    /// `["refs/heads/release/*", "refs/tags/v*"]`.
+    ///
+    /// These are globs matched against full ref names, so they should start
+    /// with `refs/`. Refs that don’t point to commits are ignored. If this is
+    /// empty, the default branch is walked.
+    pub refs: Vec<String>,
+
+    /// Only count commits that touch these paths.
+    pub paths: ScanPaths,
+}
+
+impl ScanOptions {
+    /// Count only commits by `identity`.
+    #[must_use]
+    pub fn for_identity(identity: Identity) -> Self {
+        Self { identity, ..Self::default() }
+    }
+
+    /// Find the default branch of `repo`.
+    ///
+    /// See [`get_default_branch()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn default_branch(&self, repo: &Repository) -> anyhow::Result<Oid> {
+        get_default_branch(repo, self.remote_head.as_deref())
+    }
+
+    /// Find refs matching [`refs`](Self::refs) and the commits they point
+    /// to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository or a
+    /// pattern is invalid.
+    pub fn matching_refs(
+        &self,
+        repo: &Repository,
+    ) -> anyhow::Result<Vec<(String, Oid)>> {
+        let mut refs = Vec::new();
+        for pattern in &self.refs {
+            for reference in repo.references_glob(pattern)? {
+                let reference = reference?;
+                if let (Some(name), Ok(commit)) =
+                    (reference.name(), reference.peel_to_commit())
+                {
+                    refs.push((name.to_owned(), commit.id()));
+                }
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Find the commits that walks start from.
+    ///
+    /// This is the default branch, unless [`refs`](Self::refs) is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn start_commits(&self, repo: &Repository) -> anyhow::Result<Vec<Oid>> {
+        if self.refs.is_empty() {
+            Ok(vec![self.default_branch(repo)?])
+        } else {
+            Ok(self
+                .matching_refs(repo)?
+                .into_iter()
+                .map(|(_, oid)| oid)
+                .collect())
+        }
+    }
+
+    /// Start walking commits in `repo`, newest first.
+    ///
+    /// Nothing has been pushed onto the walk yet. If
+    /// [`first_parent`](Self::first_parent) is set, only first parents are
+    /// followed.
+    ///
+    /// libgit2 uses the repository’s commit-graph file to find parents if it
+    /// has one, which makes walking large histories much faster. See
+    /// [`write_commit_graph()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn revwalk<'repo>(
+        &self,
+        repo: &'repo Repository,
+    ) -> anyhow::Result<Revwalk<'repo>> {
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TIME)?;
+        if self.first_parent {
+            revwalk.simplify_first_parent()?;
+        }
+        Ok(revwalk)
+    }
+
+    /// Start a walk of the default branch, or of [`refs`](Self::refs) if
+    /// it’s set.
+    ///
+    /// Commits on remote-tracking branches of [skipped
+    /// remotes](Self::skip_remotes) are hidden, so only commits that haven’t
+    /// been pushed to them are walked.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn default_branch_walk<'repo>(
+        &self,
+        repo: &'repo Repository,
+    ) -> anyhow::Result<Revwalk<'repo>> {
+        let mut revwalk = self.revwalk(repo)?;
+        for oid in self.start_commits(repo)? {
+            revwalk.push(oid)?;
+        }
+        for (_, oid) in self.skip_remotes.matching_remote_refs(repo)? {
+            revwalk.hide(oid)?;
+        }
+        Ok(revwalk)
+    }
+
+    /// Check if `commit` should be left out regardless of its author.
+    #[must_use]
+    pub fn skips(&self, commit: &git2::Commit<'_>) -> bool {
+        let time = self.date.of(commit);
+        (self.merges == MergeCommits::Skip && commit.parent_count() > 1)
+            || self.since.is_some_and(|since| time < since)
+            || self.until.is_some_and(|until| time >= until)
+    }
+
+    /// Check if `count` commits reach [`commit_cap`](Self::commit_cap).
+    #[must_use]
+    pub fn is_capped(&self, count: usize) -> bool {
+        self.commit_cap.is_some_and(|cap| count >= cap)
+    }
+
+    /// Check if a walk from [`revwalk()`](Self::revwalk) can stop at
+    /// `commit`, since it and every commit after it are older than
+    /// [`since`](Self::since).
+    ///
+    /// The walk is sorted by commit time, newest first.
+    #[must_use]
+    pub fn stops_at(&self, commit: &git2::Commit<'_>) -> bool {
+        self.since
+            .is_some_and(|since| commit.time().seconds() < since)
+    }
+}
+
 /// Scan history of a repository and commit dates as seconds since 1970.
 ///
+/// Only commits counted by `options` are included.
+///
 /// The path must be one of:
 ///
-///   * A repository working directory containing a `.git` directory
-///   * A `.git` directory itself
-///   * A bare repository
+///   * A repository working directory containing a `.git` directory
+///   * A `.git` directory itself
+///   * A bare repository
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn scan_repo_path<P: AsRef<Path>>(
+    path: P,
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<i64>> {
+    scan_repo(&Repository::open(path)?, options)
+}
+
+/// Scan history of a repository and commit dates as seconds since 1970.
+///
+/// Only commits counted by `options` are included. If the repository is a
+/// [shallow clone](Repository::is_shallow), commits older than the ones it has
+/// aren’t found.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository, or if it was
+/// skipped because of [`ScanOptions::limit`].
+pub fn scan_repo(
+    repo: &Repository,
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<i64>> {
+    Ok(scan_repo_commits(repo, options)?
+        .into_iter()
+        .map(|commit| commit.time)
+        .collect())
+}
+
+/// Scan history of a repository for commits by several identities at once.
+///
+/// Returns commit times for each label in `identities` that has any. The
+/// history is only walked once, and a commit is counted for every identity
+/// it matches, e.g. if it credits one as a co-author.
+/// [`ScanOptions::identity`] is ignored, and
+/// [`commit_cap`](ScanOptions::commit_cap) applies to each identity
+/// separately.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository, or if it was
+/// skipped because of [`ScanOptions::limit`].
+pub fn scan_repo_identities(
+    repo: &Repository,
+    options: &ScanOptions,
+    identities: &BTreeMap<String, Identity>,
+) -> anyhow::Result<BTreeMap<String, Vec<i64>>> {
+    let everyone = ScanOptions {
+        identity: Identity::default(),
+        commit_cap: None,
+        ..options.clone()
+    };
+    let identities = identities
+        .iter()
+        .map(|(label, identity)| Ok((label, identity.for_repo(repo)?)))
+        .collect::<Result<Vec<_>, git2::Error>>()?;
+    let mut times: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+    for commit in default_branch_commits(repo, &everyone)? {
+        for (label, identity) in &identities {
+            if !identity.matches_commit(&commit) {
+                continue;
+            }
+            let times = times.entry((*label).clone()).or_default();
+            if !options.is_capped(times.len()) {
+                times.push(options.date.of(&commit));
+            }
+        }
+    }
+    Ok(times)
+}
+
+/// A commit found while scanning.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct Commit {
+    /// Commit ID as a hex string.
+    pub oid: String,
+
+    /// Commit date as seconds since 1970. See [`ScanOptions::date`].
+    pub time: i64,
+}
+
+/// A commit found while scanning, with its summary line.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct CommitSummary {
+    /// Commit date as seconds since 1970. See [`ScanOptions::date`].
+    pub time: i64,
+
+    /// Commit ID as a hex string.
+    pub oid: String,
+
+    /// First line of the commit message.
+    pub summary: String,
+
+    /// The commit’s signature, or `None` if it isn’t signed.
+    pub signature: Option<CommitSignature>,
+}
+
+/// Which date to count a commit on.
+///
+/// ```toml
+/// commit_date = "committer"
+/// ```
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitDate {
+    /// When the change was originally written. Rebasing and cherry-picking
+    /// leave this alone.
+    #[default]
+    Author,
+
+    /// When the commit was last rewritten, e.g. by a rebase or an amend. This
+    /// is closer to when GitHub counts contributions for merged pull
+    /// requests.
+    Committer,
+}
+
+impl CommitDate {
+    /// Get this date of `commit` as seconds since 1970.
+    #[must_use]
+    pub fn of(self, commit: &git2::Commit<'_>) -> i64 {
+        match self {
+            Self::Author => commit.author().when().seconds(),
+            Self::Committer => commit.committer().when().seconds(),
+        }
+    }
+}
+
+/// Whether to count merge commits.
+///
+/// ```toml
+/// merges = "skip"
+/// ```
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeCommits {
+    /// Count merge commits like any other commit.
+    #[default]
+    Count,
+
+    /// Leave merge commits out.
+    Skip,
+}
+
+/// Whether to count unmerged commits with the same changes as other commits.
+///
+/// Commits are compared by patch ID, like `git patch-id`, so a commit that
+/// was rebased or cherry-picked onto another branch matches the original.
+/// This only affects [`scan_unmerged_commits()`].
+///
+/// ```toml
+/// duplicate_patches = "skip"
+/// ```
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PatchDuplicates {
+    /// Count every commit.
+    #[default]
+    Count,
+
+    /// Leave out commits with the same changes as a commit on the default
+    /// branch or a commit already found on another branch. This is slow,
+    /// since every commit has to be diffed.
+    Skip,
+}
+
+/// Limit on the number of commits to scan in a repository.
+///
+/// This keeps scans of huge repositories, like mirrors of large open source
+/// projects, from taking forever.
+///
+/// ```toml
+/// [commit_limit]
+/// max_commits = 100000
+/// action = "truncate"
+/// lookback_days = 365
+/// ```
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq)]
+pub struct CommitLimit {
+    /// Maximum number of commits on the default branch.
+    pub max_commits: usize,
+
+    /// What to do with repositories that have more commits.
+    #[serde(default)]
+    pub action: CommitLimitAction,
+
+    /// How far back to count commits in truncated repositories.
+    #[serde(default = "CommitLimit::default_lookback_days")]
+    pub lookback_days: u32,
+}
+
+impl CommitLimit {
+    /// Default for [`lookback_days`](Self::lookback_days).
+    const fn default_lookback_days() -> u32 {
+        365
+    }
+
+    /// Get the first day counted in truncated repositories.
+    fn cutoff_date(&self) -> chrono::NaiveDate {
+        chrono::Utc::now()
+            .date_naive()
+            .checked_sub_days(chrono::Days::new(self.lookback_days.into()))
+            .unwrap_or(chrono::NaiveDate::MIN)
+    }
+
+    /// Get the earliest commit time counted in truncated repositories.
+    ///
+    /// This is the start of [`cutoff_date()`](Self::cutoff_date) in UTC, so
+    /// that every scan on the same day agrees and can be cached.
+    fn cutoff(&self) -> i64 {
+        self.cutoff_date()
+            .and_time(chrono::NaiveTime::MIN)
+            .and_utc()
+            .timestamp()
+    }
+}
+
+/// What to do with repositories that exceed a [`CommitLimit`].
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitLimitAction {
+    /// Don’t count any commits, and report an error instead.
+    #[default]
+    Skip,
+
+    /// Only count commits from the last
+    /// [`lookback_days`](CommitLimit::lookback_days), starting at midnight
+    /// UTC.
+    Truncate,
+}
+
+/// Scan history of a repository for commits.
+///
+/// This is like [`scan_repo()`], but includes the ID of each commit so that
+/// results from different machines can be merged.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository, or if it was
+/// skipped because of [`ScanOptions::limit`].
+pub fn scan_repo_commits(
+    repo: &Repository,
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<Commit>> {
+    Ok(default_branch_commits(repo, options)?
+        .into_iter()
+        .map(|commit| Commit {
+            oid: commit.id().to_string(),
+            time: options.date.of(&commit),
+        })
+        .collect())
+}
+
+/// Scan history of a repository for commits with their summary lines and
+/// signatures.
+///
+/// This finds the same commits as [`scan_repo_commits()`]. If
+/// `verify_signatures` is set, signatures are checked with `git
+/// verify-commit`, which is slow; see [`CommitSignature::read()`].
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository, or if it was
+/// skipped because of [`ScanOptions::limit`].
+pub fn scan_repo_summaries(
+    repo: &Repository,
+    options: &ScanOptions,
+    verify_signatures: bool,
+) -> anyhow::Result<Vec<CommitSummary>> {
+    default_branch_commits(repo, options)?
+        .into_iter()
+        .map(|commit| {
+            Ok(CommitSummary {
+                time: options.date.of(&commit),
+                oid: commit.id().to_string(),
+                summary: String::from_utf8_lossy(
+                    commit.summary_bytes().unwrap_or_default(),
+                )
+                .into_owned(),
+                signature: CommitSignature::read(
+                    repo,
+                    &commit,
+                    verify_signatures,
+                )?,
+            })
+        })
+        .collect()
+}
+
+/// Scan history of a repository for commits with the number of lines they
+/// changed.
+///
+/// This finds the same commits as [`scan_repo_commits()`], but it’s much
+/// slower since it has to diff each one. Paths excluded by `filter` aren’t
+/// counted; see [`DiffStats::by_file_type()`].
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository, or if it was
+/// skipped because of [`ScanOptions::limit`].
+pub fn scan_repo_stats(
+    repo: &Repository,
+    options: &ScanOptions,
+    filter: &PathFilter,
+) -> anyhow::Result<Vec<CommitStats>> {
+    default_branch_commits(repo, options)?
+        .into_iter()
+        .map(|commit| {
+            let file_types = DiffStats::by_file_type(repo, &commit, filter)?;
+            Ok(CommitStats {
+                oid: commit.id().to_string(),
+                time: options.date.of(&commit),
+                stats: DiffStats::total(file_types.values()),
+                file_types,
+            })
+        })
+        .collect()
+}
+
+/// Find commits counted by `options` on the default branch.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository, or if it was
+/// skipped because of [`ScanOptions::limit`].
+fn default_branch_commits<'repo>(
+    repo: &'repo Repository,
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<git2::Commit<'repo>>> {
+    // Commits pushed to e.g. GitHub are already counted there.
+    let revwalk = options.default_branch_walk(repo)?;
+    let identity = options.identity.for_repo(repo)?;
+    let paths = options.paths.matcher()?;
+    let limit = options.limit.as_ref();
+    let mut commits = Vec::new();
+    let mut walked: usize = 0;
+    let mut cutoff = None;
+    for oid in revwalk {
+        if options.is_capped(commits.len()) {
+            break;
+        }
+        let commit = repo.find_commit(oid?)?;
+        if options.stops_at(&commit) {
+            break;
+        }
+        walked = walked.saturating_add(1);
+        if let Some(limit) = limit
+            && cutoff.is_none()
+            && walked > limit.max_commits
+        {
+            match limit.action {
+                CommitLimitAction::Skip => anyhow::bail!(
+                    "Skipped: more than {} commits (see commit_limit)",
+                    limit.max_commits
+                ),
+                CommitLimitAction::Truncate => {
+                    let time = limit.cutoff();
+                    commits.retain(|commit: &git2::Commit<'_>| {
+                        options.date.of(commit) >= time
+                    });
+                    cutoff = Some(time);
+                }
+            }
+        }
+
+        if let Some(cutoff) = cutoff {
+            // The walk is sorted by commit time, newest first.
+            if commit.time().seconds() < cutoff {
+                break;
+            }
+            if options.date.of(&commit) < cutoff {
+                continue;
+            }
+        }
+        if !options.skips(&commit)
+            && identity.matches_commit(&commit)
+            && paths
+                .as_ref()
+                .map_or(Ok(true), |paths| paths.touches(repo, &commit))?
+        {
+            commits.push(commit);
+        }
+    }
+    Ok(commits)
+}
+
+/// Scan local branches for commits that haven’t been merged.
+///
+/// This finds commits reachable from any local branch but not from the
+/// default branch. Once a branch is merged its commits are only counted by
+/// [`scan_repo_commits()`], so nothing is counted twice.
+///
+/// [`ScanOptions::limit`] doesn’t apply here.
+///
+/// Unlike [`scan_repo_commits()`], this includes commits that were pushed to
+/// [skipped remotes](ScanOptions::skip_remotes), since GitHub only counts
+/// commits on the default branch.
+///
+/// Commits with the same changes as other commits may be left out. See
+/// [`PatchDuplicates`].
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn scan_unmerged_commits(
+    repo: &Repository,
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<Commit>> {
+    let mut revwalk = options.revwalk(repo)?;
+    revwalk.push_glob("refs/heads")?;
+    for oid in options.start_commits(repo)? {
+        revwalk.hide(oid)?;
+    }
+
+    let identity = options.identity.for_repo(repo)?;
+    let paths = options.paths.matcher()?;
+    let dedup = options.duplicate_patches == PatchDuplicates::Skip;
+    let mut patch_ids = if dedup {
+        merged_patch_ids(repo, options, &identity)?
+    } else {
+        HashSet::new()
+    };
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if options.stops_at(&commit) {
+            break;
+        }
+        if !options.skips(&commit)
+            && identity.matches_commit(&commit)
+            && paths
+                .as_ref()
+                .map_or(Ok(true), |paths| paths.touches(repo, &commit))?
+        {
+            if dedup
+                && let Some(patch_id) = patch_id(repo, &commit)?
+                && !patch_ids.insert(patch_id)
+            {
+                continue;
+            }
+            commits.push(Commit {
+                oid: commit.id().to_string(),
+                time: options.date.of(&commit),
+            });
+        }
+    }
+    Ok(commits)
+}
+
+/// Find activity in the `HEAD` reflog.
+///
+/// Every time `HEAD` moves, e.g. for a commit, amend, rebase, or checkout,
+/// git adds an entry to the reflog with the identity and time of whoever
+/// moved it. This returns the times of entries by [`ScanOptions::identity`],
+/// newest first, so work that was later rewritten or deleted still counts as
+/// activity. Only [`since`](ScanOptions::since) and
+/// [`until`](ScanOptions::until) apply here.
+///
+/// Git expires reflog entries after 90 days by default, so this only finds
+/// recent activity. Bare repositories usually have no reflog.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn scan_reflog(
+    repo: &Repository,
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<i64>> {
+    let identity = options.identity.for_repo(repo)?;
+    Ok(repo
+        .reflog("HEAD")?
+        .iter()
+        .filter_map(|entry| {
+            let who = entry.committer();
+            identity
+                .matches(who.name(), who.email())
+                .then(|| who.when().seconds())
+        })
+        .filter(|time| {
+            options.since.is_none_or(|since| *time >= since)
+                && options.until.is_none_or(|until| *time < until)
+        })
+        .collect())
+}
+
+/// Find patch IDs of commits on the default branch that count for
+/// `identity`.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+fn merged_patch_ids(
+    repo: &Repository,
+    options: &ScanOptions,
+    identity: &RepoIdentity<'_>,
+) -> anyhow::Result<HashSet<Oid>> {
+    let mut revwalk = options.revwalk(repo)?;
+    for oid in options.start_commits(repo)? {
+        revwalk.push(oid)?;
+    }
+    let mut patch_ids = HashSet::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if options.stops_at(&commit) {
+            break;
+        }
+        if identity.matches_commit(&commit)
+            && let Some(patch_id) = patch_id(repo, &commit)?
+        {
+            patch_ids.insert(patch_id);
+        }
+    }
+    Ok(patch_ids)
+}
+
+/// Get the patch ID of `commit`, like `git patch-id`.
+///
+/// This is a hash of the changes a commit makes, so it’s the same for a
+/// commit that was rebased or cherry-picked as long as its changes didn’t
+/// need to be adjusted. Returns `None` for merge commits and commits without
+/// changes.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+fn patch_id(
+    repo: &Repository,
+    commit: &git2::Commit<'_>,
+) -> anyhow::Result<Option<Oid>> {
+    if commit.parent_count() > 1 {
+        return Ok(None);
+    }
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    };
+    let diff = repo.diff_tree_to_tree(
+        parent_tree.as_ref(),
+        Some(&commit.tree()?),
+        None,
+    )?;
+    if diff.deltas().len() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(diff.patchid(None)?))
+}
+
+/// Remote URLs of repositories that scans skip.
+///
+/// Commits pushed to GitHub are already counted by GitHub, so by default only
+/// commits that aren’t on any of a GitHub remote’s branches are counted. See
+/// [`ScanOptions::default_branch_walk()`]. Self-hosted forges can be added, or
+/// the list can be emptied to count every commit:
+///
+/// ```toml
+/// skip_remotes = ["github.com/*", "gitea.example.com/*"]
+/// ```
+///
+/// Patterns are matched against URLs normalized by
+/// [`normalize_remote()`], so
+/// `git@github.com:user/repo.git` and `https://github.com/user/repo` are both
+/// matched by `github.com/*`. `*` matches any number of characters and `?`
+/// matches exactly one.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq)]
+#[serde(transparent)]
+pub struct RemotePatterns(pub Vec<String>);
+
+impl Default for RemotePatterns {
+    fn default() -> Self {
+        Self(vec!["github.com/*".to_owned()])
+    }
+}
+
+impl RemotePatterns {
+    /// Check if `url` matches any of the patterns.
+    #[must_use]
+    pub fn matches(&self, url: &str) -> bool {
+        let url = normalize_remote(url);
+        self.0.iter().any(|pattern| glob_match(pattern, &url))
+    }
+
+    /// Check if a repository has a remote that matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn matches_repo(&self, repo: &Repository) -> anyhow::Result<bool> {
+        Ok(remote_kind(repo, self)? == RemoteKind::Skipped)
+    }
+
+    /// Check if `url`, which might not be UTF-8, matches any of the patterns.
+    ///
+    /// Non-UTF-8 URLs are compared byte by byte. See
+    /// [`normalize_remote_bytes()`].
+    #[must_use]
+    pub fn matches_bytes(&self, url: &[u8]) -> bool {
+        if let Ok(url) = str::from_utf8(url) {
+            return self.matches(url);
+        }
+        let url = normalize_remote_bytes(url);
+        self.0.iter().any(|pattern| glob_match_bytes(pattern, &url))
+    }
+
+    /// Get the remote-tracking refs of the remotes of a repository that
+    /// match, along with their targets.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository.
+    pub fn matching_remote_refs(
+        &self,
+        repo: &Repository,
+    ) -> anyhow::Result<Vec<(String, Oid)>> {
+        let prefixes: Vec<Vec<u8>> = remote_urls(repo)?
+            .into_iter()
+            .filter(|remote| self.matches_bytes(&remote.url))
+            .map(|remote| [b"refs/remotes/", &*remote.name, b"/"].concat())
+            .collect();
+        let mut refs = Vec::new();
+        if prefixes.is_empty() {
+            return Ok(refs);
+        }
+        for reference in repo.references_glob("refs/remotes/*")? {
+            let reference = reference?;
+            let name = reference.name_bytes();
+            // Symbolic refs like `origin/HEAD` point to other remote refs,
+            // and may be dangling if the remote renamed its default branch.
+            if reference.kind() == Some(ReferenceType::Direct)
+                && prefixes.iter().any(|prefix| name.starts_with(prefix))
+                && let Some(oid) = reference.target()
+            {
+                refs.push((String::from_utf8_lossy(name).into_owned(), oid));
+            }
+        }
+        Ok(refs)
+    }
+}
+
+/// The name and URL of a remote, either of which might not be UTF-8.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RemoteUrl {
+    /// The name of the remote, e.g. `origin`.
+    pub name: Vec<u8>,
+
+    /// The URL of the remote.
+    pub url: Vec<u8>,
+}
+
+impl RemoteUrl {
+    /// Check if the name and URL are both UTF-8.
+    #[must_use]
+    pub fn is_utf8(&self) -> bool {
+        str::from_utf8(&self.name).is_ok() && str::from_utf8(&self.url).is_ok()
+    }
+}
+
+/// Get the remotes of a repository with their URLs.
+///
+/// The URL is empty if a remote doesn’t have one. libgit2 can’t open remotes
+/// with non-UTF-8 names, so their URLs are read from the git configuration
+/// directly. That means `url.<base>.insteadOf` isn’t applied to them, and
+/// they’re left out if they don’t have a URL.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn remote_urls(repo: &Repository) -> anyhow::Result<Vec<RemoteUrl>> {
+    let mut remotes = Vec::new();
+    let mut non_utf8 = Vec::new();
+    for name in repo.remotes()?.iter_bytes() {
+        if let Ok(utf8_name) = str::from_utf8(name) {
+            remotes.push(RemoteUrl {
+                name: name.to_owned(),
+                url: repo.find_remote(utf8_name)?.url_bytes().to_owned(),
+            });
+        } else {
+            non_utf8.push(name.to_owned());
+        }
+    }
+    if non_utf8.is_empty() {
+        return Ok(remotes);
+    }
+
+    let config = repo.config()?.snapshot()?;
+    let mut entries = config.entries(Some(r"^remote\..*\.url$"))?;
+    let mut urls = Vec::new();
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let name = entry
+            .name_bytes()
+            .strip_prefix(b"remote.")
+            .and_then(|name| name.strip_suffix(b".url"));
+        if let Some(name) = name
+            && non_utf8.iter().any(|non_utf8| non_utf8 == name)
+        {
+            // Later entries override earlier ones.
+            urls.retain(|remote: &RemoteUrl| remote.name != name);
+            urls.push(RemoteUrl {
+                name: name.to_owned(),
+                url: entry.value_bytes().to_owned(),
+            });
+        }
+    }
+    remotes.extend(urls);
+    Ok(remotes)
+}
+
+/// What kind of remotes a repository has.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RemoteKind {
+    /// No remotes at all.
+    None,
+
+    /// Only remotes that aren’t skipped.
+    Other,
+
+    /// At least one remote that matches [`RemotePatterns`].
+    Skipped,
+}
+
+impl fmt::Display for RemoteKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::None => "none",
+            Self::Other => "other",
+            Self::Skipped => "skipped",
+        })
+    }
+}
+
+/// Classify the remotes of a repository.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn remote_kind(
+    repo: &Repository,
+    skip: &RemotePatterns,
+) -> anyhow::Result<RemoteKind> {
+    let mut kind = RemoteKind::None;
+    for remote in remote_urls(repo)? {
+        if !remote.is_utf8() {
+            tracing::warn!(
+                repo = ?repo.path(),
+                remote = %String::from_utf8_lossy(&remote.name),
+                url = %String::from_utf8_lossy(&remote.url),
+                "Remote name or URL isn’t UTF-8; matching skip_remotes \
+                against its bytes",
+            );
+        }
+        if skip.matches_bytes(&remote.url) {
+            return Ok(RemoteKind::Skipped);
+        }
+        kind = RemoteKind::Other;
+    }
+    Ok(kind)
+}
+
+/// Describe everything that the result of [`scan_repo()`] depends on.
+///
+/// If the key for a repository hasn’t changed, neither have the scan results.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn scan_key(
+    repo: &Repository,
+    options: &ScanOptions,
+) -> anyhow::Result<String> {
+    let mut lines = if options.refs.is_empty() {
+        vec![format!("head {}", options.default_branch(repo)?)]
+    } else {
+        let mut lines: Vec<_> = options
+            .refs
+            .iter()
+            .map(|pattern| format!("refs {pattern}"))
+            .collect();
+        lines.extend(
+            options
+                .matching_refs(repo)?
+                .into_iter()
+                .map(|(name, oid)| format!("ref {name} {oid}")),
+        );
+        lines
+    };
+    if options.date != CommitDate::Author {
+        lines.push(format!("date {:?}", options.date));
+    }
+    if options.first_parent {
+        lines.push("first-parent".to_owned());
+    }
+    if options.merges != MergeCommits::Count {
+        lines.push(format!("merges {:?}", options.merges));
+    }
+    if let Some(since) = options.since {
+        lines.push(format!("since {since}"));
+    }
+    if let Some(until) = options.until {
+        lines.push(format!("until {until}"));
+    }
+    if let Some(cap) = options.commit_cap {
+        lines.push(format!("cap {cap}"));
+    }
+    lines.extend(
+        options
+            .paths
+            .include
+            .iter()
+            .map(|pattern| format!("path {pattern}")),
+    );
+    lines.extend(
+        options
+            .paths
+            .exclude
+            .iter()
+            .map(|pattern| format!("exclude-path {pattern}")),
+    );
+    if options.skip_remotes != RemotePatterns::default() {
+        lines.extend(
+            options
+                .skip_remotes
+                .0
+                .iter()
+                .map(|pattern| format!("skip {pattern}")),
+        );
+    }
+    if let Some(limit) = &options.limit {
+        lines.push(format!(
+            "limit {} {:?} {}",
+            limit.max_commits, limit.action, limit.lookback_days
+        ));
+        // The cutoff moves every day, so yesterday’s results are stale.
+        if limit.action == CommitLimitAction::Truncate {
+            lines.push(format!("cutoff {}", limit.cutoff_date()));
+        }
+    }
+    lines.extend(remote_urls(repo)?.into_iter().map(|remote| {
+        format!("remote {}", String::from_utf8_lossy(&remote.url))
+    }));
+    // Deepening a shallow clone adds history without changing any refs.
+    if repo.is_shallow() {
+        let shallow = std::fs::read_to_string(repo.commondir().join("shallow"))
+            .unwrap_or_default();
+        lines.extend(shallow.lines().map(|oid| format!("shallow {oid}")));
+    }
+    lines.extend(
+        options
+            .skip_remotes
+            .matching_remote_refs(repo)?
+            .into_iter()
+            .map(|(name, oid)| format!("hide {name} {oid}")),
+    );
+    let identity = options.identity.for_repo(repo)?;
+    lines.extend(identity.emails.iter().map(|email| format!("email {email}")));
+    lines.extend(identity.names.iter().map(|name| format!("name {name}")));
+    if identity.co_authors {
+        lines.push("co-authors".to_owned());
+    }
+    if identity.mailmap {
+        lines.push(format!("mailmap {}", mailmap_id(repo)?));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Identify the contents of a repository’s `.mailmap`.
+///
+/// This hashes `.mailmap` in the working directory, or finds it in `HEAD`
+/// for bare repositories. Mailmaps from the `mailmap.file` and `mailmap.blob`
+/// settings aren’t included. Returns the zero ID if there’s no mailmap.
 ///
 /// # Errors
 ///
-/// Returns an error if there was a problem with the repository. Returns
-/// `Ok(None)` if the remote HEAD could not be found.
-pub fn scan_repo_path<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<i64>> {
-    scan_repo(&Repository::open(path)?)
+/// Returns an error if `.mailmap` could not be read.
+fn mailmap_id(repo: &Repository) -> anyhow::Result<git2::Oid> {
+    if let Some(workdir) = repo.workdir() {
+        match std::fs::read(workdir.join(".mailmap")) {
+            Ok(contents) => {
+                Ok(git2::Oid::hash_object(git2::ObjectType::Blob, &contents)?)
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                Ok(git2::Oid::zero())
+            }
+            Err(error) => Err(error.into()),
+        }
+    } else {
+        Ok(repo
+            .revparse_single("HEAD:.mailmap")
+            .map_or_else(|_| git2::Oid::zero(), |object| object.id()))
+    }
 }
 
-/// Scan history of a repository and commit dates as seconds since 1970.
+/// Get the URL of the main remote of a repository.
+///
+/// This is `origin` if it exists, then `upstream`, then the first remote.
 ///
 /// # Errors
 ///
 /// Returns an error if there was a problem with the repository. Returns
-/// `Ok(None)` if the remote HEAD could not be found.
-pub fn scan_repo(repo: &Repository) -> anyhow::Result<Vec<i64>> {
-    let mut revwalk = repo.revwalk()?;
-    revwalk.set_sorting(git2::Sort::TIME)?;
-
-    let default_branch_oid = get_default_branch(repo)?;
-    revwalk.push(default_branch_oid)?;
-
-    for remote_name in repo.remotes()?.into_iter().flatten() {
-        let remote = repo.find_remote(remote_name)?;
-        if let Some(url) = remote.url()
-            && (url.starts_with("git@github.com:")
-                || url.starts_with("https://github.com/"))
-        {
-            // GitHub remote. Skip; any local commits are equivalent to branch
-            // commits on GitHub.
-            return Ok(Vec::new());
+/// `Ok(None)` if there are no remotes with UTF-8 URLs.
+pub fn remote_url(repo: &Repository) -> anyhow::Result<Option<String>> {
+    let remotes = repo.remotes()?;
+    let mut names: Vec<&str> = remotes.iter().flatten().collect();
+    names.sort_by_key(|name| match *name {
+        "origin" => 0,
+        "upstream" => 1,
+        _ => 2,
+    });
+    for name in names {
+        if let Some(url) = repo.find_remote(name)?.url() {
+            return Ok(Some(url.to_owned()));
         }
-        // FIXME warn about non UTF-8?
     }
-
-    revwalk
-        .map(|oid| {
-            oid.and_then(|oid| repo.find_commit(oid))
-                .map(|commit| commit.author().when().seconds())
-                .map_err(anyhow::Error::from)
-        })
-        .collect()
+    Ok(None)
 }
 
 /// Find the default branch of a repository.
@@ -63,47 +1164,218 @@ pub fn scan_repo(repo: &Repository) -> anyhow::Result<Vec<i64>> {
 ///
 ///   1. `refs/remotes/origin/HEAD` to see if it points to a remote branch
 ///   2. `refs/remotes/upstream/HEAD`?
-///   3. Check if `$(git config init.defaultBranch)` is a branch
-///   4. Check if `main` is a branch.
-///   5. Check if `master` is a branch.
-///   6. Return `HEAD`
+///   3. Check if `remote_head` is a branch. This is the branch that `HEAD`
+///      points to on `origin`, usually found with [`ls_remote_head()`].
+///   4. Check if `$(git config init.defaultBranch)` is a branch
+///   5. Check if `main` is a branch.
+///   6. Check if `master` is a branch.
+///   7. Return `HEAD`
 ///
 /// # Errors
 ///
 /// Returns an error if there was a problem with the repository. Returns
 /// `Ok(None)` if the remote HEAD could not be found.
-pub fn get_default_branch(repo: &Repository) -> anyhow::Result<Oid> {
+pub fn get_default_branch(
+    repo: &Repository,
+    remote_head: Option<&str>,
+) -> anyhow::Result<Oid> {
+    find_default_branch(repo, remote_head).map(|(_, oid)| oid)
+}
+
+/// Find the name of the default branch of a repository along with the commit
+/// it points to.
+///
+/// See [`get_default_branch()`] for how the branch is chosen. The name is
+/// `HEAD` if no branch was found.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn find_default_branch(
+    repo: &Repository,
+    remote_head: Option<&str>,
+) -> anyhow::Result<(String, Oid)> {
+    let found = |branch: &str| -> anyhow::Result<Option<(String, Oid)>> {
+        let name = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+        Ok(ref_to_oid(repo, branch)?.map(|oid| (name.to_owned(), oid)))
+    };
+
     if let Some(branch) = remote_head_to_local_branch(repo, "origin")?
-        && let Some(oid) = ref_to_oid(repo, &branch)?
+        && let Some(found) = found(&branch)?
     {
-        return Ok(oid);
+        return Ok(found);
     }
 
     if let Some(branch) = remote_head_to_local_branch(repo, "upstream")?
-        && let Some(oid) = ref_to_oid(repo, &branch)?
+        && let Some(found) = found(&branch)?
+    {
+        return Ok(found);
+    }
+
+    if let Some(branch) = remote_head
+        && let Some(found) = found(branch)?
     {
-        return Ok(oid);
+        return Ok(found);
     }
 
     match repo.config()?.get_string("init.defaultBranch") {
         Ok(branch) => {
-            if let Some(oid) = ref_to_oid(repo, &branch)? {
-                return Ok(oid);
+            if let Some(found) = found(&branch)? {
+                return Ok(found);
             }
         }
         Err(error) if error.code() == ErrorCode::NotFound => {}
         Err(error) => return Err(error.into()),
     }
 
-    if let Some(oid) = ref_to_oid(repo, "refs/heads/main")? {
-        Ok(oid)
-    } else if let Some(oid) = ref_to_oid(repo, "refs/heads/master")? {
-        Ok(oid)
-    } else if let Some(oid) = ref_to_oid(repo, "HEAD")? {
-        Ok(oid)
-    } else {
-        Err(anyhow::anyhow!("Could not find a default branch"))
+    for branch in ["refs/heads/main", "refs/heads/master", "HEAD"] {
+        if let Some(found) = found(branch)? {
+            return Ok(found);
+        }
+    }
+    Err(anyhow::anyhow!("Could not find a default branch"))
+}
+
+/// Check if a repository knows which branch `HEAD` points to on a remote.
+///
+/// Plain clones usually have `refs/remotes/origin/HEAD`, but repositories
+/// that had a remote added later usually don’t.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem with the repository.
+pub fn has_remote_head(
+    repo: &Repository,
+    remote: &str,
+) -> anyhow::Result<bool> {
+    Ok(remote_head_to_local_branch(repo, remote)?.is_some())
+}
+
+/// Ask a remote which branch its `HEAD` points to.
+///
+/// This runs `git ls-remote --symref`, so it contacts the remote with the
+/// user’s usual git credentials. It never prompts for a password.
+///
+/// # Errors
+///
+/// Returns an error if `git` could not be run or if it failed, e.g. because
+/// the remote could not be reached. Returns `Ok(None)` if the remote didn’t
+/// report a branch for `HEAD`.
+pub fn ls_remote_head(
+    repo: &Repository,
+    remote: &str,
+) -> anyhow::Result<Option<String>> {
+    let output = process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["ls-remote", "--symref", "--", remote, "HEAD"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(process::Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git ls-remote {remote} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // Looks like "ref: refs/heads/main\tHEAD".
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix("ref: refs/heads/")?
+                .strip_suffix("\tHEAD")
+                .map(ToOwned::to_owned)
+        }))
+}
+
+/// Fetch all remotes of a repository, pruning refs that were deleted.
+///
+/// This runs `git fetch --all --prune`, so it uses the repository’s own
+/// refspecs; a mirror updates all of its refs. It never prompts for a
+/// password, and `git` is killed if it takes longer than `timeout`.
+///
+/// # Errors
+///
+/// Returns an error if `git` could not be run, if it failed, or if it timed
+/// out.
+pub fn fetch(repo: &Repository, timeout: Duration) -> anyhow::Result<()> {
+    let mut child = process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["fetch", "--all", "--prune", "--quiet"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+    // Read stderr as it comes so that git can’t block on a full pipe.
+    let stderr = child.stderr.take().map(|mut pipe| {
+        thread::spawn(move || {
+            let mut output = String::new();
+            pipe.read_to_string(&mut output).map(|_| output)
+        })
+    });
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            anyhow::bail!("git fetch timed out after {}s", timeout.as_secs());
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    let stderr = match stderr.map(thread::JoinHandle::join) {
+        Some(Ok(result)) => result?,
+        Some(Err(_)) => anyhow::bail!("Reading git fetch output panicked"),
+        None => String::new(),
+    };
+    if !status.success() {
+        anyhow::bail!("git fetch failed: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Check if a repository has a commit-graph file.
+///
+/// Git writes one during `git gc` and `git maintenance` if
+/// `gc.writeCommitGraph` is on (the default), so repositories that are never
+/// garbage collected usually don’t have one.
+#[must_use]
+pub fn has_commit_graph(repo: &Repository) -> bool {
+    let info = repo.commondir().join("objects").join("info");
+    info.join("commit-graph").is_file()
+        || info
+            .join("commit-graphs")
+            .join("commit-graph-chain")
+            .is_file()
+}
+
+/// Write a commit-graph file for everything reachable from a repository’s
+/// references.
+///
+/// This runs `git commit-graph write --reachable`.
+///
+/// # Errors
+///
+/// Returns an error if `git` could not be run or if it failed.
+pub fn write_commit_graph(repo: &Repository) -> anyhow::Result<()> {
+    let output = process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.commondir())
+        .args(["commit-graph", "write", "--reachable", "--no-progress"])
+        .stdin(process::Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git commit-graph write failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
     }
+    Ok(())
 }
 
 /// Get the branch name a remote HEAD points to.
@@ -154,6 +1426,7 @@ fn ref_to_oid(repo: &Repository, name: &str) -> anyhow::Result<Option<Oid>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::repos::DateRange;
     use crate::test::{FsDirectory, Home};
     use assert2::assert;
     use testdir::testdir;
@@ -168,7 +1441,7 @@ mod tests {
         let home = Home::init(testdir!());
         let repo = home.git_init("repo");
         repo.make_commit(0);
-        assert!(let Ok([_]) = scan_repo_path(repo.path()).as_deref());
+        assert!(let Ok([_]) = scan_repo_path(repo.path(), &ScanOptions::default()).as_deref());
     }
 
     #[test]
@@ -176,7 +1449,124 @@ mod tests {
         let home = Home::init(testdir!());
         let repo = home.git_init("repo");
         repo.make_commit(0);
-        assert!(let Ok([_]) = scan_repo_path(repo.path().join(".git")).as_deref());
+        assert!(let Ok([_]) = scan_repo_path(repo.path().join(".git"), &ScanOptions::default()).as_deref());
+    }
+
+    #[test]
+    fn scan_repo_identity() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.write("a", "other");
+        repo.git(["commit", "-am", "other", "--author", "O <o@example.com>"]);
+
+        let me = ScanOptions::for_identity(Identity {
+            emails: vec!["NAME@example.com".to_owned()],
+            names: vec![],
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        });
+        assert!(let Ok([_]) = scan_repo_path(repo.path(), &me).as_deref());
+
+        let both = ScanOptions::for_identity(Identity {
+            emails: vec!["name@example.com".to_owned()],
+            names: vec!["O".to_owned()],
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        });
+        assert!(let Ok([_, _]) = scan_repo_path(repo.path(), &both).as_deref());
+    }
+
+    #[test]
+    fn scan_repo_identities() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.make_commit(1);
+        repo.write("a", "other");
+        repo.git(["commit", "-am", "other", "--author", "O <o@example.com>"]);
+        let identity = |email: &str| Identity {
+            emails: vec![email.to_owned()],
+            names: vec![],
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        };
+        let identities = BTreeMap::from([
+            ("me".to_owned(), identity("name@example.com")),
+            ("other".to_owned(), identity("o@example.com")),
+            ("nobody".to_owned(), identity("nobody@example.com")),
+        ]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let mut options = ScanOptions::default();
+        let counts = |options: &ScanOptions| -> Vec<(String, usize)> {
+            super::scan_repo_identities(&repository, options, &identities)
+                .unwrap()
+                .into_iter()
+                .map(|(label, times)| (label, times.len()))
+                .collect()
+        };
+        assert!(
+            counts(&options) == [("me".to_owned(), 2), ("other".to_owned(), 1)]
+        );
+
+        options.commit_cap = Some(1);
+        assert!(
+            counts(&options) == [("me".to_owned(), 1), ("other".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn scan_repo_mailmap() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.write("a", "old");
+        repo.git(["commit", "-am", "old", "--author", "O <old@example.com>"]);
+        let repository = Repository::open(repo.path()).unwrap();
+
+        let mut me = ScanOptions::for_identity(Identity {
+            emails: vec!["name@example.com".to_owned()],
+            mailmap: true,
+            ..Identity::default()
+        });
+        assert!(let Ok([_]) = super::scan_repo(&repository, &me).as_deref());
+        let key = scan_key(&repository, &me).unwrap();
+
+        repo.write(".mailmap", "<name@example.com> <old@example.com>\n");
+        assert!(let Ok([_, _]) = super::scan_repo(&repository, &me).as_deref());
+        assert!(scan_key(&repository, &me).unwrap() != key);
+
+        me.identity.mailmap = false;
+        assert!(let Ok([_]) = super::scan_repo(&repository, &me).as_deref());
+    }
+
+    #[test]
+    fn scan_repo_identity_from_repo_config() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.git(["config", "user.email", "work@example.com"]);
+        repo.write("a", "work");
+        repo.git(["commit", "-am", "work"]);
+
+        let mut me = ScanOptions::for_identity(Identity {
+            emails: vec!["name@example.com".to_owned()],
+            names: vec![],
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        });
+        assert!(let Ok([_]) = scan_repo_path(repo.path(), &me).as_deref());
+
+        me.identity.repo_config = true;
+        assert!(let Ok([_, _]) = scan_repo_path(repo.path(), &me).as_deref());
     }
 
     #[test]
@@ -189,7 +1579,7 @@ mod tests {
         repo.git(["commit", "-m", "commit 0"]);
 
         // FIXME check error code.
-        assert!(let Err(_) = scan_repo_path(repo.join("dir")).as_deref());
+        assert!(let Err(_) = scan_repo_path(repo.join("dir"), &ScanOptions::default()).as_deref());
     }
 
     #[test]
@@ -199,7 +1589,7 @@ mod tests {
         repo.make_commit(0);
 
         // FIXME check error code.
-        assert!(let Err(_) = scan_repo_path(home.path()).as_deref());
+        assert!(let Err(_) = scan_repo_path(home.path(), &ScanOptions::default()).as_deref());
     }
 
     #[test]
@@ -210,6 +1600,522 @@ mod tests {
         repo.make_commit(0);
         repo.git(["push"]);
 
-        assert!(let Ok([_]) = scan_repo_path(bare_repo.path()).as_deref());
+        assert!(let Ok([_]) = scan_repo_path(bare_repo.path(), &ScanOptions::default()).as_deref());
+    }
+
+    #[test]
+    fn remote_patterns() {
+        let github = RemotePatterns::default();
+        assert!(github.matches("git@github.com:u/repo.git"));
+        assert!(github.matches("https://github.com/u/repo"));
+        assert!(github.matches("ssh://git@github.com/u/repo.git"));
+        assert!(!github.matches("https://gitlab.com/u/repo"));
+        assert!(!github.matches("https://github.com.example.com/u/repo"));
+
+        let forges = RemotePatterns(vec![
+            "gitea.example.com/*".to_owned(),
+            "git?.example.com/team/*".to_owned(),
+        ]);
+        assert!(forges.matches("git@gitea.example.com:u/repo.git"));
+        assert!(forges.matches("https://git1.example.com/team/repo"));
+        assert!(!forges.matches("https://git1.example.com/other/repo"));
+        assert!(!forges.matches("git@github.com:u/repo.git"));
+
+        assert!(!RemotePatterns(Vec::new()).matches("git@github.com:u/r"));
+    }
+
+    #[test]
+    fn remote_kinds() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        let open = || Repository::open(repo.path()).unwrap();
+        let github = RemotePatterns::default();
+        let kind = |skip| remote_kind(&open(), skip).unwrap();
+        assert!(kind(&github) == RemoteKind::None);
+
+        repo.git(["remote", "add", "origin", "git@example.com:repo.git"]);
+        assert!(kind(&github) == RemoteKind::Other);
+        let example = RemotePatterns(vec!["example.com/*".to_owned()]);
+        assert!(kind(&example) == RemoteKind::Skipped);
+
+        repo.git(["remote", "add", "gh", "https://github.com/u/repo.git"]);
+        assert!(kind(&github) == RemoteKind::Skipped);
+        assert!(kind(&RemotePatterns(Vec::new())) == RemoteKind::Other);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn remote_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.make_commit(1);
+        let config = repo.join(".git/config");
+        let mut contents = std::fs::read(&config).unwrap();
+        contents.extend(b"[remote \"\xff\"]\n\turl = git@github.com:u/\xff\n");
+        std::fs::write(&config, contents).unwrap();
+        repo.git([
+            OsStr::new("update-ref"),
+            OsStr::from_bytes(b"refs/remotes/\xff/main"),
+            OsStr::new("HEAD~"),
+        ]);
+        let repository = Repository::open(repo.path()).unwrap();
+
+        assert!(
+            remote_urls(&repository).unwrap()
+                == [RemoteUrl {
+                    name: b"\xff".to_vec(),
+                    url: b"git@github.com:u/\xff".to_vec(),
+                }]
+        );
+        let github = RemotePatterns::default();
+        assert!(
+            remote_kind(&repository, &github).unwrap() == RemoteKind::Skipped
+        );
+        let options = ScanOptions::default();
+        assert!(let Ok([_]) = super::scan_repo(&repository, &options).as_deref());
+    }
+
+    #[test]
+    fn scan_repo_unpushed() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.make_commit(1);
+        repo.git(["remote", "add", "origin", "git@github.com:u/repo.git"]);
+        repo.git(["update-ref", "refs/remotes/origin/main", "HEAD~"]);
+        repo.make_commit(2);
+        let repository = Repository::open(repo.path()).unwrap();
+        let scan = |skip_remotes| {
+            let options =
+                ScanOptions { skip_remotes, ..ScanOptions::default() };
+            let key = scan_key(&repository, &options).unwrap();
+            (super::scan_repo(&repository, &options).unwrap().len(), key)
+        };
+
+        let (count, key) = scan(RemotePatterns::default());
+        assert!(count == 2);
+        assert!(scan(RemotePatterns(Vec::new())).0 == 3);
+
+        // Pushing changes the results, so it has to change the key too.
+        repo.git(["update-ref", "refs/remotes/origin/main", "HEAD"]);
+        let (count, pushed_key) = scan(RemotePatterns::default());
+        assert!(count == 0);
+        assert!(pushed_key != key);
+    }
+
+    #[test]
+    fn scan_repo_dangling_remote_head() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.make_commit(1);
+        repo.git(["remote", "add", "origin", "git@github.com:u/repo.git"]);
+        repo.git(["update-ref", "refs/remotes/origin/main", "HEAD~"]);
+        repo.git([
+            "symbolic-ref",
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/gone",
+        ]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let options = ScanOptions::default();
+        let key = scan_key(&repository, &options).unwrap();
+        assert!(key.contains("hide refs/remotes/origin/main "));
+        assert!(!key.contains("refs/remotes/origin/HEAD"));
+        assert!(super::scan_repo(&repository, &options).unwrap().len() == 1);
+    }
+
+    #[test]
+    fn scan_repo_commit_limit() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.write("a", "old");
+        repo.git(["add", "a"]);
+        repo.git(["commit", "-m", "old", "--date", "2000-01-01T00:00:00Z"]);
+        repo.make_commit(1);
+        let repository = Repository::open(repo.path()).unwrap();
+        let scan = |limit: &CommitLimit| {
+            let options = ScanOptions {
+                limit: Some(limit.clone()),
+                ..ScanOptions::default()
+            };
+            super::scan_repo(&repository, &options)
+        };
+
+        let mut limit = CommitLimit {
+            max_commits: 2,
+            action: CommitLimitAction::Skip,
+            lookback_days: 365,
+        };
+        assert!(let Ok([_, _]) = scan(&limit).as_deref());
+
+        limit.max_commits = 1;
+        assert!(let Err(_) = scan(&limit));
+
+        limit.action = CommitLimitAction::Truncate;
+        let times = scan(&limit).unwrap();
+        assert!(times.len() == 1);
+        assert!(times.first() > Some(&946_684_800));
+
+        let options = ScanOptions {
+            limit: Some(limit.clone()),
+            ..ScanOptions::default()
+        };
+        let key = scan_key(&repository, &options).unwrap();
+        assert!(key.contains(&format!("cutoff {}", limit.cutoff_date())));
+        limit.lookback_days = 1;
+        let options =
+            ScanOptions { limit: Some(limit), ..ScanOptions::default() };
+        assert!(scan_key(&repository, &options).unwrap() != key);
+    }
+
+    #[test]
+    fn scan_repo_commit_date() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.write("a", "rebased");
+        repo.git(["add", "a"]);
+        repo.git(["commit", "-m", "rebased", "--date", "2000-01-01T00:00:00Z"]);
+        let repository = Repository::open(repo.path()).unwrap();
+
+        let mut options = ScanOptions::default();
+        assert!(
+            super::scan_repo(&repository, &options).unwrap() == [946_684_800]
+        );
+
+        options.date = CommitDate::Committer;
+        let times = super::scan_repo(&repository, &options).unwrap();
+        assert!(times.first() > Some(&946_684_800));
+        assert!(
+            scan_key(&repository, &options).unwrap()
+                != scan_key(&repository, &ScanOptions::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn scan_repo_merges() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.git(["switch", "-c", "deps"]);
+        repo.make_commit(1);
+        repo.git(["switch", "main"]);
+        repo.git(["merge", "--no-ff", "-m", "merge", "deps"]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let count = |first_parent, merges| {
+            let options =
+                ScanOptions { first_parent, merges, ..ScanOptions::default() };
+            super::scan_repo(&repository, &options).unwrap().len()
+        };
+
+        assert!(count(false, MergeCommits::Count) == 3);
+        assert!(count(true, MergeCommits::Count) == 2);
+        assert!(count(false, MergeCommits::Skip) == 2);
+        assert!(count(true, MergeCommits::Skip) == 1);
+    }
+
+    #[test]
+    fn scan_repo_since_until() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        for year in ["2020", "2021", "2022"] {
+            repo.write("a", year);
+            repo.git(["add", "a"]);
+            let date = format!("{year}-06-01T00:00:00Z");
+            repo.git(["commit", "-m", year, "--date", &date]);
+        }
+        // Walks are sorted by commit date.
+        repo.git(["rebase", "--root", "--committer-date-is-author-date"]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let year = |year: &str| year.parse::<DateRange>().unwrap();
+        let scan = |since: Option<i64>, until: Option<i64>| {
+            let options =
+                ScanOptions { since, until, ..ScanOptions::default() };
+            super::scan_repo(&repository, &options).unwrap().len()
+        };
+
+        assert!(scan(None, None) == 3);
+        assert!(scan(Some(year("2021").start_timestamp()), None) == 2);
+        assert!(scan(None, Some(year("2021").end_timestamp())) == 2);
+        let range = year("2021");
+        assert!(
+            scan(Some(range.start_timestamp()), Some(range.end_timestamp()))
+                == 1
+        );
+    }
+
+    #[test]
+    fn scan_repo_shallow() {
+        let home = Home::init(testdir!());
+        let origin = home.git_init("origin");
+        origin.make_commit(0);
+        origin.make_commit(1);
+        let url = format!("file://{}", origin.path().display());
+        home.git(home.path(), ["clone", "--depth", "1", &url, "repo"]);
+        let repo = home.join("repo");
+        let options = ScanOptions::default();
+        let open = || Repository::open(&repo).unwrap();
+
+        assert!(open().is_shallow());
+        assert!(let Ok([_]) = super::scan_repo(&open(), &options).as_deref());
+        let key = scan_key(&open(), &options).unwrap();
+
+        home.git(&repo, ["fetch", "--unshallow"]);
+        assert!(let Ok([_, _]) = super::scan_repo(&open(), &options).as_deref());
+        assert!(scan_key(&open(), &options).unwrap() != key);
+    }
+
+    #[test]
+    fn commit_graph() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.make_commit(1);
+        let repository = Repository::open(repo.path()).unwrap();
+        let options = ScanOptions::default();
+
+        assert!(!has_commit_graph(&repository));
+        write_commit_graph(&repository).unwrap();
+        assert!(has_commit_graph(&repository));
+        assert!(let Ok([_, _]) = super::scan_repo(&repository, &options).as_deref());
+    }
+
+    #[test]
+    fn scan_repo_summaries() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.write("a", "changed");
+        repo.git(["commit", "-am", "Change a\n\nBecause."]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let options = ScanOptions::default();
+
+        let summaries =
+            super::scan_repo_summaries(&repository, &options, false).unwrap();
+        let summary_lines: Vec<&str> = summaries
+            .iter()
+            .map(|commit| commit.summary.as_str())
+            .collect();
+        assert!(summary_lines == ["Change a", "commit 0"]);
+        let commits = scan_repo_commits(&repository, &options).unwrap();
+        assert!(summaries[0].oid == commits[0].oid);
+        assert!(summaries[0].time == commits[0].time);
+        assert!(summaries[0].signature.is_none());
+    }
+
+    #[test]
+    fn scan_repo_commit_cap() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        for n in 0..3 {
+            repo.make_commit(n);
+        }
+        let repository = Repository::open(repo.path()).unwrap();
+        let options =
+            ScanOptions { commit_cap: Some(2), ..ScanOptions::default() };
+        let all =
+            super::scan_repo(&repository, &ScanOptions::default()).unwrap();
+        let capped = super::scan_repo(&repository, &options).unwrap();
+        assert!(capped == all[..2]);
+        assert!(options.is_capped(capped.len()));
+
+        let options =
+            ScanOptions { commit_cap: Some(0), ..ScanOptions::default() };
+        assert!(super::scan_repo(&repository, &options).unwrap().is_empty());
+        assert!(scan_repo_commits(&repository, &options).unwrap().is_empty());
+    }
+
+    #[test]
+    fn default_branch_from_ls_remote() {
+        let home = Home::init(testdir!());
+        let bare_repo = home.git_init_bare("bare_repo");
+        let repo = bare_repo.clone("repo");
+        repo.make_commit(0);
+        repo.git(["push", "origin", "main"]);
+        repo.git(["switch", "-c", "trunk"]);
+        repo.make_commit(1);
+        repo.git(["push", "origin", "trunk"]);
+        home.git(
+            bare_repo.path(),
+            ["symbolic-ref", "HEAD", "refs/heads/trunk"],
+        );
+
+        let clone = bare_repo.clone("clone");
+        clone.git(["branch", "main", "origin/main"]);
+        clone.git(["remote", "set-head", "origin", "--delete"]);
+        let repository = Repository::open(clone.path()).unwrap();
+        let main = ref_to_oid(&repository, "main").unwrap();
+        let trunk = ref_to_oid(&repository, "trunk").unwrap();
+        assert!(!has_remote_head(&repository, "origin").unwrap());
+        assert!(Some(get_default_branch(&repository, None).unwrap()) == main);
+        assert!(
+            find_default_branch(&repository, None).unwrap()
+                == ("main".to_owned(), main.unwrap())
+        );
+
+        let head = ls_remote_head(&repository, "origin").unwrap();
+        assert!(head.as_deref() == Some("trunk"));
+        assert!(
+            Some(get_default_branch(&repository, head.as_deref()).unwrap())
+                == trunk
+        );
+
+        assert!(let Err(_) = ls_remote_head(&repository, "missing"));
+    }
+
+    #[test]
+    fn unmerged_commits() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        let repository = Repository::open(repo.path()).unwrap();
+        let options = ScanOptions::default();
+        let unmerged =
+            || scan_unmerged_commits(&repository, &options).unwrap().len();
+        assert!(unmerged() == 0);
+
+        repo.git(["switch", "-c", "feature"]);
+        repo.make_commit(1);
+        repo.make_commit(2);
+        assert!(unmerged() == 2);
+
+        repo.git(["switch", "main"]);
+        repo.git(["merge", "--ff-only", "feature"]);
+        assert!(unmerged() == 0);
+    }
+
+    #[test]
+    fn reflog_activity() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.make_commit(1);
+        repo.git(["commit", "--amend", "-m", "amended"]);
+        repo.git(["-c", "user.email=o@example.com", "reset", "HEAD^"]);
+        let repository = Repository::open(repo.path()).unwrap();
+
+        let mut me = ScanOptions::for_identity(Identity {
+            emails: vec!["name@example.com".to_owned()],
+            names: vec![],
+            repo_config: false,
+            github: None,
+            co_authors: false,
+            mailmap: false,
+        });
+        let reflog = |options: &ScanOptions| {
+            scan_reflog(&repository, options).unwrap().len()
+        };
+        assert!(reflog(&me) == 3);
+        assert!(reflog(&ScanOptions::default()) == 4);
+
+        me.since = Some(i64::MAX);
+        assert!(reflog(&me) == 0);
+    }
+
+    #[test]
+    fn scan_repo_refs() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.git(["switch", "-c", "release/1"]);
+        repo.make_commit(1);
+        repo.git(["tag", "-a", "v1", "-m", "v1"]);
+        repo.make_commit(2);
+        repo.git(["switch", "-c", "other", "main"]);
+        repo.make_commit(3);
+        let repository = Repository::open(repo.path()).unwrap();
+        let mut options = ScanOptions::default();
+        assert!(let Ok([_]) = super::scan_repo(&repository, &options).as_deref());
+        let key = scan_key(&repository, &options).unwrap();
+
+        options.refs = vec!["refs/tags/v*".to_owned()];
+        assert!(let Ok([_, _]) = super::scan_repo(&repository, &options).as_deref());
+        assert!(scan_key(&repository, &options).unwrap() != key);
+
+        options.refs.push("refs/heads/release/*".to_owned());
+        assert!(let Ok([_, _, _]) = super::scan_repo(&repository, &options).as_deref());
+        assert!(
+            scan_unmerged_commits(&repository, &options).unwrap().len() == 1
+        );
+
+        options.refs = vec!["refs/heads/missing".to_owned()];
+        assert!(let Ok([]) = super::scan_repo(&repository, &options).as_deref());
+    }
+
+    #[test]
+    fn scan_repo_paths() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.write("vendor/lib", "lib");
+        repo.git(["add", "vendor"]);
+        repo.git(["commit", "-m", "vendor"]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let mut options = ScanOptions::default();
+        let key = scan_key(&repository, &options).unwrap();
+
+        options.paths.exclude = vec!["vendor/".to_owned()];
+        assert!(let Ok([_]) = super::scan_repo(&repository, &options).as_deref());
+        assert!(scan_key(&repository, &options).unwrap() != key);
+
+        options.paths.include = vec!["b".to_owned()];
+        assert!(let Ok([_]) = super::scan_repo(&repository, &options).as_deref());
+
+        repo.git(["switch", "-c", "feature"]);
+        repo.write("vendor/lib", "changed");
+        repo.git(["commit", "-am", "vendor"]);
+        assert!(
+            scan_unmerged_commits(&repository, &options)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn unmerged_commits_duplicate_patches() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.git(["switch", "-c", "feature"]);
+        repo.make_commit(1);
+        repo.make_commit(2);
+        repo.git(["switch", "-c", "copy", "main"]);
+        repo.git(["cherry-pick", "-x", "main..feature"]);
+        repo.git(["switch", "main"]);
+        let repository = Repository::open(repo.path()).unwrap();
+        let mut options = ScanOptions::default();
+        let unmerged = |options: &ScanOptions| {
+            scan_unmerged_commits(&repository, options).unwrap().len()
+        };
+        assert!(unmerged(&options) == 4);
+
+        options.duplicate_patches = PatchDuplicates::Skip;
+        assert!(unmerged(&options) == 2);
+
+        repo.git(["cherry-pick", "-x", "feature~"]);
+        assert!(unmerged(&options) == 1);
+    }
+
+    #[test]
+    fn fetch_failure() {
+        let home = Home::init(testdir!());
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        let missing = home.join("missing");
+        repo.git([
+            "remote".as_ref(),
+            "add".as_ref(),
+            "origin".as_ref(),
+            missing.as_os_str(),
+        ]);
+        let repository = Repository::open(repo.path()).unwrap();
+
+        let error = fetch(&repository, Duration::from_secs(60)).unwrap_err();
+        assert!(error.to_string().starts_with("git fetch failed: "));
+        assert!(error.to_string().contains("missing"));
     }
 }
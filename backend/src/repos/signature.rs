@@ -0,0 +1,161 @@
+//! Check whether commits are signed.
+
+use git2::{ErrorCode, Repository};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::process;
+
+/// The signature on a commit.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct CommitSignature {
+    /// What kind of signature it is.
+    pub format: SignatureFormat,
+
+    /// Whether `git verify-commit` accepted the signature, if it was checked.
+    ///
+    /// This depends on the keys that git is configured to trust, e.g. the GPG
+    /// keyring or `gpg.ssh.allowedSignersFile`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+}
+
+/// Kinds of commit signatures.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureFormat {
+    /// OpenPGP, e.g. from GnuPG.
+    Gpg,
+
+    /// SSH.
+    Ssh,
+
+    /// X.509, e.g. from gpgsm.
+    X509,
+
+    /// Something else.
+    Unknown,
+}
+
+impl SignatureFormat {
+    /// Figure out the format of an armored signature.
+    #[must_use]
+    pub fn detect(signature: &[u8]) -> Self {
+        if signature.starts_with(b"-----BEGIN PGP SIGNATURE-----") {
+            Self::Gpg
+        } else if signature.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+            Self::Ssh
+        } else if signature.starts_with(b"-----BEGIN SIGNED MESSAGE-----") {
+            Self::X509
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+impl CommitSignature {
+    /// Read the signature on `commit`.
+    ///
+    /// Returns `Ok(None)` if the commit isn’t signed. If `verify` is set, this
+    /// runs `git verify-commit` to check the signature.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem with the repository, or if
+    /// `git` couldn’t be run.
+    pub fn read(
+        repo: &Repository,
+        commit: &git2::Commit<'_>,
+        verify: bool,
+    ) -> anyhow::Result<Option<Self>> {
+        let signature = match repo.extract_signature(&commit.id(), None) {
+            Ok((signature, _)) => signature,
+            Err(error) if error.code() == ErrorCode::NotFound => {
+                return Ok(None);
+            }
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Some(Self {
+            format: SignatureFormat::detect(&signature),
+            verified: if verify {
+                Some(verify_commit(repo, commit.id())?)
+            } else {
+                None
+            },
+        }))
+    }
+}
+
+/// Check the signature on a commit with `git verify-commit`.
+///
+/// # Errors
+///
+/// Returns an error if `git` couldn’t be run.
+pub fn verify_commit(
+    repo: &Repository,
+    oid: git2::Oid,
+) -> anyhow::Result<bool> {
+    let output = process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["verify-commit", "--"])
+        .arg(oid.to_string())
+        .stdin(process::Stdio::null())
+        .output()?;
+    Ok(output.status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FsDirectory, Home};
+    use assert2::assert;
+    use std::fs;
+    use testdir::testdir;
+
+    #[test]
+    fn ssh_signature() {
+        let home = Home::init(testdir!());
+        let key = home.join("key");
+        let status = process::Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+            .arg(&key)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let repo = home.git_init("repo");
+        repo.make_commit(0);
+        repo.git(["config", "gpg.format", "ssh"]);
+        repo.git(["config", "user.signingkey", key.to_str().unwrap()]);
+        repo.write("a", "signed");
+        repo.git(["commit", "-S", "-am", "signed"]);
+
+        let repository = Repository::open(repo.path()).unwrap();
+        let head = repository.head().unwrap().peel_to_commit().unwrap();
+        let parent = head.parent(0).unwrap();
+        assert!(let Ok(None) = CommitSignature::read(&repository, &parent, true));
+        assert!(
+            CommitSignature::read(&repository, &head, false).unwrap()
+                == Some(CommitSignature {
+                    format: SignatureFormat::Ssh,
+                    verified: None,
+                })
+        );
+
+        // Nothing is trusted yet.
+        let read = || CommitSignature::read(&repository, &head, true).unwrap();
+        assert!(read().unwrap().verified == Some(false));
+
+        let public_key = fs::read_to_string(key.with_extension("pub")).unwrap();
+        let allowed = home.join("allowed_signers");
+        fs::write(&allowed, format!("name@example.com {public_key}")).unwrap();
+        repo.git([
+            "config",
+            "gpg.ssh.allowedSignersFile",
+            allowed.to_str().unwrap(),
+        ]);
+        assert!(read().unwrap().verified == Some(true));
+    }
+}
@@ -0,0 +1,168 @@
+//! Recurring jobs.
+
+use chrono::{DateTime, Datelike, Days, NaiveTime, TimeZone, Weekday};
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+
+/// When a recurring job should run.
+///
+/// This is either a time every day, e.g. `20:00`, or a time on one day of the
+/// week, e.g. `mon 08:00`. Times are local.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Schedule {
+    /// Day of the week to run on, or `None` to run every day.
+    pub weekday: Option<Weekday>,
+
+    /// Time of day to run.
+    pub time: NaiveTime,
+}
+
+impl Schedule {
+    /// Find the next time this schedule fires strictly after `now`.
+    ///
+    /// Times that don’t exist because of a DST transition are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no time can be found within two weeks, which should not be
+    /// possible in any real time zone.
+    pub fn next_after<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> DateTime<Tz> {
+        let today = now.date_naive();
+        (0..15)
+            .filter_map(|offset| today.checked_add_days(Days::new(offset)))
+            .filter(|date| {
+                self.weekday.is_none_or(|weekday| date.weekday() == weekday)
+            })
+            .filter_map(|date| {
+                now.timezone()
+                    .from_local_datetime(&date.and_time(self.time))
+                    .earliest()
+            })
+            .find(|time| time > now)
+            .expect("schedule should fire within two weeks")
+    }
+
+    /// Run `job` every time this schedule fires, forever.
+    pub async fn run<F, Fut>(self, mut job: F)
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = ()> + Send,
+    {
+        loop {
+            let now = chrono::Local::now();
+            let wait = self
+                .next_after(&now)
+                .signed_duration_since(now)
+                .to_std()
+                .unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            job().await;
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ParseScheduleError;
+
+    /// Parse `HH:MM` or `DAY HH:MM`, e.g. `mon 08:00`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let error = || ParseScheduleError(input.to_owned());
+        let mut words = input.split_whitespace();
+        let (weekday, time) = match (words.next(), words.next(), words.next()) {
+            (Some(time), None, None) => (None, time),
+            (Some(weekday), Some(time), None) => {
+                (Some(weekday.parse().map_err(|_| error())?), time)
+            }
+            _ => return Err(error()),
+        };
+
+        Ok(Self {
+            weekday,
+            time: NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| error())?,
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(weekday) = self.weekday {
+            write!(f, "{weekday} ")?;
+        }
+        write!(f, "{}", self.time.format("%H:%M"))
+    }
+}
+
+/// Error returned when a [`Schedule`] can’t be parsed.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid schedule {0:?} (expected e.g. \"mon 08:00\" or \"20:00\")")]
+pub struct ParseScheduleError(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use chrono::Utc;
+
+    fn utc(input: &str) -> DateTime<Utc> {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_daily() {
+        assert!(
+            "20:00".parse::<Schedule>().unwrap()
+                == Schedule {
+                    weekday: None,
+                    time: NaiveTime::from_hms_opt(20, 0, 0).unwrap(),
+                }
+        );
+    }
+
+    #[test]
+    fn parse_weekly() {
+        assert!(
+            "monday 08:30".parse::<Schedule>().unwrap()
+                == Schedule {
+                    weekday: Some(Weekday::Mon),
+                    time: NaiveTime::from_hms_opt(8, 30, 0).unwrap(),
+                }
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(let Err(_) = "".parse::<Schedule>());
+        assert!(let Err(_) = "noon".parse::<Schedule>());
+        assert!(let Err(_) = "someday 08:00".parse::<Schedule>());
+        assert!(let Err(_) = "mon 08:00 extra".parse::<Schedule>());
+    }
+
+    #[test]
+    fn next_daily() {
+        let schedule: Schedule = "20:00".parse().unwrap();
+        assert!(
+            schedule.next_after(&utc("2024-03-05T19:59:59Z"))
+                == utc("2024-03-05T20:00:00Z")
+        );
+        assert!(
+            schedule.next_after(&utc("2024-03-05T20:00:00Z"))
+                == utc("2024-03-06T20:00:00Z")
+        );
+    }
+
+    #[test]
+    fn next_weekly() {
+        let schedule: Schedule = "mon 08:00".parse().unwrap();
+        // 2024-03-05 is a Tuesday.
+        assert!(
+            schedule.next_after(&utc("2024-03-05T12:00:00Z"))
+                == utc("2024-03-11T08:00:00Z")
+        );
+        assert!(
+            schedule.next_after(&utc("2024-03-11T07:00:00Z"))
+                == utc("2024-03-11T08:00:00Z")
+        );
+    }
+}
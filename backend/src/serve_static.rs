@@ -0,0 +1,229 @@
+//! Static file server for exported bundles.
+
+use anyhow::anyhow;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use dropshot::{
+    ApiDescription, Body, ConfigDropshot, HttpError, HttpServerStarter, Path,
+    RequestContext,
+};
+use http::{Response, StatusCode, header};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::path::PathBuf;
+use subtle::ConstantTimeEq;
+
+/// State for the static file server.
+struct StaticState {
+    /// Directory to serve files from.
+    root: PathBuf,
+
+    /// Required `user:password` for HTTP basic authentication, if any.
+    basic_auth: Option<String>,
+}
+
+/// Serve files from a directory.
+///
+/// # Errors
+///
+/// Returns an error if the bind address cannot be parsed, or if the server
+/// cannot be created or encounters an error during operation.
+#[tokio::main]
+pub async fn serve(
+    address: &str,
+    root: PathBuf,
+    basic_auth: Option<String>,
+    log: &slog::Logger,
+) -> anyhow::Result<()> {
+    let config_dropshot = ConfigDropshot {
+        bind_address: address
+            .parse()
+            .map_err(|error| anyhow!("Invalid bind address: {error}"))?,
+        default_request_body_max_bytes: 1024,
+        default_handler_task_mode: dropshot::HandlerTaskMode::Detached,
+        log_headers: vec![],
+    };
+
+    let mut api = ApiDescription::new();
+    api.register(static_file).map_err(|error| {
+        anyhow!("Failed to create API description: {error}")
+    })?;
+
+    tracing::info!("Serving {root:?} on http://{address}");
+    let state = StaticState { root, basic_auth };
+    HttpServerStarter::new(&config_dropshot, api, state, log)
+        .map_err(|error| anyhow!("Failed to create server: {error}"))?
+        .start()
+        .await
+        .map_err(|error| anyhow!("Server error: {error}"))
+}
+
+/// Path of a requested file.
+#[derive(Deserialize, JsonSchema)]
+struct FilePath {
+    /// Path components.
+    path: Vec<String>,
+}
+
+/// Serve a file.
+#[dropshot::endpoint {
+    method = GET,
+    path = "/{path:.*}",
+    unpublished = true,
+}]
+async fn static_file(
+    rqctx: RequestContext<StaticState>,
+    path: Path<FilePath>,
+) -> Result<Response<Body>, HttpError> {
+    let state = rqctx.context();
+    if let Some(expected) = &state.basic_auth
+        && !is_authorized(rqctx.request.headers(), expected)
+    {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(header::WWW_AUTHENTICATE, r#"Basic realm="RepoYear""#)
+            .body(Body::empty())
+            .map_err(|error| HttpError::for_internal_error(error.to_string()));
+    }
+
+    let components = path.into_inner().path;
+    let mut file = state.root.clone();
+    for component in &components {
+        if component == ".." || component.contains(['/', '\\']) {
+            return Err(not_found(component));
+        }
+        file.push(component);
+    }
+    if file.is_dir() {
+        file.push("index.html");
+    }
+
+    let contents = tokio::fs::read(&file)
+        .await
+        .map_err(|_| not_found(&file.display().to_string()))?;
+
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type(&name))
+        .header(header::CACHE_CONTROL, cache_control(&components.join("/")))
+        .body(contents.into())
+        .map_err(|error| HttpError::for_internal_error(error.to_string()))
+}
+
+/// Build a 404 error.
+fn not_found(path: &str) -> HttpError {
+    HttpError::for_not_found(None, format!("File not found: {path:?}"))
+}
+
+/// Check the `Authorization` header against `user:password`.
+///
+/// The credentials are compared in constant time.
+fn is_authorized(headers: &http::HeaderMap, expected: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+        .is_some_and(|decoded| decoded.ct_eq(expected.as_bytes()).into())
+}
+
+/// Guess the MIME type of a file from its name.
+fn content_type(name: &str) -> &'static str {
+    let extension =
+        name.rsplit_once('.').map_or("", |(_, extension)| extension);
+    match extension.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "txt" => "text/plain; charset=utf-8",
+        "wasm" => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Choose caching behavior for a file based on its path relative to the root.
+///
+/// Vite puts built assets in `assets/` and adds a content hash to their names,
+/// e.g. `assets/index-BvD3a8xZ.js`, so they never change and can be cached
+/// forever. Everything else, like `index.html` and data files, must be
+/// revalidated. Data files can also be in `assets/` (e.g. in exported
+/// bundles), so the hash must look like a hash: 8 characters, with at least
+/// one digit or uppercase letter.
+fn cache_control(path: &str) -> &'static str {
+    let Some(name) = path.strip_prefix("assets/") else {
+        return "no-cache";
+    };
+    let stem = name.split('.').next().unwrap_or_default();
+    let is_hashed = stem.rsplit_once('-').is_some_and(|(_, hash)| {
+        hash.len() == 8
+            && hash
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            && hash
+                .chars()
+                .any(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+    });
+
+    if is_hashed {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn content_types() {
+        assert!(content_type("index.html") == "text/html; charset=utf-8");
+        assert!(content_type("app.JS") == "text/javascript; charset=utf-8");
+        assert!(content_type("github.json") == "application/json");
+        assert!(content_type("LICENSE") == "application/octet-stream");
+    }
+
+    #[test]
+    fn cache_controls() {
+        assert!(
+            cache_control("assets/index-BvD3a8xZ.js")
+                == "public, max-age=31536000, immutable"
+        );
+        assert!(cache_control("index-BvD3a8xZ.js") == "no-cache");
+        assert!(cache_control("index.html") == "no-cache");
+        assert!(cache_control("github.json") == "no-cache");
+        assert!(cache_control("my-data.json") == "no-cache");
+        assert!(cache_control("github-contribs.json") == "no-cache");
+        assert!(cache_control("repoyear-snapshot.json") == "no-cache");
+        assert!(cache_control("team-overview.html") == "no-cache");
+        assert!(cache_control("assets/github-contribs.json") == "no-cache");
+        assert!(cache_control("assets/local.json") == "no-cache");
+    }
+
+    #[test]
+    fn basic_auth() {
+        let mut headers = http::HeaderMap::new();
+        assert!(!is_authorized(&headers, "user:pass"));
+
+        // "user:pass"
+        headers.insert(
+            header::AUTHORIZATION,
+            "Basic dXNlcjpwYXNz".parse().unwrap(),
+        );
+        assert!(is_authorized(&headers, "user:pass"));
+        assert!(!is_authorized(&headers, "user:other"));
+    }
+}
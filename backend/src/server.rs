@@ -1,9 +1,15 @@
 //! Server startup and configuration.
 
-use crate::repos;
+use crate::access_log::AccessLog;
 use anyhow::anyhow;
 use dropshot::{ConfigDropshot, HttpServerStarter};
-use repoyear_backend::api::{AppState, RepoYearApiImpl, repo_year_api_mod};
+use repoyear_backend::api::readiness::Readiness;
+use repoyear_backend::api::{
+    ApiBase, AppState, RepoYearApiImpl, repo_year_api_mod,
+};
+use repoyear_backend::notify::{Alert, Notifier, Summary};
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
 
 /// Start web server for API.
 ///
@@ -21,9 +27,10 @@ use repoyear_backend::api::{AppState, RepoYearApiImpl, repo_year_api_mod};
 #[tokio::main]
 pub async fn serve(
     address: &str,
-    github_client_id: &str,
-    github_client_secret: &str,
-    scan_config: Option<repos::Config>,
+    state: AppState,
+    notifier: Notifier,
+    warmup_timeout: Duration,
+    access_log: Option<AccessLog>,
     log: &slog::Logger,
 ) -> anyhow::Result<()> {
     let config_dropshot = ConfigDropshot {
@@ -32,28 +39,156 @@ pub async fn serve(
             .map_err(|error| anyhow!("Invalid bind address: {error}"))?,
         default_request_body_max_bytes: 1024,
         default_handler_task_mode: dropshot::HandlerTaskMode::Detached,
-        log_headers: vec![],
+        log_headers: access_log
+            .as_ref()
+            .map(AccessLog::headers)
+            .unwrap_or_default(),
     };
+    let log =
+        &access_log.map_or_else(|| log.clone(), |access| access.wrap(log));
 
     let api = repo_year_api_mod::api_description::<RepoYearApiImpl>().map_err(
         |error| anyhow!("Failed to create API description: {error}"),
     )?;
 
-    let state = AppState {
-        github_client_id: github_client_id.to_owned(),
-        github_client_secret: github_client_secret.to_owned(),
-        http_client: reqwest::Client::new(),
-        scan_config,
-    };
+    if state.scan_config.is_some() {
+        tokio::spawn(limit_warmup(state.readiness.clone(), warmup_timeout));
+    }
+    tokio::spawn(watch_repos(state.clone()));
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(state.clone()));
+
+    if notifier.is_enabled() {
+        tracing::info!(
+            "Sending summaries at {} and checking for alerts at {}",
+            notifier.summary_schedule,
+            notifier.check_schedule,
+        );
+        tokio::spawn(send_summaries(state.clone(), notifier.clone()));
+        tokio::spawn(send_alerts(state.clone(), notifier));
+    }
 
     let server = HttpServerStarter::new(&config_dropshot, api, state, log)
         .map_err(|error| anyhow!("Failed to create server: {error}"))?
         .start();
 
     let version = env!("GIT_VERSION");
-    slog::info!(log, "Server {version} running on http://{address}");
+    tracing::info!("Server {version} running on http://{address}");
 
     server
         .await
         .map_err(|error| anyhow!("Server error: {error}"))
 }
+
+/// Watch the configured repositories for changes, starting over whenever the
+/// scan configuration is reloaded.
+async fn watch_repos(state: AppState) {
+    let mut configs = state.scan_config.subscribe();
+    loop {
+        let config = configs.borrow_and_update().clone();
+        let watcher = config.and_then(|config| {
+            crate::watch::spawn(
+                config,
+                state.scan_cache.clone(),
+                state.scan_status.clone(),
+                state.contributions_updates.clone(),
+                state.readiness.clone(),
+            )
+            .inspect_err(|error| {
+                tracing::warn!(
+                    "Not watching repositories for changes: {error}"
+                );
+                tokio::spawn(warm_up(state.clone()));
+            })
+            .ok()
+        });
+
+        // This can’t fail, since `state` holds the sender.
+        let _ = configs.changed().await;
+        if let Some(watcher) = watcher {
+            watcher.abort();
+            // Wait for it to stop so that it can’t update the cache again.
+            let _ = watcher.await;
+        }
+        *state
+            .scan_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Reload the configuration whenever the process receives `SIGHUP`.
+#[cfg(unix)]
+async fn reload_on_hangup(state: AppState) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(error) => {
+            tracing::warn!("Can’t reload configuration on SIGHUP: {error}");
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        tracing::info!("Received SIGHUP; reloading configuration");
+        if let Err(error) = state.reload().await {
+            tracing::error!("Could not reload configuration: {error}");
+        }
+    }
+}
+
+/// Scan once so that the first request doesn’t have to, then mark the server
+/// ready.
+///
+/// This is only needed when the repositories aren’t being watched, since the
+/// watcher does its own initial scan. It still fills the disk cache.
+async fn warm_up(state: AppState) {
+    state.shared_scan().await;
+    state.readiness.set_ready();
+    tracing::info!("Initial scan finished");
+}
+
+/// Mark the server ready after `timeout` even if the initial scan hasn’t
+/// finished.
+async fn limit_warmup(readiness: Arc<Readiness>, timeout: Duration) {
+    if tokio::time::timeout(timeout, readiness.wait())
+        .await
+        .is_err()
+    {
+        readiness.set_ready();
+        tracing::warn!(
+            "Initial scan is taking longer than {}s; reporting ready anyway",
+            timeout.as_secs(),
+        );
+    }
+}
+
+/// Send a contribution summary every time the summary schedule fires.
+async fn send_summaries(state: AppState, notifier: Notifier) {
+    notifier
+        .summary_schedule
+        .run(|| async {
+            let contributions = state.get_contributions().await;
+            let summary = Summary::new(&contributions, &chrono::Local::now());
+            notifier
+                .send(&summary.subject(), &summary.to_string())
+                .await;
+        })
+        .await;
+}
+
+/// Check for alerts every time the check schedule fires.
+async fn send_alerts(state: AppState, notifier: Notifier) {
+    notifier
+        .check_schedule
+        .run(|| async {
+            let result = state.shared_scan().await;
+            let (contributions, errors) = &*result;
+            let summary = Summary::new(contributions, &chrono::Local::now());
+            let errors = errors.iter().map(ToString::to_string).collect();
+            for alert in Alert::check(&summary, errors) {
+                notifier.send(&alert.subject(), &alert.to_string()).await;
+            }
+        })
+        .await;
+}
@@ -0,0 +1,474 @@
+//! Summary statistics about contributions.
+
+use crate::api::definition::LocalContributions;
+use crate::repos::Calendar;
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeZone};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Default number of repositories in [`Statistics::top_repos`].
+pub const DEFAULT_TOP_REPOS: usize = 5;
+
+/// Default length of the rolling window in [`Velocity`], in days.
+pub const DEFAULT_VELOCITY_WINDOW: u32 = 28;
+
+/// Longest rolling window allowed in [`Velocity`], in days.
+pub const MAX_VELOCITY_WINDOW: u32 = 366;
+
+/// Number of days in [`Velocity::days`].
+pub const VELOCITY_DAYS: u32 = 365;
+
+/// Totals, streaks, and distributions computed from contributions.
+#[derive(Clone, Debug, Default, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct Statistics {
+    /// Total number of commits.
+    pub commits: usize,
+
+    /// Number of days with commits.
+    pub active_days: usize,
+
+    /// Consecutive days with commits ending today, or yesterday if there are
+    /// no commits yet today.
+    pub current_streak: Option<Streak>,
+
+    /// The longest run of consecutive days with commits. If there’s a tie,
+    /// this is the most recent.
+    pub longest_streak: Option<Streak>,
+
+    /// The day with the most commits. If there’s a tie, this is the most
+    /// recent.
+    pub busiest_day: Option<DayCount>,
+
+    /// Number of commits on each day of the week, starting with Sunday.
+    pub weekdays: [usize; 7],
+
+    /// Number of commits in each calendar year with commits, oldest first.
+    pub years: Vec<YearCount>,
+
+    /// Repositories with the most commits, most active first.
+    pub top_repos: Vec<RepoCount>,
+}
+
+/// A run of consecutive days with commits.
+#[derive(Clone, Debug, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct Streak {
+    /// First day (`YYYY-MM-DD`).
+    pub start: String,
+
+    /// Last day (`YYYY-MM-DD`).
+    pub end: String,
+
+    /// Number of days.
+    pub days: usize,
+}
+
+/// Number of commits on a day.
+#[derive(Clone, Debug, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct DayCount {
+    /// The day (`YYYY-MM-DD`).
+    pub date: String,
+
+    /// Number of commits.
+    pub commits: usize,
+}
+
+/// Number of commits in a calendar year.
+#[derive(Clone, Debug, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct YearCount {
+    /// The year.
+    pub year: i32,
+
+    /// Number of commits.
+    pub commits: usize,
+}
+
+/// Number of commits in a repository.
+#[derive(Clone, Debug, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct RepoCount {
+    /// Repository name.
+    pub repo: String,
+
+    /// Number of commits.
+    pub commits: usize,
+}
+
+impl Statistics {
+    /// Compute statistics for `contributions`.
+    ///
+    /// Days are calculated in the time zone of `now`. At most `top` repos are
+    /// included in [`top_repos`](Self::top_repos).
+    #[must_use]
+    pub fn new<Tz: TimeZone>(
+        contributions: &LocalContributions,
+        now: &DateTime<Tz>,
+        top: usize,
+    ) -> Self {
+        let calendar = Calendar::new(now.timezone());
+        let mut stats = Self::default();
+        let mut days: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+        for (repo, times) in contributions {
+            let dates: Vec<_> = times
+                .iter()
+                .filter_map(|&time| calendar.date(time))
+                .collect();
+            if dates.is_empty() {
+                continue;
+            }
+            for date in &dates {
+                let count = days.entry(*date).or_default();
+                *count = count.saturating_add(1);
+                let weekday = date.weekday().num_days_from_sunday();
+                if let Some(count) = stats.weekdays.get_mut(weekday as usize) {
+                    *count = count.saturating_add(1);
+                }
+            }
+            stats.commits = stats.commits.saturating_add(dates.len());
+            stats
+                .top_repos
+                .push(RepoCount { repo: repo.clone(), commits: dates.len() });
+        }
+
+        stats.top_repos.sort_by(|a, b| {
+            b.commits.cmp(&a.commits).then_with(|| a.repo.cmp(&b.repo))
+        });
+        stats.top_repos.truncate(top);
+
+        stats.active_days = days.len();
+        for (date, &commits) in &days {
+            match stats.years.last_mut() {
+                Some(last) if last.year == date.year() => {
+                    last.commits = last.commits.saturating_add(commits);
+                }
+                _ => stats.years.push(YearCount { year: date.year(), commits }),
+            }
+        }
+        stats.busiest_day = days
+            .iter()
+            .max_by_key(|&(date, commits)| (commits, date))
+            .map(|(date, &commits)| DayCount {
+                date: date.to_string(),
+                commits,
+            });
+
+        let streaks = streaks(days.keys().copied());
+        stats.longest_streak = streaks
+            .iter()
+            .max_by_key(|(start, end)| {
+                (end.signed_duration_since(*start), *end)
+            })
+            .map(|&(start, end)| Streak::new(start, end));
+
+        let today = now.date_naive();
+        stats.current_streak = streaks
+            .last()
+            .filter(|(_, end)| *end == today || end.succ_opt() == Some(today))
+            .map(|&(start, end)| Streak::new(start, end));
+
+        stats
+    }
+}
+
+impl Streak {
+    /// Create a streak from `start` to `end`, inclusive.
+    fn new(start: NaiveDate, end: NaiveDate) -> Self {
+        let days = usize::try_from(end.signed_duration_since(start).num_days())
+            .unwrap_or(0)
+            .saturating_add(1);
+        Self { start: start.to_string(), end: end.to_string(), days }
+    }
+}
+
+/// Rolling averages of commits per day.
+#[derive(Clone, Debug, JsonSchema, PartialEq, Serialize)]
+pub struct Velocity {
+    /// Length of the rolling window in days.
+    pub window: u32,
+
+    /// Every day for the past year, oldest first, ending today.
+    pub days: Vec<VelocityDay>,
+
+    /// Average commits per day in the window ending today.
+    pub current: f64,
+
+    /// Average commits per day in the window before that.
+    pub previous: f64,
+
+    /// Whether [`current`](Self::current) is higher or lower than
+    /// [`previous`](Self::previous).
+    pub trend: Trend,
+}
+
+/// Commits on one day, and the average over the window ending that day.
+#[derive(Clone, Debug, JsonSchema, PartialEq, Serialize)]
+pub struct VelocityDay {
+    /// The day (`YYYY-MM-DD`).
+    pub date: String,
+
+    /// Number of commits on the day.
+    pub commits: u32,
+
+    /// Average commits per day in the window ending on the day.
+    pub average: f64,
+}
+
+/// Direction of change between two windows.
+#[derive(Clone, Copy, Debug, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trend {
+    /// More commits than the previous window.
+    Up,
+
+    /// Fewer commits than the previous window.
+    Down,
+
+    /// The same number of commits as the previous window.
+    Flat,
+}
+
+impl Velocity {
+    /// Compute rolling averages over `window` days for `contributions`.
+    ///
+    /// Days are calculated in the time zone of `now`. `window` must be at
+    /// least 1.
+    #[must_use]
+    pub fn new<Tz: TimeZone>(
+        contributions: &LocalContributions,
+        now: &DateTime<Tz>,
+        window: u32,
+    ) -> Self {
+        let today = now.date_naive();
+        let counts = Calendar::new(now.timezone()).contributions(contributions);
+        let count = |date: NaiveDate| counts.get(&date).copied().unwrap_or(0);
+        let days_ago = |days: u32| {
+            today
+                .checked_sub_days(Days::new(days.into()))
+                .unwrap_or(today)
+        };
+
+        // Commits in the window ending on each day, oldest first. This covers
+        // an extra window before the first day so that the first day has a
+        // full window, and so that there is a previous window for the trend.
+        let first = days_ago(
+            VELOCITY_DAYS
+                .max(window)
+                .saturating_add(window)
+                .saturating_sub(1),
+        );
+        let mut sums = Vec::new();
+        let mut sum = 0_u32;
+        for (i, date) in first
+            .iter_days()
+            .take_while(|date| *date <= today)
+            .enumerate()
+        {
+            sum = sum.saturating_add(count(date));
+            if let Some(dropped) = i
+                .checked_sub(window as usize)
+                .and_then(|i| first.checked_add_days(Days::new(i as u64)))
+            {
+                sum = sum.saturating_sub(count(dropped));
+            }
+            sums.push((date, sum));
+        }
+
+        let average = |sum: u32| f64::from(sum) / f64::from(window);
+        let current = sums.last().map_or(0, |&(_, sum)| sum);
+        let previous = sums
+            .len()
+            .checked_sub((window as usize).saturating_add(1))
+            .and_then(|i| sums.get(i))
+            .map_or(0, |&(_, sum)| sum);
+        let skip = sums.len().saturating_sub(VELOCITY_DAYS as usize);
+        Self {
+            window,
+            days: sums
+                .into_iter()
+                .skip(skip)
+                .map(|(date, sum)| VelocityDay {
+                    date: date.to_string(),
+                    commits: count(date),
+                    average: average(sum),
+                })
+                .collect(),
+            current: average(current),
+            previous: average(previous),
+            trend: match current.cmp(&previous) {
+                std::cmp::Ordering::Greater => Trend::Up,
+                std::cmp::Ordering::Less => Trend::Down,
+                std::cmp::Ordering::Equal => Trend::Flat,
+            },
+        }
+    }
+}
+
+/// Parse a window length like `28d` or `4w` into a number of days.
+///
+/// Returns `None` if the input isn’t valid, or if the window is empty or
+/// longer than [`MAX_VELOCITY_WINDOW`].
+#[must_use]
+pub fn parse_window(input: &str) -> Option<u32> {
+    let (number, multiplier) = if let Some(number) = input.strip_suffix('w') {
+        (number, 7)
+    } else {
+        (input.strip_suffix('d').unwrap_or(input), 1)
+    };
+    number
+        .parse::<u32>()
+        .ok()?
+        .checked_mul(multiplier)
+        .filter(|days| (1..=MAX_VELOCITY_WINDOW).contains(days))
+}
+
+/// Find runs of consecutive dates.
+///
+/// `dates` must be sorted and unique. Returns `(start, end)` pairs.
+fn streaks<I: IntoIterator<Item = NaiveDate>>(
+    dates: I,
+) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut streaks: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    for date in dates {
+        match streaks.last_mut() {
+            Some((_, end)) if end.succ_opt() == Some(date) => *end = date,
+            _ => streaks.push((date, date)),
+        }
+    }
+    streaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+    use chrono::Utc;
+
+    fn seconds(input: &str) -> i64 {
+        input.parse::<DateTime<Utc>>().unwrap().timestamp()
+    }
+
+    fn now(input: &str) -> DateTime<Utc> {
+        input.parse().unwrap()
+    }
+
+    fn contributions() -> LocalContributions {
+        LocalContributions::from([
+            (
+                "a".to_owned(),
+                vec![
+                    seconds("2024-01-01T12:00:00Z"),
+                    seconds("2024-01-02T12:00:00Z"),
+                    seconds("2024-01-02T13:00:00Z"),
+                    seconds("2024-01-03T12:00:00Z"),
+                ],
+            ),
+            ("b".to_owned(), vec![seconds("2024-01-09T13:00:00Z")]),
+            (
+                "c".to_owned(),
+                vec![
+                    seconds("2023-06-01T13:00:00Z"),
+                    seconds("2024-01-10T13:00:00Z"),
+                ],
+            ),
+        ])
+    }
+
+    #[test]
+    fn empty() {
+        assert!(
+            Statistics::new(
+                &LocalContributions::new(),
+                &now("2024-01-10T20:00:00Z"),
+                DEFAULT_TOP_REPOS,
+            ) == Statistics::default()
+        );
+    }
+
+    #[test]
+    fn statistics() {
+        let stats =
+            Statistics::new(&contributions(), &now("2024-01-11T08:00:00Z"), 2);
+        assert!(stats.commits == 7);
+        assert!(stats.active_days == 6);
+        assert!(
+            stats.current_streak
+                == Some(Streak {
+                    start: "2024-01-09".to_owned(),
+                    end: "2024-01-10".to_owned(),
+                    days: 2,
+                })
+        );
+        assert!(
+            stats.longest_streak
+                == Some(Streak {
+                    start: "2024-01-01".to_owned(),
+                    end: "2024-01-03".to_owned(),
+                    days: 3,
+                })
+        );
+        assert!(
+            stats.busiest_day
+                == Some(DayCount { date: "2024-01-02".to_owned(), commits: 2 })
+        );
+        // 2024-01-01 was a Monday, and 2023-06-01 was a Thursday.
+        assert!(stats.weekdays == [0, 1, 3, 2, 1, 0, 0]);
+        assert!(
+            stats.years
+                == [
+                    YearCount { year: 2023, commits: 1 },
+                    YearCount { year: 2024, commits: 6 },
+                ]
+        );
+        assert!(
+            stats.top_repos
+                == [
+                    RepoCount { repo: "a".to_owned(), commits: 4 },
+                    RepoCount { repo: "c".to_owned(), commits: 2 },
+                ]
+        );
+    }
+
+    #[test]
+    #[expect(clippy::float_cmp, reason = "same calculation, so exactly equal")]
+    fn velocity() {
+        let velocity =
+            Velocity::new(&contributions(), &now("2024-01-10T20:00:00Z"), 7);
+        assert!(velocity.days.len() == VELOCITY_DAYS as usize);
+        let last = velocity.days.last().unwrap();
+        assert!(last.date == "2024-01-10");
+        assert!(last.commits == 1);
+        // 2024-01-04 through 2024-01-10.
+        assert!(velocity.current == 2.0 / 7.0);
+        // 2023-12-28 through 2024-01-03.
+        assert!(velocity.previous == 4.0 / 7.0);
+        assert!(velocity.trend == Trend::Down);
+
+        let day = |date: &str| {
+            velocity.days.iter().find(|day| day.date == date).unwrap()
+        };
+        assert!(day("2024-01-03").average == 4.0 / 7.0);
+        assert!(day("2024-01-08").average == 3.0 / 7.0);
+        assert!(day("2023-12-31").average == 0.0);
+    }
+
+    #[test]
+    fn window() {
+        assert!(parse_window("28d") == Some(28));
+        assert!(parse_window("4w") == Some(28));
+        assert!(parse_window("10") == Some(10));
+        assert!(parse_window("0d").is_none());
+        assert!(parse_window("1000d").is_none());
+        assert!(parse_window("4m").is_none());
+        assert!(parse_window("").is_none());
+    }
+
+    #[test]
+    fn streak_broken() {
+        let stats = Statistics::new(
+            &contributions(),
+            &now("2024-01-12T08:00:00Z"),
+            DEFAULT_TOP_REPOS,
+        );
+        assert!(stats.current_streak.is_none());
+        assert!(stats.longest_streak.unwrap().days == 3);
+    }
+}
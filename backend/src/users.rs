@@ -0,0 +1,129 @@
+//! Multiple users on one server.
+//!
+//! Each user has their own repositories to scan and a token to access their
+//! contributions through `/api/u/{user}/...` endpoints.
+
+use crate::repos;
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+/// Configuration for all users.
+#[derive(Debug, Clone, Default, Deserialize, Eq, PartialEq)]
+pub struct Users {
+    /// Users allowed to access the server.
+    #[serde(default)]
+    pub users: Vec<User>,
+}
+
+/// Configuration for one user.
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+pub struct User {
+    /// Name used in URLs, e.g. `/api/u/{name}/contributions`.
+    pub name: String,
+
+    /// Secret token to access this user’s data.
+    pub token: String,
+
+    /// Repositories to scan for this user.
+    #[serde(flatten)]
+    pub scan_config: repos::Config,
+}
+
+impl Users {
+    /// Parse a TOML configuration.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use assert2::assert;
+    /// use repoyear_backend::users::Users;
+    ///
+    /// let users = Users::parse(
+    ///     r#"
+    ///     [[users]]
+    ///     name = "alice"
+    ///     token = "s3cret"
+    ///
+    ///     [[users.repos]]
+    ///     root = "/home/alice/src"
+    ///     "#,
+    /// )
+    /// .unwrap();
+    /// assert!(users.users[0].name == "alice");
+    /// assert!(users.users[0].scan_config.repos.len() == 1);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if it can’t parse the configuration.
+    pub fn parse(input: &str) -> repos::Result<Self> {
+        Ok(toml::from_str(input)?)
+    }
+
+    /// Find a user and check their token.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UserError::NotFound`] if there is no user named `name`, or
+    /// [`UserError::Unauthorized`] if `token` is wrong. Tokens are compared in
+    /// constant time.
+    pub fn authenticate(
+        &self,
+        name: &str,
+        token: Option<&str>,
+    ) -> Result<&User, UserError> {
+        let user = self
+            .users
+            .iter()
+            .find(|user| user.name == name)
+            .ok_or(UserError::NotFound)?;
+        if token.is_some_and(|token| {
+            token.as_bytes().ct_eq(user.token.as_bytes()).into()
+        }) {
+            Ok(user)
+        } else {
+            Err(UserError::Unauthorized)
+        }
+    }
+}
+
+/// Error from accessing a user’s data.
+#[derive(Debug, thiserror::Error)]
+pub enum UserError {
+    /// There is no such user.
+    #[error("User not found")]
+    NotFound,
+
+    /// The token was missing or wrong.
+    #[error("Invalid token")]
+    Unauthorized,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn authenticate() {
+        let users = Users::parse(
+            r#"
+            [[users]]
+            name = "alice"
+            token = "a"
+            repos = []
+
+            [[users]]
+            name = "bob"
+            token = "b"
+            repos = []
+            "#,
+        )
+        .unwrap();
+
+        assert!(users.authenticate("bob", Some("b")).unwrap().name == "bob");
+        assert!(let Err(UserError::Unauthorized) = users.authenticate("bob", Some("a")));
+        assert!(let Err(UserError::Unauthorized) = users.authenticate("bob", None));
+        assert!(let Err(UserError::NotFound) = users.authenticate("carol", Some("c")));
+    }
+}
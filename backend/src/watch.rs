@@ -0,0 +1,129 @@
+//! Watch repositories for changes and keep the scan cache up to date.
+
+use notify::{RecursiveMode, Watcher};
+use repoyear_backend::api::readiness::Readiness;
+use repoyear_backend::repos::{self, ScanCache, ScanStatus};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// How long changes must stop for before rescanning.
+///
+/// A single commit touches several files in quick succession.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Scan the repositories in `config` into `cache`, then watch the tree roots
+/// and single repositories, and rescan repositories as they change.
+///
+/// The names of rescanned repositories are sent to `changes`. `readiness` is
+/// marked ready after the initial scan. This must be called
+/// within a tokio runtime. Aborting the returned task stops watching.
+///
+/// # Errors
+///
+/// Returns an error if the watcher can’t be started.
+pub fn spawn(
+    config: repos::Config,
+    cache: Arc<Mutex<Option<ScanCache>>>,
+    status: Arc<Mutex<ScanStatus>>,
+    changes: broadcast::Sender<Vec<String>>,
+    readiness: Arc<Readiness>,
+) -> notify::Result<JoinHandle<()>> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |result| {
+        // The receiver only goes away when the server is shutting down.
+        let _ = sender.send(result);
+    })?;
+    let roots = config.repos.iter().map(|tree| &tree.root);
+    let repos = config.single_repos.iter().map(|repo| &repo.path);
+    for root in roots.chain(repos) {
+        // Paths from git are canonical, so paths from events must be too.
+        let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+    }
+
+    Ok(tokio::spawn(async move {
+        // Keep the watcher alive as long as we’re receiving events.
+        let _watcher = watcher;
+        if let Err(error) =
+            run(config, cache, status, &changes, &readiness, receiver).await
+        {
+            tracing::error!("Stopped watching repositories: {error}");
+        }
+    }))
+}
+
+/// Do the initial scan, then rescan after each batch of events.
+async fn run(
+    config: repos::Config,
+    cache: Arc<Mutex<Option<ScanCache>>>,
+    status: Arc<Mutex<ScanStatus>>,
+    changes: &broadcast::Sender<Vec<String>>,
+    readiness: &Readiness,
+    mut receiver: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+) -> Result<(), tokio::task::JoinError> {
+    let config = Arc::new(config);
+    let mut scan = {
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || ScanCache::scan(&config)).await?
+    };
+    tracing::info!("Watching repositories for changes");
+    publish(&cache, &status, &scan);
+    readiness.set_ready();
+
+    while let Some(paths) = next_batch(&mut receiver).await {
+        let config = config.clone();
+        let updated;
+        (scan, updated) = tokio::task::spawn_blocking(move || {
+            let updated = scan.update(&config, paths);
+            (scan, updated)
+        })
+        .await?;
+        if !updated.is_empty() {
+            tracing::info!("Rescanned {}", updated.join(", "));
+            publish(&cache, &status, &scan);
+            // Nobody might be listening.
+            let _ = changes.send(updated);
+        }
+    }
+    Ok(())
+}
+
+/// Make scan results available to requests.
+fn publish(
+    cache: &Mutex<Option<ScanCache>>,
+    status: &Mutex<ScanStatus>,
+    scan: &ScanCache,
+) {
+    status
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .record(scan.metrics());
+    *cache.lock().unwrap_or_else(PoisonError::into_inner) = Some(scan.clone());
+}
+
+/// Wait for events, then collect paths until events stop for [`DEBOUNCE`].
+///
+/// Returns `None` if the watcher has stopped.
+async fn next_batch(
+    receiver: &mut mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+) -> Option<BTreeSet<PathBuf>> {
+    let mut paths = BTreeSet::new();
+    let mut result = receiver.recv().await?;
+    loop {
+        match result {
+            Ok(event) if !event.kind.is_access() => paths.extend(event.paths),
+            Ok(_) => {}
+            Err(error) => {
+                tracing::warn!("Error watching repositories: {error}");
+            }
+        }
+        match tokio::time::timeout(DEBOUNCE, receiver.recv()).await {
+            Ok(Some(next)) => result = next,
+            Ok(None) | Err(_) => return Some(paths),
+        }
+    }
+}